@@ -1,9 +1,11 @@
 use anyhow::Result;
+use clap::{CommandFactory, FromArgMatches};
 
-use garden::build;
+use garden::cli;
 use garden::cmds;
 use garden::config;
 use garden::errors;
+use garden::logging;
 use garden::model;
 
 fn main() -> Result<()> {
@@ -14,6 +16,8 @@ fn main() -> Result<()> {
                 match garden_err {
                     // ExitStatus exits without printing a message.
                     errors::GardenError::ExitStatus(status) => status,
+                    // Failing trees have already reported their errors.
+                    errors::GardenError::TreesFailed { exit_status, .. } => exit_status,
                     // Other GardenError variants print a message before exiting.
                     _ => {
                         eprintln!("error: {:#}", garden_err);
@@ -33,105 +37,78 @@ fn main() -> Result<()> {
 }
 
 fn cmd_main() -> Result<()> {
-    let mut options = parse_args();
+    // Respond to shell completion requests made by "garden completion" scripts.
+    clap_complete::CompleteEnv::with_factory(cli::MainOptions::command)
+        .var(cmds::completion::COMPLETE_VAR)
+        .complete();
+
+    let matches = cli::MainOptions::command().get_matches();
+    let main_options = cli::MainOptions::from_arg_matches(&matches)?;
+    let log_level = logging::level(main_options.log_level, &main_options.debug);
+    logging::init(log_level, &main_options.log_format, &main_options.color);
+    // Every log record is recorded inside of a span for the command being run.
+    let command_name = matches.subcommand_name().unwrap_or_default();
+    let _span = tracing::info_span!("command", command = command_name).entered();
+
+    let options = main_options.command_options()?;
 
     // The following commands run without a configuration file
-    match options.subcommand {
-        model::Command::Help => {
-            return cmds::help::main(&mut options);
+    match &main_options.command {
+        cli::Command::Completion(completion_options) => {
+            return cmds::completion::main(completion_options);
+        }
+        cli::Command::ShellInit(shell_init_options) => {
+            return cmds::shell_init::main(shell_init_options);
         }
-        model::Command::Init => {
-            return cmds::init::main(&mut options);
+        cli::Command::Help(help_options) if cmds::help::is_builtin(help_options) => {
+            return cmds::help::main(help_options);
+        }
+        cli::Command::Init(init_options) => {
+            return cmds::init::main(&options, init_options);
         }
         _ => (),
     }
 
     let config = config::from_options(&options)?;
-    let mut app = build::context_from_config(config, options)?;
-
-    match app.options.subcommand.clone() {
-        model::Command::Cmd => cmds::cmd::main(&mut app),
-        model::Command::Custom(cmd) => cmds::cmd::custom(&mut app, &cmd),
-        model::Command::Exec => cmds::exec::main(&mut app),
-        model::Command::Eval => cmds::eval::main(&mut app),
-        model::Command::Grow => cmds::grow::main(&mut app),
-        model::Command::Help => Ok(()), // Handled above
-        model::Command::Init => Ok(()), // Handled above
-        model::Command::Inspect => cmds::inspect::main(&mut app),
-        model::Command::List => cmds::list::main(&mut app),
-        model::Command::Plant => cmds::plant::main(&mut app),
-        model::Command::Prune => cmds::prune::main(&mut app),
-        model::Command::Shell => cmds::shell::main(&mut app),
-    }
-}
-
-fn parse_args() -> model::CommandOptions {
-    let color_names = model::ColorMode::names();
-    let color_help = format!("Set color mode {{{}}}", color_names);
-
-    let mut options = model::CommandOptions::new();
-    {
-        let mut ap = argparse::ArgumentParser::new();
-        ap.set_description("garden - Cultivate git trees");
-        ap.stop_on_first_argument(true);
-
-        ap.refer(&mut options.filename_str).add_option(
-            &["-c", "--config"],
-            argparse::Store,
-            "Set the config file to use",
-        );
-
-        ap.refer(&mut options.chdir).add_option(
-            &["-C", "--chdir"],
-            argparse::Store,
-            "Change directories before searching for garden files",
-        );
-
-        ap.refer(&mut options.color_mode)
-            .add_option(&["--color"], argparse::Store, &color_help);
-
-        ap.refer(&mut options.debug).add_option(
-            &["-d", "--debug"],
-            argparse::Collect,
-            "Increase verbosity for a debug category",
-        );
-
-        ap.refer(&mut options.root).add_option(
-            &["-r", "--root"],
-            argparse::Store,
-            "Set the garden tree root (default: ${GARDEN_ROOT})",
-        );
-
-        ap.refer(&mut options.variables).add_option(
-            &["-s", "--set"],
-            argparse::Collect,
-            "Set variables using 'name=value' expressions",
-        );
-
-        ap.refer(&mut options.verbose).add_option(
-            &["-v", "--verbose"],
-            argparse::IncrBy(1),
-            "Increase verbosity level (default: 0)",
-        );
-
-        ap.refer(&mut options.quiet).add_option(
-            &["-q", "--quiet"],
-            argparse::StoreTrue,
-            "Be quiet",
-        );
-
-        ap.refer(&mut options.subcommand).required().add_argument(
-            "command",
-            argparse::Store,
-            "{cmd, eval, exec, grow, help, init, inspect, ls, plant, prune, shell, <custom>}",
-        );
-
-        ap.refer(&mut options.args)
-            .add_argument("arguments", argparse::List, "Command arguments");
-
-        ap.parse_args_or_exit();
+    // Grafts are read on demand by the commands that resolve tree queries.
+    let mut app = model::ApplicationContext::new(config, options);
+
+    match &main_options.command {
+        cli::Command::Branch(branch_options) => cmds::branch::main(&mut app, branch_options),
+        cli::Command::Cache(cache_options) => cmds::cache::main(&mut app, cache_options),
+        cli::Command::Cd(cd_options) => cmds::cd::main(&mut app, cd_options),
+        cli::Command::Check(check_options) => cmds::check::main(&mut app, check_options),
+        cli::Command::Cmd(cmd_options) => cmds::cmd::main(&mut app, cmd_options),
+        cli::Command::Completion(_) => Ok(()), // Handled above
+        cli::Command::Configure(configure_options) => {
+            cmds::configure::main(&mut app, configure_options)
+        }
+        cli::Command::Custom(args) => cmds::cmd::custom(&mut app, args),
+        cli::Command::Diff(diff_options) => cmds::diff::main(&mut app, diff_options),
+        cli::Command::Doctor(doctor_options) => cmds::doctor::main(&mut app, doctor_options),
+        cli::Command::Env(env_options) => cmds::env::main(&mut app, env_options),
+        cli::Command::Exec(exec_options) => cmds::exec::main(&mut app, exec_options),
+        cli::Command::Eval(eval_options) => cmds::eval::main(&mut app, eval_options),
+        cli::Command::Export(export_options) => cmds::export::main(&mut app, export_options),
+        cli::Command::Fetch(fetch_options) => cmds::fetch::main(&mut app, fetch_options),
+        cli::Command::Grow(grow_options) => cmds::grow::main(&mut app, grow_options),
+        cli::Command::Help(help_options) => cmds::help::custom(&mut app, help_options),
+        cli::Command::Import(import_options) => cmds::import::main(&mut app, import_options),
+        cli::Command::Init(_) => Ok(()), // Handled above
+        cli::Command::Inspect(inspect_options) => cmds::inspect::main(&mut app, inspect_options),
+        cli::Command::List(list_options) => cmds::list::main(&mut app, list_options),
+        cli::Command::Lock(lock_options) => cmds::lock::main(&mut app, lock_options),
+        cli::Command::Plant(plant_options) => cmds::plant::main(&mut app, plant_options),
+        cli::Command::Prune(prune_options) => cmds::prune::main(&mut app, prune_options),
+        cli::Command::Restore(restore_options) => cmds::restore::main(&mut app, restore_options),
+        cli::Command::Shell(shell_options) => cmds::shell::main(&mut app, shell_options),
+        cli::Command::ShellInit(_) => Ok(()), // Handled above
+        cli::Command::Snapshot(snapshot_options) => {
+            cmds::snapshot::main(&mut app, snapshot_options)
+        }
+        cli::Command::Sync(sync_options) => cmds::sync::main(&mut app, sync_options),
+        cli::Command::Tree(tree_options) => cmds::tree::main(&mut app, tree_options),
+        cli::Command::Uproot(uproot_options) => cmds::uproot::main(&mut app, uproot_options),
+        cli::Command::Vars(vars_options) => cmds::vars::main(&mut app, vars_options),
     }
-    options.update();
-
-    options
 }
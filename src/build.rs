@@ -1,7 +1,10 @@
 /// Construction functions for garden entities.
 use super::config;
 use super::errors;
+use super::events;
 use super::model;
+use super::path;
+use super::syntax;
 
 pub fn context_from_path(
     path: &str,
@@ -20,3 +23,113 @@ pub fn context_from_config(
 
     Ok(app)
 }
+
+/// Build an ApplicationContext from a configuration file.
+///
+/// ```no_run
+/// # fn main() -> Result<(), garden::errors::GardenError> {
+/// let app = garden::ConfigBuilder::new()
+///     .path("garden.yaml")
+///     .set("name=value")
+///     .load()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ConfigBuilder {
+    options: model::CommandOptions,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        ConfigBuilder {
+            options: model::CommandOptions::new(),
+        }
+    }
+
+    /// Read the configuration from the specified path.
+    /// The default search paths are used when no path is specified.
+    pub fn path<P: Into<std::path::PathBuf>>(mut self, path: P) -> Self {
+        let path = path.into();
+        if path.exists() {
+            self.options.filename = Some(path::abspath(&path));
+        } else {
+            self.options.filename = Some(path);
+        }
+        self
+    }
+
+    /// Layer an override file on top of the configuration.
+    /// Override files are applied in the order they are added.
+    pub fn overlay<P: Into<std::path::PathBuf>>(mut self, path: P) -> Self {
+        let path = path.into();
+        if syntax::is_http_url(&path.to_string_lossy()) {
+            self.options.overlays.push(path);
+        } else {
+            self.options.overlays.push(path::abspath(&path));
+        }
+        self
+    }
+
+    /// Select the configuration profile to apply.
+    pub fn profile(mut self, name: &str) -> Self {
+        self.options.profile = Some(name.to_string());
+        self
+    }
+
+    /// Override the "garden.root" value.
+    pub fn root(mut self, root: &str) -> Self {
+        let root_path = std::path::PathBuf::from(root);
+        self.options.root = path::abspath(&root_path).to_string_lossy().into();
+        self
+    }
+
+    /// Override a variable using a "name=value" expression.
+    pub fn set(mut self, name_eq_value: &str) -> Self {
+        self.options.variables.push(name_eq_value.to_string());
+        self
+    }
+
+    /// Exclude trees matching the specified query.
+    pub fn exclude(mut self, query: &str) -> Self {
+        self.options.excludes.push(query.to_string());
+        self
+    }
+
+    /// Set the verbosity level.
+    pub fn verbose(mut self, value: u8) -> Self {
+        self.options.verbose = value;
+        self
+    }
+
+    /// Suppress output from commands.
+    pub fn quiet(mut self, value: bool) -> Self {
+        self.options.quiet = value;
+        self
+    }
+
+    /// Layer the user defaults from ~/.config/garden/garden.yaml under the configuration.
+    /// User defaults are not read unless enabled.
+    pub fn user_config(mut self, value: bool) -> Self {
+        self.options.user_config = value;
+        self
+    }
+
+    /// Report events for the trees processed by "exec", "grow" and "prune".
+    pub fn event_handler<H: events::ExecEventHandler + 'static>(mut self, handler: H) -> Self {
+        self.options.event_handler = Some(events::EventHandler::new(handler));
+        self
+    }
+
+    /// Read the configuration and its grafts.
+    pub fn load(self) -> Result<model::ApplicationContext, errors::GardenError> {
+        let config = config::from_options(&self.options)?;
+        context_from_config(config, self.options)
+    }
+}
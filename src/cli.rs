@@ -0,0 +1,181 @@
+use clap::{Parser, Subcommand};
+use clap_complete::engine::SubcommandCandidates;
+
+use super::cmds;
+use super::errors;
+use super::model;
+
+/// Command-line options shared by all garden commands.
+#[derive(Clone, Debug, Parser)]
+#[command(name = "garden", about = "Cultivate git trees", version)]
+#[command(disable_help_subcommand = true)]
+#[command(add = SubcommandCandidates::new(cmds::completion::commands))]
+pub struct MainOptions {
+    /// Set the color mode {auto, true, false, 1, 0, [y]es, [n]o, on, off, always, never}
+    #[arg(long, default_value = "auto", value_name = "mode")]
+    pub color: model::ColorMode,
+
+    /// Set the config file or "https://" URL to use. Additional files override the earlier files (repeatable)
+    #[arg(short, long, value_name = "file")]
+    pub config: Vec<String>,
+
+    /// Fetch configuration URLs again instead of using the cached copies
+    #[arg(long)]
+    pub refresh: bool,
+
+    /// Select a configuration profile (default: ${GARDEN_PROFILE})
+    #[arg(long, value_name = "name")]
+    pub profile: Option<String>,
+
+    /// Change directories before searching for garden files
+    #[arg(short = 'C', long, value_name = "dir")]
+    pub chdir: Option<String>,
+
+    /// Increase verbosity for a debug category
+    #[arg(short, long, value_name = "category")]
+    pub debug: Vec<String>,
+
+    /// Set the log level {off, error, warn, info, debug, trace}
+    #[arg(long, value_name = "level")]
+    pub log_level: Option<tracing_subscriber::filter::LevelFilter>,
+
+    /// Set the log format {text, json}
+    #[arg(long, default_value = "text", value_name = "format")]
+    pub log_format: model::OutputFormat,
+
+    /// Exclude the trees matched by a tree query (repeatable)
+    #[arg(long = "exclude", value_name = "query", global = true)]
+    pub excludes: Vec<String>,
+
+    /// Evaluate exec expressions without using the cache
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Set the garden tree root (default: ${GARDEN_ROOT})
+    #[arg(short, long, value_name = "path")]
+    pub root: Option<String>,
+
+    /// Set variables using 'name=value' or 'scope::name=value' expressions
+    #[arg(short = 's', long = "set", value_name = "name=value")]
+    pub variables: Vec<String>,
+
+    /// Increase verbosity level (default: 0)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Be quiet
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+impl MainOptions {
+    /// Create model::CommandOptions from the parsed command-line options.
+    pub fn command_options(&self) -> Result<model::CommandOptions, errors::GardenError> {
+        let mut options = model::CommandOptions::new().verbose(self.verbose);
+        options.user_config = true;
+        options.color_mode = self.color.clone();
+        options.debug = self.debug.clone();
+        options.variables = self.variables.clone();
+        options.quiet = self.quiet;
+        options.no_cache = self.no_cache;
+        options.excludes = self.excludes.clone();
+        options.refresh = self.refresh;
+        // Configuration URLs are fetched when the configuration is read.
+        if let Some((config, overlays)) = self.config.split_first() {
+            options.filename_str = config.to_string();
+            options.overlays = overlays.iter().map(std::path::PathBuf::from).collect();
+        }
+        // --profile overrides the GARDEN_PROFILE environment variable.
+        options.profile = self.profile.clone();
+        if options.profile.is_none() {
+            options.profile = std::env::var("GARDEN_PROFILE")
+                .ok()
+                .filter(|profile| !profile.is_empty());
+            options.profile_source = model::ProfileSource::Environment;
+        }
+        if let Some(chdir) = &self.chdir {
+            options.chdir = chdir.to_string();
+        }
+        if let Some(root) = &self.root {
+            options.root = root.to_string();
+        }
+        options.update()?;
+
+        Ok(options)
+    }
+}
+
+/// Garden subcommands.
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Create, checkout and list branches across trees
+    Branch(cmds::branch::BranchOptions),
+    /// Manage the exec expression cache and the remote graft cache
+    Cache(cmds::cache::CacheOptions),
+    /// Print the path to a tree for use by "garden shell-init" functions
+    Cd(cmds::cd::CdOptions),
+    /// Validate the garden file and report problems
+    Check(cmds::check::CheckOptions),
+    /// Run custom commands over gardens
+    Cmd(cmds::cmd::CmdOptions),
+    /// Generate shell completions
+    Completion(cmds::completion::CompletionOptions),
+    /// Apply gitconfig settings to existing trees
+    Configure(cmds::configure::ConfigureOptions),
+    /// Summarize uncommitted changes and unpushed commits
+    Diff(cmds::diff::DiffOptions),
+    /// Diagnose problems with git, shells, the garden root and remote hosts
+    Doctor(cmds::doctor::DoctorOptions),
+    /// Print the environment for a garden or tree
+    Env(cmds::env::EnvOptions),
+    /// Evaluate garden expressions
+    Eval(cmds::eval::EvalOptions),
+    /// Run commands inside gardens
+    Exec(cmds::exec::ExecOptions),
+    /// Export trees to vcstool or myrepos files
+    Export(cmds::export::ExportOptions),
+    /// Fetch remotes for trees in parallel
+    Fetch(cmds::fetch::FetchOptions),
+    /// Create and update gardens
+    Grow(cmds::grow::GrowOptions),
+    /// Print help for garden commands and custom commands
+    Help(cmds::help::HelpOptions),
+    /// Add trees from vcstool or myrepos files to a garden file
+    Import(cmds::import::ImportOptions),
+    /// Create an empty garden.yaml
+    Init(cmds::init::InitOptions),
+    /// Query tree status
+    Inspect(cmds::inspect::InspectOptions),
+    /// List gardens, groups and trees
+    #[command(name = "ls", alias = "list")]
+    List(cmds::list::ListOptions),
+    /// Record the current commit of each tree in a lockfile
+    Lock(cmds::lock::LockOptions),
+    /// Add pre-existing worktrees to a garden file
+    Plant(cmds::plant::PlantOptions),
+    /// Remove unreferenced Git repositories
+    Prune(cmds::prune::PruneOptions),
+    /// Restore trees to the state recorded by "garden snapshot"
+    Restore(cmds::restore::RestoreOptions),
+    /// Open a shell in a garden environment
+    #[command(alias = "sh")]
+    Shell(cmds::shell::ShellOptions),
+    /// Print a shell function that enables "garden cd <tree>"
+    ShellInit(cmds::shell_init::ShellInitOptions),
+    /// Record the branch, commit and uncommitted changes of each tree
+    Snapshot(cmds::snapshot::SnapshotOptions),
+    /// Fetch remotes and fast-forward trees
+    Sync(cmds::sync::SyncOptions),
+    /// Add trees to a garden file
+    Tree(cmds::tree::TreeOptions),
+    /// Remove trees from a garden file
+    Uproot(cmds::uproot::UprootOptions),
+    /// List variables and the scopes they are defined in
+    Vars(cmds::vars::VarsOptions),
+    /// Custom commands
+    #[command(external_subcommand)]
+    Custom(Vec<String>),
+}
@@ -1,5 +1,9 @@
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use super::errors;
 use super::eval;
+use super::events;
 use super::model;
 
 /// Return a subprocess::Exec instance from a command vector.
@@ -24,6 +28,15 @@ pub fn result_from_exit_status(exit_status: i32) -> Result<(), errors::GardenErr
     }
 }
 
+/// Return the last non-zero exit status from a slice of exit statuses.
+pub fn last_error_status(statuses: &[i32]) -> Option<i32> {
+    statuses
+        .iter()
+        .rev()
+        .find(|status| **status != errors::EX_OK)
+        .copied()
+}
+
 /// Extract the return status from subprocess::Result<subprocess::ExitStatus>.
 pub fn status(result: subprocess::Result<subprocess::ExitStatus>) -> i32 {
     let mut exit_status = errors::EX_ERROR;
@@ -107,51 +120,686 @@ where
 /// Parameters:
 /// - config: Mutable reference to a Configuration.
 /// - context: Reference to the TreeContext to evaluate.
-/// - quiet: Suppress messages when set true.
-/// - verbose: increase verbosity of messages.
+/// - options: Command options. "quiet" suppresses messages, "verbose" increases
+///   the verbosity of messages and "event_handler" receives start/finish events.
 /// - command: String vector of the command to run.
-
 pub fn exec_in_context<S>(
     config: &mut model::Configuration,
     context: &model::TreeContext,
-    quiet: bool,
-    verbose: u8,
+    options: &model::CommandOptions,
     command: &[S],
 ) -> Result<(), errors::GardenError>
 where
     S: AsRef<std::ffi::OsStr>,
 {
     let path;
+    let name;
     // Immutable scope over tree
     {
         let tree = &config.trees[context.tree];
         path = tree.path_as_ref()?.clone();
+        name = tree.get_name().clone();
 
         // Sparse gardens/missing trees are ok -> skip these entries.
-        if !model::print_tree(tree, verbose, quiet) {
+        if !model::print_tree(tree, options.verbose, options.quiet) {
             return Ok(());
         }
     }
     // Evaluate the tree environment and run the command.
-    let env = eval::environment(config, context);
+    let mut env = eval::environment(config, context);
     let command_vec = resolve_command(command, &env);
+    let directory = command_directory(config, options, &name, &path, &mut env);
+
+    let env = environment(&env, &config.env_filter, options.env_passthrough);
+    let command = Command::new(&name, &command_vec, &directory, env)
+        .timeout(options.timeout)
+        .log(options.log_path(&name))
+        .interactive(options.interactive);
+
+    let handler = options.event_handler.as_ref();
+    let event = events::TreeEvent::start(handler, events::ExecAction::Exec, &name, &path);
+    let exit_status = command.join();
+    event.finish(exit_status);
+
+    result_from_exit_status(exit_status)
+}
 
-    // Create an Exec object.
-    let mut exec = exec_in_dir(&command_vec, &path);
+/// Return the directory that "garden exec" runs a command in for a tree.
+/// Commands run inside of the tree by default. "--chdir-root" runs commands
+/// from the garden root and exports TREE_NAME and TREE_PATH instead.
+pub fn command_directory(
+    config: &model::Configuration,
+    options: &model::CommandOptions,
+    name: &str,
+    path: &str,
+    env: &mut Vec<(String, String)>,
+) -> String {
+    if !options.chdir_root {
+        return path.to_string();
+    }
+    env.insert(0, ("TREE_PATH".to_string(), path.to_string()));
+    env.insert(0, ("TREE_NAME".to_string(), name.to_string()));
 
-    //  Update the command environment
-    for (name, value) in &env {
+    config.root_path.to_string_lossy().to_string()
+}
+
+/// Set the environment for a command. See environment() for details.
+pub fn set_environment(
+    exec: subprocess::Exec,
+    env: &[(String, String)],
+    env_filter: &[String],
+    passthrough: model::EnvPassthrough,
+) -> subprocess::Exec {
+    exec.env_clear()
+        .env_extend(&environment(env, env_filter, passthrough))
+}
+
+/// Return the environment for a command. The parent environment is inherited by default.
+/// "garden.env-filter" glob patterns limit the inherited variables to the matching names,
+/// "--preserve-env" inherits everything and "--clean-env" inherits nothing.
+/// The garden-evaluated environment is applied last.
+pub fn environment(
+    env: &[(String, String)],
+    env_filter: &[String],
+    passthrough: model::EnvPassthrough,
+) -> Vec<(std::ffi::OsString, std::ffi::OsString)> {
+    let mut result = Vec::new();
+    match passthrough {
+        model::EnvPassthrough::Filter if !env_filter.is_empty() => {
+            let patterns: Vec<glob::Pattern> = env_filter
+                .iter()
+                .filter_map(|pattern| glob::Pattern::new(pattern).ok())
+                .collect();
+            for (name, value) in std::env::vars_os() {
+                let name_str = name.to_string_lossy();
+                if patterns.iter().any(|pattern| pattern.matches(&name_str)) {
+                    result.push((name, value));
+                }
+            }
+        }
+        model::EnvPassthrough::Clean => (),
+        _ => result.extend(std::env::vars_os()),
+    }
+    for (name, value) in env {
+        result.push((name.into(), value.into()));
+    }
+
+    result
+}
+
+/// Run a command in the specified directory with the specified environment.
+/// Each line of output is printed with the specified prefix.
+/// Returns the exit status of the command.
+pub fn run_with_prefix<S>(command: &[S], path: &str, env: &[(String, String)], prefix: &str) -> i32
+where
+    S: AsRef<std::ffi::OsStr>,
+{
+    let mut exec = exec_in_dir(command, path);
+    for (name, value) in env {
         exec = exec.env(name, value);
     }
 
-    result_from_exit_status(status(exec.join()))
+    join_with_prefix(exec, prefix)
+}
+
+/// Run a subprocess::Exec and wait for it to complete.
+/// Lines written to stdout and stderr are printed with the specified prefix.
+/// Returns the exit status of the command.
+pub fn join_with_prefix(exec: subprocess::Exec, prefix: &str) -> i32 {
+    let mut popen = match exec
+        .stdout(subprocess::Redirection::Pipe)
+        .stderr(subprocess::Redirection::Pipe)
+        .popen()
+    {
+        Ok(popen) => popen,
+        Err(_) => return errors::EX_ERROR,
+    };
+    let threads = print_with_prefix(&mut popen, Some(prefix), None);
+    for thread in threads {
+        thread.join().ok();
+    }
+
+    status(popen.wait())
+}
+
+/// A log file that receives the combined output of the commands run in a tree.
+type LogFile = std::sync::Arc<std::sync::Mutex<std::fs::File>>;
+
+/// Print the lines written to the stdout and stderr pipes of a subprocess with
+/// the specified prefix. Each pipe is drained in a separate thread so that neither
+/// pipe can fill up and block the child process. Lines are also written to the
+/// log file when specified. Returns the threads to join.
+fn print_with_prefix(
+    popen: &mut subprocess::Popen,
+    prefix: Option<&str>,
+    log: Option<LogFile>,
+) -> Vec<std::thread::JoinHandle<()>> {
+    let mut threads = Vec::new();
+    if let Some(stdout) = popen.stdout.take() {
+        let prefix = prefix
+            .map(|prefix| format!("{} ", prefix))
+            .unwrap_or_default();
+        let log = log.clone();
+        threads.push(std::thread::spawn(move || {
+            let reader = std::io::BufReader::new(stdout);
+            for line in reader.lines().map_while(Result::ok) {
+                println!("{}{}", prefix, line);
+                write_log_line(log.as_ref(), &line);
+            }
+        }));
+    }
+    if let Some(stderr) = popen.stderr.take() {
+        let prefix = prefix
+            .map(|prefix| format!("{} ", prefix))
+            .unwrap_or_default();
+        threads.push(std::thread::spawn(move || {
+            let reader = std::io::BufReader::new(stderr);
+            for line in reader.lines().map_while(Result::ok) {
+                eprintln!("{}{}", prefix, line);
+                write_log_line(log.as_ref(), &line);
+            }
+        }));
+    }
+
+    threads
+}
+
+/// Write a line of output to a log file.
+fn write_log_line(log: Option<&LogFile>, line: &str) {
+    if let Some(Ok(mut file)) = log.map(|log| log.lock()) {
+        writeln!(file, "{}", line).ok();
+    }
+}
+
+/// Open a log file for appending. Parent directories are created as needed.
+fn open_log(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+}
+
+/// Parse a "--log-dir" path. Relative paths are resolved before "--chdir" takes effect.
+pub fn parse_log_dir(value: &str) -> Result<std::path::PathBuf, String> {
+    std::path::absolute(value).map_err(|err| format!("invalid path: {}: {}", value, err))
+}
+
+/// Append text to the log file when "--log-dir" is in effect.
+/// Errors are ignored because the text has already been reported.
+pub fn write_log(path: Option<&std::path::Path>, text: &str) {
+    if let Some(Ok(mut file)) = path.map(open_log) {
+        writeln!(file, "{}", text.trim_end_matches('\n')).ok();
+    }
+}
+
+/// Read a pipe to completion in a separate thread. Lines are written to the log
+/// file as they are read so that stdout and stderr are interleaved in the log.
+fn read_pipe(pipe: Option<std::fs::File>, log: Option<LogFile>) -> std::thread::JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut output = String::new();
+        if let Some(pipe) = pipe {
+            let mut reader = std::io::BufReader::new(pipe);
+            let mut buffer = Vec::new();
+            while reader
+                .read_until(b'\n', &mut buffer)
+                .is_ok_and(|size| size > 0)
+            {
+                let line = String::from_utf8_lossy(&buffer);
+                write_log_line(log.as_ref(), line.trim_end_matches(['\r', '\n']));
+                output.push_str(&line);
+                buffer.clear();
+            }
+        }
+        output
+    })
+}
+
+/// Set once garden has been interrupted, e.g. by pressing Ctrl-C.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// The signal that interrupted garden.
+#[cfg(unix)]
+static SIGNAL: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(libc::SIGINT);
+
+/// The write end of the pipe that the signal handler uses to wake up the thread
+/// that forwards signals to the running commands.
+#[cfg(unix)]
+static SIGNAL_PIPE: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(-1);
+
+/// The commands that are currently running.
+#[cfg(unix)]
+static CHILDREN: std::sync::Mutex<Vec<Child>> = std::sync::Mutex::new(Vec::new());
+
+/// How long a timed out command is given to exit before it is killed.
+#[cfg(unix)]
+const TERMINATE_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// A running command. Commands that run in their own process group are signalled
+/// as a whole so that the processes that they spawn are signalled as well.
+#[cfg(unix)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Child {
+    pid: u32,
+    process_group: bool,
+    interactive: bool,
+}
+
+#[cfg(unix)]
+impl Child {
+    /// Forward a signal to the command. Commands that share garden's process group
+    /// already receive SIGINT from the terminal when Ctrl-C is pressed, so only
+    /// the other signals are forwarded to them.
+    fn signal(&self, signal: libc::c_int) {
+        if self.process_group {
+            signal_process_group(self.pid, signal);
+        } else if signal != libc::SIGINT {
+            // SAFETY: kill() only sends a signal and does not access memory.
+            unsafe {
+                libc::kill(self.pid as libc::pid_t, signal);
+            }
+        }
+    }
+}
+
+/// Handle SIGINT and SIGTERM so that garden runs are cancelled cleanly.
+///
+/// Commands that share garden's process group receive the interrupt from the terminal
+/// and the interrupt is forwarded to commands that run in their own process group.
+/// SIGTERM is forwarded to every running command. No new commands are started once
+/// garden has been interrupted. Interrupting garden a second time kills the running
+/// commands and exits immediately so that no commands are left running in the background.
+/// Commands that do not call this function exit as soon as they are interrupted.
+#[cfg(unix)]
+pub fn handle_interrupts() {
+    static ONCE: std::sync::Once = std::sync::Once::new();
+    ONCE.call_once(|| {
+        if let Err(err) = install_signal_handlers() {
+            tracing::debug!("unable to handle interrupts: {}", err);
+        }
+    });
+}
+
+/// Handle Ctrl-C so that garden runs are cancelled cleanly.
+#[cfg(not(unix))]
+pub fn handle_interrupts() {
+    let result = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(errors::EX_INTERRUPTED);
+        }
+    });
+    if let Err(err) = result {
+        tracing::debug!("unable to handle interrupts: {}", err);
+    }
+}
+
+/// Install the SIGINT and SIGTERM handlers. The handlers write the signal number
+/// into a pipe and a separate thread forwards the signal to the running commands
+/// because locking the list of commands is not safe inside of a signal handler.
+#[cfg(unix)]
+fn install_signal_handlers() -> std::io::Result<()> {
+    let mut fds: [libc::c_int; 2] = [-1, -1];
+    // SAFETY: pipe() writes two file descriptors into the array.
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let [read_fd, write_fd] = fds;
+    for fd in fds {
+        // SAFETY: the descriptors were just created. Commands must not inherit them.
+        unsafe {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+    }
+    SIGNAL_PIPE.store(write_fd, Ordering::SeqCst);
+    // SAFETY: read_fd is owned by the File from here on.
+    let mut pipe: std::fs::File = unsafe { std::os::fd::FromRawFd::from_raw_fd(read_fd) };
+    std::thread::spawn(move || {
+        let mut signal = [0u8; 1];
+        while std::io::Read::read_exact(&mut pipe, &mut signal).is_ok() {
+            forward_signal(signal[0] as libc::c_int);
+        }
+    });
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: on_signal() only calls write(), which is async-signal-safe.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Signal handler that wakes up the thread that forwards signals.
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    let fd = SIGNAL_PIPE.load(Ordering::SeqCst);
+    let byte = signal as u8;
+    // SAFETY: write() is async-signal-safe and the buffer outlives the call.
+    unsafe {
+        libc::write(fd, &byte as *const u8 as *const libc::c_void, 1);
+    }
+}
+
+/// Forward a signal to the running commands. The first signal is forwarded as-is.
+/// The running commands are killed and garden exits when a second signal arrives.
+/// Interactive commands handle Ctrl-C themselves, e.g. to cancel an edit in "vim",
+/// so SIGINT does not interrupt garden while an interactive command is running.
+#[cfg(unix)]
+fn forward_signal(signal: libc::c_int) {
+    if signal == libc::SIGINT && is_running_interactive() {
+        return;
+    }
+    let interrupted = INTERRUPTED.swap(true, Ordering::SeqCst);
+    let signal = if interrupted {
+        libc::SIGKILL
+    } else {
+        SIGNAL.store(signal, Ordering::SeqCst);
+        signal
+    };
+    let children = CHILDREN
+        .lock()
+        .map(|children| children.clone())
+        .unwrap_or_default();
+    for child in children {
+        child.signal(signal);
+    }
+    if interrupted {
+        std::process::exit(interrupted_status());
+    }
+}
+
+/// Return true when an interactive command is running.
+#[cfg(unix)]
+fn is_running_interactive() -> bool {
+    CHILDREN
+        .lock()
+        .map(|children| children.iter().any(|child| child.interactive))
+        .unwrap_or_default()
+}
+
+/// Return true when garden has been interrupted and should not start new commands.
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Return the exit status for an interrupted run. Runs that are cancelled by
+/// SIGTERM exit with status 143 and runs that are cancelled by Ctrl-C exit with 130.
+pub fn interrupted_status() -> i32 {
+    #[cfg(unix)]
+    if SIGNAL.load(Ordering::SeqCst) == libc::SIGTERM {
+        return errors::EX_TERMINATED;
+    }
+    errors::EX_INTERRUPTED
+}
+
+/// Return true when garden has a controlling terminal.
+/// The result is computed once and cached.
+#[cfg(unix)]
+fn has_terminal() -> bool {
+    static HAS_TERMINAL: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    *HAS_TERMINAL.get_or_init(|| std::fs::File::open("/dev/tty").is_ok())
+}
+
+/// Send a signal to every process in a process group.
+#[cfg(unix)]
+fn signal_process_group(pgid: u32, signal: libc::c_int) {
+    // SAFETY: killpg() only sends a signal and does not access memory.
+    unsafe {
+        libc::killpg(pgid as libc::pid_t, signal);
+    }
+}
+
+/// A command that runs in a directory with a fully evaluated environment.
+///
+/// Commands with a timeout run in their own process group so that every process
+/// that they spawn is terminated once the timeout elapses. These commands do not
+/// read from the terminal because they run outside of the foreground process group.
+/// Every command runs in its own process group when garden does not have a controlling
+/// terminal so that forwarded signals reach the processes that the command spawns.
+///
+/// Interactive commands always share garden's process group and inherit its stdin,
+/// stdout and stderr so that they can read from and write to the terminal.
+pub struct Command {
+    name: String,
+    argv: Vec<std::ffi::OsString>,
+    path: String,
+    env: Vec<(std::ffi::OsString, std::ffi::OsString)>,
+    timeout: Option<std::time::Duration>,
+    log: Option<std::path::PathBuf>,
+    interactive: bool,
+}
+
+impl Command {
+    /// Create a command that runs in the specified directory using the specified
+    /// environment. Timeout errors refer to the command by the specified name.
+    pub fn new<S>(
+        name: &str,
+        command: &[S],
+        path: &str,
+        env: Vec<(std::ffi::OsString, std::ffi::OsString)>,
+    ) -> Self
+    where
+        S: AsRef<std::ffi::OsStr>,
+    {
+        Command {
+            name: name.to_string(),
+            argv: command.iter().map(|arg| arg.as_ref().into()).collect(),
+            path: path.to_string(),
+            env,
+            timeout: None,
+            log: None,
+            interactive: false,
+        }
+    }
+
+    /// Builder function to set the maximum amount of time that the command can run.
+    pub fn timeout(mut self, timeout: Option<std::time::Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Builder function to tee the output of the command into a log file.
+    /// Output is appended to the log file.
+    pub fn log(mut self, log: Option<std::path::PathBuf>) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Builder function to run the command interactively. Interactive commands
+    /// inherit the terminal. Their output is not logged and they do not time out.
+    pub fn interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    /// Run the command and wait for it to complete. Returns the exit status.
+    pub fn join(&self) -> i32 {
+        if self.log.is_some() && !self.interactive {
+            return self.join_with_tee(None);
+        }
+        match self.popen(false) {
+            Ok(mut popen) => self.wait(&mut popen),
+            Err(_) => errors::EX_ERROR,
+        }
+    }
+
+    /// Run the command and wait for it to complete.
+    /// Lines written to stdout and stderr are printed with the specified prefix.
+    /// Returns the exit status of the command.
+    pub fn join_with_prefix(&self, prefix: &str) -> i32 {
+        self.join_with_tee(Some(prefix))
+    }
+
+    /// Run the command with its output sent through pipes so that each line can be
+    /// printed with an optional prefix and written to the log file.
+    fn join_with_tee(&self, prefix: Option<&str>) -> i32 {
+        let log = match self.open_log() {
+            Ok(log) => log,
+            Err(exit_status) => return exit_status,
+        };
+        let mut popen = match self.popen(true) {
+            Ok(popen) => popen,
+            Err(_) => return errors::EX_ERROR,
+        };
+        let threads = print_with_prefix(&mut popen, prefix, log);
+        let exit_status = self.wait(&mut popen);
+        for thread in threads {
+            thread.join().ok();
+        }
+
+        exit_status
+    }
+
+    /// Run the command and capture its stdout and stderr.
+    /// Returns the exit status, stdout and stderr of the command.
+    pub fn capture(&self) -> subprocess::Result<(i32, String, String)> {
+        let log = match self.open_log() {
+            Ok(log) => log,
+            Err(exit_status) => return Ok((exit_status, String::new(), String::new())),
+        };
+        let mut popen = self.popen(true)?;
+        let stdout = read_pipe(popen.stdout.take(), log.clone());
+        let stderr = read_pipe(popen.stderr.take(), log);
+        let exit_status = self.wait(&mut popen);
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+
+        Ok((exit_status, stdout, stderr))
+    }
+
+    /// Open the log file. Returns errors::EX_CANTCREAT when it cannot be opened.
+    fn open_log(&self) -> Result<Option<LogFile>, i32> {
+        let path = match &self.log {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        match open_log(path) {
+            Ok(file) => Ok(Some(std::sync::Arc::new(std::sync::Mutex::new(file)))),
+            Err(err) => {
+                eprintln!(
+                    "{} unable to open {}: {}",
+                    model::Color::red("error:").bold(),
+                    path.display(),
+                    err
+                );
+                Err(errors::EX_CANTCREAT)
+            }
+        }
+    }
+
+    /// Start the command. Output is sent to pipes when "pipe" is true.
+    fn popen(&self, pipe: bool) -> subprocess::Result<subprocess::Popen> {
+        let redirection = || {
+            if pipe {
+                subprocess::Redirection::Pipe
+            } else {
+                subprocess::Redirection::None
+            }
+        };
+        #[cfg(unix)]
+        let process_group = !self.interactive && (self.timeout.is_some() || !has_terminal());
+        let config = subprocess::PopenConfig {
+            stdout: redirection(),
+            stderr: redirection(),
+            cwd: Some(self.path.clone().into()),
+            env: Some(self.env.clone()),
+            #[cfg(unix)]
+            setpgid: process_group,
+            ..Default::default()
+        };
+        let popen = subprocess::Popen::create(&self.argv, config)?;
+        #[cfg(unix)]
+        if let Some(pid) = popen.pid() {
+            let child = Child {
+                pid,
+                process_group,
+                interactive: self.interactive,
+            };
+            if let Ok(mut children) = CHILDREN.lock() {
+                children.push(child);
+            }
+            // The signal may have arrived before the command was registered.
+            if is_interrupted() {
+                child.signal(SIGNAL.load(Ordering::SeqCst));
+            }
+        }
+
+        Ok(popen)
+    }
+
+    /// Wait for the command to complete. The command is terminated when its timeout
+    /// elapses, in which case errors::EX_TIMEOUT is returned. Commands that fail
+    /// after garden has been interrupted return the status from interrupted_status().
+    fn wait(&self, popen: &mut subprocess::Popen) -> i32 {
+        let exit_status = match self.timeout.filter(|_| !self.interactive) {
+            Some(timeout) => self.wait_timeout(popen, timeout),
+            None => status(popen.wait()),
+        };
+        #[cfg(unix)]
+        if let (Some(pid), Ok(mut children)) = (popen.pid(), CHILDREN.lock()) {
+            children.retain(|child| child.pid != pid);
+        }
+        // Commands that fail after garden was interrupted report the interruption.
+        if exit_status != errors::EX_OK && is_interrupted() {
+            return interrupted_status();
+        }
+
+        exit_status
+    }
+
+    /// Wait for the command to complete and terminate it once the timeout elapses.
+    fn wait_timeout(&self, popen: &mut subprocess::Popen, timeout: std::time::Duration) -> i32 {
+        match popen.wait_timeout(timeout) {
+            Ok(Some(exit_status)) => status(Ok(exit_status)),
+            Ok(None) => {
+                eprintln!(
+                    "{} {}: timed out after {:?}",
+                    model::Color::red("error:").bold(),
+                    self.name,
+                    timeout
+                );
+                terminate(popen);
+                errors::EX_TIMEOUT
+            }
+            Err(_) => errors::EX_ERROR,
+        }
+    }
+}
+
+/// Terminate a command that runs in its own process group. The process group is
+/// sent SIGTERM and is killed when the command does not exit within a grace period.
+#[cfg(unix)]
+fn terminate(popen: &mut subprocess::Popen) {
+    let pid = match popen.pid() {
+        Some(pid) => pid,
+        None => return,
+    };
+    signal_process_group(pid, libc::SIGTERM);
+    if let Ok(None) = popen.wait_timeout(TERMINATE_GRACE_PERIOD) {
+        signal_process_group(pid, libc::SIGKILL);
+        popen.wait().ok();
+    }
+}
+
+/// Terminate a command.
+#[cfg(not(unix))]
+fn terminate(popen: &mut subprocess::Popen) {
+    popen.kill().ok();
+    popen.wait().ok();
 }
 
 /// The command might be a path that only exists inside the resolved
 /// environment.  Resolve the path by looking for the presence of PATH
 /// and updating the command when it exists.
-
-fn resolve_command<S>(command: &[S], env: &[(String, String)]) -> Vec<String>
+pub fn resolve_command<S>(command: &[S], env: &[(String, String)]) -> Vec<String>
 where
     S: AsRef<std::ffi::OsStr>,
 {
@@ -179,8 +827,7 @@ where
 
     // Create a copy of the command so where the first entry has been replaced
     // with a $PATH-resolved absolute path.
-    let mut command_vec: Vec<String> = Vec::new();
-    command_vec.reserve(command.len());
+    let mut command_vec: Vec<String> = Vec::with_capacity(command.len());
 
     command_vec.push(cmd_path.to_string_lossy().to_string());
     for arg in &command[1..] {
@@ -218,10 +865,254 @@ pub fn current_exe() -> String {
     }
 }
 
-/// Parse arguments or exit with an error.
-pub fn parse_args(parser: argparse::ArgumentParser, arguments: Vec<String>) {
-    parser
-        .parse(arguments, &mut std::io::stdout(), &mut std::io::stderr())
-        .map_err(std::process::exit)
-        .ok();
+/// Failing trees recorded while running commands with "--keep-going".
+/// Failures can be recorded from multiple threads when using "--jobs".
+#[derive(Debug, Default)]
+pub struct Failures {
+    entries: std::sync::Mutex<Vec<(FailureKey, errors::TreeFailure)>>,
+}
+
+/// Failures are ordered by the config and tree index of the tree that failed.
+type FailureKey = (Option<model::ConfigId>, model::TreeIndex);
+
+impl Failures {
+    /// Record the command and exit status for a failing tree.
+    pub fn add(&self, context: &model::TreeContext, tree: &str, command: &str, exit_status: i32) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.push((
+                (context.config, context.tree),
+                errors::TreeFailure {
+                    tree: tree.to_string(),
+                    command: command.to_string(),
+                    exit_status,
+                },
+            ));
+        }
+    }
+
+    /// Forget the failures recorded by a previous command.
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+
+    /// Return the failing trees, commands and exit statuses in tree order.
+    /// Parallel jobs complete in any order so the entries are sorted by tree index.
+    /// Multiple failures for the same tree are kept in the order they were recorded.
+    pub fn entries(&self) -> Vec<errors::TreeFailure> {
+        let mut entries = self
+            .entries
+            .lock()
+            .map(|entries| entries.clone())
+            .unwrap_or_default();
+        entries.sort_by_key(|(key, _)| *key);
+        entries.into_iter().map(|(_, failure)| failure).collect()
+    }
+
+    /// Apply the exit status policy to the exit status of a command.
+    /// Interrupts take precedence over the exit status policy.
+    pub fn apply_policy(&self, policy: Option<model::ExitStatusPolicy>, exit_status: i32) -> i32 {
+        if is_interrupted() {
+            return exit_status;
+        }
+        self.exit_status(policy.unwrap_or_default())
+            .unwrap_or(exit_status)
+    }
+
+    /// Return the exit status that summarizes the failing trees according to
+    /// the exit status policy. None is returned when no trees have failed.
+    pub fn exit_status(&self, policy: model::ExitStatusPolicy) -> Option<i32> {
+        let entries = self.entries();
+        match policy {
+            model::ExitStatusPolicy::First => entries.first().map(|failure| failure.exit_status),
+            model::ExitStatusPolicy::Last => entries.last().map(|failure| failure.exit_status),
+            model::ExitStatusPolicy::Count if entries.is_empty() => None,
+            // Exit statuses are truncated to 8 bits so the count saturates at 255.
+            model::ExitStatusPolicy::Count => Some(entries.len().min(255) as i32),
+        }
+    }
+
+    /// Convert an exit status into a result. Non-zero exit statuses are reported
+    /// using GardenError::TreesFailed when trees have failed.
+    pub fn result(&self, exit_status: i32) -> Result<(), errors::GardenError> {
+        let failures = self.entries();
+        match exit_status {
+            errors::EX_OK => Ok(()),
+            _ if failures.is_empty() => Err(errors::GardenError::ExitStatus(exit_status)),
+            _ => Err(errors::GardenError::TreesFailed {
+                exit_status,
+                failures,
+            }),
+        }
+    }
+
+    /// Print a table of the failing trees and their exit statuses to stderr.
+    pub fn print_summary(&self) {
+        let entries = self.entries();
+        if entries.is_empty() {
+            return;
+        }
+        let width = entries
+            .iter()
+            .map(|failure| failure.tree.len())
+            .max()
+            .unwrap_or_default();
+        let trees = if entries.len() == 1 { "tree" } else { "trees" };
+        eprintln!(
+            "{} {} {} failed",
+            model::Color::red("error:").bold(),
+            entries.len(),
+            trees
+        );
+        for failure in &entries {
+            eprintln!(
+                "    {}  exit status {}",
+                model::Color::blue(format!("{:width$}", failure.tree, width = width)).bold(),
+                failure.exit_status
+            );
+        }
+    }
+}
+
+/// The kinds of shells that can run custom commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShellKind {
+    /// POSIX shells, e.g. sh, bash and zsh.
+    Posix,
+    /// PowerShell, e.g. pwsh and powershell.exe.
+    PowerShell,
+    /// The Windows command interpreter, cmd.exe.
+    Cmd,
+}
+
+/// Detect the kind of shell from a shell command, e.g. "C:\\Windows\\cmd.exe".
+pub fn shell_kind(shell: &str) -> ShellKind {
+    let basename = shell.rsplit(['/', '\\']).next().unwrap_or(shell);
+    let basename = basename.to_lowercase();
+    let name = basename.strip_suffix(".exe").unwrap_or(&basename);
+    match name {
+        "pwsh" | "powershell" => ShellKind::PowerShell,
+        "cmd" => ShellKind::Cmd,
+        _ => ShellKind::Posix,
+    }
+}
+
+/// Return the arguments used to run a command string using the specified shell.
+/// POSIX shells receive the command using "-c" with $0 set to the current executable
+/// and the arguments available as $1, $2, $N. PowerShell and cmd.exe do not support
+/// positional arguments so the quoted arguments are spliced into the command string.
+pub fn shell_args(
+    shell: &str,
+    command: &str,
+    exit_on_error: bool,
+    arguments: &[String],
+) -> Vec<String> {
+    let mut args = Vec::new();
+    match shell_kind(shell) {
+        ShellKind::Posix => {
+            if exit_on_error {
+                args.push("-e".to_string());
+            }
+            args.push("-c".to_string());
+            args.push(command.to_string());
+            args.push(current_exe());
+            args.extend(arguments.iter().cloned());
+        }
+        ShellKind::PowerShell => {
+            let mut script = String::new();
+            if exit_on_error {
+                script.push_str("$ErrorActionPreference = 'Stop'; ");
+            }
+            let quote = |arg: &str| format!("'{}'", arg.replace('\'', "''"));
+            script.push_str(&splice_arguments(command, arguments, &quote));
+            args.push("-NoProfile".to_string());
+            args.push("-Command".to_string());
+            args.push(script);
+        }
+        ShellKind::Cmd => {
+            let quote = |arg: &str| format!("\"{}\"", arg.replace('"', "\"\""));
+            let script = splice_arguments(command, arguments, &quote);
+            args.push("/D".to_string());
+            args.push("/C".to_string());
+            args.push(script);
+        }
+    }
+
+    args
+}
+
+/// Splice quoted arguments into a command string for shells without positional arguments.
+/// "$@" and "$*" are replaced by all of the arguments and "$1", "$2", $N are replaced by
+/// individual arguments. Placeholders surrounded by double quotes are replaced along with
+/// their quotes. The arguments are appended when the command contains no placeholders.
+fn splice_arguments(command: &str, arguments: &[String], quote: &dyn Fn(&str) -> String) -> String {
+    let mut result = String::new();
+    let mut spliced = false;
+    let mut remaining = command;
+    while let Some(idx) = remaining.find('$') {
+        let prefix = &remaining[..idx];
+        let suffix = &remaining[idx + 1..];
+        let digits = suffix.chars().take_while(char::is_ascii_digit).count();
+        let len = if suffix.starts_with(['@', '*']) {
+            1
+        } else {
+            digits
+        };
+        if len == 0 {
+            result.push_str(&remaining[..=idx]);
+            remaining = suffix;
+            continue;
+        }
+        let quoted = prefix.ends_with('"') && suffix[len..].starts_with('"');
+        let value = if digits == 0 {
+            let quoted_args: Vec<String> = arguments.iter().map(|arg| quote(arg)).collect();
+            quoted_args.join(" ")
+        } else {
+            let arg = match suffix[..digits].parse::<usize>() {
+                Ok(0) => Some(current_exe()),
+                Ok(index) => arguments.get(index - 1).cloned(),
+                Err(_) => None,
+            };
+            match arg {
+                Some(arg) => quote(&arg),
+                None if quoted => quote(""),
+                None => String::new(),
+            }
+        };
+        if quoted {
+            result.push_str(&prefix[..prefix.len() - 1]);
+            remaining = &suffix[len + 1..];
+        } else {
+            result.push_str(prefix);
+            remaining = &suffix[len..];
+        }
+        result.push_str(&value);
+        spliced = true;
+    }
+    result.push_str(remaining);
+    if !spliced {
+        for arg in arguments {
+            result.push(' ');
+            result.push_str(&quote(arg));
+        }
+    }
+
+    result
+}
+
+/// Print a command that will be executed.
+pub fn print_quoted_command(command: &[&str]) {
+    let mut quoted_args: Vec<String> = Vec::new();
+    for cmd in command {
+        let quoted = shlex::quote(cmd);
+        quoted_args.push(quoted.as_ref().to_string());
+    }
+
+    print_command_str(&quoted_args.join(" "));
+}
+
+/// Print a command that will be executed from a string.
+pub fn print_command_str(cmd: &str) {
+    println!("{} {}", model::Color::cyan(":"), model::Color::green(cmd),)
 }
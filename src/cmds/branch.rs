@@ -0,0 +1,173 @@
+use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+
+use super::super::cmd;
+use super::super::errors;
+use super::super::eval;
+use super::super::git;
+use super::super::model;
+use super::super::model::Color;
+use super::super::query;
+use super::completion;
+
+/// Create, checkout and list branches across trees
+#[derive(Parser, Clone, Debug)]
+pub struct BranchOptions {
+    /// Create the branch from the specified ref instead of the current HEAD
+    #[arg(long, value_name = "ref", conflicts_with_all = ["delete", "list"])]
+    pub from: Option<String>,
+    /// Delete the branch
+    #[arg(short, long, conflicts_with = "list")]
+    pub delete: bool,
+    /// Delete the branch even when it has not been merged
+    #[arg(short, long, requires = "delete")]
+    pub force: bool,
+    /// List the current branch in each tree
+    #[arg(short, long)]
+    pub list: bool,
+    /// Branch to create or checkout. Branches are not specified when using "--list"
+    pub branch: Option<String>,
+    /// Tree queries for the gardens, groups or trees to operate on (default: ".")
+    #[arg(add = ArgValueCandidates::new(completion::queries))]
+    pub queries: Vec<String>,
+}
+
+/// Main entry point for the "garden branch" command
+pub fn main(app: &mut model::ApplicationContext, options: &BranchOptions) -> Result<()> {
+    let quiet = app.options.quiet;
+    let verbose = app.options.verbose;
+    let mut queries = options.queries.clone();
+    // "--list" does not take a branch so all of the arguments are tree queries.
+    let branch = match &options.branch {
+        Some(branch) if options.list => {
+            queries.insert(0, branch.to_string());
+            String::new()
+        }
+        Some(branch) => branch.to_string(),
+        None if options.list => String::new(),
+        None => {
+            return Err(errors::GardenError::Usage("a branch must be specified".into()).into());
+        }
+    };
+    if queries.is_empty() {
+        queries.push(".".into());
+    }
+    let config = app.get_root_config_mut();
+
+    let mut exit_status = errors::EX_OK;
+    for query in &queries {
+        let contexts = query::resolve_trees(config, query);
+        if options.list {
+            list_branches(config, &contexts)?;
+            continue;
+        }
+        for ctx in &contexts {
+            let tree = &config.trees[ctx.tree];
+            // Skip symlink trees.
+            if tree.is_symlink {
+                continue;
+            }
+            if !model::print_tree(tree, verbose, quiet) {
+                continue;
+            }
+            let path = tree.path_as_ref()?.to_string();
+            let status = if options.delete {
+                delete_branch(&path, &branch, options.force, verbose)
+            } else {
+                let from = options
+                    .from
+                    .as_ref()
+                    .map(|from| eval::tree_value(config, from, ctx.tree, ctx.garden));
+                let remote = query::default_remote(config, ctx.tree, ctx.garden)
+                    .map(|remote| remote.get_name().to_string())
+                    .unwrap_or_else(|| "origin".to_string());
+                checkout_branch(&path, &branch, from.as_deref(), &remote, verbose)
+            };
+            if status != errors::EX_OK {
+                // Return the last non-zero exit status.
+                exit_status = status;
+            }
+        }
+    }
+
+    cmd::result_from_exit_status(exit_status).map_err(|err| err.into())
+}
+
+/// Checkout a branch, creating it when it does not exist. New branches track the
+/// default remote's branch when it exists and otherwise start from "from" or HEAD.
+fn checkout_branch(path: &str, branch: &str, from: Option<&str>, remote: &str, verbose: u8) -> i32 {
+    if git::current_branch(path).as_deref() == Some(branch) {
+        return errors::EX_OK;
+    }
+    let remote_branch = format!("{}/{}", remote, branch);
+    let mut command = vec!["git", "checkout"];
+    if git::ref_exists(path, &format!("refs/heads/{}", branch)) {
+        command.push(branch);
+    } else if let Some(from) = from {
+        command.extend(["-b", branch, from]);
+    } else if git::ref_exists(path, &format!("refs/remotes/{}", remote_branch)) {
+        command.extend(["-b", branch, "--track", &remote_branch]);
+    } else {
+        command.extend(["-b", branch]);
+    }
+    if verbose > 1 {
+        cmd::print_quoted_command(&command);
+    }
+
+    cmd::status(cmd::exec_in_dir(&command, path).join())
+}
+
+/// Delete a branch. Trees that do not have the branch are left untouched.
+fn delete_branch(path: &str, branch: &str, force: bool, verbose: u8) -> i32 {
+    if !git::ref_exists(path, &format!("refs/heads/{}", branch)) {
+        if verbose > 0 {
+            eprintln!("{}: branch not found", branch);
+        }
+        return errors::EX_OK;
+    }
+    let delete = if force { "-D" } else { "-d" };
+    let command = ["git", "branch", delete, branch];
+    if verbose > 1 {
+        cmd::print_quoted_command(&command);
+    }
+
+    cmd::status(cmd::exec_in_dir(&command, path).join())
+}
+
+/// Print the current branch for each tree. Detached trees display their commit.
+fn list_branches(config: &model::Configuration, contexts: &[model::TreeContext]) -> Result<()> {
+    let mut rows = Vec::new();
+    for ctx in contexts {
+        let tree = &config.trees[ctx.tree];
+        let path = tree.path_as_ref()?;
+        if tree.is_symlink || !std::path::Path::new(path).exists() {
+            continue;
+        }
+        let branch = match git::current_branch(path) {
+            Some(branch) => branch,
+            None => {
+                let command = ["git", "rev-parse", "--short", "HEAD"];
+                let commit = cmd::capture_stdout(cmd::exec_in_dir(&command, path))
+                    .map(|capture| cmd::trim_stdout(&capture))
+                    .unwrap_or_default();
+                format!("({} detached)", commit)
+            }
+        };
+        rows.push((tree.get_name(), branch));
+    }
+    let width = rows
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or_default();
+    for (name, branch) in &rows {
+        println!(
+            "{}  {}",
+            Color::blue(format!("{:width$}", name, width = width)).bold(),
+            Color::green(branch)
+        );
+    }
+
+    Ok(())
+}
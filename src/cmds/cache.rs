@@ -0,0 +1,113 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+use super::super::cmd;
+use super::super::config;
+use super::super::errors;
+use super::super::eval;
+use super::super::git;
+use super::super::model;
+use super::super::syntax;
+
+/// Manage the exec expression cache and the remote graft cache
+#[derive(Parser, Clone, Debug)]
+pub struct CacheOptions {
+    #[command(subcommand)]
+    pub command: CacheCommand,
+}
+
+/// Cache subcommands.
+#[derive(Clone, Debug, Subcommand)]
+pub enum CacheCommand {
+    /// Remove the cached exec expression values
+    Clear,
+    /// Fetch and update the grafts that are cloned from Git URLs
+    Update,
+}
+
+/// Main entry point for the "garden cache" command
+pub fn main(app: &mut model::ApplicationContext, options: &CacheOptions) -> Result<()> {
+    let quiet = app.options.quiet;
+    let config = app.get_root_config();
+    match options.command {
+        CacheCommand::Clear => clear(config, quiet),
+        CacheCommand::Update => {
+            config::clone_grafts(app)?;
+            let exit_status = update_grafts(app, app.get_root_id(), quiet);
+            cmd::result_from_exit_status(exit_status).map_err(|err| err.into())
+        }
+    }
+}
+
+/// Remove the "garden.cache" file for the configuration.
+fn clear(config: &model::Configuration, quiet: bool) -> Result<()> {
+    let path = config.get_path()?.with_extension("cache");
+    if !path.exists() {
+        return Ok(());
+    }
+    std::fs::remove_file(&path).map_err(|err| {
+        errors::GardenError::OSError(format!("unable to remove {:?}: {}", path, err))
+    })?;
+    if !quiet {
+        eprintln!("Removed {:?}", path);
+    }
+
+    Ok(())
+}
+
+/// Update the grafts that are cloned from Git URLs in a configuration and its grafts.
+/// Returns the last non-zero exit status.
+fn update_grafts(app: &model::ApplicationContext, id: model::ConfigId, quiet: bool) -> i32 {
+    let mut exit_status = errors::EX_OK;
+    let config = app.get_config(id);
+    for graft in &config.grafts {
+        let url = eval::value(config, &graft.config);
+        if syntax::is_git_url(&url) {
+            let dir = config::remote_graft_dir(&url, &graft.git_ref);
+            if !quiet {
+                eprintln!("# {}: updating {}", graft.get_name(), url);
+            }
+            let status = update_graft(&dir, &graft.git_ref);
+            if status != errors::EX_OK {
+                exit_status = status;
+            }
+        }
+        if let Some(graft_id) = graft.get_id() {
+            let status = update_grafts(app, *graft_id, quiet);
+            if status != errors::EX_OK {
+                exit_status = status;
+            }
+        }
+    }
+
+    exit_status
+}
+
+/// Fetch a remote graft and update its checkout. Grafts without a ref are fast-forwarded
+/// to their upstream branch. Branch refs are checked out from the updated remote branch.
+fn update_graft(dir: &std::path::Path, git_ref: &str) -> i32 {
+    match update_graft_checkout(dir, git_ref) {
+        Ok(()) => errors::EX_OK,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            err.into()
+        }
+    }
+}
+
+/// Fetch and update a remote graft's checkout using the Git backend.
+fn update_graft_checkout(dir: &std::path::Path, git_ref: &str) -> Result<(), errors::GardenError> {
+    let backend = git::backend();
+    backend.fetch(dir, "origin")?;
+    if git_ref.is_empty() {
+        return backend.fast_forward(dir);
+    }
+    let remote_branch = format!("origin/{}", git_ref);
+    let target = if backend.ref_exists(dir, &format!("refs/remotes/{}", remote_branch)) {
+        remote_branch.as_str()
+    } else {
+        git_ref
+    };
+
+    backend.checkout_ref(dir, "origin", target)
+}
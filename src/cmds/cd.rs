@@ -0,0 +1,44 @@
+use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+
+use super::super::errors;
+use super::super::model;
+use super::super::query;
+use super::completion;
+use super::shell;
+
+/// Print the path to a tree for use by "garden shell-init" functions
+///
+/// Add 'eval "$(garden shell-init bash)"' to ~/.bashrc to enable "garden cd <tree>".
+#[derive(Parser, Clone, Debug)]
+pub struct CdOptions {
+    /// Tree query for the tree to print (default: choose a tree interactively)
+    #[arg(add = ArgValueCandidates::new(completion::trees))]
+    pub tree: Option<String>,
+}
+
+/// Main entry point for the "garden cd" command
+pub fn main(app: &mut model::ApplicationContext, options: &CdOptions) -> Result<()> {
+    let query = match &options.tree {
+        Some(tree) => tree.to_string(),
+        None => shell::pick_tree(app.get_root_config())?,
+    };
+    let config = app.get_root_config();
+    let contexts = query::resolve_trees(config, &query);
+    // Prefer the tree whose name matches the query exactly over other matches.
+    let context = contexts
+        .iter()
+        .find(|ctx| config.trees[ctx.tree].get_name() == &query)
+        .or_else(|| contexts.first())
+        .ok_or_else(|| errors::GardenError::TreeNotFound {
+            tree: query.clone(),
+        })?;
+    let path = config.trees[context.tree].path_as_ref()?;
+    if !std::path::Path::new(path).exists() {
+        return Err(errors::GardenError::Usage(format!("tree does not exist: {}", path)).into());
+    }
+    println!("{}", path);
+
+    Ok(())
+}
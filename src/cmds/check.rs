@@ -0,0 +1,581 @@
+use anyhow::Result;
+use clap::Parser;
+use yaml_rust::yaml::Yaml;
+
+use super::super::config;
+use super::super::errors;
+use super::super::eval;
+use super::super::model;
+use super::super::query;
+use super::super::syntax;
+
+/// Validate the garden file and report problems
+#[derive(Parser, Clone, Debug)]
+pub struct CheckOptions {
+    /// Set the output format {text, json}
+    #[arg(short, long, default_value = "text", value_name = "format")]
+    pub format: model::OutputFormat,
+}
+
+/// Top-level configuration keys.
+const CONFIG_KEYS: &[&str] = &[
+    "commands",
+    "discover",
+    "garden",
+    "gardens",
+    "grafts",
+    "groups",
+    "hooks",
+    "includes",
+    "profiles",
+    "queries",
+    "templates",
+    "trees",
+    "variables",
+];
+
+/// Keys in the "garden" block.
+const GARDEN_BLOCK_KEYS: &[&str] = &[
+    "cache",
+    "cmd-order",
+    "env-filter",
+    "exit-status",
+    "root",
+    "shell",
+    "url-rewrites",
+];
+
+/// Keys in tree and template definitions.
+const TREE_KEYS: &[&str] = &[
+    "arguments",
+    "bare",
+    "branch",
+    "bundle",
+    "clone-reference",
+    "commands",
+    "default-remote",
+    "depends-on",
+    "depth",
+    "description",
+    "env-file",
+    "environment",
+    "extend",
+    "gitconfig",
+    "lfs",
+    "links",
+    "parameters",
+    "path",
+    "post-clone",
+    "profiles",
+    "ref",
+    "remotes",
+    "shell",
+    "single-branch",
+    "skip",
+    "submodules",
+    "symlink",
+    "templates",
+    "url",
+    "variables",
+    "worktree",
+];
+
+/// Keys in garden definitions.
+const GARDEN_KEYS: &[&str] = &[
+    "commands",
+    "env-file",
+    "environment",
+    "gitconfig",
+    "groups",
+    "prefix",
+    "profiles",
+    "root",
+    "shell",
+    "trees",
+    "variables",
+];
+
+/// Keys in graft definitions.
+const GRAFT_KEYS: &[&str] = &["config", "inherit", "path", "ref", "root"];
+
+/// The severity of a diagnostic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A problem found in the configuration.
+struct Diagnostic {
+    severity: Severity,
+    location: String,
+    message: String,
+    /// The "path:line" location in the configuration files, when known.
+    source: Option<String>,
+}
+
+/// Diagnostics collected while checking a configuration.
+#[derive(Default)]
+struct Diagnostics {
+    entries: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    fn error(&mut self, location: String, message: String) {
+        self.entries.push(Diagnostic {
+            severity: Severity::Error,
+            location,
+            message,
+            source: None,
+        });
+    }
+
+    fn warning(&mut self, location: String, message: String) {
+        self.entries.push(Diagnostic {
+            severity: Severity::Warning,
+            location,
+            message,
+            source: None,
+        });
+    }
+
+    fn error_at(&mut self, source: Option<String>, location: String, message: String) {
+        self.entries.push(Diagnostic {
+            severity: Severity::Error,
+            location,
+            message,
+            source,
+        });
+    }
+
+    fn has_errors(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.severity == Severity::Error)
+    }
+}
+
+/// Main entry point for the "garden check" command
+pub fn main(app: &mut model::ApplicationContext, options: &CheckOptions) -> Result<()> {
+    let config = app.get_root_config();
+    let doc = config::reader::read_yaml_with_includes(config)?;
+
+    let mut diagnostics = Diagnostics::default();
+    check_keys(&doc, &mut diagnostics);
+    check_references(&doc, config, &mut diagnostics);
+    check_expressions(&doc, config, &mut diagnostics);
+    check_tree_paths(config, &mut diagnostics);
+    check_cycles(config, &mut diagnostics);
+    check_dependencies(config, &mut diagnostics);
+    check_default_remotes(config, &mut diagnostics);
+    check_named_queries(config, &mut diagnostics);
+
+    match options.format {
+        model::OutputFormat::Json => print_json(&diagnostics)?,
+        model::OutputFormat::Text => print_text(&diagnostics),
+    }
+
+    if diagnostics.has_errors() {
+        return Err(errors::GardenError::ExitStatus(errors::EX_DATAERR).into());
+    }
+
+    Ok(())
+}
+
+/// Report keys that are not understood by garden.
+fn check_keys(doc: &Yaml, diagnostics: &mut Diagnostics) {
+    check_hash_keys(doc, "", CONFIG_KEYS, diagnostics);
+    check_hash_keys(&doc["garden"], "garden", GARDEN_BLOCK_KEYS, diagnostics);
+    for (section, keys) in [
+        ("templates", TREE_KEYS),
+        ("trees", TREE_KEYS),
+        ("gardens", GARDEN_KEYS),
+        ("grafts", GRAFT_KEYS),
+    ] {
+        for (name, value) in hash_entries(&doc[section]) {
+            let location = format!("{}.{}", section, name);
+            check_hash_keys(value, &location, keys, diagnostics);
+        }
+    }
+}
+
+/// Report unknown keys in a single hash.
+fn check_hash_keys(yaml: &Yaml, location: &str, keys: &[&str], diagnostics: &mut Diagnostics) {
+    for (key, _) in hash_entries(yaml) {
+        if !keys.contains(&key) {
+            diagnostics.error(join_location(location, key), "unknown key".into());
+        }
+    }
+}
+
+/// Report references to templates, trees and groups that do not exist,
+/// and invalid glob patterns.
+fn check_references(doc: &Yaml, config: &model::Configuration, diagnostics: &mut Diagnostics) {
+    let template_names = hash_keys(&doc["templates"]);
+    // Trees found using "discover" are not listed in the "trees" block.
+    let mut tree_names = hash_keys(&doc["trees"]);
+    for tree in &config.trees {
+        if !tree_names.contains(&tree.get_name().as_str()) {
+            tree_names.push(tree.get_name());
+        }
+    }
+    let group_names = hash_keys(&doc["groups"]);
+
+    for (name, value) in hash_entries(&doc["templates"]) {
+        let location = format!("templates.{}.extend", name);
+        for template in string_values(&value["extend"]) {
+            if !template_names.contains(&template.as_str()) {
+                let message = format!("undefined template: {}", template);
+                diagnostics.error(location.clone(), message);
+            }
+        }
+    }
+
+    for (name, value) in hash_entries(&doc["trees"]) {
+        let location = format!("trees.{}.templates", name);
+        for template in string_values(&value["templates"]) {
+            if !template_names.contains(&template.as_str()) {
+                let message = format!("undefined template: {}", template);
+                diagnostics.error(location.clone(), message);
+            }
+        }
+        for key in ["depends-on", "extend", "worktree"] {
+            let location = format!("trees.{}.{}", name, key);
+            for tree in string_values(&value[key]) {
+                // Expressions are evaluated at runtime and cannot be checked.
+                if tree.contains('$') || syntax::is_graft(&tree) {
+                    continue;
+                }
+                if !tree_names.contains(&tree.as_str()) {
+                    let message = format!("undefined tree: {}", tree);
+                    diagnostics.error(location.clone(), message);
+                }
+            }
+        }
+    }
+
+    for (name, value) in hash_entries(&doc["groups"]) {
+        let location = format!("groups.{}", name);
+        for member in string_values(value) {
+            check_pattern(&location, &member, &tree_names, "tree", diagnostics);
+        }
+    }
+
+    for (name, value) in hash_entries(&doc["gardens"]) {
+        let location = format!("gardens.{}.groups", name);
+        for group in string_values(&value["groups"]) {
+            check_pattern(&location, &group, &group_names, "group", diagnostics);
+        }
+        let location = format!("gardens.{}.trees", name);
+        for tree in string_values(&value["trees"]) {
+            check_pattern(&location, &tree, &tree_names, "tree", diagnostics);
+        }
+    }
+}
+
+/// Report malformed expressions, e.g. unterminated "${" expressions and calls to
+/// unknown functions. Expressions are checked without being evaluated.
+fn check_expressions(doc: &Yaml, config: &model::Configuration, diagnostics: &mut Diagnostics) {
+    let mut blocks = vec![
+        ("variables".to_string(), &doc["variables"]),
+        ("commands".to_string(), &doc["commands"]),
+    ];
+    for section in ["templates", "trees", "gardens"] {
+        for (name, value) in hash_entries(&doc[section]) {
+            for block in ["variables", "environment", "commands"] {
+                blocks.push((format!("{}.{}.{}", section, name, block), &value[block]));
+            }
+        }
+    }
+
+    for (block, yaml) in blocks {
+        for (key, value) in hash_entries(yaml) {
+            for expr in string_values(value) {
+                if let Err(err) = eval::check_expression(config, &expr) {
+                    let location = join_location(&block, key);
+                    let source = config::reader::find_location(config, &location);
+                    let message = format!("{}: {}", err.message, err.expr);
+                    diagnostics.error_at(source, location, message);
+                }
+            }
+        }
+    }
+}
+
+/// Check that a glob pattern is valid and that it matches at least one name.
+fn check_pattern(
+    location: &str,
+    value: &str,
+    names: &[&str],
+    kind: &str,
+    diagnostics: &mut Diagnostics,
+) {
+    // Grafted entries are resolved in other configurations.
+    if syntax::is_graft(value) {
+        return;
+    }
+    match glob::Pattern::new(value) {
+        Ok(pattern) => {
+            if !names.iter().any(|name| pattern.matches(name)) {
+                let message = format!("undefined {}: {}", kind, value);
+                diagnostics.error(location.to_string(), message);
+            }
+        }
+        Err(err) => {
+            let message = format!("invalid glob pattern '{}': {}", value, err);
+            diagnostics.error(location.to_string(), message);
+        }
+    }
+}
+
+/// Report trees that share the same path.
+fn check_tree_paths(config: &model::Configuration, diagnostics: &mut Diagnostics) {
+    let mut paths: Vec<(&str, &str)> = Vec::new();
+    for tree in &config.trees {
+        if tree.is_symlink {
+            continue;
+        }
+        let path = match tree.path_as_ref() {
+            Ok(path) => path.as_str(),
+            Err(_) => continue,
+        };
+        if let Some((other, _)) = paths.iter().find(|(_, other_path)| *other_path == path) {
+            let location = format!("trees.{}.path", tree.get_name());
+            let message = format!("duplicate path used by tree {}: {}", other, path);
+            diagnostics.warning(location, message);
+        } else {
+            paths.push((tree.get_name(), path));
+        }
+    }
+}
+
+/// Report trees that depend on themselves through other trees.
+fn check_dependencies(config: &model::Configuration, diagnostics: &mut Diagnostics) {
+    if let Err(errors::GardenError::InvalidConfiguration { msg }) =
+        query::tree_dependency_levels(config)
+    {
+        diagnostics.error("trees".into(), msg);
+    }
+}
+
+/// Report trees whose "default-remote" does not name one of their remotes.
+fn check_default_remotes(config: &model::Configuration, diagnostics: &mut Diagnostics) {
+    for (tree_idx, tree) in config.trees.iter().enumerate() {
+        if tree.default_remote.is_empty() {
+            continue;
+        }
+        let name = eval::tree_value(config, tree.default_remote.get_expr(), tree_idx, None);
+        if !tree.remotes.iter().any(|remote| *remote.get_name() == name) {
+            let location = format!("trees.{}.default-remote", tree.get_name());
+            diagnostics.error(location, format!("undefined remote: {}", name));
+        }
+    }
+}
+
+/// Report named queries that do not match any trees, e.g. because they
+/// reference themselves or contain misspelled names.
+fn check_named_queries(config: &model::Configuration, diagnostics: &mut Diagnostics) {
+    for named_query in &config.queries {
+        for tree_query in &named_query.queries {
+            // Grafted trees are resolved in other configurations.
+            if syntax::is_graft(tree_query) {
+                continue;
+            }
+            if query::resolve_trees(config, tree_query).is_empty() {
+                let location = format!("queries.{}", named_query.get_name());
+                let message = format!("query matches no trees: {}", tree_query);
+                diagnostics.warning(location, message);
+            }
+        }
+    }
+}
+
+/// Report variables that reference themselves through other variables.
+fn check_cycles(config: &model::Configuration, diagnostics: &mut Diagnostics) {
+    let global: Vec<&model::NamedVariable> = config.variables.iter().collect();
+    let mut reported = Vec::new();
+    report_cycles("variables", &global, &[], diagnostics, &mut reported);
+
+    for tree in &config.trees {
+        let location = format!("trees.{}.variables", tree.get_name());
+        let local: Vec<&model::NamedVariable> = tree.variables.iter().collect();
+        report_cycles(&location, &local, &global, diagnostics, &mut reported);
+    }
+    for garden in &config.gardens {
+        let location = format!("gardens.{}.variables", garden.get_name());
+        let local: Vec<&model::NamedVariable> = garden.variables.iter().collect();
+        report_cycles(&location, &local, &global, diagnostics, &mut reported);
+    }
+}
+
+/// Report cycles that involve the local variables. Local variables
+/// take precedence over the fallback variables.
+fn report_cycles(
+    location: &str,
+    local: &[&model::NamedVariable],
+    fallback: &[&model::NamedVariable],
+    diagnostics: &mut Diagnostics,
+    reported: &mut Vec<Vec<String>>,
+) {
+    let lookup = |name: &str| -> Option<String> {
+        local
+            .iter()
+            .chain(fallback.iter())
+            .find(|var| var.get_name() == name)
+            .map(|var| var.get_expr().to_string())
+    };
+    for var in local {
+        let mut stack = vec![var.get_name().to_string()];
+        if let Some(cycle) = find_cycle(&lookup, &mut stack) {
+            let mut key = cycle.clone();
+            key.sort();
+            key.dedup();
+            if reported.contains(&key) {
+                continue;
+            }
+            reported.push(key);
+            let message = format!("cyclic variable reference: {}", cycle.join(" -> "));
+            diagnostics.error(join_location(location, var.get_name()), message);
+        }
+    }
+}
+
+/// Walk variable references depth-first and return the first cycle found.
+fn find_cycle<F>(lookup: &F, stack: &mut Vec<String>) -> Option<Vec<String>>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let name = stack.last()?.clone();
+    let expr = lookup(&name)?;
+    for reference in variable_references(&expr) {
+        if let Some(start) = stack.iter().position(|entry| *entry == reference) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(reference);
+            return Some(cycle);
+        }
+        stack.push(reference);
+        if let Some(cycle) = find_cycle(lookup, stack) {
+            return Some(cycle);
+        }
+        stack.pop();
+    }
+
+    None
+}
+
+/// Return the names of the variables referenced by "${name}" expressions.
+fn variable_references(expr: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut remaining = expr;
+    while let Some(start) = remaining.find("${") {
+        remaining = &remaining[start + 2..];
+        let end = match syntax::find_closing_brace(remaining) {
+            Some(end) => end,
+            None => break,
+        };
+        let inner = &remaining[..end];
+        let name = match syntax::split_default(inner) {
+            Some((name, _default)) => name,
+            None => inner,
+        };
+        if !syntax::is_graft(name) {
+            result.push(name.to_string());
+        }
+        remaining = &remaining[end + 1..];
+    }
+
+    result
+}
+
+/// Print diagnostics as text.
+fn print_text(diagnostics: &Diagnostics) {
+    for entry in &diagnostics.entries {
+        let severity = match entry.severity {
+            Severity::Error => model::Color::red(entry.severity.as_str()).bold(),
+            Severity::Warning => model::Color::yellow(entry.severity.as_str()).bold(),
+        };
+        match &entry.source {
+            Some(source) => println!(
+                "{}: {}: {}: {}",
+                severity,
+                source,
+                model::Color::cyan(&entry.location),
+                entry.message
+            ),
+            None => println!(
+                "{}: {}: {}",
+                severity,
+                model::Color::cyan(&entry.location),
+                entry.message
+            ),
+        }
+    }
+}
+
+/// Print diagnostics as a JSON document.
+fn print_json(diagnostics: &Diagnostics) -> Result<()> {
+    let entries: Vec<serde_json::Value> = diagnostics
+        .entries
+        .iter()
+        .map(|entry| {
+            let mut value = serde_json::json!({
+                "severity": entry.severity.as_str(),
+                "location": entry.location,
+                "message": entry.message,
+            });
+            if let Some(source) = &entry.source {
+                value["source"] = source.as_str().into();
+            }
+            value
+        })
+        .collect();
+    println!("{}", serde_json::to_string_pretty(&entries)?);
+
+    Ok(())
+}
+
+/// Return the string keys and values of a YAML hash.
+fn hash_entries(yaml: &Yaml) -> Vec<(&str, &Yaml)> {
+    match yaml {
+        Yaml::Hash(hash) => hash
+            .iter()
+            .filter_map(|(key, value)| key.as_str().map(|key| (key, value)))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Return the string keys of a YAML hash.
+fn hash_keys(yaml: &Yaml) -> Vec<&str> {
+    hash_entries(yaml).into_iter().map(|(key, _)| key).collect()
+}
+
+/// Return a string or list of strings from a YAML value.
+fn string_values(yaml: &Yaml) -> Vec<String> {
+    match yaml {
+        Yaml::String(value) => vec![value.clone()],
+        Yaml::Array(values) => values
+            .iter()
+            .filter_map(|value| value.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Join a dotted location with a key.
+fn join_location(location: &str, key: &str) -> String {
+    if location.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", location, key)
+    }
+}
@@ -1,26 +1,195 @@
 use anyhow::Result;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use clap_complete::engine::ArgValueCandidates;
+use rayon::prelude::*;
 
 use super::super::cmd;
+use super::super::config;
 use super::super::errors;
 use super::super::eval;
 use super::super::model;
 use super::super::query;
+use super::super::syntax;
+use super::completion;
+
+/// Run custom commands over gardens
+#[derive(Parser, Clone, Debug)]
+pub struct CmdOptions {
+    /// Run a command in all trees before running the next command
+    #[arg(short, long, conflicts_with = "order")]
+    pub breadth_first: bool,
+    /// Run commands grouped by tree or by command {tree, command}
+    #[arg(long, value_name = "order")]
+    pub order: Option<model::CommandOrder>,
+    /// Chain the commands in each tree and stop when any command fails.
+    ///
+    /// The commands run with "&&" semantics. Every command must succeed in a tree
+    /// before the next tree starts and the first failure aborts the entire run.
+    /// "--and" overrides the "garden.cmd-order" setting.
+    #[arg(
+        long = "and",
+        visible_alias = "no-errexit-between",
+        conflicts_with_all = ["breadth_first", "order", "keep_going", "num_jobs", "exit_on_error"]
+    )]
+    pub chain: bool,
+    /// Only run the commands defined in the specified scope {tree, garden, config}
+    #[arg(long, value_name = "scope")]
+    pub scope: Option<model::CommandScope>,
+    /// Run commands in parallel using the specified number of jobs
+    #[arg(short = 'j', long = "jobs", default_value_t = 1, value_name = "jobs")]
+    pub num_jobs: usize,
+    /// Continue to the next tree when errors occur
+    #[arg(short, long)]
+    pub keep_going: bool,
+    /// Exit with the status of the first or last tree that failed, or the number of failed trees {first, last, count}
+    #[arg(long, value_name = "policy")]
+    pub exit_status: Option<model::ExitStatusPolicy>,
+    /// Run commands in the specified directory relative to each tree
+    #[arg(long, value_name = "path")]
+    pub directory: Option<String>,
+    /// Do not pass "-e" to the shell.
+    ///
+    /// Prevent the "errexit" shell option from being set. By default, the "-e" option
+    /// is passed to the configured shell so that multi-line and multi-statement
+    /// commands halt execution when the first statement with a non-zero exit code is
+    /// encountered. "--no-errexit" has the effect of making multi-line and
+    /// multi-statement commands run all statements even when an earlier statement
+    /// returns a non-zero exit code.
+    #[arg(short = 'n', long = "no-errexit", action = clap::ArgAction::SetFalse)]
+    pub exit_on_error: bool,
+    /// Terminate commands that run longer than the specified duration, e.g. "30s"
+    #[arg(long, value_name = "duration", value_parser = syntax::parse_duration)]
+    pub timeout: Option<std::time::Duration>,
+    /// List the commands that would run in each tree without running them
+    #[arg(short, long)]
+    pub list: bool,
+    /// Write the output of each tree to "<path>/<tree>.log" in addition to the console
+    #[arg(long, value_name = "path", value_parser = cmd::parse_log_dir)]
+    pub log_dir: Option<std::path::PathBuf>,
+    /// Gardens/Groups/Trees to exec (tree query)
+    #[arg(add = ArgValueCandidates::new(completion::queries))]
+    pub query: String,
+    /// Commands to run over resolved trees
+    #[arg(add = ArgValueCandidates::new(completion::commands), required = true)]
+    pub commands: Vec<String>,
+    /// Arguments to forward to the commands
+    #[arg(last = true)]
+    pub arguments: Vec<String>,
+}
+
+/// Run custom commands over gardens
+#[derive(Parser, Clone, Debug)]
+pub struct CustomOptions {
+    /// Run the command in each tree in turn and stop when it fails in any tree.
+    ///
+    /// "--and" runs the command with "&&" semantics across the trees.
+    #[arg(
+        long = "and",
+        visible_alias = "no-errexit-between",
+        conflicts_with_all = ["keep_going", "num_jobs", "exit_on_error"]
+    )]
+    pub chain: bool,
+    /// Only run the commands defined in the specified scope {tree, garden, config}
+    #[arg(long, value_name = "scope")]
+    pub scope: Option<model::CommandScope>,
+    /// Run commands in parallel using the specified number of jobs
+    #[arg(short = 'j', long = "jobs", default_value_t = 1, value_name = "jobs")]
+    pub num_jobs: usize,
+    /// Continue to the next tree when errors occur
+    #[arg(short, long)]
+    pub keep_going: bool,
+    /// Exit with the status of the first or last tree that failed, or the number of failed trees {first, last, count}
+    #[arg(long, value_name = "policy")]
+    pub exit_status: Option<model::ExitStatusPolicy>,
+    /// Run commands in the specified directory relative to each tree
+    #[arg(long, value_name = "path")]
+    pub directory: Option<String>,
+    /// Do not pass "-e" to the shell.
+    ///
+    /// Prevent the "errexit" shell option from being set. By default, the "-e" option
+    /// is passed to the configured shell so that multi-line and multi-statement
+    /// commands halt execution when the first statement with a non-zero exit code is
+    /// encountered. "--no-errexit" has the effect of making multi-line and
+    /// multi-statement commands run all statements even when an earlier statement
+    /// returns a non-zero exit code.
+    #[arg(short = 'n', long = "no-errexit", action = clap::ArgAction::SetFalse)]
+    pub exit_on_error: bool,
+    /// Terminate commands that run longer than the specified duration, e.g. "30s"
+    #[arg(long, value_name = "duration", value_parser = syntax::parse_duration)]
+    pub timeout: Option<std::time::Duration>,
+    /// List the commands that would run in each tree without running them
+    #[arg(short, long)]
+    pub list: bool,
+    /// Write the output of each tree to "<path>/<tree>.log" in addition to the console
+    #[arg(long, value_name = "path", value_parser = cmd::parse_log_dir)]
+    pub log_dir: Option<std::path::PathBuf>,
+    /// Gardens/Groups/Trees to exec (tree queries)
+    #[arg(add = ArgValueCandidates::new(completion::queries))]
+    pub queries: Vec<String>,
+    /// Arguments to forward to the command
+    #[arg(last = true)]
+    pub arguments: Vec<String>,
+}
 
 /// garden cmd <query> <command>...
-pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
-    let (query, params) = parse_args_cmd(&mut app.options);
-    let exit_status = cmd(app, &query, &params)?;
-    cmd::result_from_exit_status(exit_status).map_err(|err| err.into())
+pub fn main(app: &mut model::ApplicationContext, options: &CmdOptions) -> Result<()> {
+    // --and, --breadth-first and --order override the "garden.cmd-order" setting.
+    // --and cannot be combined with --no-errexit so commands always stop on errors.
+    let order = match options.order {
+        _ if options.chain => model::CommandOrder::Tree,
+        _ if options.breadth_first => model::CommandOrder::Command,
+        Some(order) => order,
+        None => app.get_root_config().cmd_order.unwrap_or_default(),
+    };
+    app.options.breadth_first = order == model::CommandOrder::Command;
+    app.options.failures.clear();
+    app.options.num_jobs = options.num_jobs;
+    app.options.keep_going = options.keep_going;
+    // --exit-status overrides the "garden.exit-status" setting.
+    app.options.exit_status = options.exit_status.or(app.get_root_config().exit_status);
+    app.options.exit_on_error = options.exit_on_error;
+    app.options.timeout = options.timeout;
+    app.options.log_dir = options.log_dir.clone();
+    app.options.command_scope = options.scope;
+    cmd::handle_interrupts();
+    app.options.working_directory = options.directory.clone();
+
+    let params = CmdParams {
+        commands: options.commands.clone(),
+        arguments: options.arguments.clone(),
+        queries: Vec::new(),
+        list: options.list,
+    };
+    if app.options.debug_level("cmd") > 0 {
+        debug!("subcommand: cmd");
+        debug!("query: {}", options.query);
+        debug!("commands: {:?}", params.commands);
+        debug!("arguments: {:?}", params.arguments);
+    }
+
+    let exit_status = cmd(app, &options.query, &params)?;
+    if app.options.keep_going {
+        app.options.failures.print_summary();
+    }
+    let exit_status = app
+        .options
+        .failures
+        .apply_policy(app.options.exit_status, exit_status);
+    app.options
+        .failures
+        .result(exit_status)
+        .map_err(|err| err.into())
 }
 
 /// CmdParams are used to control the execution of run_cmd_vec().
 ///
-/// "garden cmd" and "garden <custom-cmd>" parse command line arguments into struct CmdOptions.
+/// "garden cmd" and "garden <custom-cmd>" parse command line arguments into CmdParams.
 #[derive(Clone, Debug, Default)]
 pub struct CmdParams {
     commands: Vec<String>,
     arguments: Vec<String>,
     queries: Vec<String>,
+    list: bool,
 }
 
 impl CmdParams {
@@ -29,144 +198,83 @@ impl CmdParams {
     }
 }
 
-/// Parse "cmd" arguments.
-fn parse_args_cmd(options: &mut model::CommandOptions) -> (String, CmdParams) {
-    // Display "garden cmd" in the "garden cmd -h" help text.
-    options.args.insert(0, "garden cmd".into());
-
-    let mut query = String::new();
-    let mut params = CmdParams::new();
-    let mut commands_and_args: Vec<String> = Vec::new();
-    {
-        let mut ap = argparse::ArgumentParser::new();
-        ap.silence_double_dash(false);
-        ap.set_description("garden cmd - Run custom commands over gardens");
-        ap.refer(&mut options.breadth_first).add_option(
-            &["-b", "--breadth-first"],
-            argparse::StoreTrue,
-            "Run a command in all trees before running the next command.",
-        );
-        ap.refer(&mut options.keep_going).add_option(
-            &["-k", "--keep-going"],
-            argparse::StoreTrue,
-            "Continue to the next tree when errors occur.",
-        );
-        ap.refer(&mut options.exit_on_error).add_option(
-            &["-n", "--no-errexit"],
-            argparse::StoreFalse,
-            "Do not pass \"-e\" to the shell. This prevents the \"errexit\" shell \
-            option from being set. By default, the \"-e\" option is passed to the \
-            configured shell so that multi-line and multi-statement commands halt \
-            execution when the first statement with a non-zero exit code is \
-            encountered. \"--no-errexit\" has the effect of making multi-line and \
-            multi-statement commands run all statements even when an earlier statement \
-            returns a non-zero exit code.",
-        );
-        ap.refer(&mut query).required().add_argument(
-            "query",
-            argparse::Store,
-            "Gardens/Groups/Trees to exec (tree query).",
-        );
-        ap.refer(&mut commands_and_args).required().add_argument(
-            "commands",
-            argparse::List,
-            "Commands to run over resolved trees.",
-        );
-        cmd::parse_args(ap, options.args.to_vec());
-    }
-
-    if options.debug_level("cmd") > 0 {
-        debug!("subcommand: cmd");
-        debug!("query: {}", query);
-        debug!("commands_and_args: {:?}", commands_and_args);
+/// garden <command> <query>...
+///
+/// The first argument is the name of the custom command.
+pub fn custom(app: &mut model::ApplicationContext, args: &[String]) -> Result<()> {
+    let command = args.first().map(String::as_str).unwrap_or_default();
+    // Display "garden <command>" and the command's "help" text in "garden <commmand> -h".
+    let mut parser = CustomOptions::command();
+    if let Some(help) = command_help(app.get_root_config(), command) {
+        parser = parser.about(help);
     }
-    // Queries and arguments are separated by a double-dash "--" marker.
-    cmd::split_on_dash(
-        &commands_and_args,
-        &mut params.commands,
-        &mut params.arguments,
+    let matches = parser.get_matches_from(
+        std::iter::once(format!("garden {}", command)).chain(args.iter().skip(1).cloned()),
     );
-    if options.debug_level("cmd") > 0 {
-        debug!("commands: {:?}", params.commands);
-        debug!("arguments: {:?}", params.arguments);
-    }
-
-    (query, params)
-}
-
-/// garden <command> <query>...
-pub fn custom(app: &mut model::ApplicationContext, command: &str) -> Result<()> {
-    let params = parse_args_custom(command, &mut app.options);
-    cmds(app, &params)
-}
-
-/// Parse custom command arguments.
-fn parse_args_custom(command: &str, options: &mut model::CommandOptions) -> CmdParams {
-    // Display "garden <command>" in the "garden <commmand> -h" help text.
-    options.args.insert(0, format!("garden {}", command));
+    let options = CustomOptions::from_arg_matches(&matches)?;
+    app.options.failures.clear();
+    app.options.num_jobs = options.num_jobs;
+    app.options.keep_going = options.keep_going;
+    // --exit-status overrides the "garden.exit-status" setting.
+    app.options.exit_status = options.exit_status.or(app.get_root_config().exit_status);
+    app.options.exit_on_error = options.exit_on_error;
+    app.options.timeout = options.timeout;
+    app.options.log_dir = options.log_dir.clone();
+    app.options.command_scope = options.scope;
+    cmd::handle_interrupts();
+    app.options.working_directory = options.directory.clone();
 
     // Custom commands run breadth-first. The distinction shouldn't make a difference in practice
     // because "garden <custom-cmd> ..." is only able to run a single command, but we use
     // breadth-first because it retains the original implementation/behavior from before
     // --breadth-first was added to "garden cmd" and made otp-in.
-    options.breadth_first = true;
+    // --and runs the command depth-first so that each tree finishes before the next.
+    app.options.breadth_first = !options.chain;
 
     // Add the custom command name to the list of commands. cmds() operates on a vec of commands.
     let mut params = CmdParams::new();
     params.commands.push(command.to_string());
-
-    let mut queries_and_arguments: Vec<String> = Vec::new();
-    let mut ap = argparse::ArgumentParser::new();
-    ap.silence_double_dash(false);
-    ap.set_description("garden cmd - Run custom commands over gardens");
-
-    ap.refer(&mut options.keep_going).add_option(
-        &["-k", "--keep-going"],
-        argparse::StoreTrue,
-        "Continue to the next tree when errors occur.",
-    );
-    ap.refer(&mut options.exit_on_error).add_option(
-        &["-n", "--no-errexit"],
-        argparse::StoreFalse,
-        "Do not pass \"-e\" to the shell. This prevents the \"errexit\" shell \
-        option from being set. By default, the \"-e\" option is passed to the \
-        configured shell so that multi-line and multi-statement commands halt \
-        execution when the first statement with a non-zero exit code is \
-        encountered. \"--no-errexit\" has the effect of making multi-line and \
-        multi-statement commands run all statements even when an earlier statement \
-        returns a non-zero exit code.",
-    );
-    ap.refer(&mut queries_and_arguments).add_argument(
-        "queries",
-        argparse::List,
-        "Gardens/Groups/Trees to exec (tree queries).",
-    );
-
-    cmd::parse_args(ap, options.args.to_vec());
-
-    if options.debug_level("cmd") > 0 {
-        debug!("command: {}", command);
-        debug!("queries_and_arguments: {:?}", queries_and_arguments);
-    }
-
-    // Queries and arguments are separated by a double-dash "--" marker.
-    cmd::split_on_dash(
-        &queries_and_arguments,
-        &mut params.queries,
-        &mut params.arguments,
-    );
+    params.queries = options.queries;
+    params.arguments = options.arguments;
+    params.list = options.list;
 
     // Default to "." when no queries have been specified.
     if params.queries.is_empty() {
         params.queries.push(".".into());
     }
 
-    if options.debug_level("cmd") > 0 {
+    if app.options.debug_level("cmd") > 0 {
+        debug!("command: {}", command);
         debug!("queries {:?}", params.queries);
         debug!("arguments: {:?}", params.arguments);
     }
 
-    params
+    cmds(app, &params)
+}
+
+/// Return the custom commands with the specified name from every scope.
+pub fn find_commands<'a>(
+    config: &'a model::Configuration,
+    name: &str,
+) -> Vec<&'a model::MultiVariable> {
+    let garden_commands = config.gardens.iter().flat_map(|garden| &garden.commands);
+    let tree_commands = config.trees.iter().flat_map(|tree| &tree.commands);
+    config
+        .commands
+        .iter()
+        .chain(garden_commands)
+        .chain(tree_commands)
+        .filter(|command| command.get_name() == name)
+        .collect()
+}
+
+/// Return the first "help" description found for a custom command.
+pub fn command_help(config: &model::Configuration, name: &str) -> Option<String> {
+    find_commands(config, name)
+        .into_iter()
+        .map(|command| command.get_help())
+        .find(|help| !help.is_empty())
+        .cloned()
 }
 
 /// Strategy: resolve the trees down to a set of tree indexes paired with an
@@ -178,20 +286,65 @@ fn parse_args_custom(command: &str, options: &mut model::CommandOptions) -> CmdP
 ///
 /// If the names resolve to trees, each tree is processed independently
 /// with no garden context.
-
+///
+/// Trees are run after the trees listed in their "depends-on" entries.
 pub fn cmd(app: &mut model::ApplicationContext, query: &str, params: &CmdParams) -> Result<i32> {
-    // Mutable scope for app.get_root_config_mut()
-    let config = app.get_root_config_mut();
     // Resolve the tree query into a vector of tree contexts.
-    let contexts = query::resolve_trees(config, query);
+    config::read_query_grafts(app, query)?;
+    let mut contexts = query::resolve_app_trees(app, query);
+    sort_by_dependencies(app, &mut contexts)?;
 
-    if app.options.breadth_first {
+    if params.list {
+        list_cmd(app, &contexts, &params.commands)
+    } else if app.options.num_jobs > 1 {
+        run_cmd_parallel(app, &contexts, &params.commands, &params.arguments)
+    } else if app.options.breadth_first {
         run_cmd_breadth_first(app, &contexts, &params.commands, &params.arguments)
     } else {
         run_cmd_depth_first(app, &contexts, &params.commands, &params.arguments)
     }
 }
 
+/// Return the dependency level of each tree context.
+fn dependency_levels(
+    app: &model::ApplicationContext,
+    contexts: &[model::TreeContext],
+) -> Result<Vec<usize>, errors::GardenError> {
+    let mut config_levels: Vec<(Option<model::ConfigId>, Vec<usize>)> = Vec::new();
+    let mut result = Vec::with_capacity(contexts.len());
+    for context in contexts {
+        let idx = match config_levels
+            .iter()
+            .position(|(config_id, _)| *config_id == context.config)
+        {
+            Some(idx) => idx,
+            None => {
+                let levels = query::tree_dependency_levels(app.get_context_config(context))?;
+                config_levels.push((context.config, levels));
+                config_levels.len() - 1
+            }
+        };
+        result.push(config_levels[idx].1[context.tree]);
+    }
+
+    Ok(result)
+}
+
+/// Sort tree contexts so that trees run after the trees that they depend on.
+/// The sort is stable so trees without dependencies retain their query order.
+pub fn sort_by_dependencies(
+    app: &model::ApplicationContext,
+    contexts: &mut Vec<model::TreeContext>,
+) -> Result<(), errors::GardenError> {
+    let levels = dependency_levels(app, contexts)?;
+    let mut sorted: Vec<(usize, model::TreeContext)> =
+        levels.into_iter().zip(contexts.drain(..)).collect();
+    sorted.sort_by_key(|(level, _)| *level);
+    contexts.extend(sorted.into_iter().map(|(_, context)| context));
+
+    Ok(())
+}
+
 pub fn run_cmd_breadth_first(
     app: &mut model::ApplicationContext,
     contexts: &[model::TreeContext],
@@ -202,26 +355,26 @@ pub fn run_cmd_breadth_first(
     let keep_going = app.options.keep_going;
     let quiet = app.options.quiet;
     let verbose = app.options.verbose;
-    let shell = {
-        let config = app.get_root_config();
-        config.shell.to_string()
-    };
     // Loop over each command, evaluate the tree environment,
     // and run the command in each context.
     for name in commands {
         // One invocation runs multiple commands
         for context in contexts {
+            if cmd::is_interrupted() {
+                return Ok(cmd::interrupted_status());
+            }
             // Skip symlink trees.
-            let config = app.get_root_config();
+            let config: &model::Configuration = app.enter_context(context);
             if config.trees[context.tree].is_symlink {
                 continue;
             }
             // Evaluate the tree environment
-            let env = eval::environment(app.get_root_config(), context);
+            let env = eval::environment(config, context);
 
             // Run each command in the tree's context
             let tree = &config.trees[context.tree];
             let path = tree.path_as_ref()?.to_string();
+            let tree_name = tree.get_name().to_string();
             // Sparse gardens/missing trees are ok -> skip these entries.
             if !model::print_tree(tree, verbose, quiet) {
                 continue;
@@ -231,13 +384,32 @@ pub fn run_cmd_breadth_first(
             // When the scope is tree, only the tree's commands
             // are included.  When the scope includes a gardens,
             // its matching commands are appended to the end.
-            let cmd_seq_vec = eval::command(app, context, name);
-            app.get_root_config_mut().reset();
+            let cmd_seq_vec = command_with_hooks(app, context, name);
+            let shell = command_shell(app, context, name);
+            let cmd_path = command_path(app, context, name, &path);
+            let timeout = command_timeout(app, context, name);
+            let scopes = eval::command_scopes(app, context, name);
+            app.get_context_config_mut(context).reset();
+            print_command_scopes(&app.options, name, &scopes, None);
 
-            if let Err(cmd_status) =
-                run_cmd_vec(&app.options, &path, &shell, &env, &cmd_seq_vec, arguments)
-            {
+            let _span = cmd_span(&tree_name, name);
+            if let Err(errors::CommandError::ExitStatus {
+                status: cmd_status, ..
+            }) = run_cmd_vec(
+                &app.options,
+                &cmd_path,
+                &shell,
+                &env,
+                &cmd_seq_vec,
+                arguments,
+                timeout,
+                None,
+                app.options.log_path(&tree_name).as_deref(),
+            ) {
                 exit_status = cmd_status;
+                app.options
+                    .failures
+                    .add(context, &tree_name, name, cmd_status);
                 if !keep_going {
                     return Ok(cmd_status);
                 }
@@ -259,23 +431,20 @@ pub fn run_cmd_depth_first(
     let keep_going = app.options.keep_going;
     let quiet = app.options.quiet;
     let verbose = app.options.verbose;
-    let shell = {
-        let config = app.get_root_config();
-        config.shell.to_string()
-    };
     // Loop over each context, evaluate the tree environment and run the command.
     for context in contexts {
         // Skip symlink trees.
-        let config = app.get_root_config();
+        let config: &model::Configuration = app.enter_context(context);
         if config.trees[context.tree].is_symlink {
             continue;
         }
         // Evaluate the tree environment
-        let env = eval::environment(app.get_root_config(), context);
+        let env = eval::environment(config, context);
 
         // Run each command in the tree's context
         let tree = &config.trees[context.tree];
         let path = tree.path_as_ref()?.to_string();
+        let tree_name = tree.get_name().to_string();
 
         // Sparse gardens/missing trees are ok -> skip these entries.
         if !model::print_tree(tree, verbose, quiet) {
@@ -284,17 +453,39 @@ pub fn run_cmd_depth_first(
 
         // One invocation runs multiple commands
         for name in commands {
+            if cmd::is_interrupted() {
+                return Ok(cmd::interrupted_status());
+            }
             // One command maps to multiple command sequences.
             // When the scope is tree, only the tree's commands
             // are included.  When the scope includes a gardens,
             // its matching commands are appended to the end.
-            let cmd_seq_vec = eval::command(app, context, name);
-            app.get_root_config_mut().reset();
+            let cmd_seq_vec = command_with_hooks(app, context, name);
+            let shell = command_shell(app, context, name);
+            let cmd_path = command_path(app, context, name, &path);
+            let timeout = command_timeout(app, context, name);
+            let scopes = eval::command_scopes(app, context, name);
+            app.get_context_config_mut(context).reset();
+            print_command_scopes(&app.options, name, &scopes, None);
 
-            if let Err(cmd_status) =
-                run_cmd_vec(&app.options, &path, &shell, &env, &cmd_seq_vec, arguments)
-            {
+            let _span = cmd_span(&tree_name, name);
+            if let Err(errors::CommandError::ExitStatus {
+                status: cmd_status, ..
+            }) = run_cmd_vec(
+                &app.options,
+                &cmd_path,
+                &shell,
+                &env,
+                &cmd_seq_vec,
+                arguments,
+                timeout,
+                None,
+                app.options.log_path(&tree_name).as_deref(),
+            ) {
                 exit_status = cmd_status;
+                app.options
+                    .failures
+                    .add(context, &tree_name, name, cmd_status);
                 if !keep_going {
                     return Ok(cmd_status);
                 }
@@ -306,6 +497,428 @@ pub fn run_cmd_depth_first(
     Ok(exit_status)
 }
 
+/// Print the commands that would run in each tree context without running them.
+/// Commands are evaluated using the same scope resolution used when running commands.
+fn list_cmd(
+    app: &mut model::ApplicationContext,
+    contexts: &[model::TreeContext],
+    commands: &[String],
+) -> Result<i32> {
+    for context in contexts {
+        let config: &model::Configuration = app.enter_context(context);
+        let tree = &config.trees[context.tree];
+        if tree.is_symlink {
+            continue;
+        }
+        let path = tree.path_as_ref()?.to_string();
+        let tree_name = tree.get_name().to_string();
+        let mut first = true;
+        for name in commands {
+            let cmd_seq_vec = command_with_hooks(app, context, name);
+            let shell = command_shell(app, context, name);
+            let cmd_path = command_path(app, context, name, &path);
+            let timeout = command_timeout(app, context, name);
+            let scopes = eval::command_scopes(app, context, name);
+            app.get_context_config_mut(context).reset();
+            if cmd_seq_vec.is_empty() {
+                continue;
+            }
+            if first {
+                println!("{}:", model::Color::blue(&tree_name).bold());
+                first = false;
+            }
+            println!("    {}:", model::Color::green(name));
+            println!("        scope: {}", scope_names(&scopes));
+            println!("        shell: {}", shell);
+            println!("        errexit: {}", app.options.exit_on_error);
+            println!("        cwd: {}", cmd_path);
+            if let Some(timeout) = timeout {
+                println!("        timeout: {:?}", timeout);
+            }
+            println!("        run:");
+            for cmd_str in cmd_seq_vec.iter().flatten() {
+                for line in cmd_str.lines() {
+                    println!("            {}", line);
+                }
+            }
+        }
+    }
+
+    Ok(errors::EX_OK)
+}
+
+/// Evaluated details for running custom commands in a single tree.
+struct TreeCommands {
+    /// Dependency level of the tree. Trees only run once the lower levels are complete.
+    level: usize,
+    context: model::TreeContext,
+    name: String,
+    prefix: String,
+    env: Vec<(String, String)>,
+    /// Evaluated command sequences for each of the requested commands.
+    cmd_seq_vecs: Vec<Vec<Vec<String>>>,
+    /// Evaluated shell for each of the requested commands.
+    shells: Vec<String>,
+    /// Directory for each of the requested commands.
+    paths: Vec<String>,
+    /// Timeout for each of the requested commands.
+    timeouts: Vec<Option<std::time::Duration>>,
+    /// Scopes of the definitions that run for each of the requested commands.
+    scopes: Vec<Vec<model::CommandScope>>,
+}
+
+/// Run commands over the tree contexts in parallel using "options.num_jobs" threads.
+///
+/// Tree environments and commands are evaluated up-front because evaluation
+/// caches values inside of the configuration. The evaluated commands are then
+/// run concurrently and each line of output is prefixed with the tree name.
+/// Trees that depend on other trees are not started until their dependencies
+/// have completed.
+pub fn run_cmd_parallel(
+    app: &mut model::ApplicationContext,
+    contexts: &[model::TreeContext],
+    commands: &[String],
+    arguments: &[String],
+) -> Result<i32> {
+    let quiet = app.options.quiet;
+    let verbose = app.options.verbose;
+    let levels = dependency_levels(app, contexts)?;
+
+    let mut tree_commands = Vec::new();
+    for (context, level) in contexts.iter().zip(levels) {
+        // Skip symlink trees.
+        let config: &model::Configuration = app.enter_context(context);
+        let tree = &config.trees[context.tree];
+        if tree.is_symlink {
+            continue;
+        }
+        let path = tree.path_as_ref()?.to_string();
+        // Sparse gardens/missing trees are ok -> skip these entries.
+        if !std::path::PathBuf::from(&path).exists() {
+            if !quiet {
+                eprintln!("{}", model::display_missing_tree(tree, &path, verbose));
+            }
+            continue;
+        }
+        let tree_name = tree.get_name().to_string();
+        let prefix = model::display_tree_prefix(tree);
+        // Evaluate the tree environment
+        let env = eval::environment(config, context);
+
+        let mut cmd_seq_vecs = Vec::new();
+        let mut shells = Vec::new();
+        let mut paths = Vec::new();
+        let mut timeouts = Vec::new();
+        let mut scopes = Vec::new();
+        for name in commands {
+            cmd_seq_vecs.push(command_with_hooks(app, context, name));
+            shells.push(command_shell(app, context, name));
+            paths.push(command_path(app, context, name, &path));
+            timeouts.push(command_timeout(app, context, name));
+            scopes.push(eval::command_scopes(app, context, name));
+            app.get_context_config_mut(context).reset();
+        }
+
+        tree_commands.push(TreeCommands {
+            level,
+            context: context.clone(),
+            name: tree_name,
+            prefix,
+            env,
+            cmd_seq_vecs,
+            shells,
+            paths,
+            timeouts,
+            scopes,
+        });
+    }
+
+    let options = &app.options;
+    let keep_going = options.keep_going;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.num_jobs)
+        .build()?;
+
+    // Run the command sequence at the specified index in a single tree.
+    let run_tree_command = |tree_cmds: &TreeCommands, idx: usize| -> i32 {
+        if cmd::is_interrupted() {
+            return cmd::interrupted_status();
+        }
+        let _span = cmd_span(&tree_cmds.name, &commands[idx]);
+        print_command_scopes(
+            options,
+            &commands[idx],
+            &tree_cmds.scopes[idx],
+            Some(&tree_cmds.prefix),
+        );
+        match run_cmd_vec(
+            options,
+            &tree_cmds.paths[idx],
+            &tree_cmds.shells[idx],
+            &tree_cmds.env,
+            &tree_cmds.cmd_seq_vecs[idx],
+            arguments,
+            tree_cmds.timeouts[idx],
+            Some(&tree_cmds.prefix),
+            options.log_path(&tree_cmds.name).as_deref(),
+        ) {
+            Ok(()) => errors::EX_OK,
+            Err(errors::CommandError::ExitStatus { status, .. }) => {
+                options
+                    .failures
+                    .add(&tree_cmds.context, &tree_cmds.name, &commands[idx], status);
+                status
+            }
+        }
+    };
+
+    // Group the trees by dependency level. Each group runs after the previous group completes.
+    let mut groups: Vec<Vec<&TreeCommands>> = Vec::new();
+    for tree_cmds in &tree_commands {
+        if groups.len() <= tree_cmds.level {
+            groups.resize_with(tree_cmds.level + 1, Vec::new);
+        }
+        groups[tree_cmds.level].push(tree_cmds);
+    }
+    groups.retain(|group| !group.is_empty());
+
+    let mut exit_status = errors::EX_OK;
+    if options.breadth_first {
+        // Run each command across all of the trees before running the next command.
+        'commands: for idx in 0..commands.len() {
+            for group in &groups {
+                let statuses: Vec<i32> = pool.install(|| {
+                    group
+                        .par_iter()
+                        .map(|tree_cmds| run_tree_command(tree_cmds, idx))
+                        .collect()
+                });
+                if let Some(status) = cmd::last_error_status(&statuses) {
+                    exit_status = status;
+                    if !keep_going {
+                        break 'commands;
+                    }
+                }
+            }
+        }
+    } else {
+        // Run all of the commands in a tree before moving on to the next tree.
+        // Trees that have not yet started are skipped once an error is encountered
+        // unless "--keep-going" is in effect.
+        let failed = std::sync::atomic::AtomicBool::new(false);
+        for group in &groups {
+            if failed.load(std::sync::atomic::Ordering::SeqCst) {
+                break;
+            }
+            let statuses: Vec<i32> = pool.install(|| {
+                group
+                    .par_iter()
+                    .map(|tree_cmds| {
+                        for idx in 0..commands.len() {
+                            if failed.load(std::sync::atomic::Ordering::SeqCst) {
+                                break;
+                            }
+                            let status = run_tree_command(tree_cmds, idx);
+                            if status != errors::EX_OK {
+                                if !keep_going {
+                                    failed.store(true, std::sync::atomic::Ordering::SeqCst);
+                                }
+                                return status;
+                            }
+                        }
+                        errors::EX_OK
+                    })
+                    .collect()
+            });
+            if let Some(status) = cmd::last_error_status(&statuses) {
+                exit_status = status;
+            }
+        }
+    }
+
+    // Return the last non-zero exit status.
+    Ok(exit_status)
+}
+
+/// Enter a tracing span for running a custom command in a tree.
+fn cmd_span(tree: &str, command: &str) -> tracing::span::EnteredSpan {
+    tracing::info_span!("tree", action = "cmd", tree, command).entered()
+}
+
+/// Return the names of command scopes as a comma-separated string.
+fn scope_names(scopes: &[model::CommandScope]) -> String {
+    scopes
+        .iter()
+        .map(model::CommandScope::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Report the scopes of the command definitions that run in a tree when "--verbose" is used.
+fn print_command_scopes(
+    options: &model::CommandOptions,
+    name: &str,
+    scopes: &[model::CommandScope],
+    prefix: Option<&str>,
+) {
+    if options.verbose == 0 || options.quiet || scopes.is_empty() {
+        return;
+    }
+    let prefix = prefix
+        .map(|prefix| format!("{} ", prefix))
+        .unwrap_or_default();
+    println!(
+        "{}{} {}: {}",
+        prefix,
+        model::Color::cyan("#"),
+        model::Color::green(name),
+        scope_names(scopes)
+    );
+}
+
+/// Evaluate a custom command surrounded by the "pre-cmd" and "post-cmd" hooks.
+/// Hooks are not run for trees that do not define the command.
+fn command_with_hooks(
+    app: &model::ApplicationContext,
+    context: &model::TreeContext,
+    name: &str,
+) -> Vec<Vec<String>> {
+    eval::set_command_name(app.get_context_config(context), name);
+    // "--scope" selects the command's own definitions. Hooks are not scoped.
+    let cmd_seq_vec = eval::command(app, context, name);
+    if cmd_seq_vec.is_empty() {
+        return cmd_seq_vec;
+    }
+    let config = app.get_context_config(context);
+    let mut result = eval::hook(config, context, "pre-cmd");
+    result.extend(cmd_seq_vec);
+    result.extend(eval::hook(config, context, "post-cmd"));
+
+    result
+}
+
+/// Evaluate the shell used to run a custom command in the specified tree context.
+fn command_shell(
+    app: &model::ApplicationContext,
+    context: &model::TreeContext,
+    name: &str,
+) -> String {
+    let config = app.get_context_config(context);
+    eval::shell(config, context, Some(name), app.options.command_scope)
+}
+
+/// Return the directory that a command runs in. "--directory" overrides the
+/// command's "cwd" setting. Relative paths are relative to the tree path.
+fn command_path(
+    app: &model::ApplicationContext,
+    context: &model::TreeContext,
+    name: &str,
+    tree_path: &str,
+) -> String {
+    let config = app.get_context_config(context);
+    let directory = match &app.options.working_directory {
+        Some(directory) => Some(eval::tree_value(
+            config,
+            directory,
+            context.tree,
+            context.garden,
+        )),
+        None => eval::command_directory(config, context, name, app.options.command_scope),
+    };
+    match directory {
+        Some(directory) => std::path::Path::new(tree_path)
+            .join(directory)
+            .to_string_lossy()
+            .to_string(),
+        None => tree_path.to_string(),
+    }
+}
+
+/// Return the timeout for a command. "--timeout" overrides the command's "timeout" setting.
+fn command_timeout(
+    app: &model::ApplicationContext,
+    context: &model::TreeContext,
+    name: &str,
+) -> Option<std::time::Duration> {
+    app.options.timeout.or_else(|| {
+        eval::command_timeout(
+            app.get_context_config(context),
+            context,
+            name,
+            app.options.command_scope,
+        )
+    })
+}
+
+/// Run the named hook in the specified tree context.
+/// Hooks run inside the tree's directory, or in the garden root when the tree
+/// does not exist, e.g. when running the "pre-grow" hook.
+pub fn run_hook(
+    config: &mut model::Configuration,
+    options: &model::CommandOptions,
+    context: &model::TreeContext,
+    name: &str,
+) -> Result<(), errors::CommandError> {
+    let cmd_seq_vec = eval::hook(config, context, name);
+    run_cmd_vec_in_tree(config, options, context, &cmd_seq_vec)
+}
+
+/// Run the "post-clone" commands for a tree.
+pub fn run_post_clone(
+    config: &mut model::Configuration,
+    options: &model::CommandOptions,
+    context: &model::TreeContext,
+) -> Result<(), errors::CommandError> {
+    let cmd_seq_vec = eval::post_clone(config, context);
+    run_cmd_vec_in_tree(config, options, context, &cmd_seq_vec)
+}
+
+/// Run commands inside of a tree. Commands run from the garden root
+/// when the tree does not exist.
+fn run_cmd_vec_in_tree(
+    config: &mut model::Configuration,
+    options: &model::CommandOptions,
+    context: &model::TreeContext,
+    cmd_seq_vec: &[Vec<String>],
+) -> Result<(), errors::CommandError> {
+    if cmd_seq_vec.is_empty() {
+        return Ok(());
+    }
+    let env = eval::environment(config, context);
+    let shell = eval::shell(config, context, None, None);
+    config.reset();
+
+    let tree = &config.trees[context.tree];
+    let tree_path = tree
+        .path_as_ref()
+        .map_err(|_| errors::CommandError::ExitStatus {
+            command: cmd_seq_vec
+                .iter()
+                .flatten()
+                .next()
+                .cloned()
+                .unwrap_or_default(),
+            status: errors::EX_CONFIG,
+        })?;
+    let path = if std::path::PathBuf::from(tree_path).exists() {
+        tree_path.to_string()
+    } else {
+        config.root_path.to_string_lossy().to_string()
+    };
+
+    run_cmd_vec(
+        options,
+        &path,
+        &shell,
+        &env,
+        cmd_seq_vec,
+        &[],
+        options.timeout,
+        None,
+        options.log_path(tree.get_name()).as_deref(),
+    )
+}
+
 /// Run a vector of custom commands using the configured shell.
 /// Parameters:
 /// - path: The current working directory for the command.
@@ -313,17 +926,23 @@ pub fn run_cmd_depth_first(
 /// - env: Environment variables to set.
 /// - cmd_seq_vec: Vector of vector of command strings to run.
 /// - arguments: Additional command line arguments available in $1, $2, $N.
+/// - timeout: Terminate each command that runs longer than this duration.
+/// - prefix: Prefix each line of output with this string when specified.
+/// - log: Append the output of each command to this file when specified.
+#[allow(clippy::too_many_arguments)]
 fn run_cmd_vec(
     options: &model::CommandOptions,
     path: &str,
     shell: &str,
-    env: &Vec<(String, String)>,
+    env: &[(String, String)],
     cmd_seq_vec: &[Vec<String>],
     arguments: &[String],
-) -> Result<(), i32> {
-    // Get the current executable name
-    let current_exe = cmd::current_exe();
+    timeout: Option<std::time::Duration>,
+    prefix: Option<&str>,
+    log: Option<&std::path::Path>,
+) -> Result<(), errors::CommandError> {
     let mut exit_status = errors::EX_OK;
+    let mut failed_command = "";
 
     for cmd_seq in cmd_seq_vec {
         for cmd_str in cmd_seq {
@@ -334,33 +953,51 @@ fn run_cmd_vec(
                     model::Color::green(&cmd_str),
                 );
             }
-            let mut exec = subprocess::Exec::cmd(shell).cwd(path);
-            if options.exit_on_error {
-                exec = exec.arg("-e");
+            if cmd::is_interrupted() {
+                return Err(errors::CommandError::ExitStatus {
+                    command: cmd_str.to_string(),
+                    status: cmd::interrupted_status(),
+                });
             }
-            exec = exec
-                .arg("-c")
-                .arg(cmd_str)
-                .arg(&current_exe)
-                .args(arguments);
+            let mut command = vec![shell.to_string()];
+            command.extend(cmd::shell_args(
+                shell,
+                cmd_str,
+                options.exit_on_error,
+                arguments,
+            ));
             // Update the command environment
-            for (k, v) in env {
-                exec = exec.env(k, v);
+            let env = cmd::environment(env, &[], model::EnvPassthrough::Preserve);
+            let name = cmd_str.lines().next().unwrap_or_default();
+            let command = cmd::Command::new(name, &command, path, env)
+                .timeout(timeout)
+                .log(log.map(std::path::Path::to_path_buf));
+            let status = match prefix {
+                Some(prefix) => command.join_with_prefix(prefix),
+                None => command.join(),
+            };
+            if status == errors::EX_OK {
+                tracing::debug!(command = cmd_str, exit_status = status, "finished");
+            } else {
+                tracing::error!(command = cmd_str, exit_status = status, "failed");
             }
-            let status = cmd::status(exec.join());
             // When a command list is used then the return code from the final command
             // is the one that is returned when --no-errexit is in effect.
             if status != errors::EX_OK {
                 exit_status = status;
+                failed_command = cmd_str;
                 if options.exit_on_error {
-                    return Err(status);
+                    break;
                 }
             } else {
                 exit_status = errors::EX_OK;
             }
         }
         if exit_status != errors::EX_OK {
-            return Err(exit_status);
+            return Err(errors::CommandError::ExitStatus {
+                command: failed_command.to_string(),
+                status: exit_status,
+            });
         }
     }
 
@@ -382,6 +1019,16 @@ pub fn cmds(app: &mut model::ApplicationContext, params: &CmdParams) -> Result<(
         }
     }
 
-    // Return the last non-zero exit status.
-    cmd::result_from_exit_status(exit_status).map_err(|err| err.into())
+    if keep_going {
+        app.options.failures.print_summary();
+    }
+    let exit_status = app
+        .options
+        .failures
+        .apply_policy(app.options.exit_status, exit_status);
+
+    app.options
+        .failures
+        .result(exit_status)
+        .map_err(|err| err.into())
 }
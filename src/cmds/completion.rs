@@ -0,0 +1,146 @@
+use anyhow::Result;
+use clap::{CommandFactory, Parser};
+use clap_complete::engine::CompletionCandidate;
+
+use super::super::cli;
+use super::super::cmd;
+use super::super::config;
+use super::super::errors;
+use super::super::model;
+
+/// Generate shell completions
+///
+/// Completion scripts call back into garden to complete garden, group and tree names
+/// from the configuration that is found when the completion is performed.
+/// Add "source <(garden completion bash)" to ~/.bashrc to enable bash completions.
+#[derive(Parser, Clone, Debug)]
+pub struct CompletionOptions {
+    /// Shell to generate completions for
+    #[arg(value_parser = ["bash", "elvish", "fish", "powershell", "zsh"])]
+    pub shell: String,
+}
+
+/// The environment variable that completion scripts set when calling back into garden.
+pub const COMPLETE_VAR: &str = "GARDEN_COMPLETE";
+
+/// Main entry point for the "garden completion" command
+pub fn main(options: &CompletionOptions) -> Result<()> {
+    let shells = clap_complete::env::Shells::builtins();
+    let shell = shells.completer(&options.shell).ok_or_else(|| {
+        errors::GardenError::Usage(format!("unsupported shell: {}", options.shell))
+    })?;
+    let completer = cmd::current_exe();
+    shell.write_registration(
+        COMPLETE_VAR,
+        "garden",
+        "garden",
+        &completer,
+        &mut std::io::stdout(),
+    )?;
+
+    Ok(())
+}
+
+/// Complete tree queries using the names of gardens, groups and trees.
+pub fn queries() -> Vec<CompletionCandidate> {
+    match completion_config() {
+        Some(config) => {
+            let mut candidates = garden_candidates(&config);
+            candidates.append(&mut group_candidates(&config));
+            candidates.append(&mut named_query_candidates(&config));
+            candidates.append(&mut tree_candidates(&config));
+            candidates
+        }
+        None => Vec::new(),
+    }
+}
+
+/// Complete garden names.
+pub fn gardens() -> Vec<CompletionCandidate> {
+    match completion_config() {
+        Some(config) => garden_candidates(&config),
+        None => Vec::new(),
+    }
+}
+
+/// Complete tree names.
+pub fn trees() -> Vec<CompletionCandidate> {
+    match completion_config() {
+        Some(config) => tree_candidates(&config),
+        None => Vec::new(),
+    }
+}
+
+/// Complete the names of custom commands.
+pub fn commands() -> Vec<CompletionCandidate> {
+    let config = match completion_config() {
+        Some(config) => config,
+        None => return Vec::new(),
+    };
+    let mut names: Vec<&String> = Vec::new();
+    let tree_commands = config.trees.iter().flat_map(|tree| tree.commands.iter());
+    for command in config.commands.iter().chain(tree_commands) {
+        if !names.contains(&command.get_name()) {
+            names.push(command.get_name());
+        }
+    }
+
+    candidates(names.into_iter(), "command")
+}
+
+/// Return completion candidates for the gardens in a configuration.
+fn garden_candidates(config: &model::Configuration) -> Vec<CompletionCandidate> {
+    candidates(
+        config.gardens.iter().map(|garden| garden.get_name()),
+        "garden",
+    )
+}
+
+/// Return completion candidates for the groups in a configuration.
+fn group_candidates(config: &model::Configuration) -> Vec<CompletionCandidate> {
+    candidates(config.groups.iter().map(|group| group.get_name()), "group")
+}
+
+/// Return completion candidates for the named queries in a configuration.
+fn named_query_candidates(config: &model::Configuration) -> Vec<CompletionCandidate> {
+    candidates(config.queries.iter().map(|query| query.get_name()), "query")
+}
+
+/// Return completion candidates for the trees in a configuration.
+fn tree_candidates(config: &model::Configuration) -> Vec<CompletionCandidate> {
+    candidates(config.trees.iter().map(|tree| tree.get_name()), "tree")
+}
+
+/// Create completion candidates for the specified names.
+fn candidates<'a>(
+    names: impl Iterator<Item = &'a String>,
+    help: &'static str,
+) -> Vec<CompletionCandidate> {
+    names
+        .map(|name| CompletionCandidate::new(name).help(Some(help.into())))
+        .collect()
+}
+
+/// Read the configuration used for completions.
+///
+/// The "--chdir" and "--config" options from the command line being completed
+/// are used to locate the configuration.
+fn completion_config() -> Option<model::Configuration> {
+    // Completion scripts run "garden -- garden <args>...".
+    let args = std::env::args_os().skip_while(|arg| arg != "--").skip(1);
+    let matches = cli::MainOptions::command()
+        .ignore_errors(true)
+        .try_get_matches_from(args)
+        .ok()?;
+    let dir = match matches.get_one::<String>("chdir") {
+        Some(chdir) => std::path::PathBuf::from(chdir),
+        None => std::env::current_dir().ok()?,
+    };
+    let filename = matches
+        .get_many::<String>("config")
+        .and_then(|mut configs| configs.next())
+        .map(std::path::PathBuf::from);
+    let path = config::find_config_path(&dir, filename.as_deref())?;
+
+    config::from_path(path, "", 0, None).ok()
+}
@@ -0,0 +1,141 @@
+use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+
+use super::super::cmd;
+use super::super::errors;
+use super::super::eval;
+use super::super::git;
+use super::super::model;
+use super::super::model::Color;
+use super::super::query;
+use super::completion;
+
+/// Apply gitconfig settings to existing trees
+#[derive(Parser, Clone, Debug)]
+pub struct ConfigureOptions {
+    /// Report the settings that differ from the configured values without changing them
+    #[arg(long)]
+    pub check: bool,
+    /// Tree queries for the gardens, groups or trees to configure
+    #[arg(add = ArgValueCandidates::new(completion::queries), required = true)]
+    pub queries: Vec<String>,
+}
+
+/// Main entry point for the "garden configure" command
+pub fn main(app: &mut model::ApplicationContext, options: &ConfigureOptions) -> Result<()> {
+    let quiet = app.options.quiet;
+    let verbose = app.options.verbose;
+    let config = app.get_root_config_mut();
+
+    let mut exit_status = errors::EX_OK;
+    for query in &options.queries {
+        for ctx in query::resolve_trees(config, query) {
+            let status = if options.check {
+                check_tree(config, &ctx, verbose)?
+            } else {
+                configure_tree(config, &ctx, quiet, verbose)?
+            };
+            if status != errors::EX_OK {
+                // Return the last non-zero exit status.
+                exit_status = status;
+            }
+        }
+    }
+
+    cmd::result_from_exit_status(exit_status).map_err(|err| err.into())
+}
+
+/// Apply the gitconfig settings for a tree and report the values that drifted
+/// from the configured values. Symlinks and trees that have not been grown are skipped.
+fn configure_tree(
+    config: &model::Configuration,
+    ctx: &model::TreeContext,
+    quiet: bool,
+    verbose: u8,
+) -> Result<i32> {
+    let tree = &config.trees[ctx.tree];
+    let path = std::path::PathBuf::from(tree.path_as_ref()?);
+    if tree.is_symlink {
+        return Ok(errors::EX_OK);
+    }
+    if !path.exists() {
+        if verbose > 0 {
+            eprintln!(
+                "{}: skipped: {} does not exist",
+                tree.get_name(),
+                path.display()
+            );
+        }
+        return Ok(errors::EX_OK);
+    }
+
+    let backend = git::backend();
+    let mut exit_status = errors::EX_OK;
+    for (name, value) in eval::gitconfig(config, ctx) {
+        let current = backend.config_get(&path, &name);
+        if current.as_deref() == Some(value.as_str()) {
+            continue;
+        }
+        if !quiet {
+            println!(
+                "{}: {}: {} -> {}",
+                Color::blue(tree.get_name()).bold(),
+                name,
+                Color::red(current.as_deref().unwrap_or("(unset)")),
+                Color::green(&value)
+            );
+        }
+        if let Err(err) = backend.config_set(&path, &name, &value) {
+            eprintln!("error: {}", err);
+            exit_status = err.into();
+        }
+    }
+
+    Ok(exit_status)
+}
+
+/// Print the gitconfig settings that are missing or that differ from the configured
+/// values as a diff without changing anything. Returns errors::EX_ERROR when the
+/// tree has drifted from its configuration.
+fn check_tree(config: &model::Configuration, ctx: &model::TreeContext, verbose: u8) -> Result<i32> {
+    let tree = &config.trees[ctx.tree];
+    let path = std::path::PathBuf::from(tree.path_as_ref()?);
+    if tree.is_symlink || !path.exists() {
+        if verbose > 0 && !tree.is_symlink {
+            eprintln!(
+                "{}: skipped: {} does not exist",
+                tree.get_name(),
+                path.display()
+            );
+        }
+        return Ok(errors::EX_OK);
+    }
+
+    let backend = git::backend();
+    let mut drifted = false;
+    for (name, value) in eval::gitconfig(config, ctx) {
+        let current = backend.config_get(&path, &name);
+        if current.as_deref() == Some(value.as_str()) {
+            continue;
+        }
+        if !drifted {
+            println!(
+                "{} {}",
+                Color::cyan("#"),
+                Color::blue(tree.get_name()).bold()
+            );
+            drifted = true;
+        }
+        if let Some(current) = current {
+            println!("{}", Color::red(format!("- {} = {}", name, current)));
+        }
+        println!("{}", Color::green(format!("+ {} = {}", name, value)));
+    }
+
+    if drifted {
+        Ok(errors::EX_ERROR)
+    } else {
+        Ok(errors::EX_OK)
+    }
+}
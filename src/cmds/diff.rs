@@ -0,0 +1,103 @@
+use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+
+use super::super::cmd;
+use super::super::git;
+use super::super::model;
+use super::super::query;
+use super::completion;
+
+/// Summarize uncommitted changes and unpushed commits
+#[derive(Parser, Clone, Debug)]
+pub struct DiffOptions {
+    /// Show full diffs instead of a diffstat
+    #[arg(short, long)]
+    pub patch: bool,
+    /// Tree queries for the gardens, groups or trees to inspect
+    #[arg(add = ArgValueCandidates::new(completion::queries), required = true)]
+    pub queries: Vec<String>,
+}
+
+/// Main entry point for the "garden diff" command
+pub fn main(app: &mut model::ApplicationContext, options: &DiffOptions) -> Result<()> {
+    let quiet = app.options.quiet;
+    let verbose = app.options.verbose;
+    let color = app.options.color_mode.is_enabled();
+    let config = app.get_root_config_mut();
+
+    for query in &options.queries {
+        for ctx in query::resolve_trees(config, query) {
+            let tree = &config.trees[ctx.tree];
+            // Skip symlink trees.
+            if tree.is_symlink {
+                continue;
+            }
+            let path = tree.path_as_ref()?;
+            // Sparse gardens/missing trees are ok -> skip these entries.
+            if !std::path::PathBuf::from(path).exists() {
+                if !quiet {
+                    eprintln!("{}", model::display_missing_tree(tree, path, verbose));
+                }
+                continue;
+            }
+
+            let upstream = upstream_summary(path);
+            let diff = diff_summary(path, options.patch, color);
+            // Trees without changes are only displayed in verbose mode.
+            if upstream.is_none() && diff.is_empty() && verbose == 0 {
+                continue;
+            }
+            if !quiet {
+                println!("{}", model::display_tree(tree, path, verbose));
+            }
+            if let Some(upstream) = upstream {
+                println!("{}", upstream);
+            }
+            if !diff.is_empty() {
+                println!("{}", diff);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Return a summary of the commits ahead and behind of the upstream branch.
+/// None is returned when the branch is up to date or has no upstream.
+fn upstream_summary(path: &str) -> Option<String> {
+    let (ahead, behind) = git::ahead_behind(path)?;
+    if ahead == 0 && behind == 0 {
+        return None;
+    }
+
+    let command = ["git", "rev-parse", "--abbrev-ref", "@{upstream}"];
+    let upstream = cmd::capture_stdout(cmd::exec_in_dir(&command, path))
+        .map(|capture| cmd::trim_stdout(&capture))
+        .unwrap_or_default();
+
+    Some(format!(
+        "ahead {}, behind {} ({})",
+        model::Color::green(ahead.to_string()),
+        model::Color::red(behind.to_string()),
+        upstream
+    ))
+}
+
+/// Return the diffstat, or the full diff, of the uncommitted changes in a tree.
+fn diff_summary(path: &str, patch: bool, color: bool) -> String {
+    let color_arg = if color {
+        "--color=always"
+    } else {
+        "--color=never"
+    };
+    let mut command = vec!["git", "diff", color_arg];
+    if !patch {
+        command.push("--stat");
+    }
+    command.push("HEAD");
+
+    cmd::capture_stdout(cmd::exec_in_dir(&command, path))
+        .map(|capture| cmd::trim_stdout(&capture))
+        .unwrap_or_default()
+}
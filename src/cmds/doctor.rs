@@ -0,0 +1,383 @@
+use anyhow::Result;
+use clap::Parser;
+use rayon::prelude::*;
+
+use super::super::cmd;
+use super::super::errors;
+use super::super::eval;
+use super::super::model;
+use super::super::syntax;
+
+/// Diagnose problems with git, shells, the garden root and remote hosts
+#[derive(Parser, Clone, Debug)]
+pub struct DoctorOptions {
+    /// Set the output format {text, json}
+    #[arg(short, long, default_value = "text", value_name = "format")]
+    pub format: model::OutputFormat,
+    /// Skip the checks that connect to remote hosts
+    #[arg(long)]
+    pub offline: bool,
+    /// Maximum amount of time to wait for each remote host, e.g. "30s"
+    #[arg(
+        long,
+        default_value = "15s",
+        value_name = "duration",
+        value_parser = super::super::syntax::parse_duration
+    )]
+    pub timeout: std::time::Duration,
+}
+
+/// The outcome of a single check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+impl Status {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Error => "error",
+        }
+    }
+}
+
+/// The result of checking a single item.
+struct Report {
+    status: Status,
+    check: String,
+    message: String,
+}
+
+/// Reports collected while diagnosing the environment.
+#[derive(Default)]
+struct Reports {
+    entries: Vec<Report>,
+}
+
+impl Reports {
+    fn add(&mut self, status: Status, check: String, message: String) {
+        self.entries.push(Report {
+            status,
+            check,
+            message,
+        });
+    }
+
+    fn has_errors(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.status == Status::Error)
+    }
+}
+
+/// Main entry point for the "garden doctor" command
+pub fn main(app: &mut model::ApplicationContext, options: &DoctorOptions) -> Result<()> {
+    let config = app.get_root_config();
+    let mut reports = Reports::default();
+    check_git(&mut reports);
+    check_shells(config, &mut reports);
+    check_root(config, &mut reports);
+    check_remotes(config, options, &mut reports);
+
+    match options.format {
+        model::OutputFormat::Json => print_json(&reports)?,
+        model::OutputFormat::Text => print_text(&reports),
+    }
+
+    if reports.has_errors() {
+        return Err(errors::GardenError::ExitStatus(errors::EX_UNAVAILABLE).into());
+    }
+
+    Ok(())
+}
+
+/// Verify that git is installed.
+fn check_git(reports: &mut Reports) {
+    let command = ["git", "--version"];
+    match cmd::capture(cmd::exec_cmd(&command)) {
+        Ok(capture) if capture.exit_status.success() => {
+            reports.add(Status::Ok, "git".into(), cmd::trim_stdout(&capture));
+        }
+        _ => reports.add(
+            Status::Error,
+            "git".into(),
+            "git was not found. Install git and make sure that it is in $PATH".into(),
+        ),
+    }
+}
+
+/// Verify that the shells used to run commands exist.
+fn check_shells(config: &model::Configuration, reports: &mut Reports) {
+    let mut shells: Vec<(String, String)> =
+        vec![("garden.shell".into(), eval::value(config, &config.shell))];
+    for command in &config.commands {
+        if !command.get_shell().is_empty() {
+            let location = format!("commands.{}.shell", command.get_name());
+            shells.push((location, eval::value(config, command.get_shell())));
+        }
+    }
+    for garden in &config.gardens {
+        if !garden.shell.is_empty() {
+            let location = format!("gardens.{}.shell", garden.get_name());
+            shells.push((location, eval::value(config, garden.shell.get_expr())));
+        }
+    }
+    for (tree_idx, tree) in config.trees.iter().enumerate() {
+        let mut exprs = Vec::new();
+        if !tree.shell.is_empty() {
+            exprs.push((
+                format!("trees.{}.shell", tree.get_name()),
+                tree.shell.get_expr(),
+            ));
+        }
+        for command in &tree.commands {
+            if !command.get_shell().is_empty() {
+                let location = format!(
+                    "trees.{}.commands.{}.shell",
+                    tree.get_name(),
+                    command.get_name()
+                );
+                exprs.push((location, command.get_shell()));
+            }
+        }
+        for (location, expr) in exprs {
+            shells.push((location, eval::tree_value(config, expr, tree_idx, None)));
+        }
+    }
+
+    let mut checked: Vec<String> = Vec::new();
+    for (location, shell) in shells {
+        if checked.contains(&shell) {
+            continue;
+        }
+        match which::which(&shell) {
+            Ok(path) => reports.add(
+                Status::Ok,
+                format!("shell {}", shell),
+                path.to_string_lossy().to_string(),
+            ),
+            Err(_) => reports.add(
+                Status::Error,
+                format!("shell {}", shell),
+                format!(
+                    "\"{}\" was not found. Install it or change \"{}\" to an available shell",
+                    shell, location
+                ),
+            ),
+        }
+        checked.push(shell);
+    }
+}
+
+/// Verify that trees can be created inside of the garden root.
+fn check_root(config: &model::Configuration, reports: &mut Reports) {
+    let root = &config.root_path;
+    let check = "garden.root".to_string();
+    if !root.exists() {
+        reports.add(
+            Status::Warning,
+            check,
+            format!(
+                "{} does not exist. It will be created by \"garden grow\"",
+                root.display()
+            ),
+        );
+        return;
+    }
+    let probe = root.join(format!(".garden-doctor-{}", std::process::id()));
+    match std::fs::File::create(&probe) {
+        Ok(_) => {
+            std::fs::remove_file(&probe).ok();
+            reports.add(Status::Ok, check, format!("{} is writable", root.display()));
+        }
+        Err(err) => reports.add(
+            Status::Error,
+            check,
+            format!(
+                "{} is not writable: {}. Fix its permissions or set \"garden.root\"",
+                root.display(),
+                err
+            ),
+        ),
+    }
+}
+
+/// A remote host and the url used to verify that it can be reached.
+struct RemoteHost {
+    protocol: String,
+    host: String,
+    url: String,
+}
+
+/// Verify that each unique remote host can be reached using the configured credentials.
+/// Local repositories are checked for existence.
+fn check_remotes(config: &model::Configuration, options: &DoctorOptions, reports: &mut Reports) {
+    let mut hosts: Vec<RemoteHost> = Vec::new();
+    let mut local_paths: Vec<String> = Vec::new();
+    for (tree_idx, tree) in config.trees.iter().enumerate() {
+        for remote in &tree.remotes {
+            let url = eval::tree_value(config, remote.get_expr(), tree_idx, None);
+            let url = config.rewrite_url(&url);
+            match syntax::url_host(&url) {
+                Some((protocol, host)) => {
+                    if !hosts
+                        .iter()
+                        .any(|entry| entry.protocol == protocol && entry.host == host)
+                    {
+                        hosts.push(RemoteHost {
+                            protocol: protocol.to_string(),
+                            host: host.to_string(),
+                            url: url.clone(),
+                        });
+                    }
+                }
+                None => {
+                    let path = url.strip_prefix("file://").unwrap_or(&url).to_string();
+                    if !local_paths.contains(&path) {
+                        let path_buf = std::path::Path::new(&path);
+                        let path_buf = if path_buf.is_absolute() {
+                            path_buf.to_path_buf()
+                        } else {
+                            config.root_path.join(path_buf)
+                        };
+                        if !path_buf.exists() {
+                            reports.add(
+                                Status::Error,
+                                format!("trees.{}.remotes.{}", tree.get_name(), remote.get_name()),
+                                format!("{} does not exist", path),
+                            );
+                        }
+                        local_paths.push(path);
+                    }
+                }
+            }
+        }
+    }
+    if options.offline || hosts.is_empty() {
+        return;
+    }
+
+    if hosts.iter().any(|entry| entry.protocol == "ssh")
+        && std::env::var_os("SSH_AUTH_SOCK").is_none()
+    {
+        reports.add(
+            Status::Warning,
+            "ssh-agent".into(),
+            "SSH_AUTH_SOCK is not set. Start ssh-agent and add your key using \"ssh-add\"".into(),
+        );
+    }
+
+    let root = config.root_path.clone();
+    let timeout = options.timeout;
+    let results: Vec<(Status, String)> = hosts
+        .par_iter()
+        .map(|entry| check_remote_host(entry, timeout, &root))
+        .collect();
+    for (entry, (status, message)) in hosts.iter().zip(results) {
+        reports.add(
+            status,
+            format!("remote {} ({})", entry.host, entry.protocol),
+            message,
+        );
+    }
+}
+
+/// Connect to a remote host using "git ls-remote" without prompting for credentials.
+fn check_remote_host(
+    entry: &RemoteHost,
+    timeout: std::time::Duration,
+    root: &std::path::Path,
+) -> (Status, String) {
+    let mut env = cmd::environment(&[], &[], model::EnvPassthrough::Preserve);
+    let mut extra_env = vec![("GIT_TERMINAL_PROMPT", "0")];
+    if std::env::var_os("GIT_SSH_COMMAND").is_none() {
+        extra_env.push(("GIT_SSH_COMMAND", "ssh -o BatchMode=yes"));
+    }
+    env.retain(|(name, _)| !extra_env.iter().any(|(extra, _)| name == extra));
+    env.extend(
+        extra_env
+            .iter()
+            .map(|(name, value)| (name.into(), value.into())),
+    );
+    let path = if root.exists() {
+        root.to_string_lossy().to_string()
+    } else {
+        ".".to_string()
+    };
+    let command = ["git", "ls-remote", "--quiet", &entry.url, "HEAD"];
+    let name = format!("git ls-remote {}", entry.url);
+    let command = cmd::Command::new(&name, &command, &path, env).timeout(Some(timeout));
+    let (status, _, stderr) = match command.capture() {
+        Ok(result) => result,
+        Err(err) => return (Status::Error, format!("unable to run git: {}", err)),
+    };
+    if status == errors::EX_OK {
+        return (Status::Ok, format!("{} is reachable", entry.url));
+    }
+    let hint = match entry.protocol.as_str() {
+        "ssh" => format!(
+            "Check that your ssh key has been added to ssh-agent and to your account on {}",
+            entry.host
+        ),
+        "http" | "https" => format!(
+            "Check your Git credential helper or access token for {}",
+            entry.host
+        ),
+        _ => format!("Check your network connection to {}", entry.host),
+    };
+    let reason = if status == errors::EX_TIMEOUT {
+        format!("timed out after {:?}", timeout)
+    } else {
+        stderr
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or("git ls-remote failed")
+            .trim()
+            .trim_end_matches('.')
+            .to_string()
+    };
+
+    (
+        Status::Error,
+        format!("{} is not reachable: {}. {}", entry.url, reason, hint),
+    )
+}
+
+/// Print reports as text.
+fn print_text(reports: &Reports) {
+    for entry in &reports.entries {
+        let status = match entry.status {
+            Status::Ok => model::Color::green(entry.status.as_str()).bold(),
+            Status::Warning => model::Color::yellow(entry.status.as_str()).bold(),
+            Status::Error => model::Color::red(entry.status.as_str()).bold(),
+        };
+        println!(
+            "{}: {}: {}",
+            status,
+            model::Color::cyan(&entry.check),
+            entry.message
+        );
+    }
+}
+
+/// Print reports as a JSON document.
+fn print_json(reports: &Reports) -> Result<()> {
+    let entries: Vec<serde_json::Value> = reports
+        .entries
+        .iter()
+        .map(|entry| {
+            serde_json::json!({
+                "status": entry.status.as_str(),
+                "check": entry.check,
+                "message": entry.message,
+            })
+        })
+        .collect();
+    println!("{}", serde_json::to_string_pretty(&entries)?);
+
+    Ok(())
+}
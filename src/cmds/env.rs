@@ -0,0 +1,80 @@
+use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+
+use super::super::errors;
+use super::super::eval;
+use super::super::model;
+use super::super::query;
+use super::completion;
+
+/// Print the environment for a garden or tree
+#[derive(Parser, Clone, Debug)]
+pub struct EnvOptions {
+    /// Set the output format {sh, fish, dotenv}
+    #[arg(short, long, default_value = "sh", value_name = "format")]
+    pub format: model::EnvFormat,
+    /// Query for the garden, group or tree whose environment is printed
+    #[arg(add = ArgValueCandidates::new(completion::queries))]
+    pub query: String,
+}
+
+/// Main entry point for the "garden env" command
+pub fn main(app: &mut model::ApplicationContext, options: &EnvOptions) -> Result<()> {
+    let config = app.get_root_config_mut();
+    let query = &options.query;
+    let contexts = query::resolve_trees(config, query);
+    // Prefer a tree whose name exactly matches the query.
+    let context = contexts
+        .iter()
+        .find(|ctx| config.trees[ctx.tree].get_name() == query)
+        .or(contexts.first())
+        .ok_or_else(|| {
+            errors::GardenError::Usage(format!("tree query matched zero trees: '{}'", query))
+        })?;
+
+    let context = context.clone();
+    let config = app.enter_context(&context);
+    for (name, value) in environment(config, &context) {
+        println!("{}", format_variable(&options.format, &name, &value));
+    }
+
+    Ok(())
+}
+
+/// Evaluate the environment. Variables that are modified multiple times
+/// are reported once using their final value.
+fn environment(
+    config: &model::Configuration,
+    context: &model::TreeContext,
+) -> Vec<(String, String)> {
+    let mut result: Vec<(String, String)> = Vec::new();
+    for (name, value) in eval::environment(config, context) {
+        match result.iter_mut().find(|(existing, _)| *existing == name) {
+            Some(entry) => entry.1 = value,
+            None => result.push((name, value)),
+        }
+    }
+
+    result
+}
+
+/// Format a variable assignment for the specified shell.
+fn format_variable(format: &model::EnvFormat, name: &str, value: &str) -> String {
+    match format {
+        model::EnvFormat::Sh => format!("export {}={}", name, shlex::quote(value)),
+        model::EnvFormat::Fish => format!(
+            "set -gx {} '{}'",
+            name,
+            value.replace('\\', "\\\\").replace('\'', "\\'")
+        ),
+        model::EnvFormat::Dotenv => format!(
+            "{}=\"{}\"",
+            name,
+            value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        ),
+    }
+}
@@ -1,58 +1,244 @@
 use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
 
-use super::super::cmd;
+use super::super::config;
+use super::super::errors;
 use super::super::eval;
 use super::super::model;
 use super::super::query;
+use super::completion;
 
-pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
-    let mut expr = String::new();
-    let mut tree = String::new();
-    let mut garden = String::new();
-    parse_args(&mut app.options, &mut expr, &mut tree, &mut garden);
+/// Evaluate garden expressions
+#[derive(Parser, Clone, Debug)]
+#[command(override_usage = "garden eval [OPTIONS] <expr> [tree] [garden]
+       garden eval [OPTIONS] --file <path> [tree] [garden]")]
+pub struct EvalOptions {
+    /// Evaluate the expression in every tree matched by a tree query (default: all trees)
+    #[arg(short, long)]
+    pub all_trees: bool,
+    /// Set the output format for "--all-trees" {text, json}
+    #[arg(
+        short,
+        long,
+        default_value = "text",
+        value_name = "format",
+        requires = "all_trees"
+    )]
+    pub format: model::OutputFormat,
+    /// Prefix each value with the tree name and a tab when using "--all-trees"
+    #[arg(short, long, requires = "all_trees")]
+    pub names: bool,
+    /// Quote the printed values {raw, shell, json}
+    #[arg(
+        long,
+        default_value = "raw",
+        value_name = "mode",
+        conflicts_with = "format"
+    )]
+    pub quote: model::QuoteMode,
+    /// Terminate values with NUL characters instead of newlines
+    #[arg(short = 'z', long, conflicts_with = "format")]
+    pub null: bool,
+    /// Read expressions from a file, one per line, instead of the command line.
+    /// Use "-" to read expressions from stdin
+    #[arg(long, value_name = "path", value_parser = parse_file)]
+    pub file: Option<String>,
+    /// Expression followed by the tree and garden within which to evaluate.
+    /// The tree is a tree query when using "--all-trees".
+    /// The expression is omitted when using "--file"
+    #[arg(
+        value_names = ["expr", "tree", "garden"],
+        num_args = 0..=3,
+        required_unless_present = "file",
+        add = ArgValueCandidates::new(completion::queries)
+    )]
+    pub args: Vec<String>,
+}
 
-    let config = app.get_root_config_mut();
-    if tree.is_empty() {
-        println!("{}", eval::value(config, &expr));
-        return Ok(());
+/// The expressions and scope selected by the "garden eval" positional arguments.
+#[derive(Clone, Debug, Default)]
+struct EvalArgs {
+    exprs: Vec<String>,
+    tree: Option<String>,
+    garden: Option<String>,
+}
+
+impl EvalArgs {
+    /// The positional arguments are "<expr> [<tree>] [<garden>]" and
+    /// "[<tree>] [<garden>]" when using "--file".
+    fn new(options: &EvalOptions) -> Result<Self> {
+        let (exprs, scope) = match &options.file {
+            Some(path) => (read_exprs(path)?, &options.args[..]),
+            None => (
+                options.args.iter().take(1).cloned().collect(),
+                options.args.get(1..).unwrap_or_default(),
+            ),
+        };
+        if scope.len() > 2 {
+            return Err(
+                errors::GardenError::Usage(format!("unexpected argument: {}", scope[2])).into(),
+            );
+        }
+
+        Ok(Self {
+            exprs,
+            tree: scope.first().cloned(),
+            garden: scope.get(1).cloned(),
+        })
     }
+}
 
-    let mut garden_opt: Option<&str> = None;
-    if !garden.is_empty() {
-        garden_opt = Some(&garden);
+/// Main entry point for the "garden eval" command
+pub fn main(app: &mut model::ApplicationContext, options: &EvalOptions) -> Result<()> {
+    let args = EvalArgs::new(options)?;
+    if options.all_trees {
+        return eval_trees(app, options, &args);
     }
+    let config = app.get_root_config_mut();
+    let tree = match &args.tree {
+        Some(tree) => tree,
+        None => {
+            for expr in &args.exprs {
+                let value = eval::try_value(config, expr).map_err(|err| eval_error(config, err))?;
+                print_value(options, None, &value);
+            }
+            return Ok(());
+        }
+    };
 
-    // Evaluate and print the garden expression.
-    let ctx = query::tree_context(config, &tree, garden_opt)?;
-    let value = eval::tree_value(config, &expr, ctx.tree, ctx.garden);
-    println!("{}", value);
+    // Evaluate and print the garden expressions.
+    let ctx = query::tree_context(config, tree, args.garden.as_deref())?;
+    for expr in &args.exprs {
+        let value = eval::try_tree_value(config, expr, ctx.tree, ctx.garden)
+            .map_err(|err| eval_error(config, err))?;
+        print_value(options, None, &value);
+    }
 
     Ok(())
 }
 
-/// Parse "eval" arguments.
-fn parse_args(
-    options: &mut model::CommandOptions,
-    expr: &mut String,
-    tree: &mut String,
-    garden: &mut String,
-) {
-    let mut ap = argparse::ArgumentParser::new();
-    ap.set_description("garden eval - Evaluate garden expressions");
+/// Print a value using the "--quote" mode. Values are prefixed with the tree name
+/// and a tab when a name is specified. Values are terminated by a NUL character
+/// when using "--null" so that values containing newlines can be read safely.
+fn print_value(options: &EvalOptions, name: Option<&str>, value: &str) {
+    let terminator = if options.null { '\0' } else { '\n' };
+    let value = options.quote.quote(value);
+    match name {
+        Some(name) => print!("{}\t{}{}", name, value, terminator),
+        None => print!("{}{}", value, terminator),
+    }
+}
+
+/// Report an evaluation error along with the location of the variable that caused it.
+fn eval_error(config: &model::Configuration, mut err: errors::EvalError) -> anyhow::Error {
+    if let Some(key) = err.key() {
+        err.location = config::reader::find_location(config, &key);
+    }
+    errors::GardenError::EvalError(err).into()
+}
+
+/// Parse a "--file" path. Relative paths are resolved before "--chdir" takes effect.
+fn parse_file(value: &str) -> Result<String, String> {
+    if value == "-" {
+        return Ok(value.to_string());
+    }
+    std::path::absolute(value)
+        .map(|path| path.to_string_lossy().to_string())
+        .map_err(|err| format!("invalid path: {}: {}", value, err))
+}
+
+/// Read expressions from a file, one per line. "-" reads from stdin.
+fn read_exprs(path: &str) -> Result<Vec<String>> {
+    let content = if path == "-" {
+        std::io::read_to_string(std::io::stdin())
+    } else {
+        std::fs::read_to_string(path)
+    }
+    .map_err(|err| errors::GardenError::ReadFile {
+        path: path.into(),
+        err,
+    })?;
+
+    Ok(content.lines().map(String::from).collect())
+}
 
-    ap.refer(expr)
-        .required()
-        .add_argument("expr", argparse::Store, "Expression to evaluate");
+/// Evaluate the expressions once for each tree matched by a tree query.
+fn eval_trees(
+    app: &mut model::ApplicationContext,
+    options: &EvalOptions,
+    args: &EvalArgs,
+) -> Result<()> {
+    let config = app.get_root_config();
+    let exprs = &args.exprs;
+    let tree_query = args.tree.as_deref().unwrap_or("@*");
+    let mut contexts = query::resolve_trees(config, tree_query);
+    // Trees are evaluated in the garden's scope when a garden is specified.
+    if let Some(garden_name) = &args.garden {
+        let pattern = glob::Pattern::new(garden_name).map_err(|_| {
+            errors::GardenError::GardenPatternError {
+                garden: garden_name.into(),
+            }
+        })?;
+        let garden_contexts = query::garden_trees(config, &pattern);
+        if garden_contexts.is_empty() {
+            return Err(errors::GardenError::GardenNotFound {
+                garden: garden_name.into(),
+            }
+            .into());
+        }
+        contexts.retain_mut(|ctx| {
+            match garden_contexts
+                .iter()
+                .find(|garden_ctx| garden_ctx.tree == ctx.tree)
+            {
+                Some(garden_ctx) => {
+                    ctx.garden = garden_ctx.garden;
+                    true
+                }
+                None => false,
+            }
+        });
+    }
 
-    ap.refer(tree)
-        .add_argument("tree", argparse::Store, "Tree within which to evaluate.");
+    let mut values: Vec<(String, Vec<String>)> = Vec::new();
+    for ctx in &contexts {
+        let config = app.enter_context(ctx);
+        let name = config.trees[ctx.tree].get_name().clone();
+        // Trees can be matched more than once by the same query.
+        if values.iter().any(|(value_name, _)| *value_name == name) {
+            continue;
+        }
+        let tree_values = exprs
+            .iter()
+            .map(|expr| eval::try_tree_value(config, expr, ctx.tree, ctx.garden))
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|err| eval_error(config, err))?;
+        values.push((name, tree_values));
+    }
 
-    ap.refer(garden).add_argument(
-        "garden",
-        argparse::Store,
-        "Garden within which to evaluate.",
-    );
+    match options.format {
+        model::OutputFormat::Json => {
+            // Expressions read using "--file" are reported as a list of values.
+            let trees: Vec<serde_json::Value> = values
+                .iter()
+                .map(|(name, tree_values)| match options.file {
+                    Some(_) => serde_json::json!({ "name": name, "values": tree_values }),
+                    None => serde_json::json!({ "name": name, "value": tree_values[0] }),
+                })
+                .collect();
+            let doc = serde_json::json!({ "trees": trees });
+            println!("{}", serde_json::to_string_pretty(&doc)?);
+        }
+        model::OutputFormat::Text => {
+            for (name, tree_values) in &values {
+                for value in tree_values {
+                    let name = options.names.then_some(name.as_str());
+                    print_value(options, name, value);
+                }
+            }
+        }
+    }
 
-    options.args.insert(0, "garden eval".into());
-    cmd::parse_args(ap, options.args.to_vec());
+    Ok(())
 }
@@ -1,61 +1,197 @@
 use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+use rayon::prelude::*;
 
 use super::super::cmd;
+use super::super::config;
 use super::super::errors;
+use super::super::eval;
+use super::super::events;
+use super::super::git;
 use super::super::model;
 use super::super::query;
+use super::super::syntax;
+use super::completion;
+use super::grow;
 
-/// Main entry point for the "garden exec" command
-/// Parameters:
-/// - options: `garden::model::CommandOptions`
-
-pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
-    let mut query = String::new();
-    let mut command: Vec<String> = Vec::new();
-    parse_args(&mut app.options, &mut query, &mut command);
-
-    let quiet = app.options.quiet;
-    let verbose = app.options.verbose;
-    let config = app.get_root_config_mut();
-    exec(config, quiet, verbose, &query, &command)
+/// Run commands inside gardens
+#[derive(Parser, Clone, Debug)]
+pub struct ExecOptions {
+    /// Run commands in parallel using the specified number of jobs
+    #[arg(short = 'j', long = "jobs", default_value_t = 1, value_name = "jobs")]
+    pub num_jobs: usize,
+    /// Stop at the first tree that fails and do not start any more trees (default)
+    #[arg(long, conflicts_with = "keep_going")]
+    pub fail_fast: bool,
+    /// Continue to the next tree when errors occur
+    #[arg(short, long)]
+    pub keep_going: bool,
+    /// Exit with the status of the first or last tree that failed, or the number of failed trees {first, last, count}
+    #[arg(long, value_name = "policy")]
+    pub exit_status: Option<model::ExitStatusPolicy>,
+    /// Inherit the entire environment and ignore "garden.env-filter"
+    #[arg(long, conflicts_with = "clean_env")]
+    pub preserve_env: bool,
+    /// Do not inherit the environment. Only the garden environment is used
+    #[arg(long)]
+    pub clean_env: bool,
+    /// Capture the output of each tree and print it grouped by tree once all trees complete
+    #[arg(long)]
+    pub capture: bool,
+    /// Set the format for captured output {text, json}. "json" implies "--capture"
+    #[arg(long, default_value = "text", value_name = "format")]
+    pub format: model::OutputFormat,
+    /// Run the command from the garden root with TREE_NAME and TREE_PATH exported
+    #[arg(long)]
+    pub chdir_root: bool,
+    /// Terminate the command when it runs longer than the specified duration, e.g. "30s"
+    #[arg(long, value_name = "duration", value_parser = syntax::parse_duration)]
+    pub timeout: Option<std::time::Duration>,
+    /// Grow trees that do not exist before running the command
+    #[arg(long)]
+    pub grow_missing: bool,
+    /// Run an interactive command, e.g. "git rebase -i", in a single tree using the terminal
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["num_jobs", "capture", "format", "timeout", "log_dir"]
+    )]
+    pub interactive: bool,
+    /// Only run the command in trees that have uncommitted changes or untracked files
+    #[arg(long, conflicts_with = "clean")]
+    pub dirty: bool,
+    /// Only run the command in trees that do not have uncommitted changes
+    #[arg(long)]
+    pub clean: bool,
+    /// Only run the command in trees whose branch is behind its upstream branch
+    #[arg(long)]
+    pub behind: bool,
+    /// Write the output of each tree to "<path>/<tree>.log" in addition to the console
+    #[arg(long, value_name = "path", value_parser = cmd::parse_log_dir)]
+    pub log_dir: Option<std::path::PathBuf>,
+    /// Tree query for the gardens, groups or trees to run the command
+    #[arg(add = ArgValueCandidates::new(completion::queries))]
+    pub query: String,
+    /// Command to run in the resolved tree(s)
+    #[arg(required = true, allow_hyphen_values = true, trailing_var_arg = true)]
+    pub command: Vec<String>,
 }
 
-/// Parse "exec" arguments
-fn parse_args(options: &mut model::CommandOptions, query: &mut String, command: &mut Vec<String>) {
-    let mut ap = argparse::ArgumentParser::new();
-    ap.silence_double_dash(false);
-    ap.stop_on_first_argument(true);
-    ap.set_description("garden exec - Run commands inside gardens");
-
-    ap.refer(query).required().add_argument(
-        "query",
-        argparse::Store,
-        "Tree query for the gardens, groups or trees to run the command",
-    );
-
-    ap.refer(command).required().add_argument(
-        "command",
-        argparse::List,
-        "Command to run in the resolved tree(s)",
-    );
-
-    options.args.insert(0, "garden exec".into());
-    cmd::parse_args(ap, options.args.to_vec());
-
-    if options.debug_level("exec") > 0 {
+/// Main entry point for the "garden exec" command
+pub fn main(app: &mut model::ApplicationContext, exec_options: &ExecOptions) -> Result<()> {
+    app.options.num_jobs = exec_options.num_jobs;
+    app.options.keep_going = exec_options.keep_going && !exec_options.fail_fast;
+    // --exit-status overrides the "garden.exit-status" setting.
+    app.options.exit_status = exec_options
+        .exit_status
+        .or(app.get_root_config().exit_status);
+    if exec_options.preserve_env {
+        app.options.env_passthrough = model::EnvPassthrough::Preserve;
+    } else if exec_options.clean_env {
+        app.options.env_passthrough = model::EnvPassthrough::Clean;
+    }
+    app.options.chdir_root = exec_options.chdir_root;
+    app.options.timeout = exec_options.timeout;
+    app.options.grow_missing = exec_options.grow_missing;
+    app.options.interactive = exec_options.interactive;
+    app.options.log_dir = exec_options.log_dir.clone();
+    cmd::handle_interrupts();
+    if exec_options.capture || exec_options.format == model::OutputFormat::Json {
+        app.options.capture = Some(exec_options.format.clone());
+    }
+    if app.options.debug_level("exec") > 0 {
         debug!("command: exec");
-        debug!("query: {}", query);
-        debug!("command: {:?}", command);
+        debug!("query: {}", exec_options.query);
+        debug!("command: {:?}", exec_options.command);
     }
+
+    let filter = StateFilter {
+        dirty: exec_options.dirty,
+        clean: exec_options.clean,
+        behind: exec_options.behind,
+    };
+
+    exec(app, &exec_options.query, &exec_options.command, &filter)
+}
+
+/// Select trees based on the state of their Git repository.
+/// Trees must match every filter that is enabled.
+#[derive(Clone, Debug, Default)]
+pub struct StateFilter {
+    /// Select trees with uncommitted changes or untracked files.
+    pub dirty: bool,
+    /// Select trees without uncommitted changes.
+    pub clean: bool,
+    /// Select trees whose branch is behind its upstream branch.
+    pub behind: bool,
+}
+
+impl StateFilter {
+    /// Is any filter enabled?
+    fn is_enabled(&self) -> bool {
+        self.dirty || self.clean || self.behind
+    }
+
+    /// Does the repository at the specified path match the filters?
+    /// Paths that are not Git repositories never match.
+    fn matches(&self, path: &std::path::Path) -> bool {
+        if !git::is_repository(path) {
+            return false;
+        }
+        if self.dirty || self.clean {
+            let dirty = git::has_uncommitted_changes(path);
+            if (self.dirty && !dirty) || (self.clean && dirty) {
+                return false;
+            }
+        }
+        if self.behind {
+            return matches!(git::ahead_behind(path), Some((_, behind)) if behind > 0);
+        }
+
+        true
+    }
+}
+
+/// Return the contexts whose trees match the filter. Missing and symlink trees are omitted.
+/// Trees are inspected in parallel because each check runs git commands.
+fn filter_contexts(
+    app: &model::ApplicationContext,
+    contexts: Vec<model::TreeContext>,
+    filter: &StateFilter,
+) -> Vec<model::TreeContext> {
+    let paths: Vec<Option<std::path::PathBuf>> = contexts
+        .iter()
+        .map(|context| {
+            let tree = &app.get_context_config(context).trees[context.tree];
+            match tree.path_as_ref() {
+                Ok(path) if !tree.is_symlink => Some(std::path::PathBuf::from(path)),
+                _ => None,
+            }
+        })
+        .collect();
+    let matches: Vec<bool> = paths
+        .par_iter()
+        .map(|path| match path {
+            Some(path) => path.exists() && filter.matches(path),
+            None => false,
+        })
+        .collect();
+
+    contexts
+        .into_iter()
+        .zip(matches)
+        .filter(|(_, matched)| *matched)
+        .map(|(context, _)| context)
+        .collect()
 }
 
 /// Execute a command over every tree in the evaluated tree query.
 pub fn exec(
-    config: &mut model::Configuration,
-    quiet: bool,
-    verbose: u8,
+    app: &mut model::ApplicationContext,
     query: &str,
     command: &[String],
+    filter: &StateFilter,
 ) -> Result<()> {
     // Strategy: resolve the trees down to a set of tree indexes paired with an
     // an optional garden context.
@@ -67,30 +203,320 @@ pub fn exec(
     // If the names resolve to trees, each tree is processed independently
     // with no garden context.
 
+    // Failures from an earlier command must not affect this command's exit status.
+    app.options.failures.clear();
     // Resolve the tree query into a vector of tree contexts.
-    let contexts = query::resolve_trees(config, query);
+    config::read_query_grafts(app, query)?;
+    let contexts = query::resolve_app_trees(app, query);
+    let options = app.options.clone();
     let mut exit_status: i32 = 0;
+    let mut grow_status: i32 = errors::EX_OK;
     if command.is_empty() {
         return Err(
             errors::GardenError::Usage("a command to execute must be specified".into()).into(),
         );
     }
+    // Missing trees are grown before running the command in any tree.
+    if options.grow_missing {
+        grow_status = grow::grow_missing(app, &contexts)?;
+        if grow_status != errors::EX_OK && !options.keep_going {
+            return options
+                .failures
+                .result(grow_status)
+                .map_err(|err| err.into());
+        }
+    }
+    // Trees are filtered after missing trees have been grown.
+    let contexts = if filter.is_enabled() {
+        filter_contexts(app, contexts, filter)
+    } else {
+        contexts
+    };
+    // Interactive commands use the terminal so they can only run in a single tree.
+    let contexts = if options.interactive {
+        check_interactive(contexts)?
+    } else {
+        contexts
+    };
+    if !options.interactive && (options.num_jobs > 1 || options.capture.is_some()) {
+        exit_status = exec_parallel(app, &options, &contexts, command)?;
+    } else {
+        // Loop over each context, evaluate the tree environment,
+        // and run the command.
+        for context in &contexts {
+            if cmd::is_interrupted() {
+                exit_status = cmd::interrupted_status();
+                break;
+            }
+            let config = app.enter_context(context);
+            // Skip symlink trees.
+            if config.trees[context.tree].is_symlink {
+                continue;
+            }
+            // Run the command in the current context.
+            if let Err(errors::GardenError::ExitStatus(status)) =
+                cmd::exec_in_context(config, context, &options, command)
+            {
+                exit_status = status;
+                let tree_name = config.trees[context.tree].get_name();
+                options
+                    .failures
+                    .add(context, tree_name, &command.join(" "), status);
+                if !options.keep_going {
+                    break;
+                }
+            }
+        }
+    }
+    if options.keep_going {
+        options.failures.print_summary();
+    }
+    // Growing happens first so a grow failure is the first non-zero status.
+    if grow_status != errors::EX_OK && !cmd::is_interrupted() {
+        exit_status = grow_status;
+    }
+    let exit_status = options
+        .failures
+        .apply_policy(options.exit_status, exit_status);
+
+    options
+        .failures
+        .result(exit_status)
+        .map_err(|err| err.into())
+}
+
+/// Ensure that an interactive command runs in exactly one tree.
+/// Returns the tree's context once duplicate matches have been removed.
+fn check_interactive(contexts: Vec<model::TreeContext>) -> Result<Vec<model::TreeContext>> {
+    // Queries can match the same tree more than once, e.g. through multiple groups.
+    // Trees with the same name in different grafts are distinct trees.
+    let mut unique: Vec<model::TreeContext> = Vec::new();
+    for context in contexts {
+        if !unique
+            .iter()
+            .any(|other| other.config == context.config && other.tree == context.tree)
+        {
+            unique.push(context);
+        }
+    }
+    match unique.len() {
+        1 => Ok(unique),
+        0 => Err(errors::GardenError::Usage("--interactive: no trees matched".into()).into()),
+        _ => Err(errors::GardenError::Usage(format!(
+            "--interactive requires a single tree: {} trees matched",
+            unique.len()
+        ))
+        .into()),
+    }
+}
+
+/// Evaluated details for running a command in a single tree.
+struct TreeCommand {
+    context: model::TreeContext,
+    name: String,
+    path: String,
+    /// Directory that the command runs in.
+    directory: String,
+    prefix: String,
+    command: Vec<String>,
+    env: Vec<(String, String)>,
+    env_filter: Vec<String>,
+}
 
-    // Loop over each context, evaluate the tree environment,
-    // and run the command.
-    for context in &contexts {
+/// The captured output and exit status of a command that ran in a tree.
+struct TreeOutput {
+    exit_status: i32,
+    stdout: String,
+    stderr: String,
+}
+
+/// Execute a command over the tree contexts in parallel.
+/// The tree environments are evaluated up-front and the commands
+/// are run concurrently using a pool of "options.num_jobs" threads.
+/// Output is captured and printed grouped by tree when "options.capture" is set.
+/// Trees that have not yet started are skipped once an error is encountered
+/// unless "--keep-going" is in effect. Returns the last non-zero exit status.
+fn exec_parallel(
+    app: &mut model::ApplicationContext,
+    options: &model::CommandOptions,
+    contexts: &[model::TreeContext],
+    command: &[String],
+) -> Result<i32> {
+    let mut tree_commands = Vec::new();
+    for context in contexts {
+        let config: &model::Configuration = app.enter_context(context);
+        let tree = &config.trees[context.tree];
         // Skip symlink trees.
-        if config.trees[context.tree].is_symlink {
+        if tree.is_symlink {
             continue;
         }
-        // Run the command in the current context.
-        if let Err(errors::GardenError::ExitStatus(status)) =
-            cmd::exec_in_context(config, context, quiet, verbose, command)
-        {
-            exit_status = status;
+        let path = tree.path_as_ref()?.clone();
+        // Sparse gardens/missing trees are ok -> skip these entries.
+        if !std::path::PathBuf::from(&path).exists() {
+            if !options.quiet {
+                eprintln!(
+                    "{}",
+                    model::display_missing_tree(tree, &path, options.verbose)
+                );
+            }
+            continue;
         }
+        let mut env = eval::environment(config, context);
+        let command = cmd::resolve_command(command, &env);
+        let directory = cmd::command_directory(config, options, tree.get_name(), &path, &mut env);
+        tree_commands.push(TreeCommand {
+            context: context.clone(),
+            command,
+            directory,
+            prefix: model::display_tree_prefix(tree),
+            name: tree.get_name().clone(),
+            path,
+            env,
+            env_filter: config.env_filter.clone(),
+        });
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.num_jobs)
+        .build()?;
+    let failed = std::sync::atomic::AtomicBool::new(false);
+    let outputs: Vec<Option<TreeOutput>> = pool.install(|| {
+        tree_commands
+            .par_iter()
+            .map(|tree_cmd| {
+                if failed.load(std::sync::atomic::Ordering::SeqCst) || cmd::is_interrupted() {
+                    return None;
+                }
+                let event = events::TreeEvent::start(
+                    options.event_handler.as_ref(),
+                    events::ExecAction::Exec,
+                    &tree_cmd.name,
+                    &tree_cmd.path,
+                );
+                let env =
+                    cmd::environment(&tree_cmd.env, &tree_cmd.env_filter, options.env_passthrough);
+                let command =
+                    cmd::Command::new(&tree_cmd.name, &tree_cmd.command, &tree_cmd.directory, env)
+                        .timeout(options.timeout)
+                        .log(options.log_path(&tree_cmd.name));
+                let output = match options.capture {
+                    Some(_) => capture_output(&command),
+                    None => TreeOutput {
+                        exit_status: command.join_with_prefix(&tree_cmd.prefix),
+                        stdout: String::new(),
+                        stderr: String::new(),
+                    },
+                };
+                let status = output.exit_status;
+                event.finish(status);
+                if status != errors::EX_OK {
+                    options.failures.add(
+                        &tree_cmd.context,
+                        &tree_cmd.name,
+                        &tree_cmd.command.join(" "),
+                        status,
+                    );
+                    if !options.keep_going {
+                        failed.store(true, std::sync::atomic::Ordering::SeqCst);
+                    }
+                }
+                Some(output)
+            })
+            .collect()
+    });
+
+    match &options.capture {
+        Some(model::OutputFormat::Json) => {
+            print_outputs_json(&tree_commands, &outputs, &options.failures.entries())?
+        }
+        Some(model::OutputFormat::Text) => print_outputs(&tree_commands, &outputs, options.quiet),
+        None => (),
     }
 
     // Return the last non-zero exit status.
-    cmd::result_from_exit_status(exit_status).map_err(|err| err.into())
+    let statuses: Vec<i32> = outputs
+        .iter()
+        .flatten()
+        .map(|output| output.exit_status)
+        .collect();
+    Ok(cmd::last_error_status(&statuses).unwrap_or(errors::EX_OK))
+}
+
+/// Run a command and capture its stdout and stderr.
+fn capture_output(command: &cmd::Command) -> TreeOutput {
+    match command.capture() {
+        Ok((exit_status, stdout, stderr)) => TreeOutput {
+            exit_status,
+            stdout,
+            stderr,
+        },
+        Err(err) => TreeOutput {
+            exit_status: errors::EX_ERROR,
+            stdout: String::new(),
+            stderr: format!("{}\n", err),
+        },
+    }
+}
+
+/// Print the captured output grouped by tree. Trees that did not run are omitted.
+fn print_outputs(tree_commands: &[TreeCommand], outputs: &[Option<TreeOutput>], quiet: bool) {
+    for (tree_cmd, output) in tree_commands.iter().zip(outputs) {
+        let output = match output {
+            Some(output) => output,
+            None => continue,
+        };
+        if !quiet {
+            if output.exit_status == errors::EX_OK {
+                println!(
+                    "{} {}",
+                    model::Color::cyan("#"),
+                    model::Color::blue(&tree_cmd.name).bold()
+                );
+            } else {
+                println!(
+                    "{} {}  {}",
+                    model::Color::cyan("#"),
+                    model::Color::blue(&tree_cmd.name).bold(),
+                    model::Color::red(format!("exit status {}", output.exit_status))
+                );
+            }
+        }
+        print!("{}", output.stdout);
+        eprint!("{}", output.stderr);
+    }
+}
+
+/// Print the captured output and exit status of each tree as a JSON document.
+/// The trees where the command failed are listed in "failures".
+fn print_outputs_json(
+    tree_commands: &[TreeCommand],
+    outputs: &[Option<TreeOutput>],
+    failures: &[errors::TreeFailure],
+) -> Result<()> {
+    let mut trees = Vec::new();
+    for (tree_cmd, output) in tree_commands.iter().zip(outputs) {
+        if let Some(output) = output {
+            trees.push(serde_json::json!({
+                "name": tree_cmd.name,
+                "path": tree_cmd.path,
+                "exit_status": output.exit_status,
+                "stdout": output.stdout,
+                "stderr": output.stderr,
+            }));
+        }
+    }
+    let failures: Vec<serde_json::Value> = failures
+        .iter()
+        .map(|failure| {
+            serde_json::json!({
+                "tree": failure.tree,
+                "command": failure.command,
+                "exit_status": failure.exit_status,
+            })
+        })
+        .collect();
+    let doc = serde_json::json!({ "trees": trees, "failures": failures });
+    println!("{}", serde_json::to_string_pretty(&doc)?);
+
+    Ok(())
 }
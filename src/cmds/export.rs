@@ -0,0 +1,147 @@
+use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::yaml::Yaml;
+
+use super::super::config;
+use super::super::errors;
+use super::super::eval;
+use super::super::model;
+use super::super::query;
+use super::completion;
+
+/// Export trees to vcstool or myrepos files
+#[derive(Parser, Clone, Debug)]
+pub struct ExportOptions {
+    /// Set the output format {vcs, mr}
+    #[arg(short, long, default_value = "vcs", value_name = "format")]
+    pub format: model::ReposFormat,
+    /// File to write (default: stdout)
+    #[arg(short, long, value_name = "file")]
+    pub output: Option<String>,
+    /// Tree queries for the gardens, groups or trees to export (default: all trees)
+    #[arg(add = ArgValueCandidates::new(completion::queries))]
+    pub queries: Vec<String>,
+}
+
+/// A tree that can be represented in vcstool and myrepos files.
+struct ExportedTree {
+    path: String,
+    url: String,
+    branch: String,
+}
+
+/// Main entry point for the "garden export" command
+pub fn main(app: &mut model::ApplicationContext, options: &ExportOptions) -> Result<()> {
+    let verbose = app.options.verbose;
+    let config = app.get_root_config();
+    let contexts: Vec<model::TreeContext> = if options.queries.is_empty() {
+        (0..config.trees.len())
+            .map(|tree_idx| model::TreeContext::new(tree_idx, config.get_id(), None, None))
+            .collect()
+    } else {
+        options
+            .queries
+            .iter()
+            .flat_map(|query| query::resolve_trees(config, query))
+            .collect()
+    };
+
+    let mut trees: Vec<ExportedTree> = Vec::new();
+    for ctx in &contexts {
+        let tree = &config.trees[ctx.tree];
+        // Symlinks, worktrees and trees without remotes cannot be cloned by other tools.
+        let remote = match query::default_remote(config, ctx.tree, ctx.garden) {
+            Some(remote) if !tree.is_symlink && !tree.is_worktree => remote,
+            _ => {
+                if verbose > 0 {
+                    eprintln!("{}: skipped, no url", tree.get_name());
+                }
+                continue;
+            }
+        };
+        let tree_path = std::path::PathBuf::from(tree.path_as_ref()?);
+        let path = match tree_path.strip_prefix(&config.root_path) {
+            Ok(path) if path.as_os_str().is_empty() => ".".to_string(),
+            Ok(path) => path.to_string_lossy().to_string(),
+            Err(_) => tree_path.to_string_lossy().to_string(),
+        };
+        if trees.iter().any(|exported| exported.path == path) {
+            continue;
+        }
+        trees.push(ExportedTree {
+            path,
+            url: eval::tree_value(config, remote.get_expr(), ctx.tree, ctx.garden),
+            branch: eval::tree_value(config, tree.branch.get_expr(), ctx.tree, ctx.garden),
+        });
+    }
+
+    let content = match options.format {
+        model::ReposFormat::Vcs => format_vcs(&trees),
+        model::ReposFormat::Mr => format_mr(&trees),
+    };
+
+    match &options.output {
+        Some(output) => std::fs::write(output, content)
+            .map_err(|err| errors::GardenError::OSError(format!("{}: {}", output, err)))?,
+        None => print!("{}", content),
+    }
+
+    Ok(())
+}
+
+/// Format trees as a vcstool ".repos" file.
+fn format_vcs(trees: &[ExportedTree]) -> String {
+    let mut repositories = YamlHash::new();
+    for tree in trees {
+        let mut entry = YamlHash::new();
+        entry.insert(Yaml::String("type".into()), Yaml::String("git".into()));
+        entry.insert(Yaml::String("url".into()), Yaml::String(tree.url.clone()));
+        if !tree.branch.is_empty() {
+            entry.insert(
+                Yaml::String("version".into()),
+                Yaml::String(tree.branch.clone()),
+            );
+        }
+        repositories.insert(Yaml::String(tree.path.clone()), Yaml::Hash(entry));
+    }
+    let mut doc = YamlHash::new();
+    doc.insert(
+        Yaml::String("repositories".into()),
+        Yaml::Hash(repositories),
+    );
+
+    config::writer::emit_yaml(&Yaml::Hash(doc))
+}
+
+/// Format trees as a myrepos ".mrconfig" file.
+/// myrepos runs "checkout" commands from the parent directory of each repository.
+fn format_mr(trees: &[ExportedTree]) -> String {
+    let mut content = String::new();
+    for tree in trees {
+        let basename = std::path::Path::new(&tree.path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| tree.path.clone());
+        let mut checkout = vec!["git", "clone"];
+        if !tree.branch.is_empty() {
+            checkout.push("--branch");
+            checkout.push(&tree.branch);
+        }
+        checkout.push(&tree.url);
+        checkout.push(&basename);
+        let checkout: Vec<String> = checkout
+            .iter()
+            .map(|arg| shlex::quote(arg).to_string())
+            .collect();
+
+        if !content.is_empty() {
+            content.push('\n');
+        }
+        content.push_str(&format!("[{}]\n", tree.path));
+        content.push_str(&format!("checkout = {}\n", checkout.join(" ")));
+    }
+
+    content
+}
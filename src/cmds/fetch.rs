@@ -0,0 +1,197 @@
+use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+use rayon::prelude::*;
+
+use super::super::cmd;
+use super::super::errors;
+use super::super::model;
+use super::super::query;
+use super::completion;
+
+/// Fetch remotes for trees in parallel
+#[derive(Parser, Clone, Debug)]
+pub struct FetchOptions {
+    /// Fetch using the specified number of jobs (default: the number of CPUs)
+    #[arg(short = 'j', long = "jobs", value_name = "jobs")]
+    pub num_jobs: Option<usize>,
+    /// Fetch the named remote only (default: all configured remotes)
+    #[arg(short, long, value_name = "remote")]
+    pub remote: Option<String>,
+    /// Tree queries for the gardens, groups or trees to fetch
+    #[arg(add = ArgValueCandidates::new(completion::queries), required = true)]
+    pub queries: Vec<String>,
+}
+
+/// Details for fetching a single tree.
+struct FetchTree {
+    path: String,
+    prefix: String,
+    remotes: Vec<String>,
+}
+
+/// The outcome of fetching a single tree.
+struct FetchResult {
+    status: i32,
+    output: String,
+    updates: Vec<String>,
+}
+
+/// Main entry point for the "garden fetch" command
+pub fn main(app: &mut model::ApplicationContext, options: &FetchOptions) -> Result<()> {
+    if let Some(num_jobs) = options.num_jobs {
+        app.options.num_jobs = num_jobs;
+    }
+    let quiet = app.options.quiet;
+    let verbose = app.options.verbose;
+    let num_jobs = app.options.num_jobs;
+    let config = app.get_root_config_mut();
+
+    let mut fetch_trees = Vec::new();
+    for query in &options.queries {
+        for ctx in query::resolve_trees(config, query) {
+            let tree = &config.trees[ctx.tree];
+            // Skip symlink trees.
+            if tree.is_symlink {
+                continue;
+            }
+            let path = tree.path_as_ref()?.to_string();
+            // Sparse gardens/missing trees are ok -> skip these entries.
+            if !std::path::PathBuf::from(&path).exists() {
+                if !quiet {
+                    eprintln!("{}", model::display_missing_tree(tree, &path, verbose));
+                }
+                continue;
+            }
+            let remotes: Vec<String> = match &options.remote {
+                Some(remote) => tree
+                    .remotes
+                    .iter()
+                    .filter(|var| var.get_name() == remote)
+                    .map(|var| var.get_name().to_string())
+                    .collect(),
+                None => tree
+                    .remotes
+                    .iter()
+                    .map(|var| var.get_name().to_string())
+                    .collect(),
+            };
+            if remotes.is_empty() {
+                continue;
+            }
+            fetch_trees.push(FetchTree {
+                path,
+                prefix: model::display_tree_prefix(tree),
+                remotes,
+            });
+        }
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_jobs.max(1))
+        .build()?;
+    let results: Vec<FetchResult> =
+        pool.install(|| fetch_trees.par_iter().map(fetch_tree).collect());
+
+    // Print the results in the order of the trees.
+    let mut exit_status = errors::EX_OK;
+    let mut num_updates = 0;
+    for (fetch_tree, result) in fetch_trees.iter().zip(&results) {
+        if result.status != errors::EX_OK {
+            exit_status = result.status;
+            for line in result.output.lines() {
+                eprintln!("{} {}", fetch_tree.prefix, line);
+            }
+        }
+        if !quiet {
+            for update in &result.updates {
+                println!("{} {}", fetch_tree.prefix, update);
+            }
+        }
+        num_updates += result.updates.len();
+    }
+    if !quiet {
+        println!(
+            "{} {} {} fetched, {} {} updated",
+            model::Color::cyan("#"),
+            fetch_trees.len(),
+            if fetch_trees.len() == 1 {
+                "tree"
+            } else {
+                "trees"
+            },
+            num_updates,
+            if num_updates == 1 { "ref" } else { "refs" },
+        );
+    }
+
+    // Return the last non-zero exit status.
+    cmd::result_from_exit_status(exit_status).map_err(|err| err.into())
+}
+
+/// Fetch the remotes for a single tree and report the updated refs.
+fn fetch_tree(fetch_tree: &FetchTree) -> FetchResult {
+    let before = remote_refs(&fetch_tree.path);
+
+    let mut command = vec!["git", "fetch", "--quiet", "--multiple"];
+    command.extend(fetch_tree.remotes.iter().map(String::as_str));
+    let exec = cmd::exec_in_dir(&command, &fetch_tree.path);
+    let (status, output) = match cmd::capture(exec) {
+        Ok(capture) => (
+            cmd::status(Ok(capture.exit_status)),
+            capture.stderr_str().trim_end().to_string(),
+        ),
+        Err(err) => (errors::EX_ERROR, err.to_string()),
+    };
+
+    let after = remote_refs(&fetch_tree.path);
+    let mut updates = Vec::new();
+    for (refname, commit) in &after {
+        match before.get(refname) {
+            Some(old_commit) if old_commit == commit => (),
+            Some(old_commit) => updates.push(format!(
+                "{} {}..{}",
+                refname,
+                short_commit(old_commit),
+                short_commit(commit)
+            )),
+            None => updates.push(format!("{} (new)", refname)),
+        }
+    }
+    for refname in before.keys() {
+        if !after.contains_key(refname) {
+            updates.push(format!("{} (deleted)", refname));
+        }
+    }
+
+    FetchResult {
+        status,
+        output,
+        updates,
+    }
+}
+
+/// Return the remote-tracking refs and their commits for the repository at path.
+fn remote_refs(path: &str) -> std::collections::BTreeMap<String, String> {
+    let command = [
+        "git",
+        "for-each-ref",
+        "--format=%(refname:short) %(objectname)",
+        "refs/remotes",
+    ];
+    let mut refs = std::collections::BTreeMap::new();
+    if let Ok(capture) = cmd::capture_stdout(cmd::exec_in_dir(&command, path)) {
+        for line in capture.stdout_str().lines() {
+            if let Some((refname, commit)) = line.rsplit_once(' ') {
+                refs.insert(refname.to_string(), commit.to_string());
+            }
+        }
+    }
+
+    refs
+}
+
+/// Abbreviate a commit ID for display.
+fn short_commit(commit: &str) -> &str {
+    &commit[..commit.len().min(10)]
+}
@@ -1,75 +1,275 @@
 use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
 use std::collections::HashSet;
 
 use super::super::cmd;
 use super::super::errors;
 use super::super::eval;
+use super::super::events;
+use super::super::git;
 use super::super::model;
 use super::super::query;
+use super::completion;
+use super::lock;
 
-/// Main entry point for the "garden grow" command
-/// Parameters:
-/// - options: `garden::model::CommandOptions`
-
-pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
-    let mut queries = Vec::new();
-    parse_args(&mut queries, &mut app.options);
-
-    let quiet = app.options.quiet;
-    let verbose = app.options.verbose;
+/// Create and update gardens
+#[derive(Parser, Clone, Debug, Default)]
+pub struct GrowOptions {
+    /// Tree queries for the gardens, groups or trees to grow
+    #[arg(add = ArgValueCandidates::new(completion::queries), required = true)]
+    pub queries: Vec<String>,
+    /// Ignore the lockfile and checkout the default branch in newly cloned trees
+    #[arg(long)]
+    pub no_lock: bool,
+    /// Checkout the commits recorded in the lockfile in existing trees that do not
+    /// have uncommitted changes
+    #[arg(long, conflicts_with = "no_lock")]
+    pub locked: bool,
+    /// Clone a single branch only, as if "single-branch: true" were set on every tree
+    #[arg(long)]
+    pub single_branch: bool,
+    /// Create shallow clones with the specified history depth, as if "depth" were
+    /// set on every tree
+    #[arg(long, value_name = "N", conflicts_with = "unshallow")]
+    pub depth: Option<i64>,
+    /// Clone the full history and fetch the missing history in existing shallow trees
+    #[arg(long)]
+    pub unshallow: bool,
+    /// Borrow objects from a local repository when cloning, as if "clone-reference"
+    /// were set on every tree
+    #[arg(long, value_name = "path")]
+    pub reference: Option<String>,
+    /// Clone from a Git bundle file, as if "bundle" were set on every tree
+    #[arg(long, value_name = "file")]
+    pub bundle: Option<String>,
+    /// Checkout a tag, branch or commit in newly cloned trees, as if "ref" were set
+    /// on every tree
+    #[arg(long, value_name = "ref")]
+    pub checkout: Option<String>,
+    /// Continue to the next tree when errors occur
+    #[arg(short, long)]
+    pub keep_going: bool,
+    /// Remove remotes from existing trees when they are not configured in the garden file
+    #[arg(long)]
+    pub prune_remotes: bool,
+    /// Replace broken symlinks and symlinks that point to the wrong target
+    #[arg(long)]
+    pub rebuild_symlinks: bool,
+    /// Write the output of each tree to "<path>/<tree>.log" in addition to the console
+    #[arg(long, value_name = "path", value_parser = cmd::parse_log_dir)]
+    pub log_dir: Option<std::path::PathBuf>,
+}
 
+/// Main entry point for the "garden grow" command
+pub fn main(app: &mut model::ApplicationContext, options: &GrowOptions) -> Result<()> {
+    app.options.failures.clear();
+    app.options.keep_going = options.keep_going;
+    app.options.log_dir = options.log_dir.clone();
+    let cmd_options = app.options.clone();
     let mut exit_status = errors::EX_OK;
     let mut configured_worktrees: HashSet<String> = HashSet::new();
     let config = app.get_root_config_mut();
-    for query in &queries {
-        let status = grow(config, &mut configured_worktrees, quiet, verbose, query)?;
+    if options.single_branch {
+        for tree in config.trees.iter_mut() {
+            tree.is_single_branch = true;
+        }
+    }
+    if let Some(depth) = options.depth {
+        for tree in config.trees.iter_mut() {
+            tree.clone_depth = depth;
+        }
+    }
+    if options.unshallow {
+        for tree in config.trees.iter_mut() {
+            tree.clone_depth = 0;
+        }
+    }
+    if let Some(reference) = &options.reference {
+        // Relative paths are relative to the current directory.
+        let reference = std::env::current_dir()?.join(reference);
+        let reference = reference.to_string_lossy().to_string();
+        for tree in config.trees.iter_mut() {
+            tree.clone_reference.set_expr(reference.clone());
+        }
+    }
+    if let Some(bundle) = &options.bundle {
+        // Relative paths are relative to the current directory.
+        let bundle = std::env::current_dir()?.join(bundle);
+        let bundle = bundle.to_string_lossy().to_string();
+        for tree in config.trees.iter_mut() {
+            tree.bundle.set_expr(bundle.clone());
+        }
+    }
+    if let Some(git_ref) = &options.checkout {
+        for tree in config.trees.iter_mut() {
+            tree.git_ref.set_expr(git_ref.clone());
+        }
+    }
+    // Newly cloned trees checkout the commits recorded by "garden lock".
+    let locks = if options.no_lock {
+        lock::Locks::new()
+    } else {
+        lock::read_config_locks(config)?
+    };
+    for query in &options.queries {
+        let status = grow(
+            app,
+            &mut configured_worktrees,
+            &locks,
+            &cmd_options,
+            query,
+            options,
+        )?;
         if status != errors::EX_OK {
             exit_status = status;
+            if !cmd_options.keep_going {
+                break;
+            }
         }
     }
+    if cmd_options.keep_going {
+        cmd_options.failures.print_summary();
+    }
 
     // Return the last non-zero exit status.
-    cmd::result_from_exit_status(exit_status).map_err(|err| err.into())
+    cmd_options
+        .failures
+        .result(exit_status)
+        .map_err(|err| err.into())
 }
 
-/// Parse "garden grow" arguments.
-fn parse_args(queries: &mut Vec<String>, options: &mut model::CommandOptions) {
-    options.args.insert(0, "garden grow".into());
-
-    let mut ap = argparse::ArgumentParser::new();
-    ap.set_description("garden grow - Create and update gardens");
-
-    ap.refer(queries).required().add_argument(
-        "queries",
-        argparse::List,
-        "Tree queries for the gardens, groups or trees to grow",
-    );
+/// Create/update trees in the evaluated tree query.
+pub fn grow(
+    app: &mut model::ApplicationContext,
+    configured_worktrees: &mut HashSet<String>,
+    locks: &lock::Locks,
+    options: &model::CommandOptions,
+    query: &str,
+    grow_options: &GrowOptions,
+) -> Result<i32> {
+    let contexts = query::resolve_trees(app.get_root_config(), query);
+    grow_contexts(
+        app,
+        configured_worktrees,
+        locks,
+        options,
+        &contexts,
+        grow_options,
+    )
+}
 
-    if let Err(err) = ap.parse(
-        options.args.to_vec(),
-        &mut std::io::stdout(),
-        &mut std::io::stderr(),
-    ) {
-        std::process::exit(err);
+/// Grow the trees that do not exist. Used by "garden exec --grow-missing".
+pub fn grow_missing(
+    app: &mut model::ApplicationContext,
+    contexts: &[model::TreeContext],
+) -> Result<i32> {
+    let options = app.options.clone();
+    let grow_options = GrowOptions::default();
+    let mut configured_worktrees: HashSet<String> = HashSet::new();
+    let mut exit_status = errors::EX_OK;
+    for context in contexts {
+        let config = app.get_context_config(context);
+        let exists = match config.trees[context.tree].path_as_ref() {
+            Ok(path) => std::path::Path::new(path).exists(),
+            Err(_) => true,
+        };
+        if exists {
+            continue;
+        }
+        let locks = lock::read_config_locks(config)?;
+        let status = grow_contexts(
+            app,
+            &mut configured_worktrees,
+            &locks,
+            &options,
+            std::slice::from_ref(context),
+            &grow_options,
+        )?;
+        if status != errors::EX_OK {
+            exit_status = status;
+            if !options.keep_going {
+                break;
+            }
+        }
     }
+
+    Ok(exit_status)
 }
 
-/// Create/update trees in the evaluated tree query.
-pub fn grow(
-    config: &mut model::Configuration,
+/// Create/update the trees for the specified tree contexts.
+fn grow_contexts(
+    app: &mut model::ApplicationContext,
     configured_worktrees: &mut HashSet<String>,
-    quiet: bool,
-    verbose: u8,
-    query: &str,
+    locks: &lock::Locks,
+    options: &model::CommandOptions,
+    contexts: &[model::TreeContext],
+    grow_options: &GrowOptions,
 ) -> Result<i32> {
-    let contexts = query::resolve_trees(config, query);
     let mut exit_status = errors::EX_OK;
 
-    for ctx in &contexts {
-        let status = grow_tree_from_context(config, configured_worktrees, ctx, quiet, verbose)?;
+    for ctx in contexts {
+        let config = app.enter_context(ctx);
+        // The "pre-grow" and "post-grow" hooks run before and after each tree is grown.
+        if let Err(errors::CommandError::ExitStatus { command, status }) =
+            super::cmd::run_hook(config, options, ctx, "pre-grow")
+        {
+            exit_status = status;
+            options
+                .failures
+                .add(ctx, config.trees[ctx.tree].get_name(), &command, status);
+            if !options.keep_going {
+                break;
+            }
+            continue;
+        }
+        let tree = &config.trees[ctx.tree];
+        let (name, path) = (tree.get_name().clone(), tree.path_as_ref()?.clone());
+        let handler = options.event_handler.as_ref();
+        let event = events::TreeEvent::start(handler, events::ExecAction::Grow, &name, &path);
+        let is_new = !std::path::Path::new(&path).exists();
+        // Steps that know the command that failed record their own failures.
+        let num_failures = options.failures.entries().len();
+        let mut status = errors::EX_OK;
+        if grow_options.rebuild_symlinks {
+            status = remove_stale_symlink(&config.trees[ctx.tree], options.verbose);
+        }
+        if status == errors::EX_OK {
+            status = grow_tree_from_context(
+                config,
+                configured_worktrees,
+                locks,
+                ctx,
+                options,
+                grow_options,
+            )?;
+        }
+        if status == errors::EX_OK && grow_options.unshallow {
+            status = unshallow_tree(config, ctx, options);
+        }
+        if status == errors::EX_OK {
+            status = post_clone_tree(config, options, ctx, is_new);
+        }
+        if status == errors::EX_OK {
+            if let Err(errors::CommandError::ExitStatus {
+                command,
+                status: hook_status,
+            }) = super::cmd::run_hook(config, options, ctx, "post-grow")
+            {
+                status = hook_status;
+                options.failures.add(ctx, &name, &command, status);
+            }
+        }
+        event.finish(status);
         if status != errors::EX_OK {
             // Return the last non-zero exit status.
             exit_status = status;
+            if options.failures.entries().len() == num_failures {
+                options.failures.add(ctx, &name, "grow", status);
+            }
+            if !options.keep_going {
+                break;
+            }
         }
     }
 
@@ -81,11 +281,17 @@ pub fn grow(
 fn grow_tree_from_context(
     config: &model::Configuration,
     configured_worktrees: &mut HashSet<String>,
+    locks: &lock::Locks,
     ctx: &model::TreeContext,
-    quiet: bool,
-    verbose: u8,
+    options: &model::CommandOptions,
+    grow_options: &GrowOptions,
 ) -> Result<i32> {
     let mut exit_status = errors::EX_OK;
+    let (quiet, verbose) = (options.quiet, options.verbose);
+    let prune_remotes = grow_options.prune_remotes;
+    let tree_name = config.trees[ctx.tree].get_name();
+    let log = options.log_path(tree_name);
+    let log = log.as_deref();
 
     let path = config.trees[ctx.tree].path_as_ref()?.clone();
     model::print_tree_details(&config.trees[ctx.tree], verbose, quiet);
@@ -98,124 +304,472 @@ fn grow_tree_from_context(
         errors::GardenError::OSError(format!("unable to create {}: {}", path, err))
     })?;
 
+    if config.trees[ctx.tree].is_symlink {
+        let status = grow_symlink(config, ctx, quiet).unwrap_or(errors::EX_IOERR);
+        if status != errors::EX_OK {
+            exit_status = status;
+        }
+        return Ok(exit_status);
+    }
+
     if pathbuf.exists() {
-        return update_tree_from_context(
+        let status = if config.trees[ctx.tree].git_ref.is_empty() {
+            check_lock(config, locks, ctx, &pathbuf, options, grow_options.locked)
+        } else {
+            check_ref(config, ctx, &pathbuf, options)
+        };
+        if status != errors::EX_OK {
+            exit_status = status;
+        }
+        let status = update_tree_from_context(
             config,
             configured_worktrees,
             ctx,
             &pathbuf,
             quiet,
             verbose,
-        );
-    } else {
-        if config.trees[ctx.tree].is_symlink {
-            let status = grow_symlink(config, ctx).unwrap_or(errors::EX_IOERR);
-            if status != errors::EX_OK {
-                exit_status = status;
-            }
-            return Ok(exit_status);
+            prune_remotes,
+        )?;
+        if status != errors::EX_OK {
+            exit_status = status;
         }
-
+        return Ok(exit_status);
+    } else {
         if config.trees[ctx.tree].is_worktree {
             return grow_tree_from_context_as_worktree(
                 config,
                 configured_worktrees,
+                locks,
                 ctx,
-                quiet,
-                verbose,
+                options,
+                grow_options,
             );
         }
 
-        if config.trees[ctx.tree].remotes.is_empty() {
+        let bundle = clone_bundle(config, ctx);
+        if config.trees[ctx.tree].remotes.is_empty() && bundle.is_empty() {
             return Ok(exit_status);
         }
 
-        // The first remote is "origin" by convention
-        let remote = config.trees[ctx.tree].remotes[0].clone();
-        let url = eval::tree_value(config, remote.get_expr(), ctx.tree, ctx.garden);
-
-        // git clone [options] <url> <path>
-        let mut cmd: Vec<&str> = ["git", "clone"].to_vec();
-
-        // [options]
-        //
-        // "git clone --bare" clones bare repositories.
-        if config.trees[ctx.tree].is_bare_repository {
-            cmd.push("--bare");
-        }
+        // The first remote is "origin" by convention. "default-remote" selects another remote.
+        let (remote_name, url) = match query::default_remote(config, ctx.tree, ctx.garden) {
+            Some(remote) => (
+                remote.get_name().to_string(),
+                config.rewrite_url(&eval::tree_value(
+                    config,
+                    remote.get_expr(),
+                    ctx.tree,
+                    ctx.garden,
+                )),
+            ),
+            None => ("origin".to_string(), String::new()),
+        };
 
-        // "git clone --branch=name" clones the named branch.
         let branch_var = config.trees[ctx.tree].branch.clone();
-        let branch = eval::tree_value(config, branch_var.get_expr(), ctx.tree, ctx.garden);
-        let branch_opt;
-        if !branch.is_empty() {
-            branch_opt = format!("--branch={}", branch);
-            cmd.push(&branch_opt);
-        }
-        // "git clone --depth=N" creates shallow clones with truncated history.
-        let clone_depth = config.trees[ctx.tree].clone_depth;
-        let clone_depth_opt;
-        if clone_depth > 0 {
-            clone_depth_opt = format!("--depth={}", clone_depth);
-            cmd.push(&clone_depth_opt);
-        }
-        // "git clone --depth=N" clones a single branch by default.
-        // We generally want all branches available in our clones so we default to
-        // "single-branch: false" so that "--no-single-branch" is used. This makes
-        // all branches available by default.
-        let is_single_branch = config.trees[ctx.tree].is_single_branch;
-        if is_single_branch {
-            cmd.push("--single-branch");
-        } else {
-            cmd.push("--no-single-branch");
+        let clone_options = git::CloneOptions {
+            branch: eval::tree_value(config, branch_var.get_expr(), ctx.tree, ctx.garden),
+            depth: config.trees[ctx.tree].clone_depth,
+            is_bare: config.trees[ctx.tree].is_bare_repository,
+            is_single_branch: config.trees[ctx.tree].is_single_branch,
+            reference: clone_reference(config, ctx),
+            origin: remote_name.clone(),
+        };
+        let backend = git::backend();
+        // Trees are cloned from their bundle when available. The "url" is used
+        // when the bundle does not exist or cannot be cloned.
+        let mut is_cloned = false;
+        if !bundle.is_empty() {
+            let bundle_options = git::CloneOptions {
+                depth: 0,
+                reference: String::new(),
+                ..clone_options.clone()
+            };
+            if !std::path::Path::new(&bundle).exists() {
+                if url.is_empty() {
+                    eprintln!("error: bundle not found: {}", bundle);
+                    cmd::write_log(log, &format!("error: bundle not found: {}", bundle));
+                    return Ok(errors::EX_IOERR);
+                }
+                if !quiet {
+                    eprintln!("warning: bundle not found: {}", bundle);
+                }
+            } else {
+                if verbose > 1 {
+                    let command = bundle_options.command(&bundle, &path);
+                    let command: Vec<&str> = command.iter().map(String::as_str).collect();
+                    cmd::print_quoted_command(&command);
+                }
+                let command = bundle_options.command(&bundle, &path).join(" ");
+                cmd::write_log(log, &command);
+                match backend.clone_repository(&bundle, &path, &bundle_options) {
+                    Ok(()) => is_cloned = true,
+                    Err(err) if url.is_empty() => {
+                        eprintln!("error: {}", err);
+                        cmd::write_log(log, &format!("error: {}", err));
+                        let status = err.into();
+                        options.failures.add(ctx, tree_name, &command, status);
+                        return Ok(status);
+                    }
+                    Err(err) => {
+                        if !quiet {
+                            eprintln!("warning: unable to clone {}: {}", bundle, err);
+                        }
+                        // Remove the partial clone before cloning from the url.
+                        std::fs::remove_dir_all(&pathbuf).ok();
+                    }
+                }
+            }
         }
-
-        // <url> <path>
-        cmd.push(&url);
-        cmd.push(&path);
-        if verbose > 1 {
-            print_quoted_command(&cmd);
+        if !is_cloned {
+            if verbose > 1 {
+                let command = clone_options.command(&url, &path);
+                let command: Vec<&str> = command.iter().map(String::as_str).collect();
+                cmd::print_quoted_command(&command);
+            }
+            let command = clone_options.command(&url, &path).join(" ");
+            cmd::write_log(log, &command);
+            if let Err(err) = backend.clone_repository(&url, &path, &clone_options) {
+                eprintln!("error: {}", err);
+                cmd::write_log(log, &format!("error: {}", err));
+                let status = err.into();
+                options.failures.add(ctx, tree_name, &command, status);
+                return Ok(status);
+            }
         }
 
-        let exec = cmd::exec_cmd(&cmd);
-        let status = cmd::status(exec.join());
-        if status != 0 {
-            exit_status = status;
+        // Checkout the pinned "ref" or the commit recorded in the lockfile.
+        let git_ref_var = config.trees[ctx.tree].git_ref.clone();
+        let git_ref = eval::tree_value(config, git_ref_var.get_expr(), ctx.tree, ctx.garden);
+        if !git_ref.is_empty() {
+            let command = format!("git checkout --detach {}", git_ref);
+            if verbose > 1 {
+                cmd::print_command_str(&command);
+            }
+            cmd::write_log(log, &command);
+            if let Err(err) = backend.checkout_ref(&pathbuf, &remote_name, &git_ref) {
+                eprintln!("error: {}: unable to checkout {}: {}", path, git_ref, err);
+                cmd::write_log(log, &format!("error: {}", err));
+                exit_status = err.into();
+            }
+        } else if let Some(commit) = locks.get(config.trees[ctx.tree].get_name()) {
+            if verbose > 1 {
+                cmd::print_command_str(&format!("git checkout --detach {}", commit));
+            }
+            if let Err(err) = backend.checkout_commit(&pathbuf, &remote_name, commit) {
+                eprintln!("error: {}", err);
+                cmd::write_log(log, &format!("error: {}", err));
+                exit_status = err.into();
+            }
         }
     }
 
-    let status =
-        update_tree_from_context(config, configured_worktrees, ctx, &pathbuf, quiet, verbose)?;
+    let status = update_tree_from_context(
+        config,
+        configured_worktrees,
+        ctx,
+        &pathbuf,
+        quiet,
+        verbose,
+        prune_remotes,
+    )?;
     if status != errors::EX_OK {
         exit_status = status;
     }
+
+    // Submodules are initialized after the repository has been configured so that
+    // "gitconfig" settings, e.g. "submodule.<name>.url", are in effect.
+    let status = update_submodules(&config.trees[ctx.tree], &path, options);
+    if status != errors::EX_OK {
+        exit_status = status;
+    }
+    let status = pull_lfs(&config.trees[ctx.tree], &path, options);
+    if status != errors::EX_OK {
+        exit_status = status;
+    }
+
     Ok(exit_status)
 }
 
-/// Print a command that will be executed.
-fn print_quoted_command(command: &[&str]) {
-    let mut quoted_args: Vec<String> = Vec::new();
-    for cmd in command {
-        let quoted = shlex::quote(cmd);
-        quoted_args.push(quoted.as_ref().to_string());
+/// Compare the commit checked out in an existing tree against the lockfile.
+/// The locked commit is checked out when "locked" is true and the tree does not
+/// have uncommitted changes. Otherwise a warning is printed when they differ.
+/// Trees with a pinned "ref" are not checked.
+fn check_lock(
+    config: &model::Configuration,
+    locks: &lock::Locks,
+    ctx: &model::TreeContext,
+    path: &std::path::Path,
+    options: &model::CommandOptions,
+    locked: bool,
+) -> i32 {
+    let tree = &config.trees[ctx.tree];
+    let commit = match locks.get(tree.get_name()) {
+        Some(commit) if tree.git_ref.is_empty() => commit,
+        _ => return errors::EX_OK,
+    };
+    let backend = git::backend();
+    match backend.head_commit(path) {
+        Ok(head) if head == *commit => return errors::EX_OK,
+        Ok(_) => (),
+        Err(_) => return errors::EX_OK,
+    }
+    if !locked {
+        if !options.quiet {
+            eprintln!(
+                "warning: {}: HEAD does not match the lockfile commit {}, \
+                use '--locked' to checkout the locked commit",
+                tree.get_name(),
+                commit
+            );
+        }
+        return errors::EX_OK;
+    }
+    if git::has_uncommitted_changes(path) {
+        eprintln!(
+            "error: {}: unable to checkout {}: uncommitted changes",
+            tree.get_name(),
+            commit
+        );
+        return errors::EX_ERROR;
+    }
+    if options.verbose > 1 {
+        cmd::print_command_str(&format!("git checkout --detach {}", commit));
+    }
+    let remote_name = query::default_remote(config, ctx.tree, ctx.garden)
+        .map(|remote| remote.get_name().to_string())
+        .unwrap_or_else(|| "origin".to_string());
+    if let Err(err) = backend.checkout_commit(path, &remote_name, commit) {
+        eprintln!("error: {}", err);
+        return err.into();
     }
 
-    print_command_str(&quoted_args.join(" "));
+    errors::EX_OK
 }
 
-/// Print a command that will be executed from a string.
-fn print_command_str(cmd: &str) {
-    println!("{} {}", model::Color::cyan(":"), model::Color::green(cmd),)
+/// Checkout the pinned "ref" in an existing tree when HEAD does not point to it.
+/// Trees with uncommitted changes are left as-is and a warning is printed.
+fn check_ref(
+    config: &model::Configuration,
+    ctx: &model::TreeContext,
+    path: &std::path::Path,
+    options: &model::CommandOptions,
+) -> i32 {
+    let tree = &config.trees[ctx.tree];
+    let git_ref = eval::tree_value(config, tree.git_ref.get_expr(), ctx.tree, ctx.garden);
+    if git_ref.is_empty() {
+        return errors::EX_OK;
+    }
+    let backend = git::backend();
+    let head = match backend.head_commit(path) {
+        Ok(head) => head,
+        Err(_) => return errors::EX_OK,
+    };
+    if git::rev_parse_commit(path, &git_ref).as_deref() == Some(head.as_str()) {
+        return errors::EX_OK;
+    }
+    if git::has_uncommitted_changes(path) {
+        if !options.quiet {
+            eprintln!(
+                "warning: {}: unable to checkout {}: uncommitted changes",
+                tree.get_name(),
+                git_ref
+            );
+        }
+        return errors::EX_OK;
+    }
+    let command = format!("git checkout --detach {}", git_ref);
+    if options.verbose > 1 {
+        cmd::print_command_str(&command);
+    }
+    let log = options.log_path(tree.get_name());
+    cmd::write_log(log.as_deref(), &command);
+    let remote_name = query::default_remote(config, ctx.tree, ctx.garden)
+        .map(|remote| remote.get_name().to_string())
+        .unwrap_or_else(|| "origin".to_string());
+    if let Err(err) = backend.checkout_ref(path, &remote_name, &git_ref) {
+        eprintln!(
+            "error: {}: unable to checkout {}: {}",
+            tree.get_name(),
+            git_ref,
+            err
+        );
+        return err.into();
+    }
+
+    errors::EX_OK
+}
+
+/// Evaluate the "clone-reference" path for a tree. Relative paths are relative to
+/// the garden root.
+fn clone_reference(config: &model::Configuration, ctx: &model::TreeContext) -> String {
+    let reference_var = &config.trees[ctx.tree].clone_reference;
+    if reference_var.is_empty() {
+        return String::new();
+    }
+    let reference = eval::tree_value(config, reference_var.get_expr(), ctx.tree, ctx.garden);
+    if reference.is_empty() {
+        return reference;
+    }
+
+    config
+        .root_path
+        .join(reference)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Evaluate the "bundle" path for a tree. Relative paths are relative to the garden root.
+fn clone_bundle(config: &model::Configuration, ctx: &model::TreeContext) -> String {
+    let bundle_var = &config.trees[ctx.tree].bundle;
+    if bundle_var.is_empty() {
+        return String::new();
+    }
+    let bundle = eval::tree_value(config, bundle_var.get_expr(), ctx.tree, ctx.garden);
+    if bundle.is_empty() {
+        return bundle;
+    }
+
+    config.root_path.join(bundle).to_string_lossy().to_string()
+}
+
+/// Run the "post-clone" commands once after a tree has been cloned.
+/// The "garden-post-clone" file in the tree's Git directory records whether the
+/// commands are "pending" or "done". Pending commands are retried by the next
+/// "garden grow" when they fail.
+fn post_clone_tree(
+    config: &mut model::Configuration,
+    options: &model::CommandOptions,
+    ctx: &model::TreeContext,
+    is_new: bool,
+) -> i32 {
+    let tree = &config.trees[ctx.tree];
+    let path = match tree.path_as_ref() {
+        Ok(path) if !tree.is_symlink && !tree.post_clone.is_empty() => path,
+        _ => return errors::EX_OK,
+    };
+    let pathbuf = std::path::PathBuf::from(path);
+    if !pathbuf.exists() {
+        return errors::EX_OK;
+    }
+    let state_path = git::git_dir(&pathbuf).join("garden-post-clone");
+    if is_new {
+        if let Err(err) = std::fs::write(&state_path, "pending\n") {
+            eprintln!("error: unable to write {}: {}", state_path.display(), err);
+            return errors::EX_IOERR;
+        }
+    }
+    let is_pending = std::fs::read_to_string(&state_path)
+        .map(|state| state.trim() == "pending")
+        .unwrap_or(false);
+    if !is_pending {
+        return errors::EX_OK;
+    }
+    if let Err(errors::CommandError::ExitStatus { command, status }) =
+        super::cmd::run_post_clone(config, options, ctx)
+    {
+        let name = config.trees[ctx.tree].get_name();
+        options.failures.add(ctx, name, &command, status);
+        return status;
+    }
+    if let Err(err) = std::fs::write(&state_path, "done\n") {
+        eprintln!("error: unable to write {}: {}", state_path.display(), err);
+        return errors::EX_IOERR;
+    }
+
+    errors::EX_OK
+}
+
+/// Fetch the missing history for trees that were cloned using "depth".
+/// Trees that are not shallow are left as-is.
+fn unshallow_tree(
+    config: &model::Configuration,
+    ctx: &model::TreeContext,
+    options: &model::CommandOptions,
+) -> i32 {
+    let tree = &config.trees[ctx.tree];
+    let path = match tree.path_as_ref() {
+        Ok(path) if !tree.is_symlink => path,
+        _ => return errors::EX_OK,
+    };
+    let command = ["git", "rev-parse", "--is-shallow-repository"];
+    let is_shallow = cmd::capture_stdout(cmd::exec_in_dir(&command, path))
+        .map(|capture| cmd::trim_stdout(&capture) == "true")
+        .unwrap_or(false);
+    if !is_shallow {
+        return errors::EX_OK;
+    }
+    let remote = match query::default_remote(config, ctx.tree, ctx.garden) {
+        Some(remote) => remote.get_name().to_string(),
+        None => "origin".to_string(),
+    };
+    let command = ["git", "fetch", "--unshallow", &remote];
+    if options.verbose > 1 {
+        cmd::print_quoted_command(&command);
+    }
+
+    run_git(&command, path, options.log_path(tree.get_name()))
+}
+
+/// Run a git command in the specified directory. Output is appended to the
+/// log file when specified.
+fn run_git(command: &[&str], path: &str, log: Option<std::path::PathBuf>) -> i32 {
+    let env = cmd::environment(&[], &[], model::EnvPassthrough::Preserve);
+    let name = command.join(" ");
+
+    cmd::Command::new(&name, command, path, env).log(log).join()
+}
+
+/// Initialize and update submodules for trees that specify "submodules".
+fn update_submodules(tree: &model::Tree, path: &str, options: &model::CommandOptions) -> i32 {
+    if tree.is_bare_repository {
+        return errors::EX_OK;
+    }
+    let mut command = vec!["git", "submodule", "update", "--init"];
+    match tree.submodules {
+        model::Submodules::None => return errors::EX_OK,
+        model::Submodules::Init => (),
+        model::Submodules::Recursive => command.push("--recursive"),
+    }
+    if options.verbose > 1 {
+        cmd::print_quoted_command(&command);
+    }
+
+    run_git(&command, path, options.log_path(tree.get_name()))
+}
+
+/// Install the Git LFS hooks and download the LFS objects for trees that specify "lfs".
+fn pull_lfs(tree: &model::Tree, path: &str, options: &model::CommandOptions) -> i32 {
+    if !tree.is_lfs || tree.is_bare_repository {
+        return errors::EX_OK;
+    }
+    let commands: [&[&str]; 2] = [
+        &["git", "lfs", "install", "--local"],
+        &["git", "lfs", "pull"],
+    ];
+    for command in commands {
+        if options.verbose > 1 {
+            cmd::print_quoted_command(command);
+        }
+        let status = run_git(command, path, options.log_path(tree.get_name()));
+        if status != errors::EX_OK {
+            return status;
+        }
+    }
+
+    errors::EX_OK
 }
 
-/// Add remotes that do not already exist and synchronize .git/config values.
+/// Add remotes that do not already exist, update remotes whose urls have changed
+/// and synchronize .git/config values. Remotes that are not configured are removed
+/// when "prune_remotes" is true.
 fn update_tree_from_context(
     config: &model::Configuration,
     configured_worktrees: &mut HashSet<String>,
     ctx: &model::TreeContext,
     path: &std::path::Path,
-    _quiet: bool,
+    quiet: bool,
     verbose: u8,
+    prune_remotes: bool,
 ) -> Result<i32> {
     let mut exit_status = errors::EX_OK;
 
@@ -244,57 +798,53 @@ fn update_tree_from_context(
         }
     }
 
-    // Gather existing remotes
-    let mut existing_remotes = std::collections::HashSet::new();
-    {
-        let command = ["git", "remote"];
-        let exec = cmd::exec_in_dir(&command, path);
-        if let Ok(x) = cmd::capture_stdout(exec) {
-            let output = cmd::trim_stdout(&x);
-            for line in output.lines() {
-                existing_remotes.insert(String::from(line));
-            }
-        }
-    }
+    let backend = git::backend();
 
     // Add/update git remote configuration.
+    let existing_remotes = backend.remotes(path).unwrap_or_default();
     for (k, v) in &config_remotes {
-        let url = eval::tree_value(config, v, ctx.tree, ctx.garden);
+        let url = config.rewrite_url(&eval::tree_value(config, v, ctx.tree, ctx.garden));
+        let current_url = backend.config_get(path, &format!("remote.{}.url", k));
+        if current_url.as_deref() == Some(url.as_str()) {
+            continue;
+        }
+        if verbose > 0 && !quiet {
+            if existing_remotes.contains(k) {
+                cmd::print_command_str(&format!("git remote set-url {} {}", k, url));
+            } else {
+                cmd::print_command_str(&format!("git remote add {} {}", k, url));
+            }
+        }
+        if let Err(err) = backend.set_remote_url(path, k, &url) {
+            eprintln!("error: {}", err);
+            exit_status = err.into();
+        }
+    }
 
-        let exec = if existing_remotes.contains(k) {
-            let remote_key = format!("remote.{}.url", k);
-            let command = ["git", "config", remote_key.as_ref(), url.as_ref()];
-            if verbose > 1 {
-                print_command_str(&command.join(" "));
+    // Remove remotes that are no longer configured.
+    if prune_remotes {
+        for remote in &existing_remotes {
+            if config_remotes.contains_key(remote) {
+                continue;
             }
-            cmd::exec_in_dir(&command, path)
-        } else {
-            let command = ["git", "remote", "add", k.as_ref(), url.as_ref()];
-            if verbose > 1 {
-                print_command_str(&command.join(" "));
+            if !quiet {
+                cmd::print_command_str(&format!("git remote remove {}", remote));
+            }
+            if let Err(err) = backend.remove_remote(path, remote) {
+                eprintln!("error: {}", err);
+                exit_status = err.into();
             }
-            cmd::exec_in_dir(&command, path)
-        };
-
-        let status = cmd::status(exec.join());
-        if status != errors::EX_OK {
-            exit_status = status;
         }
     }
 
     // Set gitconfig settings
-    let mut gitconfig = Vec::new();
-    for cfg in &config.trees[ctx.tree].gitconfig {
-        gitconfig.push(cfg.clone());
-    }
-
-    for var in &gitconfig {
-        let value = eval::tree_value(config, var.get_expr(), ctx.tree, ctx.garden);
-        let command = ["git", "config", var.get_name(), value.as_ref()];
-        let exec = cmd::exec_in_dir(&command, path);
-        let status = cmd::status(exec.join());
-        if status != errors::EX_OK {
-            exit_status = status;
+    for (name, value) in eval::gitconfig(config, ctx) {
+        if backend.config_get(path, &name).as_deref() == Some(value.as_str()) {
+            continue;
+        }
+        if let Err(err) = backend.config_set(path, &name, &value) {
+            eprintln!("error: {}", err);
+            exit_status = err.into();
         }
     }
 
@@ -306,9 +856,10 @@ fn update_tree_from_context(
 fn grow_tree_from_context_as_worktree(
     config: &model::Configuration,
     configured_worktrees: &mut HashSet<String>,
+    locks: &lock::Locks,
     ctx: &model::TreeContext,
-    quiet: bool,
-    verbose: u8,
+    options: &model::CommandOptions,
+    grow_options: &GrowOptions,
 ) -> Result<i32> {
     let mut exit_status;
     let tree = &config.trees[ctx.tree];
@@ -323,8 +874,14 @@ fn grow_tree_from_context_as_worktree(
             }
         })?;
 
-    exit_status =
-        grow_tree_from_context(config, configured_worktrees, &parent_ctx, quiet, verbose)?;
+    exit_status = grow_tree_from_context(
+        config,
+        configured_worktrees,
+        locks,
+        &parent_ctx,
+        options,
+        grow_options,
+    )?;
     if exit_status != 0 {
         return Err(errors::GardenError::WorktreeParentCreationError {
             tree: tree.get_name().into(),
@@ -334,11 +891,26 @@ fn grow_tree_from_context_as_worktree(
     }
 
     let tree_path = tree.path_as_ref()?;
-    let parent_path = config.trees[parent_ctx.tree].path_as_ref()?;
+    let parent_tree = &config.trees[parent_ctx.tree];
+    let parent_path = parent_tree.path_as_ref()?;
+
+    // Branches are tracked from the parent's default remote, which is "origin" by convention.
+    let remote_name = query::default_remote(config, parent_ctx.tree, parent_ctx.garden)
+        .map(|remote| remote.get_name().as_str())
+        .unwrap_or("origin");
+    let remote_branch = format!("{}/{}", remote_name, branch);
+    let local_branch_exists =
+        !branch.is_empty() && git::ref_exists(parent_path, &format!("refs/heads/{}", branch));
+    let remote_branch_exists = !branch.is_empty()
+        && git::ref_exists(parent_path, &format!("refs/remotes/{}", remote_branch));
 
     let mut cmd: Vec<&str> = ["git", "worktree", "add"].to_vec();
-    if !branch.is_empty() {
-        cmd.push("--track");
+    // Existing local branches are checked out as-is. Otherwise the branch is created
+    // from the remote branch when it exists, or from the parent's HEAD.
+    if !branch.is_empty() && !local_branch_exists {
+        if remote_branch_exists {
+            cmd.push("--track");
+        }
         cmd.push("-b");
         cmd.push(&branch);
     }
@@ -353,19 +925,16 @@ fn grow_tree_from_context_as_worktree(
         cmd.push(tree_path);
     }
 
-    let remote_branch;
-    if !branch.is_empty() {
-        // TODO: Support tree.<tree>.branches.<branch-name>.upstream
-        // to generalize the remote branch name instead of hard-coding "origin/".
-        remote_branch = format!("origin/{}", branch);
+    if local_branch_exists {
+        cmd.push(&branch);
+    } else if remote_branch_exists {
         cmd.push(&remote_branch);
     }
 
-    if verbose > 1 {
-        print_quoted_command(&cmd);
+    if options.verbose > 1 {
+        cmd::print_quoted_command(&cmd);
     }
-    let exec = cmd::exec_in_dir(&cmd, parent_path);
-    exit_status = cmd::status(exec.join());
+    exit_status = run_git(&cmd, parent_path, options.log_path(tree.get_name()));
 
     if exit_status != 0 {
         return Err(errors::GardenError::WorktreeGitCheckoutError {
@@ -375,11 +944,48 @@ fn grow_tree_from_context_as_worktree(
         .into());
     }
 
-    Ok(exit_status)
+    // New worktrees checkout the pinned "ref" or the commit recorded in the lockfile.
+    let git_ref = eval::tree_value(config, tree.git_ref.get_expr(), ctx.tree, ctx.garden);
+    if !git_ref.is_empty() {
+        if options.verbose > 1 {
+            cmd::print_command_str(&format!("git checkout --detach {}", git_ref));
+        }
+        let backend = git::backend();
+        let path = std::path::Path::new(tree_path);
+        if let Err(err) = backend.checkout_ref(path, remote_name, &git_ref) {
+            eprintln!(
+                "error: {}: unable to checkout {}: {}",
+                tree_path, git_ref, err
+            );
+            return Ok(err.into());
+        }
+    } else if let Some(commit) = locks.get(tree.get_name()) {
+        if options.verbose > 1 {
+            cmd::print_command_str(&format!("git checkout --detach {}", commit));
+        }
+        let backend = git::backend();
+        let path = std::path::Path::new(tree_path);
+        if let Err(err) = backend.checkout_commit(path, remote_name, commit) {
+            eprintln!("error: {}", err);
+            return Ok(err.into());
+        }
+    }
+
+    let status = update_submodules(tree, tree_path, options);
+    if status != errors::EX_OK {
+        return Ok(status);
+    }
+
+    Ok(pull_lfs(tree, tree_path, options))
 }
 
 /// Initialize a tree symlink entry.
-fn grow_symlink(config: &model::Configuration, ctx: &model::TreeContext) -> Result<i32> {
+/// Existing symlinks that are broken or point to the wrong target are reported.
+fn grow_symlink(
+    config: &model::Configuration,
+    ctx: &model::TreeContext,
+    quiet: bool,
+) -> Result<i32> {
     let tree = &config.trees[ctx.tree];
     // Invalid usage: non-symlink
     if !tree.is_symlink || tree.path_as_ref()?.is_empty() || tree.symlink_as_ref()?.is_empty() {
@@ -389,35 +995,89 @@ fn grow_symlink(config: &model::Configuration, ctx: &model::TreeContext) -> Resu
         ))
         .into());
     }
-    let path_str = tree.path_as_ref()?;
-    let path = std::path::PathBuf::from(&path_str);
+    let path = std::path::PathBuf::from(tree.path_as_ref()?);
 
-    // Leave existing symlinks as-is.
-    if std::fs::read_link(&path).is_ok() || path.exists() {
-        return Ok(errors::EX_OK);
+    match tree.symlink_status() {
+        model::SymlinkStatus::Missing => (),
+        model::SymlinkStatus::Ok => return Ok(errors::EX_OK),
+        // Files and directories are never removed, even by "--rebuild-symlinks".
+        model::SymlinkStatus::NotSymlink => {
+            if !quiet {
+                eprintln!(
+                    "{} {}: {} is not a symlink: remove it to create the symlink",
+                    model::Color::yellow("warning:").bold(),
+                    tree.get_name(),
+                    path.display()
+                );
+            }
+            return Ok(errors::EX_OK);
+        }
+        status => {
+            // Leave unhealthy symlinks as-is unless "--rebuild-symlinks" removed them.
+            if !quiet {
+                eprintln!(
+                    "{} {}: {} symlink at {}: use \"garden grow --rebuild-symlinks\" to repair it",
+                    model::Color::yellow("warning:").bold(),
+                    tree.get_name(),
+                    status.as_str(),
+                    path.display()
+                );
+            }
+            return Ok(errors::EX_OK);
+        }
     }
 
-    let symlink_str = tree.symlink_as_ref()?;
-    let symlink = std::path::PathBuf::from(&symlink_str);
+    let target = tree.symlink_target()?;
+    create_symlink(&target, &path)?;
+
+    Ok(errors::EX_OK)
+}
 
-    // Note: parent directory was already created by the caller.
-    let parent = path
-        .parent()
-        .as_ref()
-        .ok_or_else(|| errors::GardenError::AssertionError(format!("parent() failed: {:?}", path)))?
-        .to_path_buf();
+/// Create a symlink at "path" that points to "target".
+#[cfg(unix)]
+fn create_symlink(target: &std::path::Path, path: &std::path::Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
 
-    // Is the link target a child of the link's parent directory?
-    let target = if symlink.starts_with(&parent) && symlink.strip_prefix(&parent).is_ok() {
-        // If so, create the symlink using a relative path.
-        symlink.strip_prefix(&parent)?.to_string_lossy()
+/// Create a symlink at "path" that points to "target". Windows distinguishes
+/// between directory and file symlinks so the target is inspected first.
+#[cfg(windows)]
+fn create_symlink(target: &std::path::Path, path: &std::path::Path) -> std::io::Result<()> {
+    // Relative targets are relative to the symlink's parent directory.
+    let resolved = match path.parent() {
+        Some(parent) => parent.join(target),
+        None => target.to_path_buf(),
+    };
+    if resolved.is_dir() {
+        std::os::windows::fs::symlink_dir(target, path)
     } else {
-        // Use an absolute path otherwise.
-        symlink.to_string_lossy()
+        std::os::windows::fs::symlink_file(target, path)
     }
-    .to_string();
+}
 
-    std::os::unix::fs::symlink(&target, &path)?;
+/// Remove a symlink tree's symlink when it is broken or points to the wrong target
+/// so that it can be recreated. Files and directories are never removed.
+/// Returns a non-zero exit status when the symlink cannot be removed.
+fn remove_stale_symlink(tree: &model::Tree, verbose: u8) -> i32 {
+    if !tree.is_symlink {
+        return errors::EX_OK;
+    }
+    let path = match (tree.symlink_status(), tree.path_as_ref()) {
+        (model::SymlinkStatus::Broken | model::SymlinkStatus::WrongTarget(_), Ok(path)) => path,
+        _ => return errors::EX_OK,
+    };
+    if verbose > 0 {
+        cmd::print_command_str(&format!("rm {}", path));
+    }
+    if let Err(err) = std::fs::remove_file(path) {
+        eprintln!(
+            "error: {}: unable to remove {}: {}",
+            tree.get_name(),
+            path,
+            err
+        );
+        return errors::EX_IOERR;
+    }
 
-    Ok(errors::EX_OK)
+    errors::EX_OK
 }
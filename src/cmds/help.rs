@@ -1,44 +1,48 @@
 use anyhow::Result;
+use clap::{CommandFactory, Parser};
 
-use super::super::cmd;
+use super::super::cli;
+use super::super::errors;
 use super::super::model;
+use super::cmd;
 
-/// Entry point for `garden help`
-/// Parameters:
-/// - options: `garden::model::CommandOptions`
-
-pub fn main(options: &mut model::CommandOptions) -> Result<()> {
-    let cmd_path = cmd::current_exe();
-    let mut help_cmd = vec![cmd_path];
-
-    let mut cmd_name = String::new();
-    {
-        let mut ap = argparse::ArgumentParser::new();
-        ap.set_description("garden help - Display command documentation");
-
-        ap.refer(&mut cmd_name).add_argument(
-            "command",
-            argparse::Store,
-            "{add, cmd, eval, exec, ls, shell}",
-        );
+/// Print help for garden commands and custom commands
+#[derive(Parser, Clone, Debug)]
+pub struct HelpOptions {
+    /// Command to display help for
+    pub command: Vec<String>,
+}
 
-        options.args.insert(0, "garden help".into());
-        cmd::parse_args(ap, options.args.to_vec());
+/// Return true when help is requested for a builtin command.
+/// Custom commands require a configuration to display their help.
+pub fn is_builtin(options: &HelpOptions) -> bool {
+    match options.command.first() {
+        Some(name) => cli::MainOptions::command().find_subcommand(name).is_some(),
+        None => true,
     }
+}
 
-    // garden help foo -> garden foo --help
-    if !cmd_name.is_empty() {
-        help_cmd.push(cmd_name);
-    }
+/// Main entry point for "garden help" with builtin commands
+pub fn main(options: &HelpOptions) -> Result<()> {
+    let args = std::iter::once("garden".to_string())
+        .chain(options.command.iter().cloned())
+        .chain(std::iter::once("--help".to_string()));
+    // Clap prints the help text and exits.
+    cli::MainOptions::command().get_matches_from(args);
 
-    help_cmd.push("--help".into());
+    Ok(())
+}
 
-    if options.verbose > 0 {
-        debug!("help command");
-        for (i, arg) in help_cmd.iter().enumerate() {
-            debug!("help_cmd[{:02}] = {:?}", i, arg);
-        }
+/// Entry point for "garden help <custom-command>"
+pub fn custom(app: &mut model::ApplicationContext, options: &HelpOptions) -> Result<()> {
+    let name = options
+        .command
+        .first()
+        .map(String::as_str)
+        .unwrap_or_default();
+    if cmd::find_commands(app.get_root_config(), name).is_empty() {
+        return Err(errors::GardenError::Usage(format!("{}: command not found", name)).into());
     }
 
-    cmd::run(&help_cmd).map_err(|err| err.into())
+    cmd::custom(app, &[name.to_string(), "--help".to_string()])
 }
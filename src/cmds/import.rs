@@ -0,0 +1,285 @@
+use anyhow::Result;
+use clap::Parser;
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::yaml::Yaml;
+use yaml_rust::YamlLoader;
+
+use super::super::config;
+use super::super::errors;
+use super::super::model;
+use super::super::path;
+
+/// Add trees from vcstool or myrepos files to a garden file
+#[derive(Parser, Clone, Debug)]
+pub struct ImportOptions {
+    /// Set the input format {vcs, mr}
+    #[arg(short, long, default_value = "vcs", value_name = "format")]
+    pub format: model::ReposFormat,
+    /// File to write (default: garden.yaml)
+    #[arg(short, long, value_name = "file")]
+    pub output: Option<String>,
+    /// The ".repos" or ".mrconfig" file to import
+    pub filename: String,
+}
+
+/// A repository read from a vcstool or myrepos file.
+struct ImportedTree {
+    path: String,
+    url: String,
+    branch: String,
+}
+
+/// Main entry point for the "garden import" command
+pub fn main(app: &mut model::ApplicationContext, options: &ImportOptions) -> Result<()> {
+    let verbose = app.options.verbose;
+    let config = app.get_root_config();
+    let mut doc = config::reader::read_yaml(config.get_path()?)?;
+
+    // Output filename defaults to the input filename.
+    let output = match &options.output {
+        Some(output) => output.to_string(),
+        None => config.get_path()?.to_string_lossy().into(),
+    };
+
+    let content = std::fs::read_to_string(&options.filename)
+        .map_err(|err| errors::GardenError::OSError(format!("{}: {}", options.filename, err)))?;
+    let imported = match options.format {
+        model::ReposFormat::Vcs => parse_vcs(&content, verbose)?,
+        model::ReposFormat::Mr => {
+            // myrepos sections are relative to the directory containing the file.
+            let filename = std::path::PathBuf::from(&options.filename);
+            let dirname = path::abspath(&filename)
+                .parent()
+                .map(|parent| parent.to_path_buf())
+                .unwrap_or_else(|| config.root_path.clone());
+            let root = path::abspath(&config.root_path);
+            parse_mr(&content, verbose)
+                .into_iter()
+                .map(|mut tree| {
+                    let tree_path = path::normpath(&dirname.join(&tree.path));
+                    tree.path = match tree_path.strip_prefix(&root) {
+                        Ok(relative) => relative.to_string_lossy().to_string(),
+                        Err(_) => tree_path.to_string_lossy().to_string(),
+                    };
+                    tree
+                })
+                .collect()
+        }
+    };
+
+    // Mutable YAML scope.
+    {
+        let doc_hash: &mut YamlHash = match doc {
+            Yaml::Hash(ref mut hash) => hash,
+            _ => {
+                return Err(errors::GardenError::InvalidConfiguration {
+                    msg: "not a hash".into(),
+                }
+                .into());
+            }
+        };
+
+        let key = Yaml::String("trees".into());
+        if !doc_hash.contains_key(&key) {
+            doc_hash.insert(key.clone(), Yaml::Hash(YamlHash::new()));
+        }
+        let trees: &mut YamlHash = match doc_hash.get_mut(&key) {
+            Some(Yaml::Hash(ref mut hash)) => hash,
+            _ => {
+                return Err(errors::GardenError::InvalidConfiguration {
+                    msg: "trees: not a hash".into(),
+                }
+                .into());
+            }
+        };
+
+        for tree in &imported {
+            import_tree(tree, trees, verbose);
+        }
+    }
+
+    Ok(config::writer::write_yaml(&doc, &output)?)
+}
+
+/// Add or update a tree entry. Existing entries retain their other settings.
+fn import_tree(tree: &ImportedTree, trees: &mut YamlHash, verbose: u8) {
+    // Absolute paths are recorded using the directory name and an explicit "path".
+    let is_absolute = std::path::Path::new(&tree.path).is_absolute();
+    let name = if is_absolute {
+        std::path::Path::new(&tree.path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| tree.path.clone())
+    } else {
+        tree.path.clone()
+    };
+    let key = Yaml::String(name.clone());
+
+    let mut entry = match trees.get(&key) {
+        Some(Yaml::Hash(hash)) => {
+            if verbose > 0 {
+                eprintln!("{}: found existing tree", name);
+            }
+            hash.clone()
+        }
+        _ => YamlHash::new(),
+    };
+    entry.insert(Yaml::String("url".into()), Yaml::String(tree.url.clone()));
+    if !tree.branch.is_empty() {
+        entry.insert(
+            Yaml::String("branch".into()),
+            Yaml::String(tree.branch.clone()),
+        );
+    }
+    if is_absolute {
+        entry.insert(Yaml::String("path".into()), Yaml::String(tree.path.clone()));
+    }
+
+    if let Some(tree_entry) = trees.get_mut(&key) {
+        *tree_entry = Yaml::Hash(entry);
+    } else {
+        trees.insert(key, Yaml::Hash(entry));
+    }
+}
+
+/// Read repositories from a vcstool ".repos" file.
+fn parse_vcs(content: &str, verbose: u8) -> Result<Vec<ImportedTree>, errors::GardenError> {
+    let docs = YamlLoader::load_from_str(content).map_err(|err| {
+        errors::GardenError::InvalidConfiguration {
+            msg: format!("unable to read repositories: {}", err),
+        }
+    })?;
+    let mut result = Vec::new();
+    let repositories = match docs.first().map(|doc| &doc["repositories"]) {
+        Some(Yaml::Hash(hash)) => hash,
+        _ => {
+            return Err(errors::GardenError::InvalidConfiguration {
+                msg: "repositories: not a hash".into(),
+            });
+        }
+    };
+    for (name, value) in repositories {
+        let path = match name.as_str() {
+            Some(path) => path.to_string(),
+            None => continue,
+        };
+        let repo_type = value["type"].as_str().unwrap_or("git");
+        let url = value["url"].as_str().unwrap_or_default();
+        if repo_type != "git" || url.is_empty() {
+            if verbose > 0 {
+                eprintln!("{}: skipped {} repository", path, repo_type);
+            }
+            continue;
+        }
+        let branch = match &value["version"] {
+            Yaml::String(version) => version.to_string(),
+            Yaml::Integer(version) => version.to_string(),
+            Yaml::Real(version) => version.to_string(),
+            _ => String::new(),
+        };
+        result.push(ImportedTree {
+            path,
+            url: url.to_string(),
+            branch,
+        });
+    }
+
+    Ok(result)
+}
+
+/// Read repositories from a myrepos ".mrconfig" file.
+/// Only sections with a "git clone" checkout command are imported.
+fn parse_mr(content: &str, verbose: u8) -> Vec<ImportedTree> {
+    let mut result = Vec::new();
+    let mut section: Option<String> = None;
+    let mut checkout: Option<String> = None;
+    let mut finish_section = |section: Option<String>, checkout: Option<String>| {
+        let (path, checkout) = match (section, checkout) {
+            (Some(path), Some(checkout)) => (path, checkout),
+            (Some(path), None) => {
+                if verbose > 0 {
+                    eprintln!("{}: skipped, no checkout command", path);
+                }
+                return;
+            }
+            _ => return,
+        };
+        match parse_git_clone(&checkout) {
+            Some((url, branch)) => result.push(ImportedTree { path, url, branch }),
+            None => {
+                if verbose > 0 {
+                    eprintln!("{}: skipped, unsupported checkout command", path);
+                }
+            }
+        }
+    };
+
+    let mut in_checkout = false;
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            continue;
+        }
+        // Lines that start with whitespace continue the previous value.
+        if line.starts_with(char::is_whitespace) {
+            if in_checkout {
+                if let Some(checkout) = checkout.as_mut() {
+                    checkout.push(' ');
+                    checkout.push_str(trimmed);
+                }
+            }
+            continue;
+        }
+        if let Some(name) = trimmed
+            .strip_prefix('[')
+            .and_then(|value| value.strip_suffix(']'))
+        {
+            finish_section(section.take(), checkout.take());
+            in_checkout = false;
+            // The DEFAULT section configures myrepos itself.
+            if name != "DEFAULT" {
+                section = Some(name.trim().to_string());
+            }
+            continue;
+        }
+        in_checkout = false;
+        if let Some((key, value)) = trimmed.split_once('=') {
+            if key.trim() == "checkout" {
+                checkout = Some(value.trim().to_string());
+                in_checkout = true;
+            }
+        }
+    }
+    finish_section(section, checkout);
+
+    result
+}
+
+/// Extract the url and branch from a "git clone" command.
+fn parse_git_clone(command: &str) -> Option<(String, String)> {
+    let args = shlex::split(command)?;
+    let start = args
+        .windows(2)
+        .position(|window| window[0] == "git" && window[1] == "clone")?;
+    let mut branch = String::new();
+    let mut positional = Vec::new();
+    let mut args = args[start + 2..].iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-b" | "--branch" => branch = args.next()?.to_string(),
+            "-o" | "--origin" | "--depth" | "--reference" | "-c" | "--config" => {
+                args.next();
+            }
+            _ if arg.starts_with("--branch=") => {
+                branch = arg["--branch=".len()..].to_string();
+            }
+            _ if arg.starts_with('-') => (),
+            // Commands may be chained, e.g. "git clone <url> <dir> && cd <dir>".
+            "&&" | ";" | "||" => break,
+            _ => positional.push(arg.to_string()),
+        }
+    }
+    let url = positional.first()?.to_string();
+
+    Some((url, branch))
+}
@@ -1,69 +1,42 @@
 use anyhow::Result;
+use clap::Parser;
 use yaml_rust::yaml::Hash as YamlHash;
 use yaml_rust::yaml::Yaml;
 
-use super::super::cmd;
 use super::super::config;
 use super::super::errors;
 use super::super::model;
 use super::super::path;
 
-struct InitOptions {
+/// Create an empty garden.yaml
+#[derive(Parser, Clone, Debug)]
+pub struct InitOptions {
+    #[arg(skip)]
     pub dirname: std::path::PathBuf,
-    pub filename: String,
-    pub force: bool,
+    /// Use the user-wide configuration directory (~/.config/garden/garden.yaml)
+    #[arg(long)]
     pub global: bool,
+    /// Overwrite existing config files
+    #[arg(short, long)]
+    pub force: bool,
+    /// Set the garden root path
+    #[arg(
+        short,
+        long,
+        default_value = "${GARDEN_CONFIG_DIR}",
+        value_name = "path"
+    )]
     pub root: String,
+    /// Config file to write
+    #[arg(default_value = "garden.yaml")]
+    pub filename: String,
 }
 
-impl std::default::Default for InitOptions {
-    fn default() -> Self {
-        InitOptions {
-            dirname: path::current_dir(),
-            filename: "garden.yaml".to_string(),
-            force: false,
-            global: false,
-            root: "${GARDEN_CONFIG_DIR}".to_string(),
-        }
-    }
-}
-
-pub fn main(options: &mut model::CommandOptions) -> Result<()> {
-    let mut init_options = InitOptions::default();
-    {
-        let mut ap = argparse::ArgumentParser::new();
-        ap.set_description("garden init - Create an empty garden.yaml");
-
-        ap.refer(&mut init_options.global).add_option(
-            &["--global"],
-            argparse::StoreTrue,
-            "Use the user-wide configuration directory (~/.config/garden/garden.yaml)",
-        );
-
-        ap.refer(&mut init_options.force).add_option(
-            &["-f", "--force"],
-            argparse::StoreTrue,
-            "Overwrite existing config files",
-        );
-
-        ap.refer(&mut init_options.root)
-            .metavar("<path>")
-            .add_option(
-                &["-r", "--root"],
-                argparse::Store,
-                "Set the garden root path (default: ${GARDEN_CONFIG_DIR})",
-            );
-
-        ap.refer(&mut init_options.filename).add_argument(
-            "filename",
-            argparse::Store,
-            "Config file to write (default: garden.yaml)",
-        );
-
-        options.args.insert(0, "garden init".into());
-        cmd::parse_args(ap, options.args.to_vec());
-    }
-
+/// Main entry point for the "garden init" command
+pub fn main(options: &model::CommandOptions, init_options: &InitOptions) -> Result<()> {
+    let mut init_options = init_options.clone();
+    // The current directory is read after "garden --chdir" has been processed.
+    init_options.dirname = path::current_dir();
     init(options, &mut init_options)
 }
 
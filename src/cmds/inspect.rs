@@ -1,43 +1,170 @@
 use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::yaml::Yaml;
 
-use super::super::cmd;
+use super::super::config;
+use super::super::eval;
 use super::super::model;
 use super::super::model::Color;
 use super::super::query;
+use super::completion;
 
-/// Main entry point for the "garden exec" command
-/// Parameters:
-/// - options: `garden::model::CommandOptions`
-
-pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
-    let mut query: Vec<String> = Vec::new();
-    parse_args(&mut app.options, &mut query);
+/// Query tree status
+#[derive(Parser, Clone, Debug)]
+pub struct InspectOptions {
+    /// Set the output format {text, json, yaml}
+    #[arg(short, long, default_value = "text", value_name = "format")]
+    pub format: model::InspectFormat,
+    /// Tree queries for the gardens, groups or trees to inspect
+    #[arg(add = ArgValueCandidates::new(completion::queries), default_value = ".")]
+    pub queries: Vec<String>,
+}
 
+/// Main entry point for the "garden inspect" command
+pub fn main(app: &mut model::ApplicationContext, options: &InspectOptions) -> Result<()> {
+    if app.options.debug_level("inspect") > 0 {
+        debug!("queries: {:?}", options.queries);
+    }
     let verbose = app.options.verbose;
-    let config = app.get_root_config_mut();
-    inspect(config, verbose, &query)
+    match options.format {
+        model::InspectFormat::Text => {
+            let config = app.get_root_config_mut();
+            inspect(config, verbose, &options.queries)
+        }
+        model::InspectFormat::Json => {
+            let doc = resolve_trees(app, &options.queries)?;
+            println!("{}", serde_json::to_string_pretty(&doc)?);
+            Ok(())
+        }
+        model::InspectFormat::Yaml => {
+            let doc = resolve_trees(app, &options.queries)?;
+            print!("{}", config::writer::emit_yaml(&json_to_yaml(&doc)));
+            Ok(())
+        }
+    }
+}
+
+/// Return a document containing the fully resolved configuration for the trees
+/// matched by the tree queries. Paths, variables, environments and commands
+/// are evaluated in the context of each tree.
+fn resolve_trees(
+    app: &mut model::ApplicationContext,
+    queries: &[String],
+) -> Result<serde_json::Value> {
+    let mut trees = Vec::new();
+    for query in queries {
+        let contexts = query::resolve_trees(app.get_root_config(), query);
+        for context in &contexts {
+            app.enter_context(context);
+            trees.push(resolve_tree(app, context)?);
+            // Reset variables so that the next tree is evaluated in its own scope.
+            app.get_context_config_mut(context).reset();
+        }
+    }
+
+    Ok(serde_json::json!({ "trees": trees }))
 }
 
-/// Parse "inspect" arguments.
-fn parse_args(options: &mut model::CommandOptions, query: &mut Vec<String>) {
-    let mut ap = argparse::ArgumentParser::new();
-    ap.set_description("garden inspect - Query tree status");
+/// Evaluate a single tree context.
+fn resolve_tree(
+    app: &model::ApplicationContext,
+    context: &model::TreeContext,
+) -> Result<serde_json::Value> {
+    let config = app.get_context_config(context);
+    let tree = &config.trees[context.tree];
+    let garden = context.garden.map(|garden_idx| &config.gardens[garden_idx]);
+    let evaluate = |expr: &str| eval::tree_value(config, expr, context.tree, context.garden);
+    let path = tree.path_as_ref()?;
 
-    ap.refer(query).add_argument(
-        "query",
-        argparse::List,
-        "Tree queries for the gardens, groups or trees to inspect",
-    );
+    let mut remotes = Vec::new();
+    for remote in &tree.remotes {
+        remotes.push(serde_json::json!({
+            "name": remote.get_name(),
+            "url": evaluate(remote.get_expr()),
+        }));
+    }
 
-    options.args.insert(0, "garden inspect".into());
-    cmd::parse_args(ap, options.args.to_vec());
+    // Garden variables override tree variables, which override global variables.
+    let mut variables = serde_json::Map::new();
+    let mut scopes = Vec::new();
+    if let Some(garden) = garden {
+        scopes.push(&garden.variables);
+    }
+    scopes.push(&tree.variables);
+    scopes.push(&config.variables);
+    for var in scopes.into_iter().flatten() {
+        if !variables.contains_key(var.get_name()) {
+            variables.insert(
+                var.get_name().to_string(),
+                serde_json::Value::String(evaluate(var.get_expr())),
+            );
+        }
+    }
 
-    if query.is_empty() {
-        query.push(".".into());
+    let mut environment = serde_json::Map::new();
+    for (name, value) in eval::environment(config, context) {
+        environment.insert(name, serde_json::Value::String(value));
+    }
+
+    let mut command_names: Vec<&String> = Vec::new();
+    let mut command_scopes = vec![&config.commands, &tree.commands];
+    if let Some(garden) = garden {
+        command_scopes.push(&garden.commands);
+    }
+    for var in command_scopes.into_iter().flatten() {
+        if !command_names.contains(&var.get_name()) {
+            command_names.push(var.get_name());
+        }
+    }
+    let mut commands = serde_json::Map::new();
+    for name in command_names {
+        let cmds: Vec<String> = eval::command(app, context, name)
+            .into_iter()
+            .flatten()
+            .collect();
+        commands.insert(name.to_string(), serde_json::json!(cmds));
     }
 
-    if options.debug_level("inspect") > 0 {
-        debug!("query: {:?}", query);
+    let symlink = if tree.is_symlink {
+        Some(tree.symlink_as_ref()?.to_string())
+    } else {
+        None
+    };
+
+    Ok(serde_json::json!({
+        "name": tree.get_name(),
+        "garden": garden.map(|garden| garden.get_name()),
+        "path": path,
+        "exists": std::path::PathBuf::from(path).exists(),
+        "symlink": symlink,
+        "branch": evaluate(tree.branch.get_expr()),
+        "remotes": remotes,
+        "variables": variables,
+        "environment": environment,
+        "commands": commands,
+    }))
+}
+
+/// Convert a JSON document into YAML.
+fn json_to_yaml(value: &serde_json::Value) -> Yaml {
+    match value {
+        serde_json::Value::Null => Yaml::Null,
+        serde_json::Value::Bool(value) => Yaml::Boolean(*value),
+        serde_json::Value::Number(value) => match value.as_i64() {
+            Some(integer) => Yaml::Integer(integer),
+            None => Yaml::Real(value.to_string()),
+        },
+        serde_json::Value::String(value) => Yaml::String(value.clone()),
+        serde_json::Value::Array(values) => Yaml::Array(values.iter().map(json_to_yaml).collect()),
+        serde_json::Value::Object(map) => {
+            let mut hash = YamlHash::new();
+            for (key, value) in map {
+                hash.insert(Yaml::String(key.clone()), json_to_yaml(value));
+            }
+            Yaml::Hash(hash)
+        }
     }
 }
 
@@ -1,36 +1,677 @@
 use anyhow::Result;
+use clap::Parser;
+use rayon::prelude::*;
+use std::io::Write;
 
+use super::super::eval;
+use super::super::git;
 use super::super::model;
+use super::super::path;
+use super::super::query;
 
-pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
+/// List gardens, groups and trees
+#[derive(Parser, Clone, Debug)]
+pub struct ListOptions {
+    /// Set the output format {text, json}
+    #[arg(short, long, default_value = "text", value_name = "format")]
+    format: model::OutputFormat,
+    /// List the custom commands defined globally and in each garden and tree
+    #[arg(long)]
+    commands: bool,
+    /// List the Git worktrees attached to each parent tree
+    #[arg(long, conflicts_with = "commands")]
+    worktrees: bool,
+    /// Print the path of each tree only, one path per line
+    #[arg(long, visible_alias = "paths-only", conflicts_with_all = ["commands", "format", "worktrees"])]
+    paths: bool,
+    /// Print the tree paths separated by NUL characters instead of newlines. Implies --paths
+    #[arg(short = 'z', long, conflicts_with_all = ["commands", "format", "worktrees"])]
+    null: bool,
+    /// Report uncommitted changes and upstream commits in the status column
+    #[arg(short, long, conflicts_with_all = ["commands", "format", "worktrees"])]
+    status: bool,
+}
+
+/// Main entry point for the "garden ls" command
+pub fn main(app: &mut model::ApplicationContext, options: &ListOptions) -> Result<()> {
     let config = app.get_root_config_mut();
+    if options.paths || options.null {
+        return list_paths(config, options.null);
+    }
+    if options.worktrees {
+        return match &options.format {
+            model::OutputFormat::Json => list_worktrees_json(config),
+            model::OutputFormat::Text => list_worktrees(config),
+        };
+    }
+    match (&options.format, options.commands) {
+        (model::OutputFormat::Json, true) => list_commands_json(config),
+        (model::OutputFormat::Text, true) => list_commands(config),
+        (model::OutputFormat::Json, false) => list_json(config),
+        (model::OutputFormat::Text, false) => list(config, options.status),
+    }
+}
+
+/// Return the (scope, scope name, commands) for each scope that defines commands.
+fn command_scopes(config: &model::Configuration) -> Vec<(&str, &str, &[model::MultiVariable])> {
+    let mut scopes = Vec::new();
+    if !config.commands.is_empty() {
+        scopes.push(("commands", "", config.commands.as_slice()));
+    }
+    for garden in &config.gardens {
+        if !garden.commands.is_empty() {
+            scopes.push((
+                "garden",
+                garden.get_name().as_str(),
+                garden.commands.as_slice(),
+            ));
+        }
+    }
+    for tree in &config.trees {
+        if !tree.commands.is_empty() {
+            scopes.push(("tree", tree.get_name().as_str(), tree.commands.as_slice()));
+        }
+    }
+
+    scopes
+}
+
+/// Print the custom commands grouped by the scope that defines them.
+/// The first line of each command's "help" text is displayed alongside its name.
+fn list_commands(config: &model::Configuration) -> Result<()> {
+    for (scope, name, commands) in command_scopes(config) {
+        if name.is_empty() {
+            println!("{}:", scope);
+        } else {
+            println!("{} {}:", scope, name);
+        }
+        let width = commands
+            .iter()
+            .map(|command| command.get_name().len())
+            .max()
+            .unwrap_or_default();
+        for command in commands {
+            let help = command.get_help().lines().next().unwrap_or_default();
+            if help.is_empty() {
+                println!("    {}", command.get_name());
+            } else {
+                println!("    {:width$}  {}", command.get_name(), help, width = width);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the custom commands and their scopes as a JSON document.
+fn list_commands_json(config: &model::Configuration) -> Result<()> {
+    let mut commands = Vec::new();
+    for (scope, name, scope_commands) in command_scopes(config) {
+        let scope = if name.is_empty() { "config" } else { scope };
+        for command in scope_commands {
+            let mut entry = serde_json::json!({
+                "name": command.get_name(),
+                "scope": scope,
+                "help": command.get_help(),
+            });
+            if !name.is_empty() {
+                entry[scope] = serde_json::json!(name);
+            }
+            commands.push(entry);
+        }
+    }
+    let doc = serde_json::json!({ "commands": commands });
+    println!("{}", serde_json::to_string_pretty(&doc)?);
+
+    Ok(())
+}
+
+/// The worktree details for a tree that has been grown.
+struct WorktreeInfo {
+    name: String,
+    path: String,
+    branch: String,
+    /// Commits ahead and behind of the upstream branch.
+    upstream: Option<(usize, usize)>,
+}
+
+/// A parent repository and the child worktrees that are attached to it.
+/// The parent is None when the parent repository is not a tree in the configuration.
+struct WorktreeGroup {
+    path: String,
+    parent: Option<WorktreeInfo>,
+    children: Vec<WorktreeInfo>,
+}
+
+/// Group the trees that have been grown into parent trees and their child worktrees.
+/// Trees that are not involved in "git worktree" relationships are omitted.
+fn worktree_groups(config: &model::Configuration) -> Vec<WorktreeGroup> {
+    let mut groups: Vec<WorktreeGroup> = Vec::new();
+    let mut children: Vec<(String, WorktreeInfo)> = Vec::new();
+    for tree in &config.trees {
+        let tree_path = match tree.path_as_ref() {
+            Ok(tree_path) if !tree.is_symlink => std::path::PathBuf::from(tree_path),
+            _ => continue,
+        };
+        if !tree_path.exists() {
+            continue;
+        }
+        let details = match git::worktree_details(&tree_path) {
+            Ok(details) => details,
+            Err(_) => continue,
+        };
+        let info = WorktreeInfo {
+            name: tree.get_name().to_string(),
+            path: path::abspath(&tree_path).to_string_lossy().to_string(),
+            upstream: git::ahead_behind(&tree_path),
+            branch: details.branch,
+        };
+        match details.tree_type {
+            model::GitTreeType::Parent => groups.push(WorktreeGroup {
+                path: info.path.clone(),
+                parent: Some(info),
+                children: Vec::new(),
+            }),
+            model::GitTreeType::Worktree(parent_path) => children.push((parent_path, info)),
+            _ => (),
+        }
+    }
+    for (parent_path, info) in children {
+        match groups.iter_mut().find(|group| group.path == parent_path) {
+            Some(group) => group.children.push(info),
+            None => groups.push(WorktreeGroup {
+                path: parent_path,
+                parent: None,
+                children: vec![info],
+            }),
+        }
+    }
+
+    groups
+}
+
+/// Describe the state of a worktree's branch relative to its upstream branch.
+fn upstream_state(upstream: Option<(usize, usize)>) -> String {
+    match upstream {
+        Some((0, 0)) => "up to date".to_string(),
+        Some((ahead, behind)) => format!("ahead {}, behind {}", ahead, behind),
+        None => "no upstream".to_string(),
+    }
+}
+
+/// Print parent trees and their child worktrees along with their branches.
+fn list_worktrees(config: &model::Configuration) -> Result<()> {
+    for group in worktree_groups(config) {
+        match &group.parent {
+            Some(parent) => println!(
+                "{} [{}]",
+                model::Color::blue(&parent.name).bold(),
+                model::Color::green(&parent.branch)
+            ),
+            None => println!("{}", model::Color::blue(&group.path).bold()),
+        }
+        let width = group
+            .children
+            .iter()
+            .map(|child| child.name.len() + child.branch.len())
+            .max()
+            .unwrap_or_default();
+        for child in &group.children {
+            let padding = width - child.name.len() - child.branch.len();
+            println!(
+                "    {} [{}]{:padding$}  {}",
+                model::Color::blue(&child.name),
+                model::Color::green(&child.branch),
+                "",
+                upstream_state(child.upstream),
+                padding = padding
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Print parent trees and their child worktrees as a JSON document.
+fn list_worktrees_json(config: &model::Configuration) -> Result<()> {
+    let to_json = |info: &WorktreeInfo| {
+        serde_json::json!({
+            "name": info.name,
+            "path": info.path,
+            "branch": info.branch,
+            "ahead": info.upstream.map(|(ahead, _)| ahead),
+            "behind": info.upstream.map(|(_, behind)| behind),
+        })
+    };
+    let mut worktrees = Vec::new();
+    for group in worktree_groups(config) {
+        worktrees.push(serde_json::json!({
+            "path": group.path,
+            "parent": group.parent.as_ref().map(to_json),
+            "worktrees": group.children.iter().map(to_json).collect::<Vec<_>>(),
+        }));
+    }
+    let doc = serde_json::json!({ "worktrees": worktrees });
+    println!("{}", serde_json::to_string_pretty(&doc)?);
+
+    Ok(())
+}
+
+/// Print the path of each tree. Paths are terminated by NUL characters when "null"
+/// is true so that paths containing whitespace can be read using "xargs -0".
+fn list_paths(config: &model::Configuration, null: bool) -> Result<()> {
+    let terminator = if null { '\0' } else { '\n' };
+    let mut stdout = std::io::stdout().lock();
+    for tree in &config.trees {
+        if let Ok(path) = tree.path_as_ref() {
+            write!(stdout, "{}{}", path, terminator)?;
+        }
+    }
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// A status glyph and the function used to color it.
+type Glyph = (String, fn(String) -> model::Color<String>);
+
+/// The state of a tree as displayed in the status column of "garden ls".
+enum TreeStatus {
+    /// The tree's path is invalid.
+    Invalid,
+    /// The tree has not been grown.
+    Missing,
+    /// The tree is a symlink tree.
+    Symlink,
+    /// The tree exists but is not a Git repository.
+    Unknown,
+    /// The tree is a Git repository whose status was not inspected.
+    Grown,
+    /// The tree is a Git repository. Ahead and behind counts are relative to
+    /// the upstream branch.
+    Repository {
+        dirty: bool,
+        upstream: Option<(usize, usize)>,
+    },
+}
 
+impl TreeStatus {
+    /// Return the glyphs that summarize the status and the colors to display them with.
+    fn glyphs(&self) -> Vec<Glyph> {
+        match self {
+            TreeStatus::Invalid => vec![("!".to_string(), model::Color::red)],
+            TreeStatus::Missing => vec![("-".to_string(), model::Color::red)],
+            TreeStatus::Symlink => vec![("@".to_string(), model::Color::cyan)],
+            TreeStatus::Unknown => vec![("?".to_string(), model::Color::yellow)],
+            TreeStatus::Grown => Vec::new(),
+            TreeStatus::Repository { dirty, upstream } => {
+                let mut glyphs: Vec<Glyph> = Vec::new();
+                if *dirty {
+                    glyphs.push(("*".to_string(), model::Color::yellow));
+                }
+                if let Some((ahead, behind)) = upstream {
+                    if *ahead > 0 {
+                        glyphs.push((format!("↑{}", ahead), model::Color::cyan));
+                    }
+                    if *behind > 0 {
+                        glyphs.push((format!("↓{}", behind), model::Color::magenta));
+                    }
+                }
+                if glyphs.is_empty() {
+                    glyphs.push(("✓".to_string(), model::Color::green));
+                }
+                glyphs
+            }
+        }
+    }
+}
+
+/// A row in the table of trees displayed by "garden ls".
+struct TreeRow {
+    name: String,
+    path: String,
+    branch: String,
+    status: TreeStatus,
+}
+
+impl TreeRow {
+    /// Inspect a tree's path to gather the details for its row.
+    /// Git repositories are only checked for changes when "status" is true.
+    fn new(name: String, tree_path: Option<String>, is_symlink: bool, status: bool) -> Self {
+        let mut row = TreeRow {
+            name,
+            path: tree_path
+                .clone()
+                .unwrap_or_else(|| "[invalid-path]".to_string()),
+            branch: "-".to_string(),
+            status: TreeStatus::Invalid,
+        };
+        let tree_path = match tree_path {
+            Some(tree_path) => std::path::PathBuf::from(tree_path),
+            None => return row,
+        };
+        row.status = if is_symlink {
+            TreeStatus::Symlink
+        } else if !tree_path.exists() {
+            TreeStatus::Missing
+        } else if !git::is_repository(&tree_path) {
+            TreeStatus::Unknown
+        } else {
+            if let Some(branch) = git::current_branch(&tree_path) {
+                row.branch = branch;
+            }
+            if status {
+                TreeStatus::Repository {
+                    dirty: git::has_uncommitted_changes(&tree_path),
+                    upstream: git::ahead_behind(&tree_path),
+                }
+            } else {
+                TreeStatus::Grown
+            }
+        };
+
+        row
+    }
+}
+
+/// Return the width of the terminal when stdout is a terminal.
+/// The COLUMNS environment variable overrides the detected width.
+fn terminal_width() -> Option<usize> {
+    if !atty::is(atty::Stream::Stdout) {
+        return None;
+    }
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|columns| *columns > 0)
+    {
+        return Some(columns);
+    }
+
+    terminal_columns()
+}
+
+/// Query the terminal for its width.
+#[cfg(unix)]
+fn terminal_columns() -> Option<usize> {
+    // SAFETY: TIOCGWINSZ only writes a winsize struct into the provided value.
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if result == 0 && size.ws_col > 0 {
+        Some(size.ws_col as usize)
+    } else {
+        None
+    }
+}
+
+/// Query the terminal for its width.
+#[cfg(not(unix))]
+fn terminal_columns() -> Option<usize> {
+    None
+}
+
+/// The indentation used for the entries in each section.
+const INDENT: usize = 4;
+
+/// The narrowest that the path column will be truncated to on narrow terminals.
+const MIN_PATH_WIDTH: usize = 12;
+
+/// Return the number of characters displayed for a value.
+fn display_width(value: &str) -> usize {
+    value.chars().count()
+}
+
+/// Pad a value with trailing spaces so that it is displayed using "width" characters.
+fn pad(value: &str, width: usize) -> String {
+    let padding = width.saturating_sub(display_width(value));
+    format!("{}{:padding$}", value, "", padding = padding)
+}
+
+/// Shorten a path to "width" characters by replacing its leading characters with "…".
+fn truncate_path(path: &str, width: usize) -> String {
+    let length = display_width(path);
+    if length <= width {
+        return path.to_string();
+    }
+    let tail: String = path.chars().skip(length - width + 1).collect();
+
+    format!("…{}", tail)
+}
+
+/// Print names separated by spaces and wrapped to fit within the terminal width.
+fn print_names<'a>(names: impl Iterator<Item = &'a String>, width: Option<usize>) {
+    let mut line = String::new();
+    for name in names {
+        let fits = match width {
+            Some(width) => INDENT + display_width(&line) + 1 + display_width(name) <= width,
+            None => true,
+        };
+        if !line.is_empty() && !fits {
+            println!("{:indent$}{}", "", line, indent = INDENT);
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(name);
+    }
+    if !line.is_empty() {
+        println!("{:indent$}{}", "", line, indent = INDENT);
+    }
+}
+
+/// Print the trees as a table of aligned name, path, branch and status columns.
+/// Paths are truncated when the table does not fit within the terminal width.
+fn print_trees(rows: &[TreeRow], width: Option<usize>) {
+    let column_width = |value: fn(&TreeRow) -> &str| {
+        rows.iter()
+            .map(|row| display_width(value(row)))
+            .max()
+            .unwrap_or_default()
+    };
+    let name_width = column_width(|row| &row.name);
+    let branch_width = column_width(|row| &row.branch);
+    let mut path_width = column_width(|row| &row.path);
+    let status_width = rows
+        .iter()
+        .map(|row| {
+            let glyphs = row.status.glyphs();
+            glyphs
+                .iter()
+                .map(|(glyph, _)| display_width(glyph))
+                .sum::<usize>()
+                + glyphs.len().saturating_sub(1)
+        })
+        .max()
+        .unwrap_or_default();
+    if let Some(width) = width {
+        let fixed = INDENT + name_width + 2 + 2 + branch_width + 2 + status_width;
+        if fixed + path_width > width {
+            path_width = width.saturating_sub(fixed).max(MIN_PATH_WIDTH);
+        }
+    }
+    for row in rows {
+        let status = row
+            .status
+            .glyphs()
+            .into_iter()
+            .map(|(glyph, color)| color(glyph).to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        // The branch is only padded when it is followed by a status.
+        let branch = if status.is_empty() {
+            model::Color::green(row.branch.clone()).to_string()
+        } else {
+            format!(
+                "{}  {}",
+                model::Color::green(pad(&row.branch, branch_width)),
+                status
+            )
+        };
+        println!(
+            "{:indent$}{}  {}  {}",
+            "",
+            model::Color::blue(pad(&row.name, name_width)).bold(),
+            pad(&truncate_path(&row.path, path_width), path_width),
+            branch,
+            indent = INDENT
+        );
+    }
+}
+
+/// Print the names of the gardens and groups followed by a table of trees.
+/// Git repositories are only checked for changes when "status" is true.
+fn list(config: &model::Configuration, status: bool) -> Result<()> {
+    let width = terminal_width();
     if !config.gardens.is_empty() {
         println!("gardens:");
-        print!("    ");
-        for garden in &config.gardens {
-            print!("{} ", garden.get_name());
-        }
-        println!();
+        print_names(config.gardens.iter().map(|garden| garden.get_name()), width);
     }
 
     if !config.groups.is_empty() {
         println!("groups:");
-        print!("    ");
-        for group in &config.groups {
-            print!("{} ", group.get_name());
-        }
-        println!();
+        print_names(config.groups.iter().map(|group| group.get_name()), width);
     }
 
     if !config.trees.is_empty() {
+        // Inspecting the trees runs several git commands per tree so the
+        // trees are inspected in parallel.
+        let trees: Vec<(String, Option<String>, bool)> = config
+            .trees
+            .iter()
+            .map(|tree| {
+                (
+                    tree.get_name().to_string(),
+                    tree.path_as_ref().ok().cloned(),
+                    tree.is_symlink,
+                )
+            })
+            .collect();
+        let rows: Vec<TreeRow> = trees
+            .into_par_iter()
+            .map(|(name, tree_path, is_symlink)| TreeRow::new(name, tree_path, is_symlink, status))
+            .collect();
         println!("trees:");
-        print!("    ");
-        for tree in &config.trees {
-            print!("{} ", tree.get_name());
+        print_trees(&rows, width);
+    }
+
+    // Report symlink trees whose symlinks are broken or point to the wrong target.
+    let unhealthy: Vec<(&model::Tree, model::SymlinkStatus)> = config
+        .trees
+        .iter()
+        .filter(|tree| tree.is_symlink)
+        .map(|tree| (tree, tree.symlink_status()))
+        .filter(|(_, status)| {
+            !matches!(
+                status,
+                model::SymlinkStatus::Ok | model::SymlinkStatus::Missing
+            )
+        })
+        .collect();
+    if !unhealthy.is_empty() {
+        println!("symlinks:");
+        let width = unhealthy
+            .iter()
+            .map(|(tree, _)| tree.get_name().len())
+            .max()
+            .unwrap_or_default();
+        for (tree, status) in &unhealthy {
+            let name = format!("{:width$}", tree.get_name(), width = width);
+            match status {
+                model::SymlinkStatus::WrongTarget(target) => println!(
+                    "    {}  {} -> {}",
+                    name,
+                    model::Color::red(status.as_str()),
+                    target
+                ),
+                _ => println!("    {}  {}", name, model::Color::red(status.as_str())),
+            }
         }
-        println!();
     }
 
     Ok(())
 }
+
+/// Print the gardens, groups and trees as a JSON document.
+fn list_json(config: &mut model::Configuration) -> Result<()> {
+    // Record the gardens and groups that each tree belongs to.
+    let mut tree_gardens: Vec<Vec<String>> = vec![Vec::new(); config.trees.len()];
+    let mut tree_groups: Vec<Vec<String>> = vec![Vec::new(); config.trees.len()];
+
+    let mut gardens = Vec::new();
+    for garden in &config.gardens {
+        for ctx in query::trees_from_garden(config, garden) {
+            append_unique(&mut tree_gardens[ctx.tree], garden.get_name());
+        }
+        gardens.push(serde_json::json!({
+            "name": garden.get_name(),
+            "groups": garden.groups,
+            "trees": garden.trees,
+        }));
+    }
+
+    let mut groups = Vec::new();
+    for group in &config.groups {
+        for ctx in query::trees_from_group(config, None, group) {
+            append_unique(&mut tree_groups[ctx.tree], group.get_name());
+        }
+        groups.push(serde_json::json!({
+            "name": group.get_name(),
+            "members": group.members,
+        }));
+    }
+
+    let mut trees = Vec::new();
+    for tree_idx in 0..config.trees.len() {
+        let tree = &config.trees[tree_idx];
+        let path = tree.path_as_ref().ok();
+        let exists = match path {
+            Some(path) => std::path::PathBuf::from(path).exists(),
+            None => false,
+        };
+        let mut remotes = Vec::new();
+        for remote in &tree.remotes {
+            let url = eval::tree_value(config, remote.get_expr(), tree_idx, None);
+            remotes.push(serde_json::json!({
+                "name": remote.get_name(),
+                "url": url,
+            }));
+        }
+        let symlink = if tree.is_symlink {
+            let status = tree.symlink_status();
+            serde_json::json!({
+                "target": tree.symlink_as_ref().ok(),
+                "status": status.as_str(),
+            })
+        } else {
+            serde_json::Value::Null
+        };
+        trees.push(serde_json::json!({
+            "name": tree.get_name(),
+            "path": path,
+            "exists": exists,
+            "symlink": symlink,
+            "remotes": remotes,
+            "gardens": tree_gardens[tree_idx],
+            "groups": tree_groups[tree_idx],
+        }));
+        // Reset variables so that the next tree is evaluated in its own scope.
+        config.reset();
+    }
+
+    let doc = serde_json::json!({
+        "gardens": gardens,
+        "groups": groups,
+        "trees": trees,
+    });
+    println!("{}", serde_json::to_string_pretty(&doc)?);
+
+    Ok(())
+}
+
+/// Append a name to a vector when it is not already present.
+fn append_unique(names: &mut Vec<String>, name: &str) {
+    if !names.iter().any(|value| value == name) {
+        names.push(name.to_string());
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::yaml::Yaml;
+use yaml_rust::YamlLoader;
+
+use super::super::config;
+use super::super::errors;
+use super::super::git;
+use super::super::model;
+use super::super::query;
+use super::completion;
+
+/// Record the current commit of each tree in a lockfile
+#[derive(Parser, Clone, Debug)]
+pub struct LockOptions {
+    /// Lockfile to write (default: the garden file with a ".lock" extension)
+    #[arg(short, long, value_name = "file")]
+    pub output: Option<String>,
+    /// Tree queries for the gardens, groups or trees to lock (default: all trees)
+    #[arg(add = ArgValueCandidates::new(completion::queries))]
+    pub queries: Vec<String>,
+}
+
+/// Commit IDs recorded for each tree, keyed by tree name.
+pub type Locks = std::collections::BTreeMap<String, String>;
+
+/// Main entry point for the "garden lock" command
+pub fn main(app: &mut model::ApplicationContext, options: &LockOptions) -> Result<()> {
+    let quiet = app.options.quiet;
+    let verbose = app.options.verbose;
+    let config = app.get_root_config_mut();
+    let path = match &options.output {
+        Some(output) => std::path::PathBuf::from(output),
+        None => lock_path(config)?,
+    };
+
+    // Locking specific trees updates the entries in an existing lockfile.
+    let mut locks = if options.queries.is_empty() || !path.exists() {
+        Locks::new()
+    } else {
+        read_locks(&path)?
+    };
+
+    let contexts: Vec<model::TreeContext> = if options.queries.is_empty() {
+        (0..config.trees.len())
+            .map(|tree_idx| model::TreeContext::new(tree_idx, config.get_id(), None, None))
+            .collect()
+    } else {
+        options
+            .queries
+            .iter()
+            .flat_map(|query| query::resolve_trees(config, query))
+            .collect()
+    };
+
+    let backend = git::backend();
+    for ctx in &contexts {
+        let tree = &config.trees[ctx.tree];
+        if tree.is_symlink {
+            continue;
+        }
+        let tree_path = tree.path_as_ref()?;
+        // Sparse gardens/missing trees are ok -> skip these entries.
+        if !std::path::PathBuf::from(tree_path).exists() {
+            if !quiet {
+                eprintln!("{}", model::display_missing_tree(tree, tree_path, verbose));
+            }
+            continue;
+        }
+        match backend.head_commit(std::path::Path::new(tree_path)) {
+            Ok(commit) => {
+                locks.insert(tree.get_name().to_string(), commit);
+            }
+            Err(err) => {
+                if !quiet {
+                    eprintln!("{}: {}", tree.get_name(), err);
+                }
+            }
+        }
+    }
+
+    write_locks(&locks, &path)?;
+
+    Ok(())
+}
+
+/// Return the default lockfile path for a configuration.
+/// "garden.yaml" is locked by "garden.lock".
+pub fn lock_path(config: &model::Configuration) -> Result<std::path::PathBuf, errors::GardenError> {
+    Ok(config.get_path()?.with_extension("lock"))
+}
+
+/// Read the lockfile for a configuration. An empty set of locks is returned
+/// when the lockfile does not exist.
+pub fn read_config_locks(config: &model::Configuration) -> Result<Locks, errors::GardenError> {
+    let path = lock_path(config)?;
+    if path.exists() {
+        read_locks(&path)
+    } else {
+        Ok(Locks::new())
+    }
+}
+
+/// Read locks from a lockfile.
+pub fn read_locks(path: &std::path::Path) -> Result<Locks, errors::GardenError> {
+    let string = std::fs::read_to_string(path).map_err(|io_err| errors::GardenError::ReadFile {
+        path: path.into(),
+        err: io_err,
+    })?;
+    let docs =
+        YamlLoader::load_from_str(&string).map_err(|err| errors::GardenError::ReadConfig {
+            err,
+            path: path.display().to_string(),
+        })?;
+
+    let mut locks = Locks::new();
+    if let Some(Yaml::Hash(trees)) = docs.first().map(|doc| &doc["trees"]) {
+        for (name, value) in trees {
+            // Entries are either "tree: <commit>" or "tree: {commit: <commit>}".
+            let commit = match value {
+                Yaml::Hash(_) => value["commit"].as_str(),
+                _ => value.as_str(),
+            };
+            if let (Some(name), Some(commit)) = (name.as_str(), commit) {
+                locks.insert(name.to_string(), commit.to_string());
+            }
+        }
+    }
+
+    Ok(locks)
+}
+
+/// Write locks to a lockfile.
+fn write_locks(locks: &Locks, path: &std::path::Path) -> Result<(), errors::GardenError> {
+    let mut trees = YamlHash::new();
+    for (name, commit) in locks {
+        let mut entry = YamlHash::new();
+        entry.insert(Yaml::String("commit".into()), Yaml::String(commit.into()));
+        trees.insert(Yaml::String(name.into()), Yaml::Hash(entry));
+    }
+    let mut doc = YamlHash::new();
+    doc.insert(Yaml::String("trees".into()), Yaml::Hash(trees));
+
+    config::writer::write_yaml(&Yaml::Hash(doc), path)
+}
@@ -1,18 +1,54 @@
+/// Branch command
+pub mod branch;
+
+/// Cache command
+pub mod cache;
+
+/// Cd command
+pub mod cd;
+
+/// Check command
+pub mod check;
+
 /// Configuration-defined commands
 pub mod cmd;
 
+/// Completion command
+pub mod completion;
+
+/// Configure command
+pub mod configure;
+
+/// Diff command
+pub mod diff;
+
+/// Doctor command
+pub mod doctor;
+
+/// Env command
+pub mod env;
+
 /// Exec command
 pub mod exec;
 
+/// Export command
+pub mod export;
+
 /// Eval command
 pub mod eval;
 
+/// Fetch command
+pub mod fetch;
+
 /// Grow command
 pub mod grow;
 
 /// Help command
 pub mod help;
 
+/// Import command
+pub mod import;
+
 /// Init command
 pub mod init;
 
@@ -22,11 +58,35 @@ pub mod inspect;
 /// List command
 pub mod list;
 
+/// Lock command
+pub mod lock;
+
 /// Plant command
 pub mod plant;
 
 /// Prune command
 pub mod prune;
 
+/// Restore command
+pub mod restore;
+
 /// Shell command
 pub mod shell;
+
+/// Shell-init command
+pub mod shell_init;
+
+/// Snapshot command
+pub mod snapshot;
+
+/// Sync command
+pub mod sync;
+
+/// Tree command
+pub mod tree;
+
+/// Uproot command
+pub mod uproot;
+
+/// Vars command
+pub mod vars;
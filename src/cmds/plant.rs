@@ -1,4 +1,5 @@
 use anyhow::Result;
+use clap::Parser;
 use yaml_rust::yaml::Hash as YamlHash;
 use yaml_rust::yaml::Yaml;
 
@@ -10,20 +11,35 @@ use super::super::model;
 use super::super::path;
 use super::super::query;
 
-pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
-    let mut output = String::new();
-    let mut paths: Vec<String> = Vec::new();
-    parse_args(&mut app.options, &mut output, &mut paths);
+/// Add pre-existing worktrees to a garden file
+#[derive(Parser, Clone, Debug)]
+pub struct PlantOptions {
+    /// File to write (default: garden.yaml)
+    #[arg(short, long, value_name = "file")]
+    pub output: Option<String>,
+    /// Plant every Git repository found by recursively scanning a directory
+    #[arg(long, value_name = "dir")]
+    pub scan: Option<String>,
+    /// Record the specified remote as the primary remote instead of "origin"
+    #[arg(long, value_name = "remote")]
+    pub default_remote: Option<String>,
+    /// Trees to plant
+    #[arg(required_unless_present = "scan")]
+    pub paths: Vec<String>,
+}
 
+/// Main entry point for the "garden plant" command
+pub fn main(app: &mut model::ApplicationContext, options: &PlantOptions) -> Result<()> {
     // Read existing configuration
     let verbose = app.options.verbose;
     let config = app.get_root_config_mut();
     let mut doc = config::reader::read_yaml(config.get_path()?)?;
 
     // Output filename defaults to the input filename.
-    if output.is_empty() {
-        output = config.get_path()?.to_string_lossy().into();
-    }
+    let output = match &options.output {
+        Some(output) => output.to_string(),
+        None => config.get_path()?.to_string_lossy().into(),
+    };
 
     // Mutable YAML scope.
     {
@@ -31,7 +47,10 @@ pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
         let doc_hash: &mut YamlHash = match doc {
             Yaml::Hash(ref mut hash) => hash,
             _ => {
-                error!("invalid config: not a hash");
+                return Err(errors::GardenError::InvalidConfiguration {
+                    msg: "not a hash".into(),
+                }
+                .into());
             }
         };
 
@@ -40,14 +59,25 @@ pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
         let trees: &mut YamlHash = match doc_hash.get_mut(&key) {
             Some(Yaml::Hash(ref mut hash)) => hash,
             _ => {
-                error!("invalid trees: not a hash");
+                return Err(errors::GardenError::InvalidConfiguration {
+                    msg: "trees: not a hash".into(),
+                }
+                .into());
             }
         };
 
+        let mut paths = options.paths.clone();
+        if let Some(scan_dir) = &options.scan {
+            paths.extend(scan_repositories(scan_dir));
+        }
         for path in &paths {
-            if let Err(msg) = plant_path(config, verbose, path, trees) {
-                error!("{}", msg);
-            }
+            plant_path(
+                config,
+                verbose,
+                path,
+                options.default_remote.as_deref(),
+                trees,
+            )?;
         }
     }
 
@@ -55,28 +85,11 @@ pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
     Ok(config::writer::write_yaml(&doc, &output)?)
 }
 
-fn parse_args(options: &mut model::CommandOptions, output: &mut String, paths: &mut Vec<String>) {
-    let mut ap = argparse::ArgumentParser::new();
-    ap.set_description("garden plant - Add pre-existing worktrees to a garden file");
-
-    ap.refer(output).add_option(
-        &["-o", "--output"],
-        argparse::Store,
-        "File to write (default: garden.yaml)",
-    );
-
-    ap.refer(paths)
-        .required()
-        .add_argument("paths", argparse::List, "Trees to plant");
-
-    options.args.insert(0, "garden plant".into());
-    cmd::parse_args(ap, options.args.to_vec());
-}
-
 fn plant_path(
     config: &model::Configuration,
     verbose: u8,
     raw_path: &str,
+    default_remote: Option<&str>,
     trees: &mut YamlHash,
 ) -> Result<()> {
     // Garden root path
@@ -109,6 +122,8 @@ fn plant_path(
 
         parent_tree_name = match query::tree_name_from_abspath(config, &parent_pathbuf) {
             Some(tree_name) => tree_name,
+            // The parent might have been planted earlier by the same "garden plant" command.
+            None if trees.contains_key(&Yaml::String(parent_path.clone())) => parent_path,
             None => {
                 return Err(errors::GardenError::WorktreeParentNotPlantedError {
                     parent: parent_path,
@@ -141,7 +156,9 @@ fn plant_path(
 
     // Update an existing tree entry if it already exists.
     // Add a new entry otherwise.
+    // "configured" includes the values inherited through YAML merge keys.
     let mut entry: YamlHash = YamlHash::new();
+    let mut configured: YamlHash = YamlHash::new();
     if let Some(tree_yaml) = trees.get(&key) {
         if let Some(tree_hash) = tree_yaml.as_hash() {
             if verbose > 0 {
@@ -149,6 +166,9 @@ fn plant_path(
             }
             entry = tree_hash.clone();
         }
+        if let Yaml::Hash(resolved) = config::reader::resolve_merge_keys(tree_yaml) {
+            configured = resolved;
+        }
     }
 
     // If this is a child worktree then record a "worktree" entry only.
@@ -178,35 +198,44 @@ fn plant_path(
         None => false,
     };
 
-    // Gather remote names
-    let mut remote_names: Vec<String> = Vec::new();
-    {
-        let command = ["git", "remote"];
-        let exec = cmd::exec_in_dir(&command, &path);
-        if let Ok(x) = cmd::capture_stdout(exec) {
-            let output = cmd::trim_stdout(&x);
-
-            for line in output.lines() {
-                // Skip "origin" since it is defined by the "url" entry.
-                if line == "origin" {
-                    continue;
-                }
-                // Any other remotes are part of the "remotes" hash.
-                remote_names.push(line.into());
+    let backend = git::backend();
+    let remote_names = backend.remotes(&path).unwrap_or_default();
+
+    // Record the primary remote as "default-remote" when it is not "origin".
+    let default_remote_key = Yaml::String("default-remote".into());
+    let default_remote = default_remote
+        .or_else(|| configured.get(&default_remote_key).and_then(Yaml::as_str))
+        .map(String::from)
+        .or_else(|| detect_default_remote(&path, &remote_names));
+    match default_remote {
+        Some(remote) if remote != "origin" && remote_names.contains(&remote) => {
+            entry.insert(default_remote_key, Yaml::String(remote));
+        }
+        Some(remote) if remote != "origin" && verbose > 0 => {
+            eprintln!("{}: no remote named {}", tree_name, remote);
+        }
+        // "origin" is the default so the setting is removed. A value inherited
+        // through a YAML merge key is overridden instead.
+        Some(remote) if remote == "origin" => {
+            if entry.contains_key(&default_remote_key) {
+                entry.remove(&default_remote_key);
+            } else if configured.contains_key(&default_remote_key) {
+                entry.insert(default_remote_key, Yaml::String(remote));
             }
         }
+        _ => (),
     }
 
-    // Gather remote urls
+    // Gather remote names and urls.
     let mut remotes: Vec<(String, String)> = Vec::new();
-    {
-        for remote in &remote_names {
-            let cmd = ["git", "config", &format!("remote.{}.url", remote)];
-            let exec = cmd::exec_in_dir(&cmd, &path);
-            if let Ok(x) = cmd::capture_stdout(exec) {
-                let output = cmd::trim_stdout(&x);
-                remotes.push((remote.clone(), output));
-            }
+    for remote in remote_names {
+        // Skip "origin" since it is defined by the "url" entry.
+        if remote == "origin" {
+            continue;
+        }
+        // Any other remotes are part of the "remotes" hash.
+        if let Some(url) = backend.config_get(&path, &format!("remote.{}.url", remote)) {
+            remotes.push((remote, config.unrewrite_url(&url)));
         }
     }
 
@@ -243,28 +272,40 @@ fn plant_path(
     }
 
     // Update the "url" field.
-    {
-        let command = ["git", "config", "remote.origin.url"];
-        let exec = cmd::exec_in_dir(&command, &path);
-        if let Ok(cmd_stdout) = cmd::capture_stdout(exec) {
-            let origin_url = cmd::trim_stdout(&cmd_stdout);
-            entry.insert(url_key, Yaml::String(origin_url));
-        }
+    if let Some(origin_url) = backend.config_get(&path, "remote.origin.url") {
+        entry.insert(url_key, Yaml::String(config.unrewrite_url(&origin_url)));
     }
 
     // Update the "bare" field.
-    {
-        let bare_key = Yaml::String("bare".into());
-        let command = ["git", "config", "--bool", "core.bare"];
-        let exec = cmd::exec_in_dir(&command, &path);
-        if let Ok(cmd_stdout) = cmd::capture_stdout(exec) {
-            let is_bare = cmd::trim_stdout(&cmd_stdout);
-            if is_bare == "true" {
-                entry.insert(bare_key, Yaml::Boolean(true));
+    if backend.is_bare(&path) {
+        entry.insert(Yaml::String("bare".into()), Yaml::Boolean(true));
+    }
+
+    // Record the "submodules" field unless it has already been configured.
+    let submodules_key = Yaml::String("submodules".into());
+    if !configured.contains_key(&submodules_key) {
+        match detect_submodules(&path) {
+            model::Submodules::None => (),
+            model::Submodules::Init => {
+                entry.insert(submodules_key, Yaml::Boolean(true));
+            }
+            model::Submodules::Recursive => {
+                entry.insert(submodules_key, Yaml::String("recursive".into()));
             }
         }
     }
 
+    // Record the "lfs" field unless it has already been configured.
+    let lfs_key = Yaml::String("lfs".into());
+    if !configured.contains_key(&lfs_key) && detect_lfs(&path) {
+        entry.insert(lfs_key, Yaml::Boolean(true));
+    }
+
+    // Values that are inherited through merge keys, e.g. "<<: *defaults", are not repeated.
+    if let Some(Yaml::Hash(original)) = trees.get(&key) {
+        remove_inherited_entries(original, &configured, &mut entry);
+    }
+
     // Move the entry into the trees container
     if let Some(tree_entry) = trees.get_mut(&key) {
         *tree_entry = Yaml::Hash(entry);
@@ -274,3 +315,148 @@ fn plant_path(
 
     Ok(())
 }
+
+/// Remove the entries that were added to a tree entry when the same values are
+/// already provided by the tree's YAML merge keys.
+fn remove_inherited_entries(original: &YamlHash, configured: &YamlHash, entry: &mut YamlHash) {
+    let inherited: Vec<Yaml> = entry
+        .iter()
+        .filter(|(key, value)| !original.contains_key(key) && configured.get(key) == Some(value))
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in &inherited {
+        entry.remove(key);
+    }
+}
+
+/// Detect the primary remote for repositories that do not have an "origin" remote.
+/// The remote tracked by the current branch is used when available.
+/// The first remote is used otherwise.
+fn detect_default_remote(path: &std::path::Path, remote_names: &[String]) -> Option<String> {
+    if remote_names.is_empty() || remote_names.iter().any(|remote| remote == "origin") {
+        return None;
+    }
+    let backend = git::backend();
+    let tracked = git::current_branch(path)
+        .and_then(|branch| backend.config_get(path, &format!("branch.{}.remote", branch)))
+        .filter(|remote| remote_names.contains(remote));
+
+    tracked.or_else(|| remote_names.first().cloned())
+}
+
+/// Recursively scan a directory for Git repositories, bare repositories
+/// and worktrees. Worktrees are returned after all other repositories so that
+/// their parent repositories are planted first.
+fn scan_repositories(dirname: &str) -> Vec<String> {
+    let mut repos = Vec::new();
+    scan_directory(std::path::Path::new(dirname), &mut repos);
+    repos.sort();
+
+    let (mut paths, worktrees): (Vec<_>, Vec<_>) = repos
+        .into_iter()
+        .partition(|pathbuf| !pathbuf.join(".git").is_file());
+    paths.extend(worktrees);
+
+    paths
+        .iter()
+        .map(|pathbuf| pathbuf.to_string_lossy().to_string())
+        .collect()
+}
+
+/// Record Git repositories found inside of a directory.
+/// Repositories are not scanned for nested repositories.
+/// Directories that contain an empty ".gardenignore" file are skipped.
+/// Glob patterns listed in a ".gardenignore" file skip the matching entries
+/// relative to the directory that contains the file.
+fn scan_directory(path: &std::path::Path, repos: &mut Vec<std::path::PathBuf>) {
+    let ignore_patterns = match read_ignore_patterns(path) {
+        Some(patterns) if patterns.is_empty() => return,
+        Some(patterns) => patterns,
+        None => Vec::new(),
+    };
+    if path.join(".git").exists() || is_bare_repository(path) {
+        repos.push(path.to_path_buf());
+        return;
+    }
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if ignore_patterns.iter().any(|pattern| pattern.matches(&name)) {
+            continue;
+        }
+        // Symlinks are not followed to avoid visiting repositories more than once.
+        if let Ok(file_type) = entry.file_type() {
+            if file_type.is_dir() {
+                scan_directory(&entry.path(), repos);
+            }
+        }
+    }
+}
+
+/// Read the glob patterns from the ".gardenignore" file inside of a directory.
+/// Blank lines and lines that start with "#" are ignored. None is returned when
+/// the directory does not contain a ".gardenignore" file.
+fn read_ignore_patterns(path: &std::path::Path) -> Option<Vec<glob::Pattern>> {
+    let content = std::fs::read_to_string(path.join(".gardenignore")).ok()?;
+    let patterns = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| glob::Pattern::new(line.trim_end_matches('/')).ok())
+        .collect();
+
+    Some(patterns)
+}
+
+/// Does the path look like a bare Git repository?
+fn is_bare_repository(path: &std::path::Path) -> bool {
+    path.join("HEAD").is_file() && path.join("objects").is_dir() && path.join("refs").is_dir()
+}
+
+/// Detect Git LFS usage in a worktree. Repositories use LFS when their
+/// ".gitattributes" file assigns the "lfs" filter to any paths.
+fn detect_lfs(path: &std::path::Path) -> bool {
+    std::fs::read_to_string(path.join(".gitattributes"))
+        .map(|attributes| {
+            attributes
+                .lines()
+                .filter(|line| !line.trim_start().starts_with('#'))
+                .any(|line| line.split_whitespace().any(|attr| attr == "filter=lfs"))
+        })
+        .unwrap_or(false)
+}
+
+/// Detect submodules in a worktree. Submodules are recursive when any of the
+/// checked-out submodules contains submodules of its own.
+fn detect_submodules(path: &std::path::Path) -> model::Submodules {
+    if !path.join(".gitmodules").is_file() {
+        return model::Submodules::None;
+    }
+    let command = [
+        "git",
+        "config",
+        "--file",
+        ".gitmodules",
+        "--get-regexp",
+        r"^submodule\..*\.path$",
+    ];
+    let is_recursive = cmd::capture_stdout(cmd::exec_in_dir(&command, path))
+        .map(|capture| {
+            capture.stdout_str().lines().any(|line| {
+                line.split_once(' ').is_some_and(|(_, submodule_path)| {
+                    path.join(submodule_path).join(".gitmodules").is_file()
+                })
+            })
+        })
+        .unwrap_or(false);
+
+    if is_recursive {
+        model::Submodules::Recursive
+    } else {
+        model::Submodules::Init
+    }
+}
@@ -1,100 +1,75 @@
 use anyhow::Result;
+use clap::Parser;
 use rayon::prelude::*;
 use std::io::prelude::*;
 
 use super::super::cmd;
 use super::super::errors;
+use super::super::events;
+use super::super::git;
 use super::super::model;
 use super::super::model::Color;
+use super::super::syntax;
+
+/// Remove unreferenced Git repositories
+#[derive(Parser, Clone, Debug)]
+pub struct PruneOptions {
+    /// Number of parallel jobs, defaults to # of CPUs
+    #[arg(short = 'j', long = "jobs", value_name = "N")]
+    pub num_jobs: Option<usize>,
+    /// Set maximum prune depth (default: none)
+    #[arg(short = 'd', long, value_name = "depth")]
+    pub max_depth: Option<isize>,
+    /// Only prune starting at the given depth
+    #[arg(long, value_name = "depth")]
+    pub min_depth: Option<isize>,
+    /// Only prune at the exact depth.
+    /// This is an alias for '--min-depth <depth> --max-depth <depth>'
+    #[arg(long, value_name = "depth")]
+    pub exact_depth: Option<isize>,
+    /// Prune all repositories without prompting (DANGEROUS!)
+    #[arg(long)]
+    pub no_prompt: bool,
+    /// Enable deletions (default: deletions are not enabled)
+    #[arg(long)]
+    pub rm: bool,
+    /// List the repositories that would be removed without prompting
+    #[arg(short = 'n', long, conflicts_with = "rm")]
+    pub dry_run: bool,
+    /// Only prune repositories that have not been used within the specified age,
+    /// e.g. "30d". Supported units: s, m, h, d, w
+    #[arg(long, value_name = "age", value_parser = syntax::parse_duration)]
+    pub older_than: Option<std::time::Duration>,
+    /// Prune repositories with uncommitted changes or unpushed commits
+    #[arg(short, long)]
+    pub force: bool,
+    /// Limit pruning to the specified subdirectories
+    pub paths: Vec<String>,
+}
 
 /// Main entry point for the "garden prune" command
-
-pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
-    let mut paths = Vec::new();
-    parse_args(&mut paths, &mut app.options);
+pub fn main(app: &mut model::ApplicationContext, prune_options: &PruneOptions) -> Result<()> {
+    update_options(&mut app.options, prune_options);
 
     let options = app.options.clone();
     let config = app.get_root_config_mut();
-    let exit_status = prune(config, &options, &paths)?;
+    let exit_status = prune(config, &options, prune_options)?;
 
     // Return the last non-zero exit status.
     cmd::result_from_exit_status(exit_status).map_err(|err| err.into())
 }
 
-/// Parse "garden prune" arguments.
-
-fn parse_args(paths: &mut Vec<String>, options: &mut model::CommandOptions) {
-    options.args.insert(0, "garden prune".into());
-    options.dry_run = true; // Enable the safe dry-run mode by default.
-
-    // Mutable scope for parser.
-    {
-        let mut parser = argparse::ArgumentParser::new();
-        parser.set_description("garden prune - Remove unreferenced Git repositories");
-
-        parser
-            .refer(&mut options.num_jobs)
-            .metavar("<N>")
-            .add_option(
-                &["-j", "--jobs"],
-                argparse::Parse,
-                "Number of parallel jobs, defaults to # of CPUs",
-            );
-
-        parser
-            .refer(&mut options.max_depth)
-            .metavar("<depth>")
-            .add_option(
-                &["-d", "--max-depth"],
-                argparse::Parse,
-                "Set maximum prune depth (default: none)",
-            );
-
-        parser
-            .refer(&mut options.min_depth)
-            .metavar("<depth>")
-            .add_option(
-                &["--min-depth"],
-                argparse::Parse,
-                "Only prune starting at the given depth",
-            );
-
-        parser
-            .refer(&mut options.exact_depth)
-            .metavar("<depth>")
-            .add_option(
-                &["--exact-depth"],
-                argparse::Parse,
-                "Only prune at the exact depth. \
-                This is an alias for '--min-depth <depth> --max-depth <depth>'",
-            );
-
-        parser.refer(&mut options.no_prompt).add_option(
-            &["--no-prompt"],
-            argparse::StoreTrue,
-            "Prune all repositories without prompting (DANGEROUS!)",
-        );
-
-        parser.refer(&mut options.dry_run).add_option(
-            &["--rm"],
-            argparse::StoreFalse,
-            "Enable deletions (default: deletions are not enabled)",
-        );
-
-        parser.refer(paths).add_argument(
-            "paths",
-            argparse::List,
-            "Limit pruning to the specified subdirectories",
-        );
-
-        if let Err(err) = parser.parse(
-            options.args.to_vec(),
-            &mut std::io::stdout(),
-            &mut std::io::stderr(),
-        ) {
-            std::process::exit(err);
-        }
+/// Apply "garden prune" options to the command options.
+fn update_options(options: &mut model::CommandOptions, prune_options: &PruneOptions) {
+    if let Some(num_jobs) = prune_options.num_jobs {
+        options.num_jobs = num_jobs;
     }
+    options.max_depth = prune_options.max_depth.unwrap_or(-1);
+    options.min_depth = prune_options.min_depth.unwrap_or(-1);
+    options.exact_depth = prune_options.exact_depth.unwrap_or(-1);
+    options.no_prompt = prune_options.no_prompt;
+    // Enable the safe dry-run mode by default.
+    options.dry_run = !prune_options.rm;
 
     // At least two threads must be running in order for the TraverseFilesystem task to
     // be able to produce results. Otherwise we'll block in the PromptUser thread without
@@ -123,18 +98,20 @@ fn parse_args(paths: &mut Vec<String>, options: &mut model::CommandOptions) {
 /// PathBufMessage is sent across channels between the TraverseFilesystem,
 /// PromptUser and RemovePaths tasks. The Path variant contains a PathBuf to process and
 /// the Finished variant is used to signal the end of the message stream.
-
 enum PathBufMessage {
     Path(std::path::PathBuf),
+    /// Repositories that are not pruned are reported along with the reason.
+    Skipped(std::path::PathBuf, String),
     Finished,
 }
 
 /// TraverseFilesystem walks the filesystem and sends a PathBufMessage as it
 /// discovers Git repositories during its traversal.
-
 struct TraverseFilesystem<'a> {
     min_depth: isize,
     max_depth: isize,
+    older_than: Option<std::time::Duration>,
+    force: bool,
     send_repo_path: crossbeam::channel::Sender<PathBufMessage>,
     root_path: std::path::PathBuf,
     path_filters: &'a Vec<std::path::PathBuf>,
@@ -179,9 +156,7 @@ impl TraverseFilesystem<'_> {
 
         if git_dir.exists() {
             if is_within_bounds(current_depth, self.min_depth, self.max_depth) {
-                self.send_repo_path
-                    .send(PathBufMessage::Path(pathbuf.to_path_buf()))
-                    .ok();
+                self.send_repository(pathbuf);
             }
             return Ok(());
         }
@@ -190,9 +165,7 @@ impl TraverseFilesystem<'_> {
         if let Some(extension) = pathbuf.extension() {
             if extension == "git" {
                 if is_within_bounds(current_depth, self.min_depth, self.max_depth) {
-                    self.send_repo_path
-                        .send(PathBufMessage::Path(pathbuf.to_path_buf()))
-                        .ok();
+                    self.send_repository(pathbuf);
                 }
                 return Ok(());
             }
@@ -214,6 +187,28 @@ impl TraverseFilesystem<'_> {
         Ok(())
     }
 
+    /// Send a repository to the PromptUser task unless it is filtered out.
+    /// Repositories with uncommitted changes or unpushed commits are reported as
+    /// skipped unless "--force" is used.
+    fn send_repository(&self, pathbuf: &std::path::Path) {
+        if let Some(older_than) = self.older_than {
+            if !is_older_than(pathbuf, older_than) {
+                return;
+            }
+        }
+        if !self.force {
+            if let Some(reason) = git::unsaved_work(pathbuf) {
+                self.send_repo_path
+                    .send(PathBufMessage::Skipped(pathbuf.to_path_buf(), reason))
+                    .ok();
+                return;
+            }
+        }
+        self.send_repo_path
+            .send(PathBufMessage::Path(pathbuf.to_path_buf()))
+            .ok();
+    }
+
     /// Validate a pathbuf for traversal.
     fn validate_entry_for_traversal(&self, path: &std::path::Path) -> Option<std::path::PathBuf> {
         if path.is_dir()
@@ -265,6 +260,22 @@ fn is_within_max_bounds(value: isize, max_depth: isize) -> bool {
     max_depth == -1 || value <= max_depth
 }
 
+/// Has the repository been unused for longer than the specified age?
+/// Repositories are considered to be used when their HEAD, index or reflog
+/// are modified, e.g. by checkouts, commits and fetches.
+fn is_older_than(path: &std::path::Path, age: std::time::Duration) -> bool {
+    let git_dir = git::git_dir(path);
+    let last_used = ["HEAD", "index", "FETCH_HEAD", "ORIG_HEAD", "logs/HEAD"]
+        .iter()
+        .filter_map(|name| std::fs::metadata(git_dir.join(name)).ok())
+        .filter_map(|metadata| metadata.modified().ok())
+        .max();
+    match last_used.and_then(|time| time.elapsed().ok()) {
+        Some(elapsed) => elapsed > age,
+        None => false,
+    }
+}
+
 /// The RemovePaths task listens for PathBufMessage messages and removes
 /// paths emitted over the recv_remove_path channel.
 struct RemovePaths {
@@ -275,6 +286,9 @@ struct RemovePaths {
     send_finished_path: crossbeam::channel::Sender<PathBufMessage>,
     /// Dry-run mode does not actually perform deletions.
     dry_run: bool,
+    /// Removals are reported to the event handler using paths relative to the root.
+    event_handler: Option<events::EventHandler>,
+    root_path: std::path::PathBuf,
 }
 
 impl RemovePaths {
@@ -286,8 +300,25 @@ impl RemovePaths {
                     // Remove paths from the filesystem and send a completion message.
                     if !self.dry_run {
                         let pathbuf = pathbuf.to_path_buf();
+                        let event_handler = self.event_handler.clone();
+                        let name = pathbuf
+                            .strip_prefix(&self.root_path)
+                            .unwrap_or(&pathbuf)
+                            .to_string_lossy()
+                            .to_string();
                         remove_scope.spawn_fifo(move |_| {
-                            rm_rf::ensure_removed(&pathbuf).ok();
+                            let path = pathbuf.to_string_lossy();
+                            let event = events::TreeEvent::start(
+                                event_handler.as_ref(),
+                                events::ExecAction::Prune,
+                                &name,
+                                &path,
+                            );
+                            let status = match rm_rf::ensure_removed(&pathbuf) {
+                                Ok(()) => errors::EX_OK,
+                                Err(_) => errors::EX_IOERR,
+                            };
+                            event.finish(status);
 
                             // Remove empty parent directorires leading up to this path.
                             let mut parent_option = pathbuf.parent();
@@ -306,6 +337,7 @@ impl RemovePaths {
                         .send(PathBufMessage::Path(pathbuf))
                         .ok();
                 }
+                Ok(PathBufMessage::Skipped(..)) => (),
                 Ok(PathBufMessage::Finished) | Err(_) => {
                     self.send_finished_path.send(PathBufMessage::Finished).ok();
                     return;
@@ -405,6 +437,8 @@ struct PromptUser {
     send_remove_path: crossbeam::channel::Sender<PathBufMessage>,
     recv_finished_path: crossbeam::channel::Receiver<PathBufMessage>,
     no_prompt: bool,
+    /// List the repositories that would be removed without prompting.
+    list_only: bool,
     quit: bool,
 }
 
@@ -413,10 +447,17 @@ impl PromptUser {
         loop {
             match self.recv_repo_path.recv() {
                 Ok(PathBufMessage::Path(pathbuf)) => {
-                    if !self.quit {
+                    if self.list_only {
+                        print_pathbuf("Would delete", &pathbuf);
+                    } else if !self.quit {
                         self.prompt_pathbuf_for_deletion(pathbuf);
                     }
                 }
+                Ok(PathBufMessage::Skipped(pathbuf, reason)) => {
+                    if !self.quit {
+                        print_skipped_pathbuf(&pathbuf, &reason);
+                    }
+                }
                 Ok(PathBufMessage::Finished) | Err(_) => {
                     self.send_remove_path.send(PathBufMessage::Finished).ok();
                     break;
@@ -480,11 +521,27 @@ impl PromptUser {
 
 /// Print a deleted path.
 fn print_deleted_pathbuf(pathbuf: &std::path::Path) {
+    print_pathbuf("Deleted", pathbuf);
+}
+
+/// Print a path with a status message.
+fn print_pathbuf(status: &str, pathbuf: &std::path::Path) {
     println!(
         "{} {}: {}",
         Color::cyan("#"),
-        Color::green("Deleted"),
+        Color::green(status),
+        Color::blue(pathbuf.to_string_lossy()).bold(),
+    );
+}
+
+/// Print a path that was not pruned because it contains unsaved work.
+fn print_skipped_pathbuf(pathbuf: &std::path::Path, reason: &str) {
+    println!(
+        "{} {}: {} ({}, use '--force' to prune)",
+        Color::cyan("#"),
+        Color::yellow("Skipped"),
         Color::blue(pathbuf.to_string_lossy()).bold(),
+        Color::yellow(reason),
     );
 }
 
@@ -493,11 +550,11 @@ fn print_deleted_pathbuf(pathbuf: &std::path::Path) {
 pub fn prune(
     config: &model::Configuration,
     options: &model::CommandOptions,
-    paths: &[String],
+    prune_options: &PruneOptions,
 ) -> Result<i32> {
     let exit_status: i32 = 0;
 
-    if options.dry_run {
+    if options.dry_run && !prune_options.dry_run {
         let msg = "NOTE: Safe mode enabled. Repositories will not be deleted.";
         println!("{}", Color::green(msg));
         let msg = "Use '--rm' to enable deletion.";
@@ -551,11 +608,13 @@ pub fn prune(
     }
 
     let root_path = config.root_path.to_path_buf();
-    let path_filters: Vec<std::path::PathBuf> = paths
+    let path_filters: Vec<std::path::PathBuf> = prune_options
+        .paths
         .iter()
         .map(|value| config.relative_pathbuf(value))
         .collect();
 
+    let remove_root_path = root_path.clone();
     rayon::scope_fifo(|scope| {
         // Spawn tasks in reverse order. Receivers first, senders after.
         scope.spawn_fifo(|remove_scope| {
@@ -564,6 +623,8 @@ pub fn prune(
                 recv_remove_path,
                 send_finished_path,
                 dry_run: options.dry_run,
+                event_handler: options.event_handler.clone(),
+                root_path: remove_root_path,
             };
             remove_paths.remove_paths(remove_scope);
         });
@@ -575,6 +636,7 @@ pub fn prune(
                 send_remove_path,
                 recv_finished_path,
                 no_prompt: options.no_prompt,
+                list_only: prune_options.dry_run,
                 quit,
             };
             prompt_user.prompt_for_deletion();
@@ -585,6 +647,8 @@ pub fn prune(
             let traverse_filesystem = TraverseFilesystem {
                 min_depth: options.min_depth,
                 max_depth: options.max_depth,
+                older_than: prune_options.older_than,
+                force: prune_options.force,
                 send_repo_path,
                 root_path,
                 path_filters: &path_filters,
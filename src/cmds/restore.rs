@@ -0,0 +1,129 @@
+use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+
+use super::super::cmd;
+use super::super::errors;
+use super::super::model;
+use super::completion;
+use super::snapshot;
+
+/// Restore trees to the state recorded by "garden snapshot"
+#[derive(Parser, Clone, Debug)]
+pub struct RestoreOptions {
+    /// Snapshot file to read (default: the garden file with a ".snapshot" extension)
+    #[arg(short, long, value_name = "file")]
+    pub input: Option<String>,
+    /// Discard uncommitted changes in trees that are restored
+    #[arg(short, long)]
+    pub force: bool,
+    /// Tree queries for the gardens, groups or trees to restore (default: all trees)
+    #[arg(add = ArgValueCandidates::new(completion::queries))]
+    pub queries: Vec<String>,
+}
+
+/// Main entry point for the "garden restore" command
+pub fn main(app: &mut model::ApplicationContext, options: &RestoreOptions) -> Result<()> {
+    let quiet = app.options.quiet;
+    let verbose = app.options.verbose;
+    let config = app.get_root_config_mut();
+    let path = match &options.input {
+        Some(input) => std::path::PathBuf::from(input),
+        None => snapshot::snapshot_path(config)?,
+    };
+    let snapshots = snapshot::read_snapshots(&path)?;
+
+    let mut exit_status = errors::EX_OK;
+    for ctx in &snapshot::contexts(config, &options.queries) {
+        let tree = &config.trees[ctx.tree];
+        let tree_snapshot = match snapshots.get(tree.get_name()) {
+            Some(tree_snapshot) if !tree.is_symlink => tree_snapshot,
+            _ => continue,
+        };
+        if !model::print_tree(tree, verbose, quiet) {
+            continue;
+        }
+        let tree_path = tree.path_as_ref()?;
+        let status = restore_tree(tree_path, tree_snapshot, options.force, verbose);
+        if status != errors::EX_OK {
+            // Return the last non-zero exit status.
+            exit_status = status;
+        }
+    }
+
+    cmd::result_from_exit_status(exit_status).map_err(|err| err.into())
+}
+
+/// Checkout the recorded branch and commit and re-apply the recorded changes.
+/// Trees with uncommitted changes are skipped unless "force" is true.
+fn restore_tree(
+    path: &str,
+    tree_snapshot: &snapshot::TreeSnapshot,
+    force: bool,
+    verbose: u8,
+) -> i32 {
+    let command = ["git", "status", "--porcelain", "--untracked-files=no"];
+    let is_dirty = cmd::capture_stdout(cmd::exec_in_dir(&command, path))
+        .map(|capture| !cmd::trim_stdout(&capture).is_empty())
+        .unwrap_or(false);
+    if is_dirty && !force {
+        eprintln!(
+            "error: {}: uncommitted changes (use --force to discard them)",
+            path
+        );
+        return errors::EX_ERROR;
+    }
+
+    // Branches are reset to the recorded commit.
+    let mut command = vec!["git", "checkout"];
+    if force {
+        command.push("--force");
+    }
+    match &tree_snapshot.branch {
+        Some(branch) => command.extend(["-B", branch.as_str()]),
+        None => command.push("--detach"),
+    }
+    command.push(&tree_snapshot.commit);
+    let status = run(&command, path, verbose);
+    if status != errors::EX_OK {
+        return status;
+    }
+
+    match &tree_snapshot.stash {
+        Some(stash) => pop_stash(path, stash, verbose),
+        None => errors::EX_OK,
+    }
+}
+
+/// Apply a stash commit and drop it from the stash list.
+fn pop_stash(path: &str, stash: &str, verbose: u8) -> i32 {
+    let status = run(&["git", "stash", "apply", stash], path, verbose);
+    if status != errors::EX_OK {
+        return status;
+    }
+    let command = ["git", "stash", "list", "--format=%H"];
+    let stashes = match cmd::capture_stdout(cmd::exec_in_dir(&command, path)) {
+        Ok(capture) => cmd::trim_stdout(&capture),
+        Err(_) => return errors::EX_OK,
+    };
+    match stashes.lines().position(|commit| commit == stash) {
+        Some(idx) => {
+            let stash_ref = format!("stash@{{{}}}", idx);
+            run(
+                &["git", "stash", "drop", "--quiet", &stash_ref],
+                path,
+                verbose,
+            )
+        }
+        None => errors::EX_OK,
+    }
+}
+
+/// Run a command in a tree and return its exit status.
+fn run(command: &[&str], path: &str, verbose: u8) -> i32 {
+    if verbose > 1 {
+        cmd::print_quoted_command(command);
+    }
+
+    cmd::status(cmd::exec_in_dir(command, path).join())
+}
@@ -1,21 +1,45 @@
 use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+use std::io::Write;
 
 use super::super::cmd;
 use super::super::errors;
 use super::super::eval;
 use super::super::model;
 use super::super::query;
+use super::completion;
 
-pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
-    let mut query = String::new();
-    let mut tree = String::new();
-    parse_args(&mut app.options, &mut query, &mut tree);
+/// Open a shell in a garden environment
+#[derive(Parser, Clone, Debug)]
+pub struct ShellOptions {
+    /// Query for trees to build an environment (default: choose a tree interactively)
+    #[arg(add = ArgValueCandidates::new(completion::queries))]
+    pub query: Option<String>,
+    /// Tree to chdir into
+    #[arg(add = ArgValueCandidates::new(completion::trees))]
+    pub tree: Option<String>,
+}
 
+/// Main entry point for the "garden shell" command
+pub fn main(app: &mut model::ApplicationContext, options: &ShellOptions) -> Result<()> {
+    let query = match &options.query {
+        Some(query) => query.to_string(),
+        None => pick_tree(app.get_root_config())?,
+    };
+    let query = &query;
+    // The shell is run quietly.
+    let mut exec_options = app.options.clone();
+    exec_options.quiet = true;
+    exec_options.verbose = 0;
     let config = app.get_root_config_mut();
-    let contexts = query::resolve_trees(config, &query);
+    let contexts = query::resolve_trees(config, query);
     if contexts.is_empty() {
-        // TODO errors::GardenError::TreeQueryMatchedNoTrees { query: query.into() }
-        error!("tree query matched zero trees: '{}'", query);
+        return Err(errors::GardenError::Usage(format!(
+            "tree query matched zero trees: '{}'",
+            query
+        ))
+        .into());
     }
 
     let mut context = contexts[0].clone();
@@ -24,7 +48,7 @@ pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
     // query that was used to find it then chdir into that tree.
     // This makes it convenient to have gardens and trees with the same name.
     for ctx in &contexts {
-        if config.trees[ctx.tree].get_name() == &query {
+        if config.trees[ctx.tree].get_name() == query {
             context.tree = ctx.tree;
             context.garden = ctx.garden;
             context.group = ctx.group;
@@ -32,10 +56,10 @@ pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
         }
     }
 
-    if !tree.is_empty() {
+    if let Some(tree) = &options.tree {
         let mut found = false;
 
-        if let Some(ctx) = query::tree_from_name(config, &tree, None, None) {
+        if let Some(ctx) = query::tree_from_name(config, tree, None, None) {
             for query_ctx in &contexts {
                 if ctx.tree == query_ctx.tree {
                     context.tree = query_ctx.tree;
@@ -46,22 +70,30 @@ pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
                 }
             }
         } else {
-            error!("unable to find '{}': No tree exists with that name", tree);
+            return Err(errors::GardenError::TreeNotFound { tree: tree.into() }.into());
         }
         if !found {
-            error!("'{}' was not found in the tree query '{}'", tree, query);
+            return Err(errors::GardenError::Usage(format!(
+                "'{}' was not found in the tree query '{}'",
+                tree, query
+            ))
+            .into());
         }
     }
 
-    // Evaluate garden.shell
-    let shell_expr = config.shell.clone();
-    let shell = eval::tree_value(config, &shell_expr, context.tree, context.garden);
+    let config = app.enter_context(&context);
+    // Evaluate the garden, tree or global shell.
+    let shell = eval::shell(config, &context, None, None);
+    // Paths to shell executables are used as-is so that Windows paths,
+    // e.g. "C:\\Program Files\\PowerShell\\7\\pwsh.exe", are not split.
+    let shell_command = if std::path::Path::new(&shell).is_file() {
+        Some(vec![shell.clone()])
+    } else {
+        shlex::split(&shell)
+    };
 
-    if let Some(value) = shlex::split(&shell) {
-        cmd::exec_in_context(
-            config, &context, /*quiet*/ true, /*verbose*/ 0, &value,
-        )
-        .map_err(|err| err.into())
+    if let Some(value) = shell_command {
+        cmd::exec_in_context(config, &context, &exec_options, &value).map_err(|err| err.into())
     } else {
         Err(errors::GardenError::InvalidConfiguration {
             msg: format!("unable to shlex::split '{}'", shell),
@@ -70,20 +102,90 @@ pub fn main(app: &mut model::ApplicationContext) -> Result<()> {
     }
 }
 
-/// Parse "shell" arguments.
-fn parse_args(options: &mut model::CommandOptions, query: &mut String, tree: &mut String) {
-    let mut ap = argparse::ArgumentParser::new();
-    ap.set_description("garden shell - Open a shell in a garden environment");
+/// Choose a tree interactively. The "sk" and "fzf" fuzzy finders are used
+/// when available. Otherwise a numbered list of trees is presented.
+pub fn pick_tree(config: &model::Configuration) -> Result<String> {
+    // Only trees that exist on disk can be chosen.
+    let trees: Vec<(&String, &String)> = config
+        .trees
+        .iter()
+        .filter(|tree| !tree.is_symlink)
+        .filter_map(|tree| {
+            let path = tree.path_as_ref().ok()?;
+            std::path::Path::new(path)
+                .exists()
+                .then_some((tree.get_name(), path))
+        })
+        .collect();
+    if trees.is_empty() {
+        return Err(
+            errors::GardenError::Usage("no trees are available to choose from".into()).into(),
+        );
+    }
+
+    // Fuzzy finders are only used interactively. The list is read from stdin otherwise.
+    let finder = ["sk", "fzf"]
+        .iter()
+        .find(|finder| atty::is(atty::Stream::Stdin) && which::which(finder).is_ok());
+    let name = match finder {
+        Some(finder) => pick_tree_with_finder(finder, &trees)?,
+        None => pick_tree_from_list(&trees)?,
+    };
+    if name.is_empty() {
+        return Err(errors::GardenError::Usage("no tree was selected".into()).into());
+    }
+
+    Ok(name)
+}
+
+/// Choose a tree using a fuzzy finder. Each candidate line contains the tree name
+/// and path separated by a tab.
+fn pick_tree_with_finder(finder: &str, trees: &[(&String, &String)]) -> Result<String> {
+    let candidates: Vec<String> = trees
+        .iter()
+        .map(|(name, path)| format!("{}\t{}", name, path))
+        .collect();
+    let command = [finder, "--prompt", "tree> "];
+    let exec = cmd::exec_cmd(&command).stdin(candidates.join("\n").as_str());
+    let capture = cmd::capture_stdout(exec)?;
+    if !capture.exit_status.success() {
+        // The finder was cancelled.
+        let status = cmd::status(Ok(capture.exit_status));
+        return Err(errors::GardenError::ExitStatus(status).into());
+    }
+    let output = cmd::trim_stdout(&capture);
 
-    ap.refer(query).required().add_argument(
-        "query",
-        argparse::Store,
-        "Query for trees to build an environment.",
-    );
+    Ok(output.split('\t').next().unwrap_or_default().to_string())
+}
 
-    ap.refer(tree)
-        .add_argument("tree", argparse::Store, "Tree to chdir into.");
+/// Choose a tree by entering its number or name.
+fn pick_tree_from_list(trees: &[(&String, &String)]) -> Result<String, errors::GardenError> {
+    for (idx, (name, path)) in trees.iter().enumerate() {
+        eprintln!(
+            "{:>3}) {}  {}",
+            idx + 1,
+            model::Color::cyan(name).bold(),
+            model::Color::blue(path)
+        );
+    }
+    eprint!("tree> ");
+    std::io::stderr().flush().ok();
 
-    options.args.insert(0, "garden shell".into());
-    cmd::parse_args(ap, options.args.to_vec());
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .map_err(|err| errors::GardenError::IOError(err.to_string()))?;
+    let answer = answer.trim();
+    if let Ok(number) = answer.parse::<usize>() {
+        if number > 0 && number <= trees.len() {
+            return Ok(trees[number - 1].0.to_string());
+        }
+    }
+    match trees.iter().find(|(name, _)| *name == answer) {
+        Some((name, _)) => Ok(name.to_string()),
+        None if answer.is_empty() => Ok(String::new()),
+        None => Err(errors::GardenError::TreeNotFound {
+            tree: answer.into(),
+        }),
+    }
 }
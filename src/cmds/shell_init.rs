@@ -0,0 +1,48 @@
+use anyhow::Result;
+use clap::Parser;
+
+/// Print a shell function that enables "garden cd <tree>"
+///
+/// The "garden" shell function changes directory to the tree printed by "garden cd"
+/// and runs the "garden" executable for all other commands.
+/// Add 'eval "$(garden shell-init bash)"' to ~/.bashrc,
+/// 'eval "$(garden shell-init zsh)"' to ~/.zshrc or
+/// "garden shell-init fish | source" to ~/.config/fish/config.fish.
+#[derive(Parser, Clone, Debug)]
+pub struct ShellInitOptions {
+    /// Shell to generate the function for
+    #[arg(value_parser = ["bash", "fish", "zsh"])]
+    pub shell: String,
+}
+
+/// Shell function for bash and zsh.
+const POSIX_FUNCTION: &str = r#"garden() {
+    if [ "$1" = cd ]; then
+        shift
+        local garden_dir
+        garden_dir="$(command garden cd "$@")" && builtin cd -- "$garden_dir"
+    else
+        command garden "$@"
+    fi
+}
+"#;
+
+/// Shell function for fish.
+const FISH_FUNCTION: &str = r#"function garden --wraps garden
+    if test "$argv[1]" = cd
+        set -l garden_dir (command garden cd $argv[2..-1]); and builtin cd -- $garden_dir
+    else
+        command garden $argv
+    end
+end
+"#;
+
+/// Main entry point for the "garden shell-init" command
+pub fn main(options: &ShellInitOptions) -> Result<()> {
+    match options.shell.as_str() {
+        "fish" => print!("{}", FISH_FUNCTION),
+        _ => print!("{}", POSIX_FUNCTION),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::yaml::Yaml;
+use yaml_rust::YamlLoader;
+
+use super::super::cmd;
+use super::super::config;
+use super::super::errors;
+use super::super::git;
+use super::super::model;
+use super::super::query;
+use super::completion;
+
+/// Record the branch, commit and uncommitted changes of each tree
+#[derive(Parser, Clone, Debug)]
+pub struct SnapshotOptions {
+    /// Snapshot file to write (default: the garden file with a ".snapshot" extension)
+    #[arg(short, long, value_name = "file")]
+    pub output: Option<String>,
+    /// Tree queries for the gardens, groups or trees to snapshot (default: all trees)
+    #[arg(add = ArgValueCandidates::new(completion::queries))]
+    pub queries: Vec<String>,
+}
+
+/// The state of a tree recorded by "garden snapshot".
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TreeSnapshot {
+    /// The checked out branch. None when HEAD is detached.
+    pub branch: Option<String>,
+    /// The commit checked out at HEAD.
+    pub commit: String,
+    /// The stash commit that records uncommitted changes.
+    pub stash: Option<String>,
+}
+
+/// Tree snapshots keyed by tree name.
+pub type Snapshots = std::collections::BTreeMap<String, TreeSnapshot>;
+
+/// Main entry point for the "garden snapshot" command
+pub fn main(app: &mut model::ApplicationContext, options: &SnapshotOptions) -> Result<()> {
+    let quiet = app.options.quiet;
+    let verbose = app.options.verbose;
+    let config = app.get_root_config_mut();
+    let path = match &options.output {
+        Some(output) => std::path::PathBuf::from(output),
+        None => snapshot_path(config)?,
+    };
+
+    // Snapshotting specific trees updates the entries in an existing snapshot.
+    let mut snapshots = if options.queries.is_empty() || !path.exists() {
+        Snapshots::new()
+    } else {
+        read_snapshots(&path)?
+    };
+
+    let backend = git::backend();
+    for ctx in &contexts(config, &options.queries) {
+        let tree = &config.trees[ctx.tree];
+        if tree.is_symlink {
+            continue;
+        }
+        let tree_path = tree.path_as_ref()?;
+        // Sparse gardens/missing trees are ok -> skip these entries.
+        if !std::path::PathBuf::from(tree_path).exists() {
+            if !quiet {
+                eprintln!("{}", model::display_missing_tree(tree, tree_path, verbose));
+            }
+            continue;
+        }
+        let commit = match backend.head_commit(std::path::Path::new(tree_path)) {
+            Ok(commit) => commit,
+            Err(err) => {
+                if !quiet {
+                    eprintln!("{}: {}", tree.get_name(), err);
+                }
+                continue;
+            }
+        };
+        let stash = if tree.is_bare_repository {
+            None
+        } else {
+            stash_changes(tree_path, verbose)
+        };
+        snapshots.insert(
+            tree.get_name().to_string(),
+            TreeSnapshot {
+                branch: git::current_branch(tree_path),
+                commit,
+                stash,
+            },
+        );
+    }
+
+    write_snapshots(&snapshots, &path)?;
+
+    Ok(())
+}
+
+/// Return the tree contexts for a set of queries. All trees are returned when
+/// no queries are specified.
+pub fn contexts(config: &model::Configuration, queries: &[String]) -> Vec<model::TreeContext> {
+    if queries.is_empty() {
+        (0..config.trees.len())
+            .map(|tree_idx| model::TreeContext::new(tree_idx, config.get_id(), None, None))
+            .collect()
+    } else {
+        queries
+            .iter()
+            .flat_map(|query| query::resolve_trees(config, query))
+            .collect()
+    }
+}
+
+/// Record the uncommitted changes in a tree as a stash commit without modifying
+/// the worktree. The commit is stored in the stash list so that it is not garbage
+/// collected. None is returned when there are no uncommitted changes.
+fn stash_changes(path: &str, verbose: u8) -> Option<String> {
+    let command = ["git", "stash", "create", "garden snapshot"];
+    let capture = cmd::capture_stdout(cmd::exec_in_dir(&command, path)).ok()?;
+    let stash = cmd::trim_stdout(&capture);
+    if stash.is_empty() {
+        return None;
+    }
+    let command = ["git", "stash", "store", "-m", "garden snapshot", &stash];
+    if verbose > 1 {
+        cmd::print_quoted_command(&command);
+    }
+    if cmd::status(cmd::exec_in_dir(&command, path).join()) != errors::EX_OK {
+        return None;
+    }
+
+    Some(stash)
+}
+
+/// Return the default snapshot path for a configuration.
+/// "garden.yaml" is snapshotted into "garden.snapshot".
+pub fn snapshot_path(
+    config: &model::Configuration,
+) -> Result<std::path::PathBuf, errors::GardenError> {
+    Ok(config.get_path()?.with_extension("snapshot"))
+}
+
+/// Read tree snapshots from a snapshot file.
+pub fn read_snapshots(path: &std::path::Path) -> Result<Snapshots, errors::GardenError> {
+    let string = std::fs::read_to_string(path).map_err(|io_err| errors::GardenError::ReadFile {
+        path: path.into(),
+        err: io_err,
+    })?;
+    let docs =
+        YamlLoader::load_from_str(&string).map_err(|err| errors::GardenError::ReadConfig {
+            err,
+            path: path.display().to_string(),
+        })?;
+
+    let mut snapshots = Snapshots::new();
+    if let Some(Yaml::Hash(trees)) = docs.first().map(|doc| &doc["trees"]) {
+        for (name, value) in trees {
+            if let (Some(name), Some(commit)) = (name.as_str(), value["commit"].as_str()) {
+                snapshots.insert(
+                    name.to_string(),
+                    TreeSnapshot {
+                        branch: value["branch"].as_str().map(String::from),
+                        commit: commit.to_string(),
+                        stash: value["stash"].as_str().map(String::from),
+                    },
+                );
+            }
+        }
+    }
+
+    Ok(snapshots)
+}
+
+/// Write tree snapshots to a snapshot file.
+fn write_snapshots(
+    snapshots: &Snapshots,
+    path: &std::path::Path,
+) -> Result<(), errors::GardenError> {
+    let mut trees = YamlHash::new();
+    for (name, snapshot) in snapshots {
+        let mut entry = YamlHash::new();
+        if let Some(branch) = &snapshot.branch {
+            entry.insert(Yaml::String("branch".into()), Yaml::String(branch.into()));
+        }
+        entry.insert(
+            Yaml::String("commit".into()),
+            Yaml::String(snapshot.commit.to_string()),
+        );
+        if let Some(stash) = &snapshot.stash {
+            entry.insert(Yaml::String("stash".into()), Yaml::String(stash.into()));
+        }
+        trees.insert(Yaml::String(name.into()), Yaml::Hash(entry));
+    }
+    let mut doc = YamlHash::new();
+    doc.insert(Yaml::String("trees".into()), Yaml::Hash(trees));
+
+    config::writer::write_yaml(&Yaml::Hash(doc), path)
+}
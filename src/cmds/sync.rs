@@ -0,0 +1,120 @@
+use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+
+use super::super::cmd;
+use super::super::errors;
+use super::super::model;
+use super::super::query;
+use super::completion;
+
+/// Fetch remotes and fast-forward trees
+#[derive(Parser, Clone, Debug)]
+pub struct SyncOptions {
+    /// Rebase the current branch onto its upstream instead of fast-forwarding
+    #[arg(long)]
+    pub rebase: bool,
+    /// Tree queries for the gardens, groups or trees to sync
+    #[arg(add = ArgValueCandidates::new(completion::queries), required = true)]
+    pub queries: Vec<String>,
+}
+
+/// Main entry point for the "garden sync" command
+pub fn main(app: &mut model::ApplicationContext, options: &SyncOptions) -> Result<()> {
+    let quiet = app.options.quiet;
+    let verbose = app.options.verbose;
+    let config = app.get_root_config_mut();
+
+    let mut exit_status = errors::EX_OK;
+    for query in &options.queries {
+        let status = sync(config, quiet, verbose, options.rebase, query)?;
+        if status != errors::EX_OK {
+            exit_status = status;
+        }
+    }
+
+    // Return the last non-zero exit status.
+    cmd::result_from_exit_status(exit_status).map_err(|err| err.into())
+}
+
+/// Fetch and update the trees in the evaluated tree query.
+pub fn sync(
+    config: &mut model::Configuration,
+    quiet: bool,
+    verbose: u8,
+    rebase: bool,
+    query: &str,
+) -> Result<i32> {
+    let contexts = query::resolve_trees(config, query);
+    let mut exit_status = errors::EX_OK;
+
+    for ctx in &contexts {
+        let tree = &config.trees[ctx.tree];
+        // Skip symlink trees.
+        if tree.is_symlink {
+            continue;
+        }
+        if !model::print_tree(tree, verbose, quiet) {
+            continue;
+        }
+        let status = sync_tree(tree, quiet, verbose, rebase)?;
+        if status != errors::EX_OK {
+            // Return the last non-zero exit status.
+            exit_status = status;
+        }
+    }
+
+    Ok(exit_status)
+}
+
+/// Fetch all of the tree's remotes and update its current branch.
+fn sync_tree(tree: &model::Tree, quiet: bool, verbose: u8, rebase: bool) -> Result<i32> {
+    let path = tree.path_as_ref()?;
+
+    // Dirty trees are left untouched.
+    let status_cmd = ["git", "status", "--porcelain", "--untracked-files=no"];
+    let capture = cmd::capture_stdout(cmd::exec_in_dir(&status_cmd, path))?;
+    if !cmd::trim_stdout(&capture).is_empty() {
+        if !quiet {
+            eprintln!(
+                "warning: {}: skipping tree with uncommitted changes",
+                tree.get_name()
+            );
+        }
+        return Ok(errors::EX_OK);
+    }
+
+    for remote in &tree.remotes {
+        let command = ["git", "fetch", "--quiet", remote.get_name()];
+        if verbose > 1 {
+            cmd::print_quoted_command(&command);
+        }
+        let exit_status = cmd::status(cmd::exec_in_dir(&command, path).join());
+        if exit_status != errors::EX_OK {
+            return Ok(exit_status);
+        }
+    }
+
+    // Trees without an upstream branch, e.g. detached HEADs, are fetched only.
+    let upstream_cmd = ["git", "rev-parse", "--quiet", "--verify", "@{upstream}"];
+    let upstream = cmd::exec_in_dir(&upstream_cmd, path)
+        .stdout(subprocess::NullFile)
+        .stderr(subprocess::NullFile);
+    if cmd::status(upstream.join()) != errors::EX_OK {
+        if verbose > 0 {
+            eprintln!("{}: no upstream branch", tree.get_name());
+        }
+        return Ok(errors::EX_OK);
+    }
+
+    let command = if rebase {
+        ["git", "rebase", "--quiet", "@{upstream}"]
+    } else {
+        ["git", "merge", "--ff-only", "@{upstream}"]
+    };
+    if verbose > 1 {
+        cmd::print_quoted_command(&command);
+    }
+
+    Ok(cmd::status(cmd::exec_in_dir(&command, path).join()))
+}
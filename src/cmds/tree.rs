@@ -0,0 +1,171 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::yaml::Yaml;
+
+use super::super::config;
+use super::super::errors;
+use super::super::model;
+
+/// Add trees to a garden file
+#[derive(Parser, Clone, Debug)]
+pub struct TreeOptions {
+    #[command(subcommand)]
+    pub command: TreeCommand,
+}
+
+/// Tree subcommands.
+#[derive(Clone, Debug, Subcommand)]
+pub enum TreeCommand {
+    /// Add a new tree entry to a garden file
+    Add(TreeAddOptions),
+}
+
+/// Add a new tree entry to a garden file
+#[derive(Parser, Clone, Debug)]
+pub struct TreeAddOptions {
+    /// File to write (default: garden.yaml)
+    #[arg(short, long, value_name = "file")]
+    pub output: Option<String>,
+    /// URL to clone the tree from
+    #[arg(short, long, value_name = "url")]
+    pub url: Option<String>,
+    /// Path to the tree relative to the garden root (default: <name>)
+    #[arg(short, long, value_name = "path")]
+    pub path: Option<String>,
+    /// Branch to checkout when the tree is grown
+    #[arg(short, long, value_name = "branch")]
+    pub branch: Option<String>,
+    /// Template to apply to the tree (repeatable)
+    #[arg(short, long = "template", value_name = "template")]
+    pub templates: Vec<String>,
+    /// Add the tree to a group. The group is created when it does not exist (repeatable)
+    #[arg(short, long = "group", value_name = "group")]
+    pub groups: Vec<String>,
+    /// Name of the tree to add
+    pub name: String,
+}
+
+/// Main entry point for the "garden tree" command
+pub fn main(app: &mut model::ApplicationContext, options: &TreeOptions) -> Result<()> {
+    match &options.command {
+        TreeCommand::Add(add_options) => add(app, add_options),
+    }
+}
+
+/// Add a tree entry to the garden file without requiring an existing checkout.
+fn add(app: &mut model::ApplicationContext, options: &TreeAddOptions) -> Result<()> {
+    let config = app.get_root_config();
+    if options.name.is_empty() {
+        return Err(errors::GardenError::Usage("a tree name must be specified".into()).into());
+    }
+    if config
+        .trees
+        .iter()
+        .any(|tree| tree.get_name() == &options.name)
+    {
+        return Err(
+            errors::GardenError::Usage(format!("tree already exists: {}", options.name)).into(),
+        );
+    }
+    for template in &options.templates {
+        if !config
+            .templates
+            .iter()
+            .any(|config_template| config_template.get_name() == template)
+        {
+            return Err(
+                errors::GardenError::Usage(format!("unknown template: {}", template)).into(),
+            );
+        }
+    }
+    let mut doc = config::reader::read_yaml(config.get_path()?)?;
+
+    // Output filename defaults to the input filename.
+    let output = match &options.output {
+        Some(output) => output.to_string(),
+        None => config.get_path()?.to_string_lossy().into(),
+    };
+
+    // Mutable YAML scope.
+    {
+        let doc_hash: &mut YamlHash = match doc {
+            Yaml::Hash(ref mut hash) => hash,
+            _ => {
+                return Err(errors::GardenError::InvalidConfiguration {
+                    msg: "not a hash".into(),
+                }
+                .into());
+            }
+        };
+        let trees = get_hash_mut(doc_hash, "trees")?;
+        trees.insert(Yaml::String(options.name.clone()), tree_entry(options));
+
+        for group in &options.groups {
+            let groups = get_hash_mut(doc_hash, "groups")?;
+            add_group_member(groups, group, &options.name);
+        }
+    }
+
+    Ok(config::writer::write_yaml(&doc, &output)?)
+}
+
+/// Build the YAML entry for a new tree.
+fn tree_entry(options: &TreeAddOptions) -> Yaml {
+    let mut entry = YamlHash::new();
+    if !options.templates.is_empty() {
+        entry.insert(
+            Yaml::String("templates".into()),
+            string_or_list(&options.templates),
+        );
+    }
+    if let Some(path) = options.path.as_ref().filter(|path| **path != options.name) {
+        entry.insert(Yaml::String("path".into()), Yaml::String(path.clone()));
+    }
+    if let Some(url) = &options.url {
+        entry.insert(Yaml::String("url".into()), Yaml::String(url.clone()));
+    }
+    if let Some(branch) = &options.branch {
+        entry.insert(Yaml::String("branch".into()), Yaml::String(branch.clone()));
+    }
+
+    Yaml::Hash(entry)
+}
+
+/// Return a single value as a string and multiple values as a list.
+fn string_or_list(values: &[String]) -> Yaml {
+    match values {
+        [value] => Yaml::String(value.clone()),
+        _ => Yaml::Array(values.iter().cloned().map(Yaml::String).collect()),
+    }
+}
+
+/// Return a mutable reference to a top-level hash, creating it when it does not exist.
+fn get_hash_mut<'a>(doc_hash: &'a mut YamlHash, name: &str) -> Result<&'a mut YamlHash> {
+    let key = Yaml::String(name.into());
+    if !doc_hash.contains_key(&key) || doc_hash[&key].is_null() {
+        doc_hash.insert(key.clone(), Yaml::Hash(YamlHash::new()));
+    }
+    match doc_hash.get_mut(&key) {
+        Some(Yaml::Hash(ref mut hash)) => Ok(hash),
+        _ => Err(errors::GardenError::InvalidConfiguration {
+            msg: format!("{}: not a hash", name),
+        }
+        .into()),
+    }
+}
+
+/// Append a tree to a group. Groups that are a single string are promoted to a list.
+fn add_group_member(groups: &mut YamlHash, group: &str, tree: &str) {
+    let key = Yaml::String(group.into());
+    let tree = Yaml::String(tree.into());
+    let mut members = match groups.get(&key) {
+        Some(Yaml::Array(members)) => members.clone(),
+        Some(Yaml::String(member)) => vec![Yaml::String(member.clone())],
+        _ => Vec::new(),
+    };
+    if !members.contains(&tree) {
+        members.push(tree);
+        groups.insert(key, Yaml::Array(members));
+    }
+}
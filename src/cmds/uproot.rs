@@ -0,0 +1,216 @@
+use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::yaml::Yaml;
+
+use super::super::cmd;
+use super::super::config;
+use super::super::errors;
+use super::super::git;
+use super::super::model;
+use super::super::query;
+use super::completion;
+
+/// Remove trees from a garden file
+#[derive(Parser, Clone, Debug)]
+pub struct UprootOptions {
+    /// File to write (default: garden.yaml)
+    #[arg(short, long, value_name = "file")]
+    pub output: Option<String>,
+    /// Remove the trees from the groups and gardens that reference them
+    #[arg(short, long)]
+    pub references: bool,
+    /// Delete the trees' working directories
+    #[arg(long)]
+    pub delete: bool,
+    /// Delete working directories that contain uncommitted changes or unpushed commits
+    #[arg(short, long, requires = "delete")]
+    pub force: bool,
+    /// Trees to uproot
+    #[arg(add = ArgValueCandidates::new(completion::trees), required = true)]
+    pub trees: Vec<String>,
+}
+
+/// Main entry point for the "garden uproot" command
+pub fn main(app: &mut model::ApplicationContext, options: &UprootOptions) -> Result<()> {
+    let quiet = app.options.quiet;
+    let verbose = app.options.verbose;
+    let config = app.get_root_config();
+    let mut doc = config::reader::read_yaml(config.get_path()?)?;
+
+    // Output filename defaults to the input filename.
+    let output = match &options.output {
+        Some(output) => output.to_string(),
+        None => config.get_path()?.to_string_lossy().into(),
+    };
+
+    // Validate the trees and their working directories before making any changes.
+    let mut delete_paths = Vec::new();
+    for tree in &options.trees {
+        let ctx = query::tree_from_name(config, tree, None, None)
+            .filter(|ctx| config.trees[ctx.tree].get_name() == tree)
+            .ok_or_else(|| errors::GardenError::TreeNotFound { tree: tree.into() })?;
+        // Trees that are defined by "includes" files cannot be removed.
+        if !is_defined(&doc, tree) {
+            return Err(errors::GardenError::ConfigurationError(format!(
+                "{}: tree is not defined in {}",
+                tree, output
+            ))
+            .into());
+        }
+        if options.delete {
+            if let Some(path) = deletable_path(config, &ctx, options)? {
+                delete_paths.push((ctx, path));
+            }
+        }
+    }
+
+    // Mutable YAML scope.
+    {
+        let doc_hash: &mut YamlHash = match doc {
+            Yaml::Hash(ref mut hash) => hash,
+            _ => {
+                return Err(errors::GardenError::InvalidConfiguration {
+                    msg: "not a hash".into(),
+                }
+                .into());
+            }
+        };
+
+        if let Some(Yaml::Hash(trees)) = doc_hash.get_mut(&Yaml::String("trees".into())) {
+            for tree in &options.trees {
+                trees.remove(&Yaml::String(tree.into()));
+            }
+        }
+
+        if options.references {
+            if let Some(Yaml::Hash(groups)) = doc_hash.get_mut(&Yaml::String("groups".into())) {
+                for (_, members) in groups.iter_mut() {
+                    remove_names(members, &options.trees);
+                }
+            }
+            if let Some(Yaml::Hash(gardens)) = doc_hash.get_mut(&Yaml::String("gardens".into())) {
+                for (_, garden) in gardens.iter_mut() {
+                    if let Yaml::Hash(garden) = garden {
+                        if let Some(trees) = garden.get_mut(&Yaml::String("trees".into())) {
+                            remove_names(trees, &options.trees);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    config::writer::write_yaml(&doc, &output)?;
+
+    for (ctx, path) in &delete_paths {
+        let tree = &config.trees[ctx.tree];
+        if !quiet {
+            eprintln!(
+                "{}",
+                model::display_tree(tree, &path.to_string_lossy(), verbose)
+            );
+        }
+        if tree.is_symlink {
+            std::fs::remove_file(path)
+        } else {
+            std::fs::remove_dir_all(path)
+        }
+        .map_err(|err| errors::GardenError::OSError(format!("{:?}: {}", path, err)))?;
+
+        // Let the parent repository know that the worktree has been removed.
+        if tree.is_worktree {
+            let parent_path = query::shared_worktree_path(config, ctx);
+            let command = ["git", "worktree", "prune"];
+            cmd::exec_in_dir(&command, &parent_path).join()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Return true when a tree is defined in the "trees" block of a garden file.
+fn is_defined(doc: &Yaml, tree: &str) -> bool {
+    match &doc["trees"] {
+        Yaml::Hash(trees) => trees.contains_key(&Yaml::String(tree.into())),
+        _ => false,
+    }
+}
+
+/// Return the path to delete for a tree after checking that it is safe to delete.
+/// None is returned when the tree does not exist on disk.
+fn deletable_path(
+    config: &model::Configuration,
+    ctx: &model::TreeContext,
+    options: &UprootOptions,
+) -> Result<Option<std::path::PathBuf>, errors::GardenError> {
+    let tree = &config.trees[ctx.tree];
+    let path = std::path::PathBuf::from(tree.path_as_ref()?);
+    if tree.is_symlink {
+        return Ok(path.is_symlink().then_some(path));
+    }
+    let path = match path.canonicalize() {
+        Ok(path) => path,
+        Err(_) => return Ok(None),
+    };
+    let unsafe_path = |reason: String| {
+        errors::GardenError::Usage(format!(
+            "{}: unable to delete {:?}: {}",
+            tree.get_name(),
+            path,
+            reason
+        ))
+    };
+
+    // Never delete the garden root or directories outside of it.
+    let root = config
+        .root_path
+        .canonicalize()
+        .unwrap_or_else(|_| config.root_path.clone());
+    if path == root || !path.starts_with(&root) {
+        return Err(unsafe_path(
+            "the path is not inside of the garden root".into(),
+        ));
+    }
+
+    // Never delete trees that contain other trees.
+    for (idx, other) in config.trees.iter().enumerate() {
+        if idx == ctx.tree || other.is_symlink || options.trees.contains(other.get_name()) {
+            continue;
+        }
+        let other_path = other
+            .path_as_ref()
+            .ok()
+            .and_then(|other_path| std::path::PathBuf::from(other_path).canonicalize().ok());
+        if let Some(other_path) = other_path {
+            if other_path.starts_with(&path) {
+                return Err(unsafe_path(format!("contains tree {}", other.get_name())));
+            }
+        }
+    }
+
+    if !options.force {
+        if let Some(reason) = git::unsaved_work(&path) {
+            return Err(unsafe_path(format!("{}, use '--force' to delete", reason)));
+        }
+    }
+
+    Ok(Some(path))
+}
+
+/// Remove the specified names from a string or list of strings.
+fn remove_names(yaml: &mut Yaml, names: &[String]) {
+    match yaml {
+        Yaml::Array(values) => {
+            values.retain(|value| match value.as_str() {
+                Some(value) => !names.iter().any(|name| name == value),
+                None => true,
+            });
+        }
+        Yaml::String(value) if names.contains(value) => {
+            *yaml = Yaml::Array(Vec::new());
+        }
+        _ => (),
+    }
+}
@@ -0,0 +1,136 @@
+use anyhow::Result;
+use clap::Parser;
+use clap_complete::engine::ArgValueCandidates;
+
+use super::super::eval;
+use super::super::model;
+use super::super::model::Color;
+use super::super::query;
+use super::completion;
+
+/// List variables and the scopes they are defined in
+#[derive(Parser, Clone, Debug)]
+pub struct VarsOptions {
+    /// Tree whose variables should be listed
+    #[arg(add = ArgValueCandidates::new(completion::trees))]
+    pub tree: Option<String>,
+    /// Garden whose variables should be listed
+    #[arg(add = ArgValueCandidates::new(completion::gardens))]
+    pub garden: Option<String>,
+}
+
+/// A variable definition and the scope it was found in.
+struct ScopedVariable {
+    scope: &'static str,
+    name: String,
+    expr: String,
+    value: String,
+    overridden_by: Option<&'static str>,
+}
+
+/// Main entry point for the "garden vars" command
+pub fn main(app: &mut model::ApplicationContext, options: &VarsOptions) -> Result<()> {
+    let config = app.get_root_config_mut();
+    let variables = match &options.tree {
+        Some(tree) => {
+            let ctx = query::tree_context(config, tree, options.garden.as_deref())?;
+            tree_variables(config, ctx.tree, ctx.garden)
+        }
+        None => config_variables(config),
+    };
+    print_variables(&variables);
+
+    Ok(())
+}
+
+/// Return the variables visible at global scope.
+fn config_variables(config: &model::Configuration) -> Vec<ScopedVariable> {
+    let mut variables = Vec::new();
+    add_variables(&mut variables, "config", &config.variables, |expr| {
+        eval::value(config, expr)
+    });
+
+    variables
+}
+
+/// Return the variables visible in a tree context. Variables are listed in order
+/// of precedence: garden scope overrides tree scope, which overrides global scope.
+fn tree_variables(
+    config: &model::Configuration,
+    tree_idx: model::TreeIndex,
+    garden_idx: Option<model::GardenIndex>,
+) -> Vec<ScopedVariable> {
+    let evaluate = |expr: &str| eval::tree_value(config, expr, tree_idx, garden_idx);
+    let mut variables = Vec::new();
+    if let Some(garden_idx) = garden_idx {
+        let garden_vars = &config.gardens[garden_idx].variables;
+        add_variables(&mut variables, "garden", garden_vars, evaluate);
+    }
+    let tree_vars = &config.trees[tree_idx].variables;
+    add_variables(&mut variables, "tree", tree_vars, evaluate);
+    add_variables(&mut variables, "config", &config.variables, evaluate);
+
+    variables
+}
+
+/// Evaluate and record the variables from a single scope.
+/// Variables that were already defined by a higher-precedence scope are marked
+/// as being overridden by that scope.
+fn add_variables<F>(
+    variables: &mut Vec<ScopedVariable>,
+    scope: &'static str,
+    scope_vars: &[model::NamedVariable],
+    evaluate: F,
+) where
+    F: Fn(&str) -> String,
+{
+    for var in scope_vars {
+        let overridden_by = variables
+            .iter()
+            .find(|existing| existing.name == *var.get_name() && existing.overridden_by.is_none())
+            .map(|existing| existing.scope);
+        variables.push(ScopedVariable {
+            scope,
+            name: var.get_name().to_string(),
+            expr: var.get_expr().to_string(),
+            value: evaluate(var.get_expr()),
+            overridden_by,
+        });
+    }
+}
+
+/// Print variables along with their scope, expression and resolved value.
+fn print_variables(variables: &[ScopedVariable]) {
+    let width = variables
+        .iter()
+        .map(|var| var.scope.len())
+        .max()
+        .unwrap_or_default();
+    for var in variables {
+        let scope = format!("{:width$}", var.scope, width = width);
+        match var.overridden_by {
+            Some(overridden_by) => println!(
+                "{} {} = {} {}",
+                Color::cyan(scope).dimmed(),
+                Color::blue(&var.name).dimmed(),
+                Color::white(&var.value).dimmed(),
+                Color::yellow(format!("(overridden by {})", overridden_by)).dimmed(),
+            ),
+            None => println!(
+                "{} {} = {}",
+                Color::cyan(scope),
+                Color::blue(&var.name).bold(),
+                var.value,
+            ),
+        }
+        if var.expr != var.value {
+            println!(
+                "{:width$}   {} {}",
+                "",
+                Color::black("#").bold(),
+                var.expr,
+                width = width
+            );
+        }
+    }
+}
@@ -1,7 +1,11 @@
 use super::errors;
+use super::eval;
+use super::git;
 use super::model;
 use super::model::ConfigId;
 use super::path;
+use super::query;
+use super::syntax;
 
 /// YAML reader
 pub mod reader;
@@ -13,15 +17,21 @@ pub mod writer;
 //  .
 //  ./garden
 //  ./etc/garden
+//  $GARDEN_CONFIG_PATH
 //  ~/.config/garden
 //  ~/etc/garden
 //  /etc/garden
 
 fn search_path() -> Vec<std::path::PathBuf> {
+    search_path_from(&path::current_dir())
+}
+
+/// Return the search path using the specified directory as the current directory.
+fn search_path_from(current_dir: &std::path::Path) -> Vec<std::path::PathBuf> {
     // Result: Vec<PathBufs> in priority order
     let mut paths: Vec<std::path::PathBuf> = Vec::new();
 
-    let current_dir = path::current_dir();
+    let current_dir = current_dir.to_path_buf();
     let home_dir = path::home_dir();
 
     // . Current directory
@@ -42,6 +52,11 @@ fn search_path() -> Vec<std::path::PathBuf> {
         paths.push(current_etc_garden_dir);
     }
 
+    // $GARDEN_CONFIG_PATH directories in the order they are listed.
+    if let Some(config_path) = std::env::var_os("GARDEN_CONFIG_PATH") {
+        paths.extend(std::env::split_paths(&config_path).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+
     // $XDG_CONFIG_HOME/garden (typically ~/.config/garden)
     paths.push(xdg_dir());
 
@@ -64,30 +79,74 @@ fn search_path() -> Vec<std::path::PathBuf> {
 
 /// $XDG_CONFIG_HOME/garden (typically ~/.config/garden)
 pub fn xdg_dir() -> std::path::PathBuf {
-    let mut home_config_dir;
-
-    if let Ok(xdg_dirs) = xdg::BaseDirectories::new() {
-        home_config_dir = xdg_dirs.get_config_home();
-    } else {
-        home_config_dir = path::home_dir();
-        home_config_dir.push(".config")
-    }
+    let mut home_config_dir = config_home_dir();
     home_config_dir.push("garden");
 
     home_config_dir
 }
 
+/// $XDG_CONFIG_HOME (typically ~/.config)
+#[cfg(unix)]
+fn config_home_dir() -> std::path::PathBuf {
+    match xdg::BaseDirectories::new() {
+        Ok(xdg_dirs) => xdg_dirs.get_config_home(),
+        Err(_) => path::home_dir().join(".config"),
+    }
+}
+
+/// The user's configuration directory, e.g. %APPDATA% on Windows.
+#[cfg(not(unix))]
+fn config_home_dir() -> std::path::PathBuf {
+    dirs::config_dir().unwrap_or_else(|| path::home_dir().join(".config"))
+}
+
 pub fn new(
     config: &Option<std::path::PathBuf>,
     root: &str,
     config_verbose: u8,
     parent: Option<ConfigId>,
+) -> Result<model::Configuration, errors::GardenError> {
+    let fetch_options = model::FetchOptions::default();
+    read(
+        config,
+        root,
+        config_verbose,
+        parent,
+        &[],
+        None,
+        fetch_options,
+    )
+}
+
+/// Read configuration and layer the "overlays" files and the selected profile on top of it.
+/// Configuration URLs are fetched into the configuration cache.
+fn read(
+    config: &Option<std::path::PathBuf>,
+    root: &str,
+    config_verbose: u8,
+    parent: Option<ConfigId>,
+    overlays: &[std::path::PathBuf],
+    profile: Option<(&str, model::ProfileSource)>,
+    fetch_options: model::FetchOptions,
 ) -> Result<model::Configuration, errors::GardenError> {
     let mut cfg = model::Configuration::new();
     if let Some(parent_id) = parent {
         cfg.set_parent(parent_id);
     }
     cfg.verbose = config_verbose;
+    cfg.fetch_options = fetch_options;
+    cfg.overlays = overlays
+        .iter()
+        .map(|overlay| fetch_config_file(&mut cfg, overlay))
+        .collect::<Result<_, _>>()?;
+    if let Some((name, source)) = profile {
+        cfg.profile = Some(name.to_string());
+        cfg.profile_source = source;
+    }
+    let config = match config {
+        Some(path) => Some(fetch_config_file(&mut cfg, path)?),
+        None => None,
+    };
 
     // Override the configured garden root
     if !root.is_empty() {
@@ -98,7 +157,7 @@ pub fn new(
 
     // Find garden.yaml in the search path
     let mut found = false;
-    if let Some(config_path) = config {
+    if let Some(config_path) = &config {
         if config_path.is_file() || config_path.is_absolute() {
             // If an absolute path was specified, or if the file exists,
             // short-circuit the search; the config file might be missing but
@@ -150,6 +209,43 @@ pub fn new(
     Ok(cfg)
 }
 
+/// Find a garden file using the specified directory as the current directory.
+/// "garden.yaml" is searched for when no filename is specified.
+pub fn find_config_path(
+    dir: &std::path::Path,
+    filename: Option<&std::path::Path>,
+) -> Option<std::path::PathBuf> {
+    let basename = filename.unwrap_or(std::path::Path::new("garden.yaml"));
+    let candidate = dir.join(basename);
+    if candidate.is_file() {
+        return Some(candidate);
+    }
+    if basename.is_absolute() {
+        return None;
+    }
+
+    search_path_from(dir)
+        .into_iter()
+        .map(|entry| entry.join(basename))
+        .find(|candidate| candidate.exists())
+}
+
+/// Fetch a configuration URL into the configuration cache and record the URL
+/// that the cached copy was fetched from. Local paths are returned as-is.
+fn fetch_config_file(
+    cfg: &mut model::Configuration,
+    path: &std::path::Path,
+) -> Result<std::path::PathBuf, errors::GardenError> {
+    let url = path.to_string_lossy().to_string();
+    if !syntax::is_http_url(&url) {
+        return Ok(path.to_path_buf());
+    }
+    let cache_path = reader::fetch_config_path(&url, cfg.fetch_options)?;
+    cfg.urls.insert(cache_path.clone(), url);
+
+    Ok(cache_path)
+}
+
 /// Read configuration from a path.  Wraps new() to make the path required..
 pub fn from_path(
     path: std::path::PathBuf,
@@ -173,42 +269,162 @@ pub fn from_options(
     options: &model::CommandOptions,
 ) -> Result<model::Configuration, errors::GardenError> {
     let config_verbose = options.debug_level("config");
-    let mut config = new(&options.filename, &options.root, config_verbose, None)?;
+    let mut config = read(
+        &options.filename,
+        &options.root,
+        config_verbose,
+        None,
+        &options.overlays,
+        options
+            .profile
+            .as_deref()
+            .map(|name| (name, options.profile_source)),
+        options.fetch_options(),
+    )?;
 
     if config.path.is_none() {
-        error!("unable to find a configuration file -- use --config <path>");
+        return Err(errors::GardenError::ConfigurationError(
+            "unable to find a configuration file -- use --config <path>".into(),
+        ));
     }
     if config_verbose > 1 {
         eprintln!("config: {:?}", config.get_path()?);
     }
+
+    // Personal defaults from ~/.config/garden/garden.yaml are layered under the project.
+    if options.user_config {
+        read_user_defaults(&mut config, config_verbose)?;
+    }
+
     if config_verbose > 2 {
         debug!("{}", config);
     }
 
+    // Exec expressions are cached in "garden.cache" when "garden.cache" is enabled.
+    if config.cache && !options.no_cache {
+        let cache_path = config.get_path()?.with_extension("cache");
+        config.exec_cache = Some(model::ExecCache::new(cache_path));
+    }
+
     for key in &options.debug {
         let current = *config.debug.get(key).unwrap_or(&0);
         config.debug.insert(key.into(), current + 1);
     }
 
+    // Scoped variables are applied before "--exclude" so that excluded trees are
+    // still matched by their scope.
     for k_eq_v in &options.variables {
-        let name: String;
-        let expr: String;
-        let values: Vec<&str> = k_eq_v.splitn(2, '=').collect();
-        if values.len() == 1 {
-            name = values[0].into();
-            expr = "".into();
-        } else if values.len() == 2 {
-            name = values[0].into();
-            expr = values[1].into();
-        } else {
-            error!("unable to split '{}'", k_eq_v);
+        set_variable(&mut config, k_eq_v)?;
+    }
+
+    config
+        .exclude_queries
+        .extend(options.excludes.iter().cloned());
+
+    Ok(config)
+}
+
+/// Layer the variables, commands and gardens from the user's garden.yaml
+/// in $XDG_CONFIG_HOME/garden under the configuration. Set GARDEN_NO_USER_CONFIG
+/// to disable the user defaults.
+fn read_user_defaults(
+    config: &mut model::Configuration,
+    config_verbose: u8,
+) -> Result<(), errors::GardenError> {
+    if std::env::var_os("GARDEN_NO_USER_CONFIG").is_some() {
+        return Ok(());
+    }
+    let user_path = xdg_dir().join("garden.yaml");
+    if !user_path.is_file() {
+        return Ok(());
+    }
+    // The user's garden.yaml is used directly when no project config was found.
+    let config_path = config.get_path()?;
+    if path::abspath(config_path) == path::abspath(&user_path) {
+        return Ok(());
+    }
+    if config_verbose > 1 {
+        eprintln!("config: user defaults: {:?}", user_path);
+    }
+    let defaults = from_path(user_path, "", config_verbose, None)?;
+    config.add_defaults(defaults);
+
+    Ok(())
+}
+
+/// Apply a "name=value" variable override from the command-line.
+/// "scope::name=value" overrides the variable in a narrower scope.
+/// ":garden::name=value" sets the variable in the matching gardens.
+/// Any other scope is a tree query, e.g. "@tree::name" or "%group::name",
+/// and sets the variable in each of the matching trees.
+fn set_variable(
+    config: &mut model::Configuration,
+    k_eq_v: &str,
+) -> Result<(), errors::GardenError> {
+    // "name" without a value sets the variable to an empty value.
+    let (name, expr) = k_eq_v.split_once('=').unwrap_or((k_eq_v, ""));
+    let (scope, name) = match name.rsplit_once("::") {
+        Some((scope, name)) => (scope, name),
+        None => {
+            config.variables.insert(
+                0,
+                model::NamedVariable::new(name.to_string(), expr.to_string(), None),
+            );
+            return Ok(());
         }
-        config
+    };
+    let variable = model::NamedVariable::new(name.to_string(), expr.to_string(), None);
+
+    if syntax::is_garden(scope) {
+        let garden_name = syntax::trim(scope);
+        let pattern = glob::Pattern::new(garden_name).map_err(|_| {
+            errors::GardenError::GardenPatternError {
+                garden: garden_name.to_string(),
+            }
+        })?;
+        let mut found = false;
+        for garden in config.gardens.iter_mut() {
+            if pattern.matches(garden.get_name()) {
+                garden.variables.insert(0, variable.clone());
+                found = true;
+            }
+        }
+        if !found {
+            return Err(errors::GardenError::GardenNotFound {
+                garden: garden_name.to_string(),
+            });
+        }
+        return Ok(());
+    }
+
+    let contexts = query::resolve_trees(config, scope);
+    if contexts.is_empty() {
+        return Err(errors::GardenError::TreeNotFound {
+            tree: scope.to_string(),
+        });
+    }
+    let mut tree_indexes: Vec<model::TreeIndex> =
+        contexts.iter().map(|context| context.tree).collect();
+    tree_indexes.sort_unstable();
+    tree_indexes.dedup();
+    for tree_idx in tree_indexes {
+        let tree = &mut config.trees[tree_idx];
+        // Tree variables start with the built-in ${TREE_NAME}, ${TREE_PATH},
+        // ${TREE_URL} and ${TREE_BRANCH} variables.
+        let idx = tree
             .variables
-            .insert(0, model::NamedVariable::new(name, expr, None));
+            .iter()
+            .take_while(|var| {
+                matches!(
+                    var.get_name().as_str(),
+                    "TREE_NAME" | "TREE_PATH" | "TREE_URL" | "TREE_BRANCH"
+                )
+            })
+            .count();
+        tree.variables.insert(idx, variable.clone());
     }
 
-    Ok(config)
+    Ok(())
 }
 
 /// Parse and apply configuration from a YAML/JSON string
@@ -220,58 +436,329 @@ pub fn parse(
     reader::parse(config_string, verbose, cfg)?;
     // Initialize the configuration now that the values have been read.
     cfg.initialize();
+    // Trees are discovered relative to the resolved garden.root.
+    if !cfg.discover.is_empty() {
+        reader::discover_trees(cfg, verbose);
+        cfg.initialize();
+    }
 
     Ok(())
 }
 
+/// $XDG_CACHE_HOME/garden (typically ~/.cache/garden)
+fn cache_dir() -> std::path::PathBuf {
+    let mut cache_dir = cache_home_dir();
+    cache_dir.push("garden");
+
+    cache_dir
+}
+
+/// $XDG_CACHE_HOME (typically ~/.cache)
+#[cfg(unix)]
+fn cache_home_dir() -> std::path::PathBuf {
+    match xdg::BaseDirectories::new() {
+        Ok(xdg_dirs) => xdg_dirs.get_cache_home(),
+        Err(_) => path::home_dir().join(".cache"),
+    }
+}
+
+/// The user's cache directory, e.g. %LOCALAPPDATA% on Windows.
+#[cfg(not(unix))]
+fn cache_home_dir() -> std::path::PathBuf {
+    dirs::cache_dir().unwrap_or_else(|| path::home_dir().join(".cache"))
+}
+
+/// $XDG_CACHE_HOME/garden/grafts (typically ~/.cache/garden/grafts)
+pub fn graft_cache_dir() -> std::path::PathBuf {
+    cache_dir().join("grafts")
+}
+
+/// $XDG_CACHE_HOME/garden/configs (typically ~/.cache/garden/configs)
+pub fn config_cache_dir() -> std::path::PathBuf {
+    cache_dir().join("configs")
+}
+
+/// Return a file name for caching the contents of a URL.
+/// The SHA-1 digest of the URL is appended so that URLs that sanitize to the same
+/// name, e.g. "h/a-b/c.yaml" and "h/a/b-c.yaml", are cached separately.
+pub fn cache_name(url: &str) -> String {
+    let location = url.split_once("://").map_or(url, |(_, location)| location);
+    let mut name = sanitize_name(location);
+    // Keep the name well under the file name limit. The digest keeps it unique.
+    if let Some((idx, _)) = name.char_indices().nth(MAX_CACHE_NAME_LEN) {
+        name.truncate(idx);
+    }
+
+    format!("{}-{}", name, digest(url))
+}
+
+/// The maximum length of the readable portion of a cache name.
+const MAX_CACHE_NAME_LEN: usize = 80;
+
+/// Return the hex SHA-1 digest of a string. The digest is stable across
+/// releases so that cached files keep their names.
+fn digest(value: &str) -> String {
+    git2::Oid::hash_object(git2::ObjectType::Blob, value.as_bytes())
+        .map(|oid| oid.to_string())
+        .unwrap_or_default()
+}
+
+/// Replace characters that are not safe to use in file names.
+fn sanitize_name(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>()
+        .trim_matches('-')
+        .to_string()
+}
+
+/// Return the directory that a graft's Git URL is cloned into.
+/// The same URL checked out at different refs is cloned into separate directories.
+pub fn remote_graft_dir(url: &str, git_ref: &str) -> std::path::PathBuf {
+    // URLs cannot contain newlines so the URL and ref are hashed unambiguously.
+    let name = if git_ref.is_empty() {
+        cache_name(url)
+    } else {
+        cache_name(&format!("{}\n{}", url, git_ref))
+    };
+
+    graft_cache_dir().join(name)
+}
+
+/// Return true when a graft's config is a Git URL that has not been cloned yet.
+fn is_uncloned_graft(config: &model::Configuration, graft: &model::Graft) -> bool {
+    let url = eval::value(config, &graft.config);
+    syntax::is_git_url(&url) && !remote_graft_dir(&url, &graft.git_ref).exists()
+}
+
+/// Return the path to the garden file for a graft whose config is a Git URL.
+/// The repository is cloned into the graft cache when it has not been cloned yet.
+fn remote_graft_path(
+    graft: &model::Graft,
+    url: &str,
+    quiet: bool,
+) -> Result<std::path::PathBuf, errors::GardenError> {
+    let dir = remote_graft_dir(url, &graft.git_ref);
+    if !dir.exists() {
+        if !quiet {
+            eprintln!("# {}: cloning {}", graft.get_name(), url);
+        }
+        clone_remote_graft(url, &graft.git_ref, &dir).map_err(|err| {
+            errors::GardenError::ConfigurationError(format!(
+                "{}: unable to clone {}: {}",
+                graft.get_name(),
+                url,
+                err
+            ))
+        })?;
+    }
+    let path = if graft.path.is_empty() {
+        "garden.yaml"
+    } else {
+        graft.path.as_str()
+    };
+
+    Ok(dir.join(path))
+}
+
+/// Clone a remote graft and checkout its ref. Partial clones are removed on failure.
+fn clone_remote_graft(
+    url: &str,
+    git_ref: &str,
+    dir: &std::path::Path,
+) -> Result<(), errors::GardenError> {
+    if let Some(parent) = dir.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| errors::GardenError::OSError(err.to_string()))?;
+    }
+    let backend = git::backend();
+    let options = git::CloneOptions::default();
+    let result = backend
+        .clone_repository(url, &dir.to_string_lossy(), &options)
+        .and_then(|_| {
+            if git_ref.is_empty() {
+                Ok(())
+            } else {
+                backend.checkout_ref(dir, "origin", git_ref)
+            }
+        });
+    if result.is_err() {
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    result
+}
+
+/// Return the root for a graft whose config is a Git URL. Trees are grown
+/// in a directory named after the graft inside of the parent's root by default
+/// rather than inside of the graft cache.
+fn remote_graft_root(config: &model::Configuration, graft: &model::Graft) -> String {
+    if graft.root.is_empty() {
+        return config
+            .root_path
+            .join(graft.get_name())
+            .to_string_lossy()
+            .to_string();
+    }
+
+    config.eval_config_path(&graft.root)
+}
+
 /// Read grafts into the root configuration on down.
+/// Grafts whose config is a Git URL are only read once they have been cloned by
+/// "garden cache update" or by a tree query that uses them.
 pub fn read_grafts(app: &mut model::ApplicationContext) -> Result<(), errors::GardenError> {
     let root_id = app.get_root_id();
-    read_grafts_recursive(app, root_id)
+    read_grafts_recursive(app, root_id, false)
 }
 
-/// Read grafts into the specified configuration
+/// Read grafts into the root configuration on down and clone the grafts
+/// whose config is a Git URL when they have not been cloned yet.
+pub fn clone_grafts(app: &mut model::ApplicationContext) -> Result<(), errors::GardenError> {
+    let root_id = app.get_root_id();
+    read_grafts_recursive(app, root_id, true)
+}
+
+/// Read grafts into the specified configuration. Remote grafts that have not been
+/// cloned are skipped unless "clone" is true.
 fn read_grafts_recursive(
     app: &mut model::ApplicationContext,
     id: ConfigId,
+    clone: bool,
 ) -> Result<(), errors::GardenError> {
-    // Defer the recursive calls to avoid an immutable borrow from preventing us from
-    // recursively taking an immutable borrow.
-    //
-    // We build a vector of paths inside an immutable scope and defer construction of
-    // the graft Configuration since it requires a mutable borrow against app.
-    let mut details = Vec::new();
-
-    // Immutable scope for traversing the configuration.
-    {
-        let config = app.get_config(id); // Immutable borrow.
-        for (idx, graft) in config.grafts.iter().enumerate() {
-            let path_str = config.eval_config_path(&graft.config);
-            let path = std::path::PathBuf::from(&path_str);
-            if !path.exists() {
-                let config_path = config.get_path()?;
-                return Err(errors::GardenError::ConfigurationError(format!(
-                    "{}: invalid graft in {:?}",
-                    graft.get_name(),
-                    config_path
-                )));
-            }
-            details.push((idx, path, graft.root.to_string()));
+    for idx in 0..app.get_config(id).grafts.len() {
+        let config = app.get_config(id);
+        if !clone && is_uncloned_graft(config, &config.grafts[idx]) {
+            continue;
         }
+        let graft_id = read_graft(app, id, idx)?;
+        // Read child grafts recursively.
+        read_grafts_recursive(app, graft_id, clone)?;
     }
 
-    // Read child grafts recursively after the immutable scope has ended.
-    let config_verbose = app.options.debug_level("config");
-    for (idx, path, root) in details {
-        // Read the Configuration referenced by the graft.
-        let graft_config = from_path(path, &root, config_verbose, Some(id))?;
-        // The app Arena takes ownershp of the Configuration.
-        let graft_id = app.add_graft(id, graft_config);
-        // Record the config ID in the graft structure.
-        app.get_config_mut(id).grafts[idx].set_id(graft_id);
-        // Read child grafts recursively.
-        read_grafts_recursive(app, graft_id)?;
+    Ok(())
+}
+
+/// Read the grafts that are needed to resolve a tree query.
+///
+/// Grafts are read on demand so that configurations that graft many large files
+/// only read the grafts that a command uses. "graft::query" reads the named graft
+/// and wildcard queries read every graft because they match trees in all grafts.
+pub fn read_query_grafts(
+    app: &mut model::ApplicationContext,
+    query: &str,
+) -> Result<(), errors::GardenError> {
+    let root_id = app.get_root_id();
+    read_query_grafts_in(app, root_id, query)
+}
+
+/// Read the grafts needed to resolve a tree query within a configuration.
+fn read_query_grafts_in(
+    app: &mut model::ApplicationContext,
+    id: ConfigId,
+    query: &str,
+) -> Result<(), errors::GardenError> {
+    let term = query.trim();
+    if let Some(graft_name) = syntax::graft_basename(term) {
+        let idx = app
+            .get_config(id)
+            .grafts
+            .iter()
+            .position(|graft| graft.get_name() == syntax::trim(&graft_name));
+        if let (Some(idx), Some(graft_query)) = (idx, syntax::trim_graft(term)) {
+            let graft_id = read_graft(app, id, idx)?;
+            return read_query_grafts_in(app, graft_id, &graft_query);
+        }
+    }
+    // Remote grafts are cloned when a query uses them for the first time.
+    if query::is_wildcard(query) {
+        read_grafts_recursive(app, id, true)?;
     }
 
     Ok(())
 }
+
+/// Read the graft at index "idx" of the specified configuration unless it has
+/// already been read. Returns the ConfigId of the graft's configuration.
+fn read_graft(
+    app: &mut model::ApplicationContext,
+    id: ConfigId,
+    idx: usize,
+) -> Result<ConfigId, errors::GardenError> {
+    if let Some(graft_id) = app.get_config(id).grafts[idx].get_id() {
+        return Ok(*graft_id);
+    }
+    // The path details are gathered inside an immutable scope because
+    // constructing the graft Configuration requires a mutable borrow against app.
+    let quiet = app.options.quiet;
+    let (path, root, inherited) = {
+        let config = app.get_config(id); // Immutable borrow.
+        let graft = &config.grafts[idx];
+        // Grafts whose config is a Git URL are read from the graft cache.
+        let value = eval::value(config, &graft.config);
+        let (path, root) = if syntax::is_git_url(&value) {
+            (
+                remote_graft_path(graft, &value, quiet)?,
+                remote_graft_root(config, graft),
+            )
+        } else {
+            // Relative grafts of garden files fetched from URLs are fetched
+            // relative to the URL when the graft is read.
+            (
+                std::path::PathBuf::from(config.config_file_path(&value)),
+                graft.root.to_string(),
+            )
+        };
+        if !path.exists() && !syntax::is_http_url(&path.to_string_lossy()) {
+            let config_path = config.get_path()?;
+            return Err(errors::GardenError::ConfigurationError(format!(
+                "{}: invalid graft in {:?}",
+                graft.get_name(),
+                config_path
+            )));
+        }
+        // "inherit: true" grafts can read the variables from the parent configuration.
+        let inherited = if graft.inherit {
+            config.variables.clone()
+        } else {
+            Vec::new()
+        };
+        (path, root, inherited)
+    };
+
+    let config_verbose = app.options.debug_level("config");
+    let profile = app.options.profile.clone();
+    // Read the Configuration referenced by the graft. Grafts use the same profile
+    // and ignore it when they do not define it.
+    let mut graft_config = read(
+        &Some(path),
+        &root,
+        config_verbose,
+        Some(id),
+        &[],
+        profile
+            .as_deref()
+            .map(|name| (name, model::ProfileSource::Graft)),
+        app.options.fetch_options(),
+    )?;
+    // Variables defined by the graft override the inherited variables.
+    for var in inherited {
+        let name = var.get_name();
+        if !graft_config.variables.iter().any(|v| v.get_name() == name) {
+            graft_config.variables.push(var);
+        }
+    }
+    // The app Arena takes ownershp of the Configuration.
+    let graft_id = app.add_graft(id, graft_config);
+    // Record the config ID in the graft structure.
+    app.get_config_mut(id).grafts[idx].set_id(graft_id);
+
+    Ok(graft_id)
+}
@@ -2,8 +2,10 @@ use yaml_rust::yaml::Hash as YamlHash;
 use yaml_rust::yaml::Yaml;
 use yaml_rust::YamlLoader;
 
+use super::super::cmd;
 use super::super::errors;
 use super::super::eval;
+use super::super::git;
 use super::super::model;
 use super::super::path;
 use super::super::syntax;
@@ -24,12 +26,49 @@ pub fn parse(
             path: config.get_path()?.into(),
         });
     }
-    let doc = &docs[0];
+    // Merge the contents of "includes" files into the document.
+    let mut visited = Vec::new();
+    if let Some(path) = config.path.as_ref() {
+        visited.push(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+    }
+    let mut doc = merge_includes(
+        &docs[0],
+        config.path.as_deref(),
+        config.get_url().map(String::as_str),
+        config_verbose,
+        config.fetch_options,
+        &mut visited,
+    )?;
+    // Files from additional "--config" options override the garden file.
+    for overlay in &config.overlays {
+        if config_verbose > 1 {
+            debug!("yaml: overlay: {:?}", overlay);
+        }
+        let overlay_doc = load_yaml(overlay)?;
+        let overlay_doc = merge_includes(
+            &overlay_doc,
+            Some(overlay),
+            config.urls.get(overlay).map(String::as_str),
+            config_verbose,
+            config.fetch_options,
+            &mut visited,
+        )?;
+        overlay_yaml(&mut doc, &overlay_doc);
+    }
+    // The selected profile overrides the garden file and overlays.
+    if let Some(profile) = config.profile.as_deref() {
+        if config_verbose > 1 {
+            debug!("yaml: profile: {}", profile);
+        }
+        apply_profile(&mut doc, profile, config.profile_source)?;
+    }
+    let doc = &doc;
 
     // Debug support
     if config_verbose > 2 {
         dump_node(doc, 1, "");
     }
+    check_variables(doc)?;
 
     // garden.root
     if config.root.is_empty() {
@@ -52,6 +91,62 @@ pub fn parse(
         debug!("yaml: garden.shell = {}", config.shell);
     }
 
+    // garden.cache
+    if get_bool(&doc["garden"]["cache"], &mut config.cache) && config_verbose > 0 {
+        debug!("yaml: garden.cache = {}", config.cache);
+    }
+
+    // garden.cmd-order
+    let mut cmd_order = String::new();
+    if get_str(&doc["garden"]["cmd-order"], &mut cmd_order) {
+        config.cmd_order =
+            Some(
+                cmd_order
+                    .parse()
+                    .map_err(|msg| errors::GardenError::InvalidConfiguration {
+                        msg: format!("garden.cmd-order: {}", msg),
+                    })?,
+            );
+        if config_verbose > 0 {
+            debug!("yaml: garden.cmd-order = {}", cmd_order);
+        }
+    }
+
+    // garden.exit-status
+    let mut exit_status = String::new();
+    if get_str(&doc["garden"]["exit-status"], &mut exit_status) {
+        config.exit_status =
+            Some(
+                exit_status
+                    .parse()
+                    .map_err(|msg| errors::GardenError::InvalidConfiguration {
+                        msg: format!("garden.exit-status: {}", msg),
+                    })?,
+            );
+        if config_verbose > 0 {
+            debug!("yaml: garden.exit-status = {}", exit_status);
+        }
+    }
+
+    // garden.env-filter
+    if get_vec_str(&doc["garden"]["env-filter"], &mut config.env_filter) && config_verbose > 0 {
+        debug!("yaml: garden.env-filter = {:?}", config.env_filter);
+    }
+
+    // garden.url-rewrites
+    if let Yaml::Hash(ref hash) = doc["garden"]["url-rewrites"] {
+        for (prefix, replacement) in hash {
+            if let (Some(prefix), Some(replacement)) = (prefix.as_str(), replacement.as_str()) {
+                config
+                    .url_rewrites
+                    .push((prefix.to_string(), replacement.to_string()));
+            }
+        }
+        if config_verbose > 0 {
+            debug!("yaml: garden.url-rewrites = {:?}", config.url_rewrites);
+        }
+    }
+
     // grafts
     if config_verbose > 1 {
         debug!("yaml: grafts");
@@ -71,7 +166,21 @@ pub fn parse(
         None,
     ));
 
-    if let Some(config_path_raw) = config.dirname.as_ref() {
+    // Provide GARDEN_PROFILE
+    config.variables.push(model::NamedVariable::new(
+        "GARDEN_PROFILE".to_string(),
+        config.profile.clone().unwrap_or_default(),
+        None,
+    ));
+
+    if let Some(url) = config.get_url() {
+        // GARDEN_CONFIG_DIR is the URL's directory for garden files fetched from URLs.
+        config.variables.push(model::NamedVariable::new(
+            "GARDEN_CONFIG_DIR".to_string(),
+            syntax::url_dirname(url).to_string(),
+            None,
+        ));
+    } else if let Some(config_path_raw) = config.dirname.as_ref() {
         // Calculate an absolute path for GARDEN_CONFIG_DIR.
         if let Ok(config_path) = config_path_raw.canonicalize() {
             config.variables.push(model::NamedVariable::new(
@@ -82,6 +191,24 @@ pub fn parse(
         }
     }
 
+    // GARDEN_NAME is defined by each garden and GROUP_NAME is set when evaluating
+    // commands in a group. GARDEN_CMD_NAME is set when evaluating custom commands.
+    // GARDEN_CMD_QUIET and GARDEN_CMD_VERBOSE are set from the command-line options.
+    // They are empty by default.
+    for name in [
+        "GARDEN_NAME",
+        "GROUP_NAME",
+        "GARDEN_CMD_NAME",
+        "GARDEN_CMD_QUIET",
+        "GARDEN_CMD_VERBOSE",
+    ] {
+        config.variables.push(model::NamedVariable::new(
+            name.to_string(),
+            String::new(),
+            None,
+        ));
+    }
+
     if !get_variables(&doc["variables"], &mut config.variables) && config_verbose > 1 {
         debug!("yaml: no variables");
     }
@@ -94,6 +221,14 @@ pub fn parse(
         debug!("yaml: no commands");
     }
 
+    // hooks
+    if config_verbose > 1 {
+        debug!("yaml: hooks");
+    }
+    if !get_multivariables(&doc["hooks"], &mut config.hooks) && config_verbose > 1 {
+        debug!("yaml: no hooks");
+    }
+
     // templates
     if config_verbose > 1 {
         debug!("yaml: templates");
@@ -118,6 +253,22 @@ pub fn parse(
         debug!("yaml: no groups");
     }
 
+    // discover
+    if config_verbose > 1 {
+        debug!("yaml: discover");
+    }
+    if !get_discover(&doc["discover"], &mut config.discover) && config_verbose > 1 {
+        debug!("yaml: no discover");
+    }
+
+    // queries
+    if config_verbose > 1 {
+        debug!("yaml: queries");
+    }
+    if !get_named_queries(&doc["queries"], &mut config.queries) && config_verbose > 1 {
+        debug!("yaml: no queries");
+    }
+
     // gardens
     if config_verbose > 1 {
         debug!("yaml: gardens");
@@ -198,6 +349,30 @@ fn get_bool(yaml: &Yaml, value: &mut bool) -> bool {
     result
 }
 
+/// Yaml::String, Yaml::Integer or Yaml::Boolean -> String
+fn get_scalar(yaml: &Yaml, string: &mut String) -> bool {
+    match yaml {
+        Yaml::String(yaml_string) => *string = yaml_string.clone(),
+        Yaml::Integer(yaml_integer) => *string = yaml_integer.to_string(),
+        Yaml::Boolean(yaml_bool) => *string = bool_to_string(yaml_bool),
+        _ => return false,
+    }
+    true
+}
+
+/// Yaml::Boolean or "recursive" -> Submodules
+fn get_submodules(yaml: &Yaml, value: &mut model::Submodules) -> bool {
+    match yaml {
+        Yaml::Boolean(true) => *value = model::Submodules::Init,
+        Yaml::Boolean(false) => *value = model::Submodules::None,
+        Yaml::String(yaml_string) if yaml_string == "recursive" => {
+            *value = model::Submodules::Recursive;
+        }
+        _ => return false,
+    }
+    true
+}
+
 /// Yaml::String or Yaml::Array<Yaml::String> -> Vec<String>
 fn get_vec_str(yaml: &Yaml, vec: &mut Vec<String>) -> bool {
     if let Yaml::String(yaml_string) = yaml {
@@ -266,10 +441,8 @@ fn get_variables(yaml: &Yaml, vec: &mut Vec<model::NamedVariable>) -> bool {
                         Some(value.clone()),
                     ));
                 }
-                _ => {
-                    dump_node(v, 1, "");
-                    error!("invalid variables");
-                }
+                // Invalid values are reported by check_variables().
+                _ => (),
             }
         }
         return true;
@@ -278,6 +451,71 @@ fn get_variables(yaml: &Yaml, vec: &mut Vec<model::NamedVariable>) -> bool {
     false
 }
 
+/// Return an error when a variables, commands or environment block contains values
+/// that cannot be read, e.g. nested hashes in a "variables" block.
+fn check_variables(doc: &Yaml) -> Result<(), errors::GardenError> {
+    check_block(&doc["variables"], "variables", is_variable_value)?;
+    check_block(&doc["commands"], "commands", is_multivariable_value)?;
+    check_block(&doc["hooks"], "hooks", is_multivariable_value)?;
+
+    for section in ["templates", "trees", "gardens"] {
+        let entries = match &doc[section] {
+            Yaml::Hash(hash) => hash,
+            _ => continue,
+        };
+        for (name, value) in entries {
+            let name = name.as_str().unwrap_or_default();
+            for block in ["variables", "gitconfig"] {
+                let block_path = format!("{}.{}.{}", section, name, block);
+                check_block(&value[block], &block_path, is_variable_value)?;
+            }
+            for block in ["environment", "commands"] {
+                let block_path = format!("{}.{}.{}", section, name, block);
+                check_block(&value[block], &block_path, is_multivariable_value)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that every entry in a hash block contains a valid value.
+fn check_block(
+    yaml: &Yaml,
+    block_path: &str,
+    is_valid: fn(&Yaml) -> bool,
+) -> Result<(), errors::GardenError> {
+    if let Yaml::Hash(ref hash) = yaml {
+        for (key, value) in hash {
+            if let Some(key) = key.as_str() {
+                if !is_valid(value) {
+                    return Err(errors::GardenError::InvalidConfiguration {
+                        msg: format!("invalid variables: {}.{}", block_path, key),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Values that can be read by get_variables().
+fn is_variable_value(value: &Yaml) -> bool {
+    matches!(
+        value,
+        Yaml::String(_) | Yaml::Array(_) | Yaml::Integer(_) | Yaml::Boolean(_)
+    )
+}
+
+/// Values that can be read by get_multivariables().
+fn is_multivariable_value(value: &Yaml) -> bool {
+    matches!(
+        value,
+        Yaml::String(_) | Yaml::Array(_) | Yaml::Integer(_) | Yaml::Hash(_)
+    )
+}
+
 fn bool_to_string(value: &bool) -> String {
     match *value {
         true => "true".into(),
@@ -312,10 +550,38 @@ fn get_multivariables(yaml: &Yaml, vec: &mut Vec<model::MultiVariable>) -> bool
                     let variables = vec![model::Variable::new(value.clone(), Some(value))];
                     vec.push(model::MultiVariable::new(key, variables));
                 }
-                _ => {
-                    dump_node(v, 1, "");
-                    error!("invalid variables");
+                // Commands can be specified as a hash with "run", "help", "shell", "cwd"
+                // and "timeout" entries.
+                Yaml::Hash(_) => {
+                    let mut variables = Vec::new();
+                    let mut values = Vec::new();
+                    get_vec_str(&v["run"], &mut values);
+                    for value in values {
+                        variables.push(model::Variable::new(value, None));
+                    }
+                    let mut multi_var = model::MultiVariable::new(key, variables);
+                    let mut help = String::new();
+                    if get_str(&v["help"], &mut help) {
+                        multi_var.set_help(help.trim_end().to_string());
+                    }
+                    let mut shell = String::new();
+                    if get_str(&v["shell"], &mut shell) {
+                        multi_var.set_shell(shell);
+                    }
+                    let mut cwd = String::new();
+                    if get_str(&v["cwd"], &mut cwd) {
+                        multi_var.set_cwd(cwd);
+                    }
+                    let timeout = match &v["timeout"] {
+                        Yaml::Integer(seconds) => syntax::duration(&seconds.to_string()),
+                        Yaml::Real(value) | Yaml::String(value) => syntax::duration(value),
+                        _ => None,
+                    };
+                    multi_var.set_timeout(timeout);
+                    vec.push(multi_var);
                 }
+                // Invalid values are reported by check_variables().
+                _ => (),
             }
         }
         return true;
@@ -328,7 +594,7 @@ fn get_multivariables(yaml: &Yaml, vec: &mut Vec<model::MultiVariable>) -> bool
 fn get_templates(yaml: &Yaml, templates: &mut Vec<model::Template>) -> bool {
     if let Yaml::Hash(ref hash) = yaml {
         for (name, value) in hash {
-            templates.push(get_template(name, value, yaml));
+            templates.push(get_template(name, value, yaml, &Yaml::BadValue));
         }
         return true;
     }
@@ -336,8 +602,10 @@ fn get_templates(yaml: &Yaml, templates: &mut Vec<model::Template>) -> bool {
     false
 }
 
-/// Read a single template definition
-fn get_template(name: &Yaml, value: &Yaml, templates: &Yaml) -> model::Template {
+/// Read a single template definition. "arguments" are the values passed
+/// to the template's "parameters" by the tree that uses the template.
+fn get_template(name: &Yaml, value: &Yaml, templates: &Yaml, arguments: &Yaml) -> model::Template {
+    let value = &substitute_parameters(value, arguments);
     let mut template = model::Template::default();
     get_str(name, template.get_name_mut());
 
@@ -375,6 +643,7 @@ fn get_template(name: &Yaml, value: &Yaml, templates: &Yaml) -> model::Template
                 &Yaml::String(template_name.clone()),
                 &templates[template_name.as_ref()],
                 templates,
+                arguments,
             );
 
             base.apply(&mut template.tree);
@@ -385,15 +654,29 @@ fn get_template(name: &Yaml, value: &Yaml, templates: &Yaml) -> model::Template
     get_variables(&value["gitconfig"], &mut template.tree.gitconfig);
 
     get_multivariables(&value["environment"], &mut template.tree.environment);
+    get_vec_str(&value["env-file"], &mut template.tree.env_files);
     get_multivariables(&value["commands"], &mut template.tree.commands);
+    get_vec_str(&value["depends-on"], &mut template.tree.depends_on);
+    get_vec_str(&value["post-clone"], &mut template.tree.post_clone);
 
     get_variable(&value["branch"], &mut template.tree.branch);
+    get_variable(&value["bundle"], &mut template.tree.bundle);
+    get_variable(
+        &value["clone-reference"],
+        &mut template.tree.clone_reference,
+    );
+    get_variable(&value["default-remote"], &mut template.tree.default_remote);
+    get_variable(&value["ref"], &mut template.tree.git_ref);
+    get_variable(&value["shell"], &mut template.tree.shell);
     get_variable(&value["symlink"], &mut template.tree.symlink);
     get_variable(&value["worktree"], &mut template.tree.worktree);
 
     get_i64(&value["depth"], &mut template.tree.clone_depth);
     get_bool(&value["bare"], &mut template.tree.is_bare_repository);
+    get_bool(&value["lfs"], &mut template.tree.is_lfs);
     get_bool(&value["single-branch"], &mut template.tree.is_single_branch);
+    get_bool(&value["skip"], &mut template.tree.is_skipped);
+    get_submodules(&value["submodules"], &mut template.tree.submodules);
 
     get_remotes(&value["remotes"], &mut template.tree.remotes);
 
@@ -407,6 +690,7 @@ fn get_template(name: &Yaml, value: &Yaml, templates: &Yaml) -> model::Template
                 &Yaml::String(template_name.clone()),
                 &templates[template_name.as_ref()],
                 templates,
+                arguments,
             );
 
             template.tree.variables.append(&mut base.tree.variables);
@@ -416,6 +700,61 @@ fn get_template(name: &Yaml, value: &Yaml, templates: &Yaml) -> model::Template
     template
 }
 
+/// Substitute "${parameter}" references in a template definition.
+/// Parameters are declared in the template's "parameters" block along with their
+/// default values. Trees override the defaults using their "arguments" block.
+fn substitute_parameters(value: &Yaml, arguments: &Yaml) -> Yaml {
+    let mut parameters = Vec::new();
+    if let Yaml::Hash(ref hash) = value["parameters"] {
+        for (name, default) in hash {
+            let mut name_str = String::new();
+            if !get_str(name, &mut name_str) {
+                continue;
+            }
+            let mut param_value = String::new();
+            if !get_scalar(&arguments[name_str.as_ref()], &mut param_value) {
+                get_scalar(default, &mut param_value);
+            }
+            parameters.push((format!("${{{}}}", name_str), param_value));
+        }
+    }
+    if parameters.is_empty() {
+        return value.clone();
+    }
+
+    substitute_yaml(value, &parameters)
+}
+
+/// Replace parameter references in every string within a YAML document.
+fn substitute_yaml(value: &Yaml, parameters: &[(String, String)]) -> Yaml {
+    match value {
+        Yaml::String(string) => {
+            let mut result = string.clone();
+            for (reference, param_value) in parameters {
+                result = result.replace(reference, param_value);
+            }
+            Yaml::String(result)
+        }
+        Yaml::Array(array) => Yaml::Array(
+            array
+                .iter()
+                .map(|entry| substitute_yaml(entry, parameters))
+                .collect(),
+        ),
+        Yaml::Hash(hash) => {
+            let mut result = YamlHash::new();
+            for (key, entry) in hash {
+                result.insert(
+                    substitute_yaml(key, parameters),
+                    substitute_yaml(entry, parameters),
+                );
+            }
+            Yaml::Hash(result)
+        }
+        _ => value.clone(),
+    }
+}
+
 /// Read tree definitions
 fn get_trees(config: &mut model::Configuration, yaml: &Yaml, templates: &Yaml) -> bool {
     if let Yaml::Hash(ref hash) = yaml {
@@ -464,6 +803,15 @@ fn get_tree_from_url(name: &Yaml, url: &str) -> model::Tree {
             None,
         ),
     );
+    // ${TREE_URL} and ${TREE_BRANCH} are updated by Configuration::reset().
+    tree.variables.insert(
+        2,
+        model::NamedVariable::new("TREE_URL".to_string(), String::new(), None),
+    );
+    tree.variables.insert(
+        3,
+        model::NamedVariable::new("TREE_BRANCH".to_string(), String::new(), None),
+    );
 
     tree.remotes.push(model::NamedVariable::new(
         "origin".to_string(),
@@ -512,7 +860,7 @@ fn get_tree(
         tree.is_bare_repository = true;
     }
 
-    // Add the TREE_NAME and TREE_PATH variables
+    // Add the TREE_NAME, TREE_PATH, TREE_URL and TREE_BRANCH variables
     if variables {
         // Register the ${TREE_NAME} variable.
         tree.variables.insert(
@@ -528,6 +876,15 @@ fn get_tree(
                 None,
             ),
         );
+        // ${TREE_URL} and ${TREE_BRANCH} are updated by Configuration::reset().
+        tree.variables.insert(
+            2,
+            model::NamedVariable::new("TREE_URL".to_string(), String::new(), None),
+        );
+        tree.variables.insert(
+            3,
+            model::NamedVariable::new("TREE_BRANCH".to_string(), String::new(), None),
+        );
     }
 
     {
@@ -553,6 +910,7 @@ fn get_tree(
                 &Yaml::String(template_name.clone()),
                 &templates[template_name.as_ref()],
                 templates,
+                &value["arguments"],
             );
             if tree.remotes.is_empty() {
                 tree.remotes.append(&mut base.tree.remotes);
@@ -562,6 +920,7 @@ fn get_tree(
                 &Yaml::String(template_name.clone()),
                 &templates[template_name.as_ref()],
                 templates,
+                &value["arguments"],
             );
             base.apply(&mut tree);
         }
@@ -584,15 +943,26 @@ fn get_tree(
     get_variables(&value["gitconfig"], &mut tree.gitconfig);
 
     get_multivariables(&value["environment"], &mut tree.environment);
+    get_vec_str(&value["env-file"], &mut tree.env_files);
     get_multivariables(&value["commands"], &mut tree.commands);
+    get_vec_str(&value["depends-on"], &mut tree.depends_on);
+    get_vec_str(&value["post-clone"], &mut tree.post_clone);
 
     get_variable(&value["branch"], &mut tree.branch);
+    get_variable(&value["bundle"], &mut tree.bundle);
+    get_variable(&value["clone-reference"], &mut tree.clone_reference);
+    get_variable(&value["default-remote"], &mut tree.default_remote);
+    get_variable(&value["ref"], &mut tree.git_ref);
+    get_variable(&value["shell"], &mut tree.shell);
     get_variable(&value["symlink"], &mut tree.symlink);
     get_variable(&value["worktree"], &mut tree.worktree);
 
     get_i64(&value["depth"], &mut tree.clone_depth);
     get_bool(&value["bare"], &mut tree.is_bare_repository);
+    get_bool(&value["lfs"], &mut tree.is_lfs);
     get_bool(&value["single-branch"], &mut tree.is_single_branch);
+    get_bool(&value["skip"], &mut tree.is_skipped);
+    get_submodules(&value["submodules"], &mut tree.submodules);
 
     // Remotes
     get_remotes(&value["remotes"], &mut tree.remotes);
@@ -607,6 +977,7 @@ fn get_tree(
                 &Yaml::String(template_name.clone()),
                 &templates[template_name.as_ref()],
                 templates,
+                &value["arguments"],
             );
 
             tree.variables.append(&mut base.tree.variables);
@@ -646,6 +1017,136 @@ fn get_groups(yaml: &Yaml, groups: &mut Vec<model::Group>) -> bool {
     false
 }
 
+/// Read the "discover" block. "discover" is either a list of glob patterns
+/// or a hash that maps group names to patterns.
+fn get_discover(yaml: &Yaml, discover: &mut Vec<model::Discover>) -> bool {
+    match yaml {
+        Yaml::Hash(hash) => {
+            for (name, value) in hash {
+                let mut patterns = Vec::new();
+                if let (Some(name), true) = (name.as_str(), get_vec_str(value, &mut patterns)) {
+                    discover.push(model::Discover {
+                        group: Some(name.to_string()),
+                        patterns,
+                    });
+                }
+            }
+            true
+        }
+        _ => {
+            let mut patterns = Vec::new();
+            if !get_vec_str(yaml, &mut patterns) {
+                return false;
+            }
+            discover.push(model::Discover {
+                group: None,
+                patterns,
+            });
+            true
+        }
+    }
+}
+
+/// Add trees for the Git repositories that match the "discover" glob patterns.
+/// Discovered trees are added to the named groups. Patterns are relative to
+/// garden.root so trees are discovered once the configuration has been initialized.
+pub fn discover_trees(config: &mut model::Configuration, config_verbose: u8) {
+    let entries = config.discover.clone();
+    let root = config.root_path.clone();
+    for entry in &entries {
+        let mut members = Vec::new();
+        for pattern in &entry.patterns {
+            let pattern = eval::value(config, pattern);
+            for path in discover_repositories(&root, &pattern) {
+                let tree_path = match path.strip_prefix(&root) {
+                    Ok(tree_path) => tree_path.to_string_lossy().to_string(),
+                    Err(_) => continue,
+                };
+                // Trees that are configured explicitly take precedence. Their paths
+                // are compared after evaluating and canonicalizing them.
+                let canonical_path = path.canonicalize().unwrap_or_else(|_| path.clone());
+                let existing = config.trees.iter().find(|tree| {
+                    tree.get_name() == &tree_path
+                        || tree.path_as_ref().is_ok_and(|tree_path| {
+                            let tree_path = std::path::Path::new(tree_path);
+                            tree_path
+                                .canonicalize()
+                                .unwrap_or_else(|_| tree_path.to_path_buf())
+                                == canonical_path
+                        })
+                });
+                if let Some(tree) = existing {
+                    members.push(glob::Pattern::escape(tree.get_name()));
+                } else {
+                    if config_verbose > 0 {
+                        debug!("yaml: discover: {}", tree_path);
+                    }
+                    let backend = git::backend();
+                    let url = backend
+                        .config_get(&path, "remote.origin.url")
+                        .unwrap_or_default();
+                    let name = Yaml::String(tree_path.clone());
+                    let mut tree = get_tree_from_url(&name, &url);
+                    if url.is_empty() {
+                        tree.remotes.clear();
+                    }
+                    config.trees.push(tree);
+                    members.push(glob::Pattern::escape(&tree_path));
+                }
+            }
+        }
+        let group_name = match &entry.group {
+            Some(group_name) => group_name,
+            None => continue,
+        };
+        match config
+            .groups
+            .iter_mut()
+            .find(|group| group.get_name() == group_name)
+        {
+            Some(group) => group.members.extend(members),
+            None => {
+                let mut group = model::Group::default();
+                *group.get_name_mut() = group_name.to_string();
+                group.members = members;
+                config.groups.push(group);
+            }
+        }
+    }
+}
+
+/// Return the Git repositories that match a glob pattern relative to the root.
+fn discover_repositories(root: &std::path::Path, pattern: &str) -> Vec<std::path::PathBuf> {
+    let pattern = root.join(pattern).to_string_lossy().to_string();
+    let paths = match glob::glob(&pattern) {
+        Ok(paths) => paths,
+        Err(_) => return Vec::new(),
+    };
+
+    paths
+        .flatten()
+        .filter(|path| {
+            path.join(".git").exists()
+                || (syntax::is_git_dir(&path.to_string_lossy()) && git::backend().is_bare(path))
+        })
+        .collect()
+}
+
+/// Read named query definitions
+fn get_named_queries(yaml: &Yaml, queries: &mut Vec<model::NamedQuery>) -> bool {
+    if let Yaml::Hash(ref hash) = yaml {
+        for (name, value) in hash {
+            let mut named_query = model::NamedQuery::default();
+            get_str(name, named_query.get_name_mut());
+            get_vec_str(value, &mut named_query.queries);
+            queries.push(named_query);
+        }
+        return true;
+    }
+
+    false
+}
+
 /// Read garden definitions
 fn get_gardens(yaml: &Yaml, gardens: &mut Vec<model::Garden>) -> bool {
     if let Yaml::Hash(ref hash) = yaml {
@@ -654,8 +1155,20 @@ fn get_gardens(yaml: &Yaml, gardens: &mut Vec<model::Garden>) -> bool {
             get_str(name, garden.get_name_mut());
             get_vec_str(&value["groups"], &mut garden.groups);
             get_vec_str(&value["trees"], &mut garden.trees);
+            // "prefix" is accepted as an alias for "root".
+            if !get_variable(&value["root"], &mut garden.root) {
+                get_variable(&value["prefix"], &mut garden.root);
+            }
+            get_variable(&value["shell"], &mut garden.shell);
             get_variables(&value["variables"], &mut garden.variables);
+            // Register the ${GARDEN_NAME} variable after the garden's variables.
+            garden.variables.push(model::NamedVariable::new(
+                "GARDEN_NAME".to_string(),
+                garden.get_name().clone(),
+                None,
+            ));
             get_multivariables(&value["environment"], &mut garden.environment);
+            get_vec_str(&value["env-file"], &mut garden.env_files);
             get_multivariables(&value["commands"], &mut garden.commands);
             get_variables(&value["gitconfig"], &mut garden.gitconfig);
             gardens.push(garden);
@@ -683,23 +1196,470 @@ fn get_graft(name: &Yaml, graft: &Yaml) -> model::Graft {
     let mut graft_name = "".to_string();
     let mut config = "".to_string();
     let mut root = "".to_string();
+    let mut inherit = false;
+    let mut git_ref = "".to_string();
+    let mut path = "".to_string();
 
     get_str(name, &mut graft_name);
 
     if !get_str(graft, &mut config) {
         // The root was not specified.
         if let Yaml::Hash(ref _hash) = graft {
-            // A config expression and root might be specified.
+            // A config expression, root and inherit might be specified.
             get_str(&graft["config"], &mut config);
             get_str(&graft["root"], &mut root);
+            get_bool(&graft["inherit"], &mut inherit);
+            // "ref" and "path" are used when the config is a Git URL.
+            get_str(&graft["ref"], &mut git_ref);
+            get_str(&graft["path"], &mut path);
+        }
+    }
+
+    let mut result = model::Graft::new(graft_name, root, config);
+    result.inherit = inherit;
+    result.git_ref = git_ref;
+    result.path = path;
+
+    result
+}
+
+/// Merge the files listed in "includes" into a document.
+///
+/// Included files are merged in the order they are listed and the including
+/// document is merged last so that entries from later files override entries
+/// with the same name from earlier files. Include paths are evaluated using
+/// the variables defined in the including document and relative paths are
+/// resolved relative to the including document's directory.
+/// Include files that do not exist are ignored.
+/// Read a configuration file and merge the contents of its "includes" files.
+pub fn read_yaml_with_includes(config: &model::Configuration) -> Result<Yaml, errors::GardenError> {
+    let path = config.get_path()?;
+    let doc = read_yaml(path)?;
+    let mut visited = vec![path.canonicalize().unwrap_or_else(|_| path.to_path_buf())];
+    merge_includes(
+        &doc,
+        Some(path),
+        config.get_url().map(String::as_str),
+        0,
+        cached_fetch_options(config),
+        &mut visited,
+    )
+}
+
+/// Return options for reading files that were already fetched while reading a config.
+fn cached_fetch_options(config: &model::Configuration) -> model::FetchOptions {
+    model::FetchOptions {
+        refresh: false,
+        ..config.fetch_options
+    }
+}
+
+/// Fetch a configuration URL into the configuration cache and return the path
+/// to the cached copy. Cached copies are reused unless "refresh" is enabled.
+pub fn fetch_config_path(
+    url: &str,
+    options: model::FetchOptions,
+) -> Result<std::path::PathBuf, errors::GardenError> {
+    let cache_path = super::config_cache_dir().join(super::cache_name(url));
+    if options.refresh || !cache_path.exists() {
+        if let Err(err) = fetch_url(url, &cache_path, options.quiet) {
+            // Use the cached copy when the URL cannot be fetched, e.g. when offline.
+            if !cache_path.exists() {
+                return Err(err);
+            }
+            if !options.quiet {
+                eprintln!("warning: {}: using cached copy", err);
+            }
+        }
+    }
+
+    Ok(cache_path)
+}
+
+/// Download a configuration file using curl. The file is only written to the
+/// destination once it has been downloaded and validated.
+fn fetch_url(url: &str, path: &std::path::Path, quiet: bool) -> Result<(), errors::GardenError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| errors::GardenError::OSError(err.to_string()))?;
+    }
+    if !quiet {
+        eprintln!("# fetching {}", url);
+    }
+    let mut download = path.as_os_str().to_owned();
+    download.push(".download");
+    let download = std::path::PathBuf::from(download);
+    let command = [
+        "curl",
+        "--fail",
+        "--silent",
+        "--show-error",
+        "--location",
+        "--output",
+        &download.to_string_lossy(),
+        url,
+    ];
+    let fetch_error = |msg: String| {
+        std::fs::remove_file(&download).ok();
+        errors::GardenError::ConfigurationError(format!("unable to fetch {}: {}", url, msg))
+    };
+    let capture =
+        cmd::capture(cmd::exec_cmd(&command)).map_err(|err| fetch_error(err.to_string()))?;
+    if !capture.exit_status.success() {
+        return Err(fetch_error(capture.stderr_str().trim_end().to_string()));
+    }
+    // Avoid replacing a good cached copy with an invalid document.
+    let string = std::fs::read_to_string(&download).map_err(|err| fetch_error(err.to_string()))?;
+    YamlLoader::load_from_str(&string).map_err(|err| fetch_error(err.to_string()))?;
+    std::fs::rename(&download, path).map_err(|err| fetch_error(err.to_string()))?;
+
+    Ok(())
+}
+
+/// Return the "path:line" location of a dotted key, eg. "trees.example.variables.name".
+/// Overlays are searched first followed by the garden file and its includes.
+pub fn find_location(config: &model::Configuration, key: &str) -> Option<String> {
+    let path = config.path.as_ref()?;
+    let mut paths: Vec<std::path::PathBuf> = config.overlays.iter().rev().cloned().collect();
+    paths.push(path.to_path_buf());
+    if let Ok(doc) = read_yaml(path) {
+        let mut visited = vec![path.canonicalize().unwrap_or_else(|_| path.to_path_buf())];
+        let url = config.get_url().map(String::as_str);
+        let options = cached_fetch_options(config);
+        if merge_includes(&doc, Some(path), url, 0, options, &mut visited).is_ok() {
+            paths.extend(visited.into_iter().skip(1));
+        }
+    }
+
+    paths.iter().find_map(|path| {
+        let string = std::fs::read_to_string(path).ok()?;
+        let line = find_key_line(&string, key)?;
+        Some(format!("{}:{}", path.display(), line))
+    })
+}
+
+/// Return the line number of a dotted key within a YAML document.
+fn find_key_line(string: &str, key: &str) -> Option<usize> {
+    let mut locator = KeyLocator {
+        key,
+        line: None,
+        stack: Vec::new(),
+    };
+    let mut parser = yaml_rust::parser::Parser::new(string.chars());
+    parser.load(&mut locator, false).ok()?;
+
+    locator.line
+}
+
+/// A node that is being traversed by the KeyLocator.
+enum KeyFrame {
+    Mapping { path: String, key: Option<String> },
+    Sequence { path: String, index: usize },
+}
+
+/// Find the line on which a dotted key is defined by tracking the path to each
+/// node while parsing a YAML document.
+struct KeyLocator<'a> {
+    key: &'a str,
+    line: Option<usize>,
+    stack: Vec<KeyFrame>,
+}
+
+impl KeyLocator<'_> {
+    /// Return the dotted path for the next value in the current node.
+    fn child_path(&self) -> String {
+        let (path, child) = match self.stack.last() {
+            Some(KeyFrame::Mapping { path, key }) => (path, key.clone().unwrap_or_default()),
+            Some(KeyFrame::Sequence { path, index }) => (path, index.to_string()),
+            None => return String::new(),
+        };
+        if path.is_empty() {
+            child
+        } else {
+            format!("{}.{}", path, child)
+        }
+    }
+
+    /// Advance past a value in the current node.
+    fn end_value(&mut self) {
+        match self.stack.last_mut() {
+            Some(KeyFrame::Mapping { key, .. }) => *key = None,
+            Some(KeyFrame::Sequence { index, .. }) => *index += 1,
+            None => (),
+        }
+    }
+}
+
+impl yaml_rust::parser::MarkedEventReceiver for KeyLocator<'_> {
+    fn on_event(&mut self, event: yaml_rust::Event, mark: yaml_rust::scanner::Marker) {
+        match event {
+            yaml_rust::Event::Scalar(value, ..) => match self.stack.last_mut() {
+                Some(KeyFrame::Mapping { key, .. }) if key.is_none() => {
+                    *key = Some(value);
+                    if self.line.is_none() && self.child_path() == self.key {
+                        self.line = Some(mark.line());
+                    }
+                }
+                _ => self.end_value(),
+            },
+            yaml_rust::Event::MappingStart(_) => {
+                let path = self.child_path();
+                self.stack.push(KeyFrame::Mapping { path, key: None });
+            }
+            yaml_rust::Event::SequenceStart(_) => {
+                let path = self.child_path();
+                self.stack.push(KeyFrame::Sequence { path, index: 0 });
+            }
+            yaml_rust::Event::MappingEnd | yaml_rust::Event::SequenceEnd => {
+                self.stack.pop();
+                self.end_value();
+            }
+            yaml_rust::Event::Alias(_) => self.end_value(),
+            _ => (),
         }
     }
+}
+
+/// Merge the "includes" of the document read from "path". Documents that were
+/// fetched from a URL resolve their relative includes against the URL.
+fn merge_includes(
+    doc: &Yaml,
+    path: Option<&std::path::Path>,
+    url: Option<&str>,
+    config_verbose: u8,
+    fetch_options: model::FetchOptions,
+    visited: &mut Vec<std::path::PathBuf>,
+) -> Result<Yaml, errors::GardenError> {
+    let doc = &resolve_merge_keys(doc);
+    let mut includes = Vec::new();
+    if !get_vec_str(&doc["includes"], &mut includes) {
+        return Ok(doc.clone());
+    }
+
+    // Evaluate include paths in a scope that contains the document's variables.
+    let mut scope = model::Configuration::new();
+    if let Some(path) = path {
+        scope.set_path(path.to_path_buf());
+        let config_dir = match url {
+            Some(url) => {
+                scope.urls.insert(path.to_path_buf(), url.to_string());
+                Some(syntax::url_dirname(url).to_string())
+            }
+            None => path
+                .parent()
+                .and_then(|dirname| dirname.canonicalize().ok())
+                .map(|dirname| dirname.to_string_lossy().to_string()),
+        };
+        if let Some(config_dir) = config_dir {
+            scope.variables.push(model::NamedVariable::new(
+                "GARDEN_CONFIG_DIR".to_string(),
+                config_dir,
+                None,
+            ));
+        }
+    }
+    get_variables(&doc["variables"], &mut scope.variables);
+
+    let mut merged = Yaml::Hash(YamlHash::new());
+    for include in &includes {
+        let value = scope.config_file_path(&eval::value(&scope, include));
+        // Includes that are URLs are fetched into the configuration cache.
+        let (path, include_url) = if syntax::is_http_url(&value) {
+            (fetch_config_path(&value, fetch_options)?, Some(value))
+        } else {
+            (std::path::PathBuf::from(value), None)
+        };
+        if !path.exists() {
+            if config_verbose > 0 {
+                debug!("yaml: includes: skipping missing file {:?}", path);
+            }
+            continue;
+        }
+        // Guard against include cycles.
+        let path = path.canonicalize().unwrap_or(path);
+        if visited.contains(&path) {
+            continue;
+        }
+        visited.push(path.clone());
+
+        if config_verbose > 1 {
+            debug!("yaml: includes: {:?}", path);
+        }
+        let include_doc = load_yaml(&path)?;
+        let include_doc = merge_includes(
+            &include_doc,
+            Some(&path),
+            include_url.as_deref(),
+            config_verbose,
+            fetch_options,
+            visited,
+        )?;
+        merge_yaml(&mut merged, &include_doc);
+    }
+    merge_yaml(&mut merged, doc);
+
+    Ok(merged)
+}
+
+/// Merge the top-level entries from one document into another.
+///
+/// Top-level hashes are merged one level deep so that the individual trees,
+/// groups, gardens, variables and commands from the source replace entries
+/// with the same name in the destination.
+fn merge_yaml(dst: &mut Yaml, src: &Yaml) {
+    let (dst_hash, src_hash) = match (dst, src) {
+        (Yaml::Hash(dst_hash), Yaml::Hash(src_hash)) => (dst_hash, src_hash),
+        _ => return,
+    };
+    let includes_key = Yaml::String("includes".into());
+    for (key, value) in src_hash {
+        if *key == includes_key {
+            continue;
+        }
+        match (dst_hash.get_mut(key), value) {
+            (Some(Yaml::Hash(dst_entry)), Yaml::Hash(src_entry)) => {
+                for (entry_key, entry_value) in src_entry {
+                    set_entry(dst_entry, entry_key, entry_value);
+                }
+            }
+            _ => set_entry(dst_hash, key, value),
+        }
+    }
+}
 
-    model::Graft::new(graft_name, root, config)
+/// Recursively merge an override document into another document.
+///
+/// Hashes are merged at every level so that overrides can replace individual
+/// tree, variable and command entries. Lists and scalar values are replaced.
+fn overlay_yaml(dst: &mut Yaml, src: &Yaml) {
+    let (dst_hash, src_hash) = match (dst, src) {
+        (Yaml::Hash(dst_hash), Yaml::Hash(src_hash)) => (dst_hash, src_hash),
+        _ => return,
+    };
+    let includes_key = Yaml::String("includes".into());
+    for (key, value) in src_hash {
+        if *key == includes_key {
+            continue;
+        }
+        match dst_hash.get_mut(key) {
+            Some(dst_entry @ Yaml::Hash(_)) if matches!(value, Yaml::Hash(_)) => {
+                overlay_yaml(dst_entry, value);
+            }
+            _ => set_entry(dst_hash, key, value),
+        }
+    }
+}
+
+/// Layer the entries from the selected profile on top of the document.
+/// The top-level "profiles.<profile>" block is layered over the entire document and
+/// the "profiles.<profile>" block in each tree, template and garden is layered over its entry.
+/// Profiles that are not defined are errors only when selected using "--profile".
+fn apply_profile(
+    doc: &mut Yaml,
+    profile: &str,
+    source: model::ProfileSource,
+) -> Result<(), errors::GardenError> {
+    let profile_key = Yaml::String(profile.into());
+    let profiles_key = Yaml::String("profiles".into());
+    let Yaml::Hash(doc_hash) = doc else {
+        return Ok(());
+    };
+    if let Some(Yaml::Hash(profiles)) = doc_hash.get(&profiles_key) {
+        match (profiles.get(&profile_key).cloned(), source) {
+            (Some(profile_doc), _) => overlay_yaml(doc, &profile_doc),
+            (None, model::ProfileSource::Option) => {
+                return Err(errors::GardenError::InvalidConfiguration {
+                    msg: format!("profiles: unknown profile: {}", profile),
+                });
+            }
+            (None, model::ProfileSource::Environment) => {
+                eprintln!("warning: profiles: unknown profile: {}", profile);
+            }
+            (None, model::ProfileSource::Graft) => (),
+        }
+    }
+    let Yaml::Hash(doc_hash) = doc else {
+        return Ok(());
+    };
+    for section in ["templates", "trees", "gardens"] {
+        let Some(Yaml::Hash(entries)) = doc_hash.get_mut(&Yaml::String(section.into())) else {
+            continue;
+        };
+        for (_, entry) in entries.iter_mut() {
+            let profile_entry = match entry {
+                Yaml::Hash(entry_hash) => match entry_hash.get(&profiles_key) {
+                    Some(profiles) => profiles[profile].clone(),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            overlay_yaml(entry, &profile_entry);
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve YAML merge keys, e.g. "<<: *anchor", into the hashes that contain them.
+///
+/// The value of a merge key is a hash or a list of hashes. Entries that are defined
+/// explicitly take precedence over merged entries and hashes listed earlier in a
+/// merge list take precedence over later ones. Merged entries are placed at the
+/// position of the merge key.
+pub fn resolve_merge_keys(yaml: &Yaml) -> Yaml {
+    match yaml {
+        Yaml::Hash(hash) => {
+            let merge_key = Yaml::String("<<".into());
+            let mut result = YamlHash::new();
+            for (key, value) in hash {
+                if *key != merge_key {
+                    result.insert(key.clone(), resolve_merge_keys(value));
+                    continue;
+                }
+                let sources = match value {
+                    Yaml::Array(values) => values.iter().collect(),
+                    _ => vec![value],
+                };
+                for source in sources {
+                    if let Yaml::Hash(source_hash) = resolve_merge_keys(source) {
+                        for (merged_key, merged_value) in source_hash {
+                            if !hash.contains_key(&merged_key) && !result.contains_key(&merged_key)
+                            {
+                                result.insert(merged_key, merged_value);
+                            }
+                        }
+                    }
+                }
+            }
+            Yaml::Hash(result)
+        }
+        Yaml::Array(values) => Yaml::Array(values.iter().map(resolve_merge_keys).collect()),
+        _ => yaml.clone(),
+    }
+}
+
+/// Set a hash entry while retaining the position of existing entries.
+fn set_entry(hash: &mut YamlHash, key: &Yaml, value: &Yaml) {
+    if let Some(entry) = hash.get_mut(key) {
+        *entry = value.clone();
+    } else {
+        hash.insert(key.clone(), value.clone());
+    }
 }
 
 /// Read and parse YAML from a file path.
 pub fn read_yaml<P>(path: P) -> Result<Yaml, errors::GardenError>
+where
+    P: std::convert::AsRef<std::path::Path> + std::fmt::Debug,
+{
+    let mut doc = load_yaml(path)?;
+    add_missing_sections(&mut doc)?;
+
+    Ok(doc)
+}
+
+/// Load the first YAML document from a file path.
+fn load_yaml<P>(path: P) -> Result<Yaml, errors::GardenError>
 where
     P: std::convert::AsRef<std::path::Path> + std::fmt::Debug,
 {
@@ -721,9 +1681,7 @@ where
         });
     }
 
-    add_missing_sections(&mut docs[0])?;
-
-    Ok(docs[0].clone())
+    Ok(docs.swap_remove(0))
 }
 
 fn add_missing_sections(doc: &mut Yaml) -> Result<(), errors::GardenError> {
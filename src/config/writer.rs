@@ -1,24 +1,23 @@
 use std::io::Write;
 
+use yaml_rust::yaml::Hash as YamlHash;
 use yaml_rust::Yaml;
 use yaml_rust::YamlEmitter;
+use yaml_rust::YamlLoader;
 
 use super::super::errors;
 
-/// Write a Yaml object to a file
-
+/// Write a Yaml object to a file.
+/// Existing files are edited in place so that comments, anchors and the
+/// formatting of entries that did not change are preserved.
 pub fn write_yaml<P>(doc: &Yaml, path: P) -> Result<(), errors::GardenError>
 where
     P: std::convert::AsRef<std::path::Path> + std::fmt::Debug,
 {
-    // Emit the YAML configuration into a string
-    let mut out_str = String::new();
-    {
-        let mut emitter = YamlEmitter::new(&mut out_str);
-        emitter.multiline_strings(true);
-        emitter.dump(doc).ok(); // dump the YAML object to a String
-    }
-    out_str += "\n";
+    let out_str = match std::fs::read_to_string(&path) {
+        Ok(existing) => edit_yaml(&existing, doc).unwrap_or_else(|| emit_yaml(doc)),
+        Err(_) => emit_yaml(doc),
+    };
 
     let mut file = std::fs::File::create(&path).map_err(|io_err| {
         errors::GardenError::CreateConfigurationError {
@@ -39,3 +38,317 @@ where
             err: sync_err,
         })
 }
+
+/// Emit a Yaml object into a string.
+pub fn emit_yaml(doc: &Yaml) -> String {
+    let mut out_str = String::new();
+    {
+        let mut emitter = YamlEmitter::new(&mut out_str);
+        emitter.multiline_strings(true);
+        emitter.dump(doc).ok(); // dump the YAML object to a String
+    }
+    out_str += "\n";
+
+    out_str
+}
+
+/// Update the text of an existing YAML document so that it contains the values from "doc".
+///
+/// Only the entries whose values have changed are re-emitted. Everything else,
+/// including comments, anchors and key ordering, is left as-is. None is returned
+/// when the existing text cannot be edited in place, e.g. when it uses flow-style
+/// collections in the places that need to change.
+pub fn edit_yaml(text: &str, doc: &Yaml) -> Option<String> {
+    let original = YamlLoader::load_from_str(text).ok()?.into_iter().next()?;
+    let (old_hash, new_hash) = match (&original, doc) {
+        (Yaml::Hash(old_hash), Yaml::Hash(new_hash)) => (old_hash, new_hash),
+        _ => return None,
+    };
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let mut edits = Vec::new();
+    edit_hash(&lines, 0, lines.len(), 0, old_hash, new_hash, &mut edits)?;
+
+    let mut result = String::new();
+    let mut line_idx = 0;
+    // Stable sort so that multiple insertions at the same line retain their order.
+    edits.sort_by_key(|edit| edit.begin);
+    for edit in &edits {
+        for line in &lines[line_idx..edit.begin] {
+            result.push_str(line);
+        }
+        if !result.is_empty() && !result.ends_with('\n') {
+            result.push('\n');
+        }
+        result.push_str(&edit.text);
+        line_idx = line_idx.max(edit.end);
+    }
+    for line in &lines[line_idx..] {
+        result.push_str(line);
+    }
+
+    // Only use the edited text when it round-trips to the expected document.
+    let edited = YamlLoader::load_from_str(&result)
+        .ok()?
+        .into_iter()
+        .next()?;
+    if is_equivalent(&edited, doc) {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+/// Replace lines [begin, end) with the specified text. Insertions have begin == end.
+struct Edit {
+    begin: usize,
+    end: usize,
+    text: String,
+}
+
+/// A "key: value" entry spanning lines [begin, end).
+struct Block {
+    key: String,
+    begin: usize,
+    end: usize,
+}
+
+/// Record the edits needed to turn the entries of "old_hash" into "new_hash".
+/// The entries are located in lines [begin, end) at the specified indentation.
+fn edit_hash(
+    lines: &[&str],
+    begin: usize,
+    end: usize,
+    indent: usize,
+    old_hash: &YamlHash,
+    new_hash: &YamlHash,
+    edits: &mut Vec<Edit>,
+) -> Option<()> {
+    let blocks = find_blocks(lines, begin, end, indent)?;
+    // New entries are added after the last entry.
+    let append_idx = blocks.last().map(|block| block.end).unwrap_or(end);
+
+    for (key, value) in new_hash {
+        let key_str = key.as_str()?;
+        let block = blocks.iter().find(|block| block.key == key_str);
+        let block = match block {
+            Some(block) => block,
+            None => {
+                if !is_empty(value) {
+                    edits.push(Edit {
+                        begin: append_idx,
+                        end: append_idx,
+                        text: emit_entry(key, value, indent),
+                    });
+                }
+                continue;
+            }
+        };
+        let old_value = old_hash.get(key).unwrap_or(&Yaml::Null);
+        if old_value == value || (is_empty(old_value) && is_empty(value)) {
+            continue;
+        }
+        // Edit nested hashes in place when possible. Replace the entire entry otherwise.
+        if let (Yaml::Hash(old_child), Yaml::Hash(new_child)) = (old_value, value) {
+            let mut child_edits = Vec::new();
+            if edit_child_hash(lines, block, indent, old_child, new_child, &mut child_edits)
+                .is_some()
+            {
+                edits.extend(child_edits);
+                continue;
+            }
+        }
+        edits.push(Edit {
+            begin: block.begin,
+            end: block.end,
+            text: emit_entry(key, value, indent),
+        });
+    }
+
+    // Remove entries that no longer exist.
+    for block in &blocks {
+        if !new_hash.contains_key(&Yaml::String(block.key.clone())) {
+            edits.push(Edit {
+                begin: block.begin,
+                end: block.end,
+                text: String::new(),
+            });
+        }
+    }
+
+    Some(())
+}
+
+/// Edit the nested hash entries that are contained within a block.
+fn edit_child_hash(
+    lines: &[&str],
+    block: &Block,
+    indent: usize,
+    old_hash: &YamlHash,
+    new_hash: &YamlHash,
+    edits: &mut Vec<Edit>,
+) -> Option<()> {
+    // The value must be a block-style hash. "key: {}" and "key: value" cannot be edited.
+    let header = lines[block.begin].trim();
+    let (_, value) = split_key(header)?;
+    let value = value.trim();
+    if !(value.is_empty() || value.starts_with('#') || is_anchor(value)) {
+        return None;
+    }
+    let child_indent = (block.begin + 1..block.end)
+        .map(|idx| lines[idx])
+        .find(|line| !is_ignored(line))
+        .map(indentation)?;
+    if child_indent <= indent {
+        return None;
+    }
+
+    edit_hash(
+        lines,
+        block.begin + 1,
+        block.end,
+        child_indent,
+        old_hash,
+        new_hash,
+        edits,
+    )
+}
+
+/// Find the "key: value" entries at the specified indentation within lines [begin, end).
+/// None is returned when a line at the indentation level is not a simple "key:" entry.
+fn find_blocks(lines: &[&str], begin: usize, end: usize, indent: usize) -> Option<Vec<Block>> {
+    let mut blocks: Vec<Block> = Vec::new();
+    for idx in begin..end {
+        let line = lines[idx];
+        if is_ignored(line) || indentation(line) > indent {
+            continue;
+        }
+        // Sequences can be indented at the same level as their key.
+        let trimmed = line.trim();
+        if !blocks.is_empty() && (trimmed == "-" || trimmed.starts_with("- ")) {
+            continue;
+        }
+        if indentation(line) < indent {
+            return None;
+        }
+        let (key, _) = split_key(trimmed)?;
+        if let Some(block) = blocks.last_mut() {
+            block.end = block_end(lines, block.begin, idx, indent);
+        }
+        blocks.push(Block {
+            key,
+            begin: idx,
+            end,
+        });
+    }
+    if let Some(block) = blocks.last_mut() {
+        block.end = block_end(lines, block.begin, end, indent);
+    }
+
+    Some(blocks)
+}
+
+/// Return the end of a block that starts at "begin" and ends before "end".
+/// Trailing blank lines and comments that are not indented beyond the block's key
+/// belong to the following entry and are not included.
+fn block_end(lines: &[&str], begin: usize, mut end: usize, indent: usize) -> usize {
+    while end > begin + 1 {
+        let line = lines[end - 1];
+        if line.trim().is_empty() || (is_ignored(line) && indentation(line) <= indent) {
+            end -= 1;
+        } else {
+            break;
+        }
+    }
+
+    end
+}
+
+/// Split a "key: value" line into its key and the remaining text.
+fn split_key(line: &str) -> Option<(String, &str)> {
+    let (key, rest) = match line.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let close = line[1..].find(quote)? + 1;
+            (line[1..close].to_string(), &line[close + 1..])
+        }
+        '-' | '?' | '{' | '[' | '&' | '*' | '!' | '|' | '>' => return None,
+        _ => {
+            let colon = line.find(": ").or_else(|| {
+                if line.ends_with(':') {
+                    Some(line.len() - 1)
+                } else {
+                    None
+                }
+            })?;
+            (line[..colon].trim_end().to_string(), &line[colon..])
+        }
+    };
+    let rest = rest.strip_prefix(':')?;
+    if !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+
+    Some((key, rest))
+}
+
+/// Is the value an anchor definition, e.g. "&anchor"?
+fn is_anchor(value: &str) -> bool {
+    value.starts_with('&') && !value.contains(' ')
+}
+
+/// Blank lines, comments and document markers are ignored when looking for entries.
+fn is_ignored(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty()
+        || trimmed.starts_with('#')
+        || trimmed.starts_with('%')
+        || trimmed == "---"
+        || trimmed == "..."
+}
+
+/// Return the number of leading spaces in a line.
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Treat null values and empty hashes as equivalent.
+fn is_empty(value: &Yaml) -> bool {
+    match value {
+        Yaml::Null => true,
+        Yaml::Hash(hash) => hash.is_empty(),
+        _ => false,
+    }
+}
+
+/// Compare documents while treating missing entries, null values and empty hashes
+/// as equivalent. Empty entries are not added when editing in place.
+fn is_equivalent(a: &Yaml, b: &Yaml) -> bool {
+    match (a, b) {
+        (Yaml::Hash(a_hash), Yaml::Hash(b_hash)) => {
+            let matches = |lhs: &YamlHash, rhs: &YamlHash| {
+                lhs.iter().all(|(key, value)| match rhs.get(key) {
+                    Some(other) => is_equivalent(value, other),
+                    None => is_empty(value),
+                })
+            };
+            matches(a_hash, b_hash) && matches(b_hash, a_hash)
+        }
+        _ => a == b || (is_empty(a) && is_empty(b)),
+    }
+}
+
+/// Emit a single "key: value" entry at the specified indentation.
+fn emit_entry(key: &Yaml, value: &Yaml, indent: usize) -> String {
+    let mut entry = YamlHash::new();
+    entry.insert(key.clone(), value.clone());
+    let emitted = emit_yaml(&Yaml::Hash(entry));
+    let prefix = " ".repeat(indent);
+
+    let mut result = String::new();
+    for line in emitted.lines().filter(|line| *line != "---") {
+        result.push_str(&prefix);
+        result.push_str(line);
+        result.push('\n');
+    }
+
+    result
+}
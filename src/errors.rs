@@ -21,12 +21,18 @@ pub enum GardenError {
     #[error("exit status {0}")]
     ExitStatus(i32),
 
+    #[error("{0}")]
+    EvalError(#[from] EvalError),
+
     #[error("{0}")]
     FileExists(String),
 
     #[error("file not found")]
     FileNotFound,
 
+    #[error("git error: {0}")]
+    GitError(String),
+
     #[error("unable to find '{garden}': No garden exists with that name")]
     GardenNotFound { garden: String },
 
@@ -66,6 +72,14 @@ pub enum GardenError {
     #[error("unable to find '{tree}': No tree exists with that name")]
     TreeNotFound { tree: String },
 
+    /// TreesFailed reports the trees where commands failed. The summarized
+    /// exit status is used when exiting and no error message is printed.
+    #[error("{} failed: {}", plural_trees(failures.len()), failed_tree_names(failures))]
+    TreesFailed {
+        exit_status: i32,
+        failures: Vec<TreeFailure>,
+    },
+
     #[error("invalid arguments: {0}")]
     Usage(String),
 
@@ -85,6 +99,105 @@ pub enum GardenError {
     WriteConfigurationError { path: std::path::PathBuf },
 }
 
+/// An error encountered while evaluating a garden expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvalError {
+    /// The name of the variable whose expression failed to evaluate.
+    pub variable: Option<String>,
+    /// The offending expression.
+    pub expr: String,
+    /// The configuration block containing the variable, eg. "trees.example.variables".
+    pub scope: Option<String>,
+    /// The "path:line" location of the variable in the configuration files.
+    pub location: Option<String>,
+    /// A description of the error.
+    pub message: String,
+}
+
+impl EvalError {
+    pub fn new(expr: &str, message: String) -> Self {
+        EvalError {
+            variable: None,
+            expr: expr.to_string(),
+            scope: None,
+            location: None,
+            message,
+        }
+    }
+
+    /// Attribute the error to a variable unless the error was already attributed
+    /// to a variable. Errors are reported against the innermost variable.
+    pub fn in_scope(mut self, scope: &str, variable: &str) -> Self {
+        if self.variable.is_none() {
+            self.variable = Some(variable.to_string());
+            self.scope = Some(scope.to_string());
+        }
+        self
+    }
+
+    /// Return the dotted configuration path for the variable, eg. "variables.name".
+    pub fn key(&self) -> Option<String> {
+        let variable = self.variable.as_ref()?;
+        match &self.scope {
+            Some(scope) if !scope.is_empty() => Some(format!("{}.{}", scope, variable)),
+            _ => Some(variable.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(location) = &self.location {
+            write!(f, "{}: ", location)?;
+        }
+        if let Some(key) = self.key() {
+            write!(f, "{}: ", key)?;
+        }
+        write!(f, "{}: {}", self.message, self.expr)
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// A command that failed when it ran in a tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeFailure {
+    /// The name of the tree where the command failed.
+    pub tree: String,
+    /// The command that failed, eg. a custom command name, an "exec" command line,
+    /// or the hook or clone command that failed while growing the tree.
+    pub command: String,
+    /// The exit status returned by the command.
+    pub exit_status: i32,
+}
+
+impl std::fmt::Display for TreeFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} returned exit status {}",
+            self.tree, self.command, self.exit_status
+        )
+    }
+}
+
+/// Return "1 tree" or "N trees".
+fn plural_trees(count: usize) -> String {
+    match count {
+        1 => "1 tree".into(),
+        _ => format!("{} trees", count),
+    }
+}
+
+/// Return a comma-separated list of the failing trees.
+fn failed_tree_names(failures: &[TreeFailure]) -> String {
+    failures
+        .iter()
+        .map(|failure| failure.tree.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 #[derive(Error, Debug)]
 pub enum CommandError {
     /// ExitStatus is used to exit without printing an error message.
@@ -97,11 +210,16 @@ pub const EX_OK: i32 = 0;
 pub const EX_ERROR: i32 = 1;
 pub const EX_USAGE: i32 = 64;
 pub const EX_DATAERR: i32 = 65;
+pub const EX_UNAVAILABLE: i32 = 69;
 pub const EX_SOFTWARE: i32 = 70;
 pub const EX_OSERR: i32 = 71;
 pub const EX_CANTCREAT: i32 = 73;
 pub const EX_IOERR: i32 = 74;
 pub const EX_CONFIG: i32 = 78;
+// Exit statuses used by timeout(1) and shells for timeouts and interrupts.
+pub const EX_TIMEOUT: i32 = 124;
+pub const EX_INTERRUPTED: i32 = 130;
+pub const EX_TERMINATED: i32 = 143;
 
 impl std::convert::From<GardenError> for i32 {
     fn from(garden_err: GardenError) -> Self {
@@ -110,9 +228,11 @@ impl std::convert::From<GardenError> for i32 {
             GardenError::ConfigurationError(_) => EX_CONFIG,
             GardenError::CreateConfigurationError { .. } => EX_CANTCREAT,
             GardenError::EmptyConfiguration { .. } => EX_CONFIG,
+            GardenError::EvalError(_) => EX_DATAERR,
             GardenError::ExitStatus(status) => status, // Explicit exit code
             GardenError::FileExists(_) => EX_CANTCREAT,
             GardenError::FileNotFound => EX_IOERR,
+            GardenError::GitError(_) => EX_ERROR,
             GardenError::GardenNotFound { .. } => EX_USAGE,
             GardenError::GardenPatternError { .. } => EX_DATAERR,
             GardenError::IOError(_) => EX_IOERR,
@@ -123,6 +243,7 @@ impl std::convert::From<GardenError> for i32 {
             GardenError::ReadFile { .. } => EX_IOERR,
             GardenError::SyncConfigurationError { .. } => EX_IOERR,
             GardenError::TreeNotFound { .. } => EX_USAGE,
+            GardenError::TreesFailed { exit_status, .. } => exit_status,
             GardenError::Usage(_) => EX_USAGE,
             GardenError::WorktreeGitCheckoutError { .. } => EX_CANTCREAT,
             GardenError::WorktreeParentCreationError { .. } => EX_CANTCREAT,
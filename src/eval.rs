@@ -1,112 +1,94 @@
-use std::borrow::Cow;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 
 use super::cmd;
+use super::config;
+use super::errors;
 use super::model;
+use super::path;
 use super::query;
 use super::syntax;
 
+thread_local! {
+    /// The scope and name of the variables that are currently being evaluated.
+    /// This is used to detect variables that reference themselves.
+    static EVALUATING: RefCell<Vec<(String, String)>> = const { RefCell::new(Vec::new()) };
+    /// Evaluation errors that have already been reported as warnings.
+    static REPORTED: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+}
+
+/// Lookup function used to resolve variable names while expanding expressions.
+type Lookup<'a> = dyn Fn(&str) -> Result<String, errors::EvalError> + 'a;
+
 /// Expand variables across all scopes (garden, tree, and global)
 fn expand_tree_vars(
     config: &model::Configuration,
     tree_idx: model::TreeIndex,
     garden_idx: Option<model::GardenIndex>,
     name: &str,
-) -> Result<Option<String>, String> {
+) -> Result<Option<String>, errors::EvalError> {
     // Special case $0, $1, .. $N so they can be used in commands.
     if syntax::is_digit(name) {
         return Ok(Some(format!("${}", name)));
     }
+    // ${ARGS} expands to "$@" so that commands can place their arguments.
+    if syntax::is_arguments(name) {
+        return Ok(Some("$@".to_string()));
+    }
+    // ${env.NAME} reads the OS environment and bypasses garden variables.
+    if let Some(env_name) = syntax::env_name(name) {
+        return Ok(Some(std::env::var(env_name).unwrap_or_default()));
+    }
 
     // Special-case evaluation of ${graft::values}.
     if syntax::is_graft(name) {
-        // TODO: make the error messages more precise by including the tree
-        // details by looking up the tree in the configuration.
-        let (ok, graft_name, _remainder) = syntax::split_graft(name);
-        if !ok {
-            return Err(format!("Invalid graft: {}", name));
-        }
-
-        let graft = config
-            .get_graft(graft_name)
-            .map_err(|_err| format!("Invalid graft: {}", graft_name))?;
-
-        // TODO recurse on the remainder and evaluate it using the ConfigId
-        // for the graft.
-        let _graft_id = graft.get_id().ok_or(format!("Invalid graft: {}", name))?;
+        check_graft(config, name)?;
     }
 
-    let mut var_idx: usize = 0;
-    let mut found = false;
-
     // First check for the variable at the garden scope.
     // Garden scope overrides tree and global scope.
     if let Some(garden) = garden_idx {
-        for (idx, var) in config.gardens[garden].variables.iter().enumerate() {
-            if var.get_name() == name {
-                if let Some(var_value) = var.get_value() {
-                    return Ok(Some(var_value.to_string()));
-                }
-                var_idx = idx;
-                found = true;
-                break;
+        let garden = &config.gardens[garden];
+        if let Some(var) = garden.variables.iter().find(|var| var.get_name() == name) {
+            if let Some(var_value) = var.get_value() {
+                return Ok(Some(var_value.to_string()));
             }
-        }
-
-        if found {
-            let expr = config.gardens[garden].variables[var_idx]
-                .get_expr()
-                .to_string();
-            let result = tree_value(config, &expr, tree_idx, garden_idx);
-            config.gardens[garden].variables[var_idx].set_value(result.clone());
+            let scope = format!("gardens.{}.variables", garden.get_name());
+            let expr = var.get_expr().to_string();
+            let result = eval_variable(&scope, name, &expr, || {
+                try_tree_value(config, &expr, tree_idx, garden_idx)
+            })?;
+            var.set_value(result.clone());
             return Ok(Some(result));
         }
     }
 
     // Nothing was found -- check for the variable in tree scope.
-    found = false;
-    var_idx = 0;
-
-    for (idx, var) in config.trees[tree_idx].variables.iter().enumerate() {
-        if var.get_name() == name {
-            if let Some(var_value) = var.get_value() {
-                return Ok(Some(var_value.to_string()));
-            }
-            found = true;
-            var_idx = idx;
-            break;
+    let tree = &config.trees[tree_idx];
+    if let Some(var) = tree.variables.iter().find(|var| var.get_name() == name) {
+        if let Some(var_value) = var.get_value() {
+            return Ok(Some(var_value.to_string()));
         }
-    }
-
-    if found {
-        let expr = config.trees[tree_idx].variables[var_idx]
-            .get_expr()
-            .to_string();
-        let result = tree_value(config, &expr, tree_idx, garden_idx);
-        config.trees[tree_idx].variables[var_idx].set_value(result.to_string());
+        let scope = format!("trees.{}.variables", tree.get_name());
+        let expr = var.get_expr().to_string();
+        let result = eval_variable(&scope, name, &expr, || {
+            try_tree_value(config, &expr, tree_idx, garden_idx)
+        })?;
+        var.set_value(result.clone());
         return Ok(Some(result));
     }
 
     // Nothing was found.  Check for the variable in global/config scope.
-    found = false;
-    var_idx = 0;
-
-    for (idx, var) in config.variables.iter().enumerate() {
-        if var.get_name() == name {
-            // Return the value immediately if it's already been evaluated.
-            if let Some(var_value) = var.get_value() {
-                return Ok(Some(var_value.to_string()));
-            }
-            found = true;
-            var_idx = idx;
-            break;
+    if let Some(var) = config.variables.iter().find(|var| var.get_name() == name) {
+        // Return the value immediately if it's already been evaluated.
+        if let Some(var_value) = var.get_value() {
+            return Ok(Some(var_value.to_string()));
         }
-    }
-
-    if found {
-        let expr = config.variables[var_idx].get_expr().to_string();
-        let result = tree_value(config, &expr, tree_idx, garden_idx);
-        config.variables[var_idx].set_value(result.clone());
+        let expr = var.get_expr().to_string();
+        let result = eval_variable("variables", name, &expr, || {
+            try_tree_value(config, &expr, tree_idx, garden_idx)
+        })?;
+        var.set_value(result.clone());
         return Ok(Some(result));
     }
 
@@ -129,30 +111,30 @@ fn _expand_tree_context_vars(
 }
 
 /// Expand variables at global scope only
-fn expand_vars(config: &model::Configuration, name: &str) -> Result<Option<String>, String> {
+fn expand_vars(
+    config: &model::Configuration,
+    name: &str,
+) -> Result<Option<String>, errors::EvalError> {
     // Special case $0, $1, .. $N so they can be used in commands.
     if syntax::is_digit(name) {
         return Ok(Some(format!("${}", name)));
     }
-
-    let mut var_idx: usize = 0;
-    let mut found = false;
-
-    for (idx, var) in config.variables.iter().enumerate() {
-        if var.get_name() == name {
-            if let Some(var_value) = var.get_value() {
-                return Ok(Some(var_value.to_string()));
-            }
-            var_idx = idx;
-            found = true;
-            break;
-        }
+    // ${ARGS} expands to "$@" so that commands can place their arguments.
+    if syntax::is_arguments(name) {
+        return Ok(Some("$@".to_string()));
+    }
+    // ${env.NAME} reads the OS environment and bypasses garden variables.
+    if let Some(env_name) = syntax::env_name(name) {
+        return Ok(Some(std::env::var(env_name).unwrap_or_default()));
     }
 
-    if found {
-        let expr = config.variables[var_idx].get_expr().to_string();
-        let result = value(config, &expr);
-        config.variables[var_idx].set_value(result.clone());
+    if let Some(var) = config.variables.iter().find(|var| var.get_name() == name) {
+        if let Some(var_value) = var.get_value() {
+            return Ok(Some(var_value.to_string()));
+        }
+        let expr = var.get_expr().to_string();
+        let result = eval_variable("variables", name, &expr, || try_value(config, &expr))?;
+        var.set_value(result.clone());
 
         return Ok(Some(result));
     }
@@ -166,6 +148,64 @@ fn expand_vars(config: &model::Configuration, name: &str) -> Result<Option<Strin
     Ok(Some("".into()))
 }
 
+/// Evaluate a variable's expression. Errors are attributed to the variable and
+/// variables that reference themselves are reported instead of recursing forever.
+fn eval_variable<F>(
+    scope: &str,
+    name: &str,
+    expr: &str,
+    eval: F,
+) -> Result<String, errors::EvalError>
+where
+    F: FnOnce() -> Result<String, errors::EvalError>,
+{
+    let cycle = EVALUATING.with(|stack| {
+        let stack = stack.borrow();
+        stack
+            .iter()
+            .position(|(var_scope, var_name)| var_scope == scope && var_name == name)
+            .map(|start| {
+                stack[start..]
+                    .iter()
+                    .map(|(_, var_name)| var_name.as_str())
+                    .chain(std::iter::once(name))
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            })
+    });
+    if let Some(cycle) = cycle {
+        let message = format!("recursive variable reference: {}", cycle);
+        return Err(errors::EvalError::new(expr, message).in_scope(scope, name));
+    }
+
+    EVALUATING.with(|stack| {
+        stack
+            .borrow_mut()
+            .push((scope.to_string(), name.to_string()))
+    });
+    let result = eval();
+    EVALUATING.with(|stack| stack.borrow_mut().pop());
+
+    result.map_err(|err| err.in_scope(scope, name))
+}
+
+/// Check that a "${graft::name}" expression references a known graft.
+fn check_graft(config: &model::Configuration, name: &str) -> Result<(), errors::EvalError> {
+    let expr = format!("${{{}}}", name);
+    let (ok, graft_name, _remainder) = syntax::split_graft(name);
+    if !ok {
+        return Err(errors::EvalError::new(&expr, "invalid graft".into()));
+    }
+    // Grafts are read on demand so the graft only needs to be defined.
+    // TODO recurse on the remainder and evaluate it using the ConfigId
+    // for the graft.
+    config
+        .get_graft(graft_name)
+        .map_err(|_err| errors::EvalError::new(&expr, format!("unknown graft: {}", graft_name)))?;
+
+    Ok(())
+}
+
 /// Resolve ~ to the current user's home directory
 fn home_dir() -> Option<std::path::PathBuf> {
     // Honor $HOME when set in the environment.
@@ -175,17 +215,42 @@ fn home_dir() -> Option<std::path::PathBuf> {
     dirs::home_dir()
 }
 
-/// Resolve a variable in a garden/tree/global scope
+/// Resolve a variable in a garden/tree/global scope.
+/// Evaluation errors are reported as warnings. Variables that cannot be evaluated
+/// expand to empty strings and the rest of the expression is expanded.
 pub fn tree_value(
     config: &model::Configuration,
     expr: &str,
     tree_idx: model::TreeIndex,
     garden_idx: Option<model::GardenIndex>,
 ) -> String {
-    let expanded = shellexpand::full_with_context(expr, home_dir, |x| {
+    try_tree_value(config, expr, tree_idx, garden_idx).unwrap_or_else(|err| {
+        warn_eval_error(config, err);
+        // Graft references that cannot be evaluated are left unexpanded.
+        let lookup = |name: &str| {
+            expand_tree_vars(config, tree_idx, garden_idx, name)
+                .unwrap_or_else(|_| (!syntax::is_graft(name)).then(String::new))
+        };
+        let expanded = expand_lenient(expr, &lookup);
+        exec_expression_cached(config, config.trees[tree_idx].get_name(), &expanded)
+    })
+}
+
+/// Resolve a variable in a garden/tree/global scope and report evaluation errors.
+pub fn try_tree_value(
+    config: &model::Configuration,
+    expr: &str,
+    tree_idx: model::TreeIndex,
+    garden_idx: Option<model::GardenIndex>,
+) -> Result<String, errors::EvalError> {
+    let resolved = expand_defaults(expr, &|name| {
+        expand_tree_vars(config, tree_idx, garden_idx, name).map(Option::unwrap_or_default)
+    })
+    .map_err(|err| with_expr(err, expr))?;
+    let expanded = shellexpand::full_with_context(&resolved, home_dir, |x| {
         expand_tree_vars(config, tree_idx, garden_idx, x)
     })
-    .unwrap_or_else(|_| Cow::from(expr))
+    .map_err(|err| with_expr(err.cause, expr))?
     .to_string();
 
     // TODO exec_expression_with_path() to use the tree path.
@@ -193,16 +258,207 @@ pub fn tree_value(
     // exec expression will implicitly depend on the entire environment,
     // and potentially many variables (including itself).  Exec expressions
     // always use the default environment.
-    exec_expression(&expanded)
+    Ok(exec_expression_cached(
+        config,
+        config.trees[tree_idx].get_name(),
+        &expanded,
+    ))
 }
 
-/// Resolve a variable in configuration/global scope
+/// Resolve a variable in configuration/global scope.
+/// Evaluation errors are reported as warnings. Variables that cannot be evaluated
+/// expand to empty strings and the rest of the expression is expanded.
 pub fn value(config: &model::Configuration, expr: &str) -> String {
-    let expanded = shellexpand::full_with_context(expr, home_dir, |x| expand_vars(config, x))
-        .unwrap_or_else(|_| Cow::from(""))
+    try_value(config, expr).unwrap_or_else(|err| {
+        warn_eval_error(config, err);
+        let lookup = |name: &str| expand_vars(config, name).unwrap_or_else(|_| Some(String::new()));
+        let expanded = expand_lenient(expr, &lookup);
+        exec_expression_cached(config, "", &expanded)
+    })
+}
+
+/// Expand an expression using a lookup function that does not fail.
+/// An empty string is returned when the expression itself is malformed.
+fn expand_lenient(expr: &str, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+    let resolved = match expand_defaults(expr, &|name| Ok(lookup(name).unwrap_or_default())) {
+        Ok(resolved) => resolved,
+        Err(_) => return String::new(),
+    };
+    shellexpand::full_with_context_no_errors(&resolved, home_dir, lookup).to_string()
+}
+
+/// Report an evaluation error as a warning along with the location of the variable
+/// that caused it. Each error is reported once.
+fn warn_eval_error(config: &model::Configuration, mut err: errors::EvalError) {
+    let reported = REPORTED.with(|reported| reported.borrow_mut().insert(err.to_string()));
+    if !reported {
+        return;
+    }
+    if let Some(key) = err.key() {
+        err.location = config::reader::find_location(config, &key);
+    }
+    eprintln!("warning: {}", err);
+}
+
+/// Resolve a variable in configuration/global scope and report evaluation errors.
+pub fn try_value(config: &model::Configuration, expr: &str) -> Result<String, errors::EvalError> {
+    let resolved = expand_defaults(expr, &|name| {
+        expand_vars(config, name).map(Option::unwrap_or_default)
+    })
+    .map_err(|err| with_expr(err, expr))?;
+    let expanded = shellexpand::full_with_context(&resolved, home_dir, |x| expand_vars(config, x))
+        .map_err(|err| with_expr(err.cause, expr))?
         .to_string();
 
-    exec_expression(&expanded)
+    Ok(exec_expression_cached(config, "", &expanded))
+}
+
+/// Check an expression for errors without evaluating variables or exec expressions.
+pub fn check_expression(
+    config: &model::Configuration,
+    expr: &str,
+) -> Result<(), errors::EvalError> {
+    let check_name = |name: &str| {
+        if syntax::is_graft(name) {
+            check_graft(config, name)?;
+        }
+        Ok(String::new())
+    };
+    let resolved = expand_defaults(expr, &check_name).map_err(|err| with_expr(err, expr))?;
+    shellexpand::env_with_context(&resolved, |name| check_name(name).map(Some))
+        .map_err(|err| with_expr(err.cause, expr))?;
+
+    Ok(())
+}
+
+/// Report errors that are not attributed to a variable against the evaluated expression.
+fn with_expr(mut err: errors::EvalError, expr: &str) -> errors::EvalError {
+    if err.variable.is_none() {
+        err.expr = expr.to_string();
+    }
+    err
+}
+
+/// Resolve "${name:-default}" expressions into either "${name}" when the variable
+/// has a non-empty value or into the default expression otherwise.
+/// The result is expanded by shellexpand so default values can contain ${variables}.
+fn expand_defaults(expr: &str, lookup: &Lookup) -> Result<String, errors::EvalError> {
+    let mut result = String::new();
+    let mut remaining = expr;
+    while let Some(start) = remaining.find("${") {
+        result.push_str(&remaining[..start]);
+        // "$${" is an escaped "$" followed by a literal "{".
+        let escaped = result.chars().rev().take_while(|c| *c == '$').count() % 2 == 1;
+        if escaped {
+            result.push_str("${");
+            remaining = &remaining[start + 2..];
+            continue;
+        }
+        let inner_start = start + 2;
+        let end = match syntax::find_closing_brace(&remaining[inner_start..]) {
+            Some(offset) => inner_start + offset,
+            None => {
+                return Err(errors::EvalError::new(
+                    expr,
+                    "unterminated \"${\" expression".into(),
+                ))
+            }
+        };
+        let inner = &remaining[inner_start..end];
+        if let Some(value) = call_function(inner, lookup)? {
+            // Escape "$" so that the value is not expanded again.
+            result.push_str(&value.replace('$', "$$"));
+            remaining = &remaining[end + 1..];
+            continue;
+        }
+        match syntax::split_default(inner) {
+            Some((name, default)) => {
+                if lookup(name)?.is_empty() {
+                    result.push_str(&expand_defaults(default, lookup)?);
+                } else {
+                    result.push_str(&format!("${{{}}}", name));
+                }
+            }
+            None => result.push_str(&remaining[start..=end]),
+        }
+        remaining = &remaining[end + 1..];
+    }
+    result.push_str(remaining);
+
+    Ok(result)
+}
+
+/// Evaluate a "${ function(arg, ...) }" expression. Arguments are either quoted
+/// strings, variable names or nested function calls.
+/// None is returned when the expression is not a function call.
+fn call_function(expr: &str, lookup: &Lookup) -> Result<Option<String>, errors::EvalError> {
+    let (name, args) = match syntax::split_function(expr) {
+        Some(function) => function,
+        None => return Ok(None),
+    };
+    let call = format!("${{{}}}", expr);
+    let arity = match name {
+        "basename" | "dirname" => 1,
+        "replace" => 3,
+        _ => {
+            let message = format!("unknown function: {}()", name);
+            return Err(errors::EvalError::new(&call, message));
+        }
+    };
+    if args.len() != arity {
+        let plural = if arity == 1 { "" } else { "s" };
+        let message = format!(
+            "{}() expects {} argument{} but {} were given",
+            name,
+            arity,
+            plural,
+            args.len()
+        );
+        return Err(errors::EvalError::new(&call, message));
+    }
+    let mut values = Vec::new();
+    for arg in &args {
+        let value = match syntax::unquote(arg) {
+            Some(literal) => literal.to_string(),
+            None => match call_function(arg, lookup)? {
+                Some(value) => value,
+                None => lookup(arg)?,
+            },
+        };
+        values.push(value);
+    }
+
+    let path = std::path::Path::new(&values[0]);
+    let result = match name {
+        "basename" => match path.file_name() {
+            Some(basename) => basename.to_string_lossy().to_string(),
+            None => values[0].clone(),
+        },
+        "dirname" => match path.parent() {
+            Some(parent) if parent.as_os_str().is_empty() => ".".to_string(),
+            Some(parent) => parent.to_string_lossy().to_string(),
+            None => values[0].clone(),
+        },
+        _ => values[0].replace(&values[1], &values[2]),
+    };
+
+    Ok(Some(result))
+}
+
+/// Evaluate exec expressions using the configuration's exec cache when enabled.
+/// Cached values are keyed by the tree name and the evaluated expression.
+fn exec_expression_cached(config: &model::Configuration, tree: &str, string: &str) -> String {
+    let cache = match &config.exec_cache {
+        Some(cache) if syntax::is_exec(string) => cache,
+        _ => return exec_expression(string),
+    };
+    if let Some(value) = cache.get(tree, string) {
+        return value;
+    }
+    let value = exec_expression(string);
+    cache.insert(tree, string, &value);
+
+    value
 }
 
 /// Evaluate "$ <command>" command strings, AKA "exec expressions".
@@ -246,6 +502,20 @@ pub fn multi_variable(
     result
 }
 
+/// Record the name of the custom command that is being evaluated in the
+/// ${GARDEN_CMD_NAME} built-in variable.
+pub fn set_command_name(config: &model::Configuration, name: &str) {
+    if let Some(var) = config
+        .variables
+        .iter()
+        .find(|var| var.get_name() == "GARDEN_CMD_NAME")
+    {
+        if var.get_value().map(String::as_str) != Some(name) {
+            var.set_value(name.to_string());
+        }
+    }
+}
+
 /// Evaluate environments
 pub fn environment(
     config: &model::Configuration,
@@ -302,6 +572,13 @@ pub fn environment(
     // values hashmap.
     let mut values: HashMap<String, String> = HashMap::new();
 
+    // Values from "env-file" entries are applied before the "environment" entries
+    // so that the environment configured in the garden file takes precedence.
+    for (name, value) in env_file_values(config, context) {
+        values.insert(name.clone(), value.clone());
+        result.push((name, value));
+    }
+
     for (var_name, env_values) in &var_values {
         let mut name = var_name.clone();
         let mut is_assign = false;
@@ -364,14 +641,14 @@ pub fn environment(
             if !is_append {
                 path_values.push(value.clone());
             }
-            for path in current.split(':') {
+            for path in current.split(path::LIST_SEPARATOR) {
                 path_values.push(path.into());
             }
             if is_append {
                 path_values.push(value.clone());
             }
 
-            let path_value = path_values.join(":");
+            let path_value = path_values.join(path::LIST_SEPARATOR);
             values.insert(name.clone(), path_value.clone());
             result.push((name.clone(), path_value));
         }
@@ -380,49 +657,258 @@ pub fn environment(
     result
 }
 
+/// Read the variables from the tree's and the garden's "env-file" entries.
+/// Tree files are relative to the tree path and garden files are relative to the
+/// garden root. Files that do not exist are ignored.
+fn env_file_values(
+    config: &model::Configuration,
+    context: &model::TreeContext,
+) -> Vec<(String, String)> {
+    let tree = &config.trees[context.tree];
+    let tree_path = tree
+        .path_as_ref()
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|_| config.root_path.clone());
+    let mut paths = Vec::new();
+    for env_file in &tree.env_files {
+        paths.push(tree_path.join(tree_value(config, env_file, context.tree, context.garden)));
+    }
+    if let Some(idx) = context.garden {
+        for env_file in &config.gardens[idx].env_files {
+            paths.push(config.root_path.join(tree_value(
+                config,
+                env_file,
+                context.tree,
+                context.garden,
+            )));
+        }
+    }
+
+    let mut result = Vec::new();
+    for path in paths {
+        match std::fs::read_to_string(&path) {
+            Ok(content) => result.append(&mut syntax::parse_env_file(&content)),
+            Err(err) => tracing::debug!("unable to read {}: {}", path.display(), err),
+        }
+    }
+
+    result
+}
+
 /// Evaluate commands
 pub fn command(
     app: &model::ApplicationContext,
     context: &model::TreeContext,
     name: &str,
 ) -> Vec<Vec<String>> {
-    let mut vars = Vec::new();
-    let mut result = Vec::new();
-    let config = match context.config {
-        Some(config_id) => app.get_config(config_id),
-        None => app.get_root_config(),
-    };
+    let config = app.get_context_config(context);
+    let mut vars: Vec<model::MultiVariable> =
+        command_definitions(config, context, name, app.options.command_scope)
+            .into_iter()
+            .map(|(_, var)| var.clone())
+            .collect();
+
+    vars.iter_mut()
+        .map(|var| multi_variable(config, var, context))
+        .collect()
+}
 
+/// Return the scopes of the command definitions that "garden cmd" runs for a
+/// command in the given context. Scopes are returned in the order that they run.
+pub fn command_scopes(
+    app: &model::ApplicationContext,
+    context: &model::TreeContext,
+    name: &str,
+) -> Vec<model::CommandScope> {
+    let config = app.get_context_config(context);
+    let mut scopes = Vec::new();
+    for (scope, _) in command_definitions(config, context, name, app.options.command_scope) {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+
+    scopes
+}
+
+/// Return the command definitions that match a command name in the order that they run:
+/// config, tree and then garden scope. Only the definitions from the specified scope
+/// are returned when a scope is specified.
+fn command_definitions<'a>(
+    config: &'a model::Configuration,
+    context: &model::TreeContext,
+    name: &str,
+    scope: Option<model::CommandScope>,
+) -> Vec<(model::CommandScope, &'a model::MultiVariable)> {
     let pattern = match glob::Pattern::new(name) {
         Ok(value) => value,
-        Err(_) => return result,
+        Err(_) => return Vec::new(),
     };
+    let mut scopes: Vec<(model::CommandScope, &'a [model::MultiVariable])> = vec![
+        (model::CommandScope::Config, &config.commands),
+        (
+            model::CommandScope::Tree,
+            &config.trees[context.tree].commands,
+        ),
+    ];
+    // Optional garden command scope
+    if let Some(garden) = context.garden {
+        scopes.push((
+            model::CommandScope::Garden,
+            &config.gardens[garden].commands,
+        ));
+    }
 
-    // Global commands
-    for var in &config.commands {
-        if pattern.matches(var.get_name()) {
-            vars.push(var.clone());
-        }
+    scopes
+        .into_iter()
+        .filter(|(command_scope, _)| scope.is_none() || scope == Some(*command_scope))
+        .flat_map(|(command_scope, commands)| {
+            commands
+                .iter()
+                .filter(|var| pattern.matches(var.get_name()))
+                .map(move |var| (command_scope, var))
+        })
+        .collect()
+}
+
+/// Return the command definitions that match a command name ordered from the most
+/// specific scope to the least specific scope: garden, tree and then config.
+fn most_specific_commands<'a>(
+    config: &'a model::Configuration,
+    context: &model::TreeContext,
+    name: &str,
+    scope: Option<model::CommandScope>,
+) -> Vec<&'a model::MultiVariable> {
+    let definitions = command_definitions(config, context, name, scope);
+    [
+        model::CommandScope::Garden,
+        model::CommandScope::Tree,
+        model::CommandScope::Config,
+    ]
+    .iter()
+    .flat_map(|command_scope| {
+        definitions
+            .iter()
+            .filter(move |(scope, _)| scope == command_scope)
+            .map(|(_, var)| *var)
+    })
+    .collect()
+}
+
+/// Evaluate the shell used to run commands in the given context.
+/// The most specific setting wins. The shell specified by a command overrides
+/// the garden's shell, which overrides the tree's shell, which overrides the
+/// global "garden.shell" setting.
+pub fn shell(
+    config: &model::Configuration,
+    context: &model::TreeContext,
+    command: Option<&str>,
+    scope: Option<model::CommandScope>,
+) -> String {
+    let tree = &config.trees[context.tree];
+    let garden = context.garden.map(|garden_idx| &config.gardens[garden_idx]);
+    let mut shell_expr = None;
+
+    if let Some(name) = command {
+        shell_expr = most_specific_commands(config, context, name, scope)
+            .into_iter()
+            .find(|var| !var.get_shell().is_empty())
+            .map(|var| var.get_shell());
     }
+    if shell_expr.is_none() {
+        shell_expr = garden
+            .filter(|garden| !garden.shell.is_empty())
+            .map(|garden| garden.shell.get_expr());
+    }
+    if shell_expr.is_none() && !tree.shell.is_empty() {
+        shell_expr = Some(tree.shell.get_expr());
+    }
+    let shell_expr = shell_expr.unwrap_or(&config.shell).to_string();
+
+    tree_value(config, &shell_expr, context.tree, context.garden)
+}
+
+/// Evaluate the "cwd" directory for a command in the given context.
+/// The most specific command scope wins: garden, tree and then global commands.
+/// None is returned when the command does not specify a directory.
+pub fn command_directory(
+    config: &model::Configuration,
+    context: &model::TreeContext,
+    command: &str,
+    scope: Option<model::CommandScope>,
+) -> Option<String> {
+    let cwd_expr = most_specific_commands(config, context, command, scope)
+        .into_iter()
+        .find(|var| !var.get_cwd().is_empty())
+        .map(|var| var.get_cwd())?;
+
+    Some(tree_value(config, cwd_expr, context.tree, context.garden))
+}
 
-    // Tree commands
-    for var in &config.trees[context.tree].commands {
-        if pattern.matches(var.get_name()) {
-            vars.push(var.clone());
+/// Return the "timeout" for a command in the given context.
+/// The most specific command scope wins: garden, tree and then global commands.
+pub fn command_timeout(
+    config: &model::Configuration,
+    context: &model::TreeContext,
+    command: &str,
+    scope: Option<model::CommandScope>,
+) -> Option<std::time::Duration> {
+    most_specific_commands(config, context, command, scope)
+        .into_iter()
+        .find_map(|var| var.get_timeout())
+}
+
+/// Evaluate hooks, e.g. "pre-cmd" and "post-grow", in the given context.
+pub fn hook(
+    config: &model::Configuration,
+    context: &model::TreeContext,
+    name: &str,
+) -> Vec<Vec<String>> {
+    let mut result = Vec::new();
+    for var in &config.hooks {
+        if var.get_name() == name {
+            result.push(multi_variable(config, &mut var.clone(), context));
         }
     }
 
-    // Optional garden command scope
-    if let Some(garden) = context.garden {
-        for var in &config.gardens[garden].commands {
-            if pattern.matches(var.get_name()) {
-                vars.push(var.clone());
-            }
-        }
+    result
+}
+
+/// Evaluate the "post-clone" commands for a tree in the given context.
+pub fn post_clone(config: &model::Configuration, context: &model::TreeContext) -> Vec<Vec<String>> {
+    let tree = &config.trees[context.tree];
+    if tree.post_clone.is_empty() {
+        return Vec::new();
     }
+    let variables = tree
+        .post_clone
+        .iter()
+        .map(|expr| model::Variable::new(expr.clone(), None))
+        .collect();
+    let mut multi_var = model::MultiVariable::new("post-clone".to_string(), variables);
+
+    vec![multi_variable(config, &mut multi_var, context)]
+}
 
-    for var in vars.iter_mut() {
-        result.push(multi_variable(config, var, context));
+/// Evaluate the "gitconfig" settings for a tree in the given context.
+/// Garden settings are applied after the tree's settings, including the settings
+/// from its templates, and follow last-set-wins semantics.
+pub fn gitconfig(
+    config: &model::Configuration,
+    context: &model::TreeContext,
+) -> Vec<(String, String)> {
+    let tree = &config.trees[context.tree];
+    let mut vars: Vec<&model::NamedVariable> = tree.gitconfig.iter().collect();
+    if let Some(garden_idx) = context.garden {
+        vars.extend(&config.gardens[garden_idx].gitconfig);
+    }
+    let mut result: Vec<(String, String)> = Vec::new();
+    for var in vars {
+        let value = tree_value(config, var.get_expr(), context.tree, context.garden);
+        match result.iter_mut().find(|(name, _)| name == var.get_name()) {
+            Some(entry) => entry.1 = value,
+            None => result.push((var.get_name().to_string(), value)),
+        }
     }
 
     result
@@ -0,0 +1,141 @@
+/// The operation that produced an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecAction {
+    /// A command run by "garden exec" or "garden shell".
+    Exec,
+    /// A tree grown by "garden grow".
+    Grow,
+    /// A path removed by "garden prune".
+    Prune,
+}
+
+impl ExecAction {
+    /// Return the name of the action as used in log records.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExecAction::Exec => "exec",
+            ExecAction::Grow => "grow",
+            ExecAction::Prune => "prune",
+        }
+    }
+}
+
+/// Events reported to an ExecEventHandler as trees are processed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExecEvent {
+    /// Processing has started for a tree.
+    Start {
+        action: ExecAction,
+        tree: String,
+        path: String,
+    },
+    /// Processing has finished for a tree with the specified exit status.
+    Finish {
+        action: ExecAction,
+        tree: String,
+        path: String,
+        exit_status: i32,
+    },
+}
+
+/// Observers receive events as commands run over trees. Events can be sent
+/// from multiple threads when commands are run in parallel.
+///
+/// ```
+/// use garden::events::{ExecEvent, ExecEventHandler};
+///
+/// struct Progress;
+///
+/// impl ExecEventHandler for Progress {
+///     fn handle_event(&self, event: &ExecEvent) {
+///         if let ExecEvent::Finish { tree, exit_status, .. } = event {
+///             println!("{}: {}", tree, exit_status);
+///         }
+///     }
+/// }
+///
+/// let builder = garden::ConfigBuilder::new().event_handler(Progress);
+/// ```
+pub trait ExecEventHandler: Send + Sync {
+    fn handle_event(&self, event: &ExecEvent);
+}
+
+/// A shared reference to an ExecEventHandler.
+#[derive(Clone)]
+pub struct EventHandler(std::sync::Arc<dyn ExecEventHandler>);
+
+impl EventHandler {
+    pub fn new<H: ExecEventHandler + 'static>(handler: H) -> Self {
+        EventHandler(std::sync::Arc::new(handler))
+    }
+
+    /// Report the start of processing for a tree.
+    pub fn start(&self, action: ExecAction, tree: &str, path: &str) {
+        self.0.handle_event(&ExecEvent::Start {
+            action,
+            tree: tree.to_string(),
+            path: path.to_string(),
+        });
+    }
+
+    /// Report the completion of processing for a tree.
+    pub fn finish(&self, action: ExecAction, tree: &str, path: &str, exit_status: i32) {
+        self.0.handle_event(&ExecEvent::Finish {
+            action,
+            tree: tree.to_string(),
+            path: path.to_string(),
+            exit_status,
+        });
+    }
+}
+
+impl std::fmt::Debug for EventHandler {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str("EventHandler")
+    }
+}
+
+/// Track the processing of a single tree. Start and finish events are sent to
+/// the event handler and logged inside of a "tree" span.
+pub struct TreeEvent<'a> {
+    handler: Option<&'a EventHandler>,
+    action: ExecAction,
+    tree: &'a str,
+    path: &'a str,
+    _span: tracing::span::EnteredSpan,
+}
+
+impl<'a> TreeEvent<'a> {
+    /// Report the start of processing for a tree.
+    pub fn start(
+        handler: Option<&'a EventHandler>,
+        action: ExecAction,
+        tree: &'a str,
+        path: &'a str,
+    ) -> Self {
+        let span = tracing::info_span!("tree", action = action.as_str(), tree, path).entered();
+        tracing::debug!("started");
+        if let Some(handler) = handler {
+            handler.start(action, tree, path);
+        }
+        TreeEvent {
+            handler,
+            action,
+            tree,
+            path,
+            _span: span,
+        }
+    }
+
+    /// Report the completion of processing for a tree.
+    pub fn finish(self, exit_status: i32) {
+        if exit_status == 0 {
+            tracing::info!(exit_status, "finished");
+        } else {
+            tracing::error!(exit_status, "failed");
+        }
+        if let Some(handler) = self.handler {
+            handler.finish(self.action, self.tree, self.path, exit_status);
+        }
+    }
+}
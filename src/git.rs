@@ -4,6 +4,665 @@ use super::model::GitTreeDetails;
 use super::model::GitTreeType;
 use super::path;
 
+/// Options that control how repositories are cloned.
+#[derive(Clone, Debug, Default)]
+pub struct CloneOptions {
+    /// Branch to clone and checkout. The remote's default branch is used when empty.
+    pub branch: String,
+    /// Create a shallow clone with the specified number of commits when non-zero.
+    pub depth: i64,
+    /// Create a bare repository.
+    pub is_bare: bool,
+    /// Clone a single branch only.
+    pub is_single_branch: bool,
+    /// Borrow objects from a local repository, e.g. a mirror, when non-empty.
+    pub reference: String,
+    /// Name of the remote that is cloned from. "origin" is used when empty.
+    pub origin: String,
+}
+
+impl CloneOptions {
+    /// Return the "git clone" command that is equivalent to these options.
+    pub fn command(&self, url: &str, path: &str) -> Vec<String> {
+        let mut command: Vec<String> = vec!["git".into(), "clone".into()];
+        // "git clone --bare" clones bare repositories.
+        if self.is_bare {
+            command.push("--bare".into());
+        }
+        // "git clone --branch=name" clones the named branch.
+        if !self.branch.is_empty() {
+            command.push(format!("--branch={}", self.branch));
+        }
+        // "git clone --origin=name" names the remote that is cloned from.
+        // Bare repositories do not track remotes so the option is not used.
+        if !self.is_bare && !self.origin.is_empty() && self.origin != "origin" {
+            command.push(format!("--origin={}", self.origin));
+        }
+        // "git clone --depth=N" creates shallow clones with truncated history.
+        if self.depth > 0 {
+            command.push(format!("--depth={}", self.depth));
+        }
+        // "git clone --depth=N" clones a single branch by default.
+        // We generally want all branches available in our clones so we default to
+        // "single-branch: false" so that "--no-single-branch" is used. This makes
+        // all branches available by default.
+        if self.is_single_branch {
+            command.push("--single-branch".into());
+        } else {
+            command.push("--no-single-branch".into());
+        }
+        // "git clone --reference-if-able=path --dissociate" borrows objects from a
+        // local repository and then copies them so that the clone remains usable
+        // when the reference repository is removed.
+        if !self.reference.is_empty() {
+            command.push(format!("--reference-if-able={}", self.reference));
+            command.push("--dissociate".into());
+        }
+        command.push(url.into());
+        command.push(path.into());
+
+        command
+    }
+}
+
+/// GitBackend provides the Git operations used by garden commands.
+pub trait GitBackend {
+    /// Return the names of the remotes configured in a repository.
+    fn remotes(&self, path: &std::path::Path) -> Result<Vec<String>, errors::GardenError>;
+
+    /// Read a configuration value from a repository.
+    fn config_get(&self, path: &std::path::Path, name: &str) -> Option<String>;
+
+    /// Set a configuration value in a repository's local configuration.
+    fn config_set(
+        &self,
+        path: &std::path::Path,
+        name: &str,
+        value: &str,
+    ) -> Result<(), errors::GardenError>;
+
+    /// Add a remote or update the url of an existing remote.
+    fn set_remote_url(
+        &self,
+        path: &std::path::Path,
+        name: &str,
+        url: &str,
+    ) -> Result<(), errors::GardenError>;
+
+    /// Remove a remote and its remote-tracking branches.
+    fn remove_remote(&self, path: &std::path::Path, name: &str) -> Result<(), errors::GardenError>;
+
+    /// Return true if the repository is a bare repository.
+    fn is_bare(&self, path: &std::path::Path) -> bool;
+
+    /// Clone a repository from a url into the specified path.
+    fn clone_repository(
+        &self,
+        url: &str,
+        path: &str,
+        options: &CloneOptions,
+    ) -> Result<(), errors::GardenError>;
+
+    /// Return the commit ID that HEAD points to.
+    fn head_commit(&self, path: &std::path::Path) -> Result<String, errors::GardenError>;
+
+    /// Checkout a commit as a detached HEAD. The commit is fetched from
+    /// the specified remote when it is not present in the repository.
+    fn checkout_commit(
+        &self,
+        path: &std::path::Path,
+        remote: &str,
+        commit: &str,
+    ) -> Result<(), errors::GardenError>;
+
+    /// Checkout a tag, branch or commit as a detached HEAD. Tags and commits are
+    /// fetched from the specified remote when they are not present in the repository.
+    fn checkout_ref(
+        &self,
+        path: &std::path::Path,
+        remote: &str,
+        git_ref: &str,
+    ) -> Result<(), errors::GardenError>;
+
+    /// Return true if a ref or revision exists in a repository.
+    fn ref_exists(&self, path: &std::path::Path, refname: &str) -> bool;
+
+    /// Fetch the branches and tags from a remote.
+    fn fetch(&self, path: &std::path::Path, remote: &str) -> Result<(), errors::GardenError>;
+
+    /// Fast-forward the current branch to its upstream branch.
+    fn fast_forward(&self, path: &std::path::Path) -> Result<(), errors::GardenError>;
+}
+
+/// Return the default Git backend.
+pub fn backend() -> Box<dyn GitBackend> {
+    Box::new(LibGit2Backend)
+}
+
+/// GitBackend implementation that uses libgit2 and avoids spawning subprocesses.
+pub struct LibGit2Backend;
+
+impl LibGit2Backend {
+    /// Open the repository at the specified path.
+    fn open(path: &std::path::Path) -> Result<git2::Repository, errors::GardenError> {
+        git2::Repository::open(path).map_err(git_error)
+    }
+
+    /// Clone a repository using libgit2.
+    fn clone_with_libgit2(
+        url: &str,
+        path: &str,
+        options: &CloneOptions,
+    ) -> Result<(), errors::GardenError> {
+        let mut branch = options.branch.to_string();
+        // Single-branch clones need to know which branch to fetch.
+        if options.is_single_branch && branch.is_empty() {
+            branch = remote_default_branch(url)?;
+        }
+
+        let mut fetch_options = git2::FetchOptions::new();
+        fetch_options.remote_callbacks(remote_callbacks());
+        if options.depth > 0 {
+            fetch_options.depth(options.depth as i32);
+        }
+
+        let mut builder = git2::build::RepoBuilder::new();
+        builder.bare(options.is_bare);
+        builder.fetch_options(fetch_options);
+        if !branch.is_empty() {
+            builder.branch(&branch);
+        }
+
+        // Bare repositories store branches directly in refs/heads/, just like
+        // "git clone --bare". Single-branch clones only fetch the cloned branch.
+        // Remotes other than "origin" are created with their configured name.
+        let local_refs = if options.is_bare { "refs/heads" } else { "" };
+        let is_single_branch = options.is_single_branch;
+        let origin = if options.origin.is_empty() {
+            "origin".to_string()
+        } else {
+            options.origin.to_string()
+        };
+        if options.is_bare || is_single_branch || origin != "origin" {
+            let fetch_branch = branch.to_string();
+            builder.remote_create(move |repo, _name, url| {
+                let name = origin.as_str();
+                let source = if is_single_branch {
+                    format!("refs/heads/{}", fetch_branch)
+                } else {
+                    "refs/heads/*".to_string()
+                };
+                let destination = if local_refs.is_empty() {
+                    source.replacen("refs/heads", &format!("refs/remotes/{}", name), 1)
+                } else {
+                    source.to_string()
+                };
+                repo.remote_with_fetch(name, url, &format!("+{}:{}", source, destination))
+            });
+        }
+
+        builder
+            .clone(url, std::path::Path::new(path))
+            .map(|_| ())
+            .map_err(git_error)
+    }
+}
+
+impl GitBackend for LibGit2Backend {
+    fn remotes(&self, path: &std::path::Path) -> Result<Vec<String>, errors::GardenError> {
+        let repo = Self::open(path)?;
+        let remotes = repo.remotes().map_err(git_error)?;
+
+        Ok(remotes.iter().flatten().map(String::from).collect())
+    }
+
+    fn config_get(&self, path: &std::path::Path, name: &str) -> Option<String> {
+        let repo = Self::open(path).ok()?;
+        let config = repo.config().ok()?;
+        config.get_string(name).ok()
+    }
+
+    fn config_set(
+        &self,
+        path: &std::path::Path,
+        name: &str,
+        value: &str,
+    ) -> Result<(), errors::GardenError> {
+        let repo = Self::open(path)?;
+        let mut config = repo
+            .config()
+            .and_then(|config| config.open_level(git2::ConfigLevel::Local))
+            .map_err(git_error)?;
+        config.set_str(name, value).map_err(git_error)
+    }
+
+    fn set_remote_url(
+        &self,
+        path: &std::path::Path,
+        name: &str,
+        url: &str,
+    ) -> Result<(), errors::GardenError> {
+        let repo = Self::open(path)?;
+        if repo.find_remote(name).is_ok() {
+            repo.remote_set_url(name, url).map_err(git_error)
+        } else {
+            repo.remote(name, url).map(|_| ()).map_err(git_error)
+        }
+    }
+
+    fn remove_remote(&self, path: &std::path::Path, name: &str) -> Result<(), errors::GardenError> {
+        let repo = Self::open(path)?;
+        repo.remote_delete(name).map_err(git_error)
+    }
+
+    fn is_bare(&self, path: &std::path::Path) -> bool {
+        match Self::open(path) {
+            Ok(repo) => repo.is_bare(),
+            Err(_) => false,
+        }
+    }
+
+    fn clone_repository(
+        &self,
+        url: &str,
+        path: &str,
+        options: &CloneOptions,
+    ) -> Result<(), errors::GardenError> {
+        // libgit2 does not support shallow fetches over its local transport and
+        // is stricter than git about "file://" urls. Local clones are delegated
+        // to git, which also hardlinks objects when cloning local repositories.
+        // libgit2 does not support reference repositories so those clones are
+        // also delegated to git.
+        if is_local_url(url) || !options.reference.is_empty() {
+            return CommandBackend.clone_repository(url, path, options);
+        }
+
+        // libgit2 only clones into missing or empty directories so anything found
+        // at the path after a failed clone was written by libgit2.
+        let is_empty = std::fs::read_dir(path).map_or(true, |mut entries| entries.next().is_none());
+        let result = Self::clone_with_libgit2(url, path, options);
+        // libgit2 only authenticates using ssh-agent and the default "~/.ssh/id_*" keys
+        // and does not read "~/.ssh/config". SSH clones that fail are retried using
+        // "git clone", which uses OpenSSH and its configuration. The partial clone is
+        // removed first because "git clone" refuses to clone into a non-empty directory.
+        if result.is_err() && is_ssh_url(url) {
+            if is_empty {
+                std::fs::remove_dir_all(path).ok();
+            }
+            return CommandBackend.clone_repository(url, path, options);
+        }
+
+        result
+    }
+
+    fn head_commit(&self, path: &std::path::Path) -> Result<String, errors::GardenError> {
+        let repo = Self::open(path)?;
+        let commit = repo
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .map_err(git_error)?;
+
+        Ok(commit.id().to_string())
+    }
+
+    fn checkout_commit(
+        &self,
+        path: &std::path::Path,
+        remote: &str,
+        commit: &str,
+    ) -> Result<(), errors::GardenError> {
+        let repo = Self::open(path)?;
+        let oid = git2::Oid::from_str(commit).map_err(git_error)?;
+        if repo.find_commit(oid).is_err() {
+            let mut fetch_options = git2::FetchOptions::new();
+            fetch_options.remote_callbacks(remote_callbacks());
+            repo.find_remote(remote)
+                .and_then(|mut remote| remote.fetch(&[commit], Some(&mut fetch_options), None))
+                .map_err(git_error)?;
+        }
+        let object = repo.find_object(oid, None).map_err(git_error)?;
+        if !repo.is_bare() {
+            let mut checkout = git2::build::CheckoutBuilder::new();
+            checkout.safe();
+            repo.checkout_tree(&object, Some(&mut checkout))
+                .map_err(git_error)?;
+        }
+        repo.set_head_detached(oid).map_err(git_error)
+    }
+
+    fn checkout_ref(
+        &self,
+        path: &std::path::Path,
+        remote: &str,
+        git_ref: &str,
+    ) -> Result<(), errors::GardenError> {
+        let repo = Self::open(path)?;
+        let fetch = |refspec: &str| {
+            let mut fetch_options = git2::FetchOptions::new();
+            fetch_options.remote_callbacks(remote_callbacks());
+            repo.find_remote(remote)
+                .and_then(|mut remote| remote.fetch(&[refspec], Some(&mut fetch_options), None))
+        };
+        let spec = format!("{}^{{commit}}", git_ref);
+        let commit = match repo.revparse_single(&spec) {
+            Ok(commit) => commit,
+            Err(_) => {
+                // Tags are fetched into "refs/tags". Commits are fetched into FETCH_HEAD.
+                fetch(&format!("+refs/tags/{0}:refs/tags/{0}", git_ref)).ok();
+                match repo.revparse_single(&spec) {
+                    Ok(commit) => commit,
+                    Err(_) => {
+                        fetch(git_ref).map_err(git_error)?;
+                        repo.revparse_single("FETCH_HEAD^{commit}")
+                            .map_err(git_error)?
+                    }
+                }
+            }
+        };
+        if !repo.is_bare() {
+            let mut checkout = git2::build::CheckoutBuilder::new();
+            checkout.safe();
+            repo.checkout_tree(&commit, Some(&mut checkout))
+                .map_err(git_error)?;
+        }
+        repo.set_head_detached(commit.id()).map_err(git_error)
+    }
+
+    fn ref_exists(&self, path: &std::path::Path, refname: &str) -> bool {
+        match Self::open(path) {
+            Ok(repo) => repo.revparse_single(refname).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn fetch(&self, path: &std::path::Path, remote: &str) -> Result<(), errors::GardenError> {
+        let repo = Self::open(path)?;
+        let mut fetch_options = git2::FetchOptions::new();
+        fetch_options.remote_callbacks(remote_callbacks());
+        fetch_options.download_tags(git2::AutotagOption::All);
+        // An empty list of refspecs fetches the remote's configured refspecs.
+        repo.find_remote(remote)
+            .and_then(|mut remote| remote.fetch::<&str>(&[], Some(&mut fetch_options), None))
+            .map_err(git_error)
+    }
+
+    fn fast_forward(&self, path: &std::path::Path) -> Result<(), errors::GardenError> {
+        let repo = Self::open(path)?;
+        let head = repo.head().map_err(git_error)?;
+        let upstream = git2::Branch::wrap(head).upstream().map_err(git_error)?;
+        let target = upstream.get().target().ok_or_else(|| {
+            errors::GardenError::GitError(format!("invalid upstream in {}", path.display()))
+        })?;
+        let annotated = repo.find_annotated_commit(target).map_err(git_error)?;
+        let (analysis, _) = repo.merge_analysis(&[&annotated]).map_err(git_error)?;
+        if analysis.is_up_to_date() {
+            return Ok(());
+        }
+        if !analysis.is_fast_forward() {
+            return Err(errors::GardenError::GitError(format!(
+                "unable to fast-forward {}",
+                path.display()
+            )));
+        }
+        let object = repo.find_object(target, None).map_err(git_error)?;
+        let mut checkout = git2::build::CheckoutBuilder::new();
+        checkout.safe();
+        repo.checkout_tree(&object, Some(&mut checkout))
+            .map_err(git_error)?;
+        repo.head()
+            .and_then(|mut head| head.set_target(target, "garden: fast-forward"))
+            .map(|_| ())
+            .map_err(git_error)
+    }
+}
+
+/// GitBackend implementation that runs "git" commands.
+pub struct CommandBackend;
+
+impl GitBackend for CommandBackend {
+    fn remotes(&self, path: &std::path::Path) -> Result<Vec<String>, errors::GardenError> {
+        let command = ["git", "remote"];
+        let exec = cmd::exec_in_dir(&command, path);
+        let capture = cmd::capture_stdout(exec)
+            .map_err(|err| errors::GardenError::GitError(err.to_string()))?;
+        let output = cmd::trim_stdout(&capture);
+
+        Ok(output.lines().map(String::from).collect())
+    }
+
+    fn config_get(&self, path: &std::path::Path, name: &str) -> Option<String> {
+        let command = ["git", "config", name];
+        let exec = cmd::exec_in_dir(&command, path);
+        match cmd::capture_stdout(exec) {
+            Ok(capture) if capture.success() => Some(cmd::trim_stdout(&capture)),
+            _ => None,
+        }
+    }
+
+    fn config_set(
+        &self,
+        path: &std::path::Path,
+        name: &str,
+        value: &str,
+    ) -> Result<(), errors::GardenError> {
+        let command = ["git", "config", name, value];
+        let exec = cmd::exec_in_dir(&command, path);
+        cmd::result_from_exit_status(cmd::status(exec.join()))
+    }
+
+    fn set_remote_url(
+        &self,
+        path: &std::path::Path,
+        name: &str,
+        url: &str,
+    ) -> Result<(), errors::GardenError> {
+        let command = if self.remotes(path)?.iter().any(|remote| remote == name) {
+            vec!["git", "remote", "set-url", name, url]
+        } else {
+            vec!["git", "remote", "add", name, url]
+        };
+        let exec = cmd::exec_in_dir(&command, path);
+        cmd::result_from_exit_status(cmd::status(exec.join()))
+    }
+
+    fn remove_remote(&self, path: &std::path::Path, name: &str) -> Result<(), errors::GardenError> {
+        let command = ["git", "remote", "remove", name];
+        let exec = cmd::exec_in_dir(&command, path);
+        cmd::result_from_exit_status(cmd::status(exec.join()))
+    }
+
+    fn is_bare(&self, path: &std::path::Path) -> bool {
+        let command = ["git", "config", "--bool", "core.bare"];
+        let exec = cmd::exec_in_dir(&command, path);
+        match cmd::capture_stdout(exec) {
+            Ok(capture) => cmd::trim_stdout(&capture) == "true",
+            Err(_) => false,
+        }
+    }
+
+    fn clone_repository(
+        &self,
+        url: &str,
+        path: &str,
+        options: &CloneOptions,
+    ) -> Result<(), errors::GardenError> {
+        let command = options.command(url, path);
+        let exec = cmd::exec_cmd(&command);
+        cmd::result_from_exit_status(cmd::status(exec.join()))
+    }
+
+    fn head_commit(&self, path: &std::path::Path) -> Result<String, errors::GardenError> {
+        let command = ["git", "rev-parse", "HEAD"];
+        let exec = cmd::exec_in_dir(&command, path);
+        match cmd::capture_stdout(exec) {
+            Ok(capture) if capture.success() => Ok(cmd::trim_stdout(&capture)),
+            Ok(_) => Err(errors::GardenError::GitError(format!(
+                "unable to resolve HEAD in {}",
+                path.display()
+            ))),
+            Err(err) => Err(errors::GardenError::GitError(err.to_string())),
+        }
+    }
+
+    fn checkout_commit(
+        &self,
+        path: &std::path::Path,
+        remote: &str,
+        commit: &str,
+    ) -> Result<(), errors::GardenError> {
+        let object = format!("{}^{{commit}}", commit);
+        let command = ["git", "cat-file", "-e", object.as_str()];
+        let exec = cmd::exec_in_dir(&command, path);
+        if cmd::status(exec.join()) != errors::EX_OK {
+            let command = ["git", "fetch", remote, commit];
+            let exec = cmd::exec_in_dir(&command, path);
+            cmd::result_from_exit_status(cmd::status(exec.join()))?;
+        }
+        let command = if self.is_bare(path) {
+            ["git", "update-ref", "--no-deref", "HEAD", commit]
+        } else {
+            ["git", "checkout", "--quiet", "--detach", commit]
+        };
+        let exec = cmd::exec_in_dir(&command, path);
+        cmd::result_from_exit_status(cmd::status(exec.join()))
+    }
+
+    fn checkout_ref(
+        &self,
+        path: &std::path::Path,
+        remote: &str,
+        git_ref: &str,
+    ) -> Result<(), errors::GardenError> {
+        let commit_exists = |git_ref: &str| {
+            let object = format!("{}^{{commit}}", git_ref);
+            let command = ["git", "cat-file", "-e", object.as_str()];
+            cmd::status(cmd::exec_in_dir(&command, path).join()) == errors::EX_OK
+        };
+        let mut commit = git_ref;
+        if !commit_exists(git_ref) {
+            // Tags are fetched into "refs/tags". Commits are fetched into FETCH_HEAD.
+            let tag = format!("+refs/tags/{0}:refs/tags/{0}", git_ref);
+            let command = ["git", "fetch", "--quiet", remote, tag.as_str()];
+            let exec = cmd::exec_in_dir(&command, path).stderr(subprocess::NullFile);
+            if cmd::status(exec.join()) != errors::EX_OK || !commit_exists(git_ref) {
+                let command = ["git", "fetch", remote, git_ref];
+                let exec = cmd::exec_in_dir(&command, path);
+                cmd::result_from_exit_status(cmd::status(exec.join()))?;
+                commit = "FETCH_HEAD";
+            }
+        }
+        let command = if self.is_bare(path) {
+            ["git", "update-ref", "--no-deref", "HEAD", commit]
+        } else {
+            ["git", "checkout", "--quiet", "--detach", commit]
+        };
+        let exec = cmd::exec_in_dir(&command, path);
+        cmd::result_from_exit_status(cmd::status(exec.join()))
+    }
+
+    fn ref_exists(&self, path: &std::path::Path, refname: &str) -> bool {
+        ref_exists(path, refname)
+    }
+
+    fn fetch(&self, path: &std::path::Path, remote: &str) -> Result<(), errors::GardenError> {
+        let command = ["git", "fetch", "--quiet", "--tags", remote];
+        let exec = cmd::exec_in_dir(&command, path);
+        cmd::result_from_exit_status(cmd::status(exec.join()))
+    }
+
+    fn fast_forward(&self, path: &std::path::Path) -> Result<(), errors::GardenError> {
+        let command = ["git", "merge", "--quiet", "--ff-only", "@{upstream}"];
+        let exec = cmd::exec_in_dir(&command, path);
+        cmd::result_from_exit_status(cmd::status(exec.join()))
+    }
+}
+
+/// Convert a git2::Error into a GardenError.
+fn git_error(err: git2::Error) -> errors::GardenError {
+    errors::GardenError::GitError(err.message().to_string())
+}
+
+/// Return true if the url refers to a repository on the local filesystem.
+fn is_local_url(url: &str) -> bool {
+    url.starts_with("file:") || std::path::Path::new(url).exists()
+}
+
+/// Return true if the url is an "ssh://" url or an scp-style "[user@]host:path" url.
+pub fn is_ssh_url(url: &str) -> bool {
+    if let Some((scheme, _)) = url.split_once("://") {
+        return matches!(scheme, "ssh" | "git+ssh" | "ssh+git");
+    }
+    // scp-style urls have a colon before the first slash. Single-letter hosts
+    // are not considered so that Windows paths such as "C:/src" are excluded.
+    match url.find(':') {
+        Some(colon) => colon > 1 && !url[..colon].contains('/'),
+        None => false,
+    }
+}
+
+/// Return the default SSH private keys that exist in "~/.ssh".
+fn default_ssh_keys() -> Vec<std::path::PathBuf> {
+    let ssh_dir = path::home_dir().join(".ssh");
+    ["id_ed25519", "id_ecdsa", "id_rsa"]
+        .iter()
+        .map(|name| ssh_dir.join(name))
+        .filter(|key| key.exists())
+        .collect()
+}
+
+/// Return remote callbacks that provide credentials from ssh-agent, the default
+/// SSH keys and the configured Git credential helpers.
+fn remote_callbacks<'a>() -> git2::RemoteCallbacks<'a> {
+    let mut attempts = 0;
+    let mut tried_agent = false;
+    let mut ssh_keys = default_ssh_keys().into_iter();
+    let mut callbacks = git2::RemoteCallbacks::new();
+    callbacks.credentials(move |url, username_from_url, allowed_types| {
+        // libgit2 calls this callback repeatedly until authentication succeeds.
+        // SSH authentication tries ssh-agent first and then each of the default keys.
+        if allowed_types.contains(git2::CredentialType::SSH_KEY) {
+            let username = username_from_url.unwrap_or("git");
+            if !tried_agent {
+                tried_agent = true;
+                return git2::Cred::ssh_key_from_agent(username);
+            }
+            return match ssh_keys.next() {
+                Some(key) => git2::Cred::ssh_key(username, None, &key, None),
+                None => Err(git2::Error::from_str(
+                    "unable to authenticate using ssh-agent or ~/.ssh/id_*",
+                )),
+            };
+        }
+        // Give up after a few attempts to avoid looping forever.
+        attempts += 1;
+        if attempts > 3 {
+            return Err(git2::Error::from_str("unable to authenticate"));
+        }
+        if allowed_types.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+            let config = git2::Config::open_default()?;
+            return git2::Cred::credential_helper(&config, url, username_from_url);
+        }
+        git2::Cred::default()
+    });
+
+    callbacks
+}
+
+/// Query a remote for its default branch.
+fn remote_default_branch(url: &str) -> Result<String, errors::GardenError> {
+    let mut remote = git2::Remote::create_detached(url).map_err(git_error)?;
+    let connection = remote
+        .connect_auth(git2::Direction::Fetch, Some(remote_callbacks()), None)
+        .map_err(git_error)?;
+    let default_branch = connection.default_branch().map_err(git_error)?;
+    let refname = default_branch.as_str().unwrap_or_default();
+
+    Ok(refname
+        .strip_prefix("refs/heads/")
+        .unwrap_or(refname)
+        .to_string())
+}
+
 /// Return Ok(garden::model::GitTreeDetails) for the specified path on success
 /// or Err(garden::errors::CommandError) when Git commands error out.
 pub fn worktree_details(pathbuf: &std::path::Path) -> Result<GitTreeDetails, errors::CommandError> {
@@ -63,3 +722,144 @@ pub fn worktree_details(pathbuf: &std::path::Path) -> Result<GitTreeDetails, err
         tree_type: GitTreeType::Worktree(parent_path),
     })
 }
+
+/// Return the Git directory for a worktree or bare repository.
+pub fn git_dir(path: &std::path::Path) -> std::path::PathBuf {
+    let dot_git = path.join(".git");
+    if dot_git.is_dir() {
+        return dot_git;
+    }
+    // Worktrees created by "git worktree" contain a "gitdir: <path>" file.
+    if let Ok(contents) = std::fs::read_to_string(&dot_git) {
+        if let Some(gitdir) = contents.trim().strip_prefix("gitdir: ") {
+            return path.join(gitdir);
+        }
+    }
+
+    path.to_path_buf()
+}
+
+/// Is the path a Git worktree or bare repository?
+pub fn is_repository(path: &std::path::Path) -> bool {
+    path.join(".git").exists() || path.join("HEAD").exists()
+}
+
+/// Does the specified ref exist in the repository?
+pub fn ref_exists<P>(path: P, refname: &str) -> bool
+where
+    P: AsRef<std::path::Path>,
+{
+    let command = ["git", "rev-parse", "--verify", "--quiet", refname];
+    let exec = cmd::exec_in_dir(&command, path);
+    match cmd::capture(exec) {
+        Ok(capture) => capture.exit_status.success(),
+        Err(_) => false,
+    }
+}
+
+/// Return the commit ID that a ref or revision points to.
+/// None is returned when the ref does not exist in the repository.
+pub fn rev_parse_commit<P>(path: P, refname: &str) -> Option<String>
+where
+    P: AsRef<std::path::Path>,
+{
+    let object = format!("{}^{{commit}}", refname);
+    let command = ["git", "rev-parse", "--verify", "--quiet", object.as_str()];
+    let capture = cmd::capture(cmd::exec_in_dir(&command, path)).ok()?;
+    if !capture.exit_status.success() {
+        return None;
+    }
+
+    Some(cmd::trim_stdout(&capture))
+}
+
+/// Return the name of the branch that is checked out in a repository.
+/// None is returned when HEAD is detached.
+pub fn current_branch<P>(path: P) -> Option<String>
+where
+    P: AsRef<std::path::Path>,
+{
+    let command = ["git", "symbolic-ref", "--quiet", "--short", "HEAD"];
+    let capture = cmd::capture(cmd::exec_in_dir(&command, path)).ok()?;
+    if !capture.exit_status.success() {
+        return None;
+    }
+
+    Some(cmd::trim_stdout(&capture))
+}
+
+/// Return the number of commits ahead and behind of the upstream branch.
+/// None is returned when the current branch has no upstream branch.
+pub fn ahead_behind<P>(path: P) -> Option<(usize, usize)>
+where
+    P: AsRef<std::path::Path>,
+{
+    let command = [
+        "git",
+        "rev-list",
+        "--left-right",
+        "--count",
+        "HEAD...@{upstream}",
+    ];
+    let capture = cmd::capture(cmd::exec_in_dir(&command, path)).ok()?;
+    if !capture.exit_status.success() {
+        return None;
+    }
+    let output = cmd::trim_stdout(&capture);
+    let (ahead, behind) = output.split_once('\t')?;
+
+    Some((ahead.parse().ok()?, behind.parse().ok()?))
+}
+
+/// Return the local branches that contain commits that are not present on any remote.
+pub fn unpushed_branches(path: &std::path::Path) -> Vec<String> {
+    let command = [
+        "git",
+        "for-each-ref",
+        "--format=%(refname:short)",
+        "refs/heads",
+    ];
+    let branches = match cmd::capture_stdout(cmd::exec_in_dir(&command, path)) {
+        Ok(capture) => cmd::trim_stdout(&capture),
+        Err(_) => return Vec::new(),
+    };
+    branches
+        .lines()
+        .filter(|branch| {
+            let command = ["git", "rev-list", "--count", branch, "--not", "--remotes"];
+            match cmd::capture(cmd::exec_in_dir(&command, path)) {
+                Ok(capture) => capture.exit_status.success() && cmd::trim_stdout(&capture) != "0",
+                Err(_) => false,
+            }
+        })
+        .map(String::from)
+        .collect()
+}
+
+/// Return true when a repository's worktree has modified or untracked files.
+pub fn has_uncommitted_changes(path: &std::path::Path) -> bool {
+    let command = ["git", "status", "--porcelain"];
+    match cmd::capture_stdout(cmd::exec_in_dir(&command, path)) {
+        Ok(capture) => !cmd::trim_stdout(&capture).is_empty(),
+        Err(_) => false,
+    }
+}
+
+/// Return a description of the unsaved work in a repository.
+/// None is returned when the repository has no uncommitted changes and none of
+/// its local branches have commits that are not present on any remote.
+pub fn unsaved_work(path: &std::path::Path) -> Option<String> {
+    // Bare repositories do not have uncommitted changes.
+    if !path.join(".git").exists() {
+        return None;
+    }
+    if has_uncommitted_changes(path) {
+        return Some("uncommitted changes".to_string());
+    }
+    let branches = unpushed_branches(path);
+    if !branches.is_empty() {
+        return Some(format!("unpushed commits on {}", branches.join(", ")));
+    }
+
+    None
+}
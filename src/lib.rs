@@ -4,6 +4,10 @@ pub mod macros;
 
 /// Builders
 pub mod build;
+pub use build::ConfigBuilder;
+
+/// Command-line interface
+pub mod cli;
 
 /// Command utilities
 pub mod cmd;
@@ -20,9 +24,15 @@ pub mod errors;
 /// Variable evaluation
 pub mod eval;
 
+/// Event handlers for library consumers
+pub mod events;
+
 /// Git queries
 pub mod git;
 
+/// Structured logging
+pub mod logging;
+
 /// Model objects
 pub mod model;
 
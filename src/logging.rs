@@ -0,0 +1,40 @@
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+use super::model;
+
+/// Install a global subscriber that writes log records to stderr.
+/// Logging is disabled when the level is "off". Closing a span logs the time spent
+/// inside of it. The "json" format emits one JSON object per line for use by log
+/// processing tools.
+pub fn init(level: LevelFilter, format: &model::OutputFormat, color: &model::ColorMode) {
+    if level == LevelFilter::OFF {
+        return;
+    }
+    let ansi = match color {
+        model::ColorMode::Auto => atty::is(atty::Stream::Stderr),
+        model::ColorMode::Off => false,
+        model::ColorMode::On => true,
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_ansi(ansi)
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_target(false);
+    // A subscriber may have already been installed, e.g. by a library consumer.
+    let _ = match format {
+        model::OutputFormat::Json => builder.with_ansi(false).json().try_init(),
+        model::OutputFormat::Text => builder.try_init(),
+    };
+}
+
+/// Return the log level for the command-line options. "--debug" categories enable
+/// debug logging when no explicit level is specified.
+pub fn level(log_level: Option<LevelFilter>, debug: &[String]) -> LevelFilter {
+    match log_level {
+        Some(level) => level,
+        None if !debug.is_empty() => LevelFilter::DEBUG,
+        None => LevelFilter::OFF,
+    }
+}
@@ -3,18 +3,20 @@
 /// Parameters:
 /// - `args`: A `std::fmt::Arguments`
 pub fn error(args: std::fmt::Arguments) {
+    tracing::error!("{}", args);
     eprintln!("error: {}", args);
 }
 
-/// Print a message to stderr with a "debug: " prefix
+/// Log a debug message. Debug messages are displayed when logging is enabled
+/// using "--log-level debug" or "--debug <category>".
 ///
 /// Parameters:
 /// - `args`: A `std::fmt::Arguments`
 pub fn debug(args: std::fmt::Arguments) {
-    eprintln!("debug: {}", args);
+    tracing::debug!("{}", args);
 }
 
-/// Print a message to stderr with an "debug : " prefix
+/// Log a debug message
 ///
 /// Parameters:
 /// - `fmt`: A format string.
@@ -2,8 +2,10 @@ use indextree::{Arena, NodeId};
 use std::cell::RefCell;
 use which::which;
 
+use super::cmd;
 use super::errors;
 use super::eval;
+use super::events;
 use super::path;
 use super::syntax;
 
@@ -128,13 +130,60 @@ impl NamedVariable {
 pub struct MultiVariable {
     name: String,
     variables: Vec<Variable>,
+    shell: String,
+    cwd: String,
+    help: String,
+    timeout: Option<std::time::Duration>,
 }
 
 impl_display!(MultiVariable);
 
 impl MultiVariable {
     pub fn new(name: String, variables: Vec<Variable>) -> Self {
-        MultiVariable { name, variables }
+        MultiVariable {
+            name,
+            variables,
+            shell: String::new(),
+            cwd: String::new(),
+            help: String::new(),
+            timeout: None,
+        }
+    }
+
+    /// Return the description of a command. Empty when unset.
+    pub fn get_help(&self) -> &String {
+        &self.help
+    }
+
+    pub fn set_help(&mut self, help: String) {
+        self.help = help;
+    }
+
+    /// Return the shell used to run a command. Empty when unset.
+    pub fn get_shell(&self) -> &String {
+        &self.shell
+    }
+
+    pub fn set_shell(&mut self, shell: String) {
+        self.shell = shell;
+    }
+
+    /// Return the directory that a command runs in. Empty when unset.
+    pub fn get_cwd(&self) -> &String {
+        &self.cwd
+    }
+
+    pub fn set_cwd(&mut self, cwd: String) {
+        self.cwd = cwd;
+    }
+
+    /// Return the maximum amount of time that a command is allowed to run.
+    pub fn get_timeout(&self) -> Option<std::time::Duration> {
+        self.timeout
+    }
+
+    pub fn set_timeout(&mut self, timeout: Option<std::time::Duration>) {
+        self.timeout = timeout;
     }
 
     pub fn get(&self, idx: usize) -> &Variable {
@@ -159,25 +208,48 @@ impl MultiVariable {
         }
     }
 
-    pub fn iter(&self) -> std::slice::Iter<Variable> {
+    pub fn iter(&self) -> std::slice::Iter<'_, Variable> {
         self.variables.iter()
     }
 }
 
+/// How Git submodules are initialized when a tree is grown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Submodules {
+    /// Submodules are not initialized.
+    #[default]
+    None,
+    /// "git submodule update --init"
+    Init,
+    /// "git submodule update --init --recursive"
+    Recursive,
+}
+
 /// Trees represent a single worktree
 #[derive(Clone, Debug, Default)]
 pub struct Tree {
     pub commands: Vec<MultiVariable>,
+    pub depends_on: Vec<String>,
     pub environment: Vec<MultiVariable>,
+    pub env_files: Vec<String>,
+    pub post_clone: Vec<String>,
     pub gitconfig: Vec<NamedVariable>,
     pub remotes: Vec<NamedVariable>,
     pub symlink: Variable,
     pub templates: Vec<String>,
     pub variables: Vec<NamedVariable>,
     pub branch: Variable,
+    pub bundle: Variable,
+    pub clone_reference: Variable,
+    pub default_remote: Variable,
+    pub git_ref: Variable,
+    pub shell: Variable,
     pub worktree: Variable,
     pub clone_depth: i64,
+    pub submodules: Submodules,
+    pub is_lfs: bool,
     pub is_single_branch: bool,
+    pub is_skipped: bool,
     pub is_symlink: bool,
     pub is_bare_repository: bool,
     pub is_worktree: bool,
@@ -244,6 +316,51 @@ impl Tree {
         }
     }
 
+    /// Return the target that a symlink tree's symlink should contain.
+    /// Targets inside of the symlink's parent directory are relative to the parent directory.
+    pub fn symlink_target(&self) -> Result<std::path::PathBuf, errors::GardenError> {
+        let path = std::path::PathBuf::from(self.path_as_ref()?);
+        let symlink = std::path::PathBuf::from(self.symlink_as_ref()?);
+        let parent = path.parent().ok_or_else(|| {
+            errors::GardenError::AssertionError(format!("parent() failed: {:?}", path))
+        })?;
+
+        // Is the link target a child of the link's parent directory?
+        // If so, create the symlink using a relative path. Use an absolute path otherwise.
+        match symlink.strip_prefix(parent) {
+            Ok(relative) => Ok(relative.to_path_buf()),
+            Err(_) => Ok(symlink),
+        }
+    }
+
+    /// Check whether a symlink tree's symlink exists and points to its configured target.
+    pub fn symlink_status(&self) -> SymlinkStatus {
+        let (path, expect) = match (self.path_as_ref(), self.symlink_target()) {
+            (Ok(path), Ok(expect)) => (std::path::PathBuf::from(path), expect),
+            _ => return SymlinkStatus::Missing,
+        };
+        let actual = match std::fs::read_link(&path) {
+            Ok(actual) => actual,
+            Err(_) if path.exists() => return SymlinkStatus::NotSymlink,
+            Err(_) => return SymlinkStatus::Missing,
+        };
+        if !path.exists() {
+            return SymlinkStatus::Broken;
+        }
+        if actual != expect {
+            // Symlinks that resolve to the same location using a different spelling are ok.
+            let parent = path.parent().unwrap_or(std::path::Path::new(""));
+            let resolved = std::fs::canonicalize(parent.join(&actual));
+            let configured = std::fs::canonicalize(parent.join(&expect));
+            match (resolved, configured) {
+                (Ok(resolved), Ok(configured)) if resolved == configured => (),
+                _ => return SymlinkStatus::WrongTarget(actual.to_string_lossy().to_string()),
+            }
+        }
+
+        SymlinkStatus::Ok
+    }
+
     pub fn reset_variables(&self) {
         // self.path is a variable but it is not reset because
         // the tree path is evaluated once when the configuration
@@ -269,8 +386,14 @@ impl Tree {
     pub fn clone_from_tree(&mut self, tree: &Tree, clone_variables: bool) {
         // "commands" are concatenated across templates.
         self.commands.append(&mut tree.commands.clone());
+        // "depends-on" are concatenated across templates.
+        self.depends_on.append(&mut tree.depends_on.clone());
         // "environment" follow last-set-wins semantics.
         self.environment.append(&mut tree.environment.clone());
+        // "env-file" entries are concatenated across templates.
+        self.env_files.append(&mut tree.env_files.clone());
+        // "post-clone" commands are concatenated across templates.
+        self.post_clone.append(&mut tree.post_clone.clone());
         // "gitconfig" follows last-set-wins semantics.
         self.gitconfig.append(&mut tree.gitconfig.clone());
 
@@ -289,10 +412,22 @@ impl Tree {
             self.is_bare_repository = tree.is_bare_repository;
         }
 
+        if tree.is_lfs {
+            self.is_lfs = tree.is_lfs;
+        }
+
         if tree.is_single_branch {
             self.is_single_branch = tree.is_single_branch;
         }
 
+        if tree.is_skipped {
+            self.is_skipped = tree.is_skipped;
+        }
+
+        if tree.submodules != Submodules::None {
+            self.submodules = tree.submodules;
+        }
+
         if tree.is_worktree {
             self.is_worktree = tree.is_worktree;
         }
@@ -304,6 +439,26 @@ impl Tree {
             self.branch = tree.branch.clone();
         }
 
+        if !tree.bundle.is_empty() {
+            self.bundle = tree.bundle.clone();
+        }
+
+        if !tree.default_remote.is_empty() {
+            self.default_remote = tree.default_remote.clone();
+        }
+
+        if !tree.clone_reference.is_empty() {
+            self.clone_reference = tree.clone_reference.clone();
+        }
+
+        if !tree.git_ref.is_empty() {
+            self.git_ref = tree.git_ref.clone();
+        }
+
+        if !tree.shell.is_empty() {
+            self.shell = tree.shell.clone();
+        }
+
         if !tree.symlink.is_empty() {
             self.symlink = tree.symlink.clone();
         }
@@ -361,6 +516,26 @@ impl Group {
     }
 }
 
+/// Named queries are tree queries that are referenced as "%name" in other queries.
+/// The trees matched by each of the queries are combined.
+#[derive(Clone, Debug, Default)]
+pub struct NamedQuery {
+    name: String,
+    pub queries: Vec<String>,
+}
+
+impl_display!(NamedQuery);
+
+impl NamedQuery {
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn get_name_mut(&mut self) -> &mut String {
+        &mut self.name
+    }
+}
+
 /// Templates can be used to create trees.
 /// They contain a (path-less) tree object which can be used for creating
 /// materialized trees.
@@ -398,8 +573,11 @@ impl Template {
 pub struct Garden {
     pub commands: Vec<MultiVariable>,
     pub environment: Vec<MultiVariable>,
+    pub env_files: Vec<String>,
     pub gitconfig: Vec<NamedVariable>,
     pub groups: Vec<String>,
+    pub root: Variable,
+    pub shell: Variable,
     pub trees: Vec<String>,
     pub variables: Vec<NamedVariable>,
     name: String,
@@ -423,6 +601,20 @@ impl Garden {
 }
 
 /// Return the default shell to use for custom commands and "garden shell".
+#[cfg(windows)]
+fn get_default_shell() -> String {
+    if which("pwsh").is_ok() {
+        "pwsh"
+    } else if which("powershell").is_ok() {
+        "powershell"
+    } else {
+        "cmd"
+    }
+    .to_string()
+}
+
+/// Return the default shell to use for custom commands and "garden shell".
+#[cfg(not(windows))]
 fn get_default_shell() -> String {
     if which("zsh").is_ok() {
         "zsh"
@@ -434,23 +626,119 @@ fn get_default_shell() -> String {
     .to_string()
 }
 
+/// Exec expression output keyed by tree name and then by the evaluated expression.
+type ExecCacheEntries =
+    std::collections::BTreeMap<String, std::collections::BTreeMap<String, String>>;
+
+/// ExecCache persists the output of "$ exec" expressions across invocations.
+#[derive(Clone, Debug, Default)]
+pub struct ExecCache {
+    path: std::path::PathBuf,
+    entries: RefCell<ExecCacheEntries>,
+}
+
+impl ExecCache {
+    /// Load the cache from the specified path. Missing or invalid cache files are empty.
+    pub fn new(path: std::path::PathBuf) -> Self {
+        let entries = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|string| serde_json::from_str(&string).ok())
+            .unwrap_or_default();
+        ExecCache {
+            path,
+            entries: RefCell::new(entries),
+        }
+    }
+
+    /// Return the path to the cache file.
+    pub fn get_path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Return the cached output for an expression evaluated in the named tree.
+    pub fn get(&self, tree: &str, expr: &str) -> Option<String> {
+        self.entries
+            .borrow()
+            .get(tree)
+            .and_then(|exprs| exprs.get(expr))
+            .cloned()
+    }
+
+    /// Record the output for an expression and write the cache file.
+    /// Failures to write the cache are ignored; the value is recomputed next time.
+    pub fn insert(&self, tree: &str, expr: &str, value: &str) {
+        self.entries
+            .borrow_mut()
+            .entry(tree.to_string())
+            .or_default()
+            .insert(expr.to_string(), value.to_string());
+        if let Ok(string) = serde_json::to_string_pretty(&*self.entries.borrow()) {
+            std::fs::write(&self.path, string).ok();
+        }
+    }
+}
+
+/// Glob patterns from the "discover" block and the group for the discovered trees.
+#[derive(Clone, Debug, Default)]
+pub struct Discover {
+    pub group: Option<String>,
+    pub patterns: Vec<String>,
+}
+
+/// Options used when fetching configuration files from URLs.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FetchOptions {
+    /// Fetch URLs again instead of using the cached copies.
+    pub refresh: bool,
+    /// Do not report the URLs that are being fetched.
+    pub quiet: bool,
+}
+
+/// Where the selected configuration profile came from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProfileSource {
+    /// Selected using "garden --profile". Unknown profiles are errors.
+    #[default]
+    Option,
+    /// Selected using the GARDEN_PROFILE environment variable.
+    Environment,
+    /// Inherited by a graft from its parent configuration.
+    Graft,
+}
+
 // Configuration represents an instantiated garden configuration
 #[derive(Clone, Debug, Default)]
 pub struct Configuration {
+    pub cache: bool,
+    pub cmd_order: Option<CommandOrder>,
     pub commands: Vec<MultiVariable>,
     pub debug: std::collections::HashMap<String, u8>,
+    pub discover: Vec<Discover>,
     pub environment: Vec<MultiVariable>,
+    pub env_filter: Vec<String>,
+    pub exclude_queries: Vec<String>,
+    pub exec_cache: Option<ExecCache>,
+    pub exit_status: Option<ExitStatusPolicy>,
+    pub fetch_options: FetchOptions,
     pub gardens: Vec<Garden>,
     pub grafts: Vec<Graft>,
     pub groups: Vec<Group>,
+    pub hooks: Vec<MultiVariable>,
+    pub overlays: Vec<std::path::PathBuf>,
     pub path: Option<std::path::PathBuf>,
     pub dirname: Option<std::path::PathBuf>,
+    pub profile: Option<String>,
+    pub profile_source: ProfileSource,
+    pub queries: Vec<NamedQuery>,
     pub root: Variable,
     pub root_path: std::path::PathBuf,
     pub shell: String,
     pub templates: Vec<Template>,
     pub tree_search_path: Vec<std::path::PathBuf>,
     pub trees: Vec<Tree>,
+    pub url_rewrites: Vec<(String, String)>,
+    /// The URLs that the garden file and overlays were fetched from, keyed by cached path.
+    pub urls: std::collections::HashMap<std::path::PathBuf, String>,
     pub variables: Vec<NamedVariable>,
     pub verbose: u8,
     id: Option<ConfigId>,
@@ -459,7 +747,43 @@ pub struct Configuration {
 
 impl_display!(Configuration);
 
+/// Replace the longest matching prefix of a URL using (prefix, replacement) rules.
+fn rewrite_url<'a, I>(url: &str, rules: I) -> String
+where
+    I: Iterator<Item = (&'a String, &'a String)>,
+{
+    match rules
+        .filter(|(prefix, _)| !prefix.is_empty() && url.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+    {
+        Some((prefix, replacement)) => format!("{}{}", replacement, &url[prefix.len()..]),
+        None => url.to_string(),
+    }
+}
+
 impl Configuration {
+    /// Apply the "garden.url-rewrites" rules to a URL that is about to be cloned.
+    /// The longest matching prefix is replaced, similar to Git's "url.<base>.insteadOf".
+    pub fn rewrite_url(&self, url: &str) -> String {
+        rewrite_url(
+            url,
+            self.url_rewrites
+                .iter()
+                .map(|(prefix, replacement)| (prefix, replacement)),
+        )
+    }
+
+    /// Reverse the "garden.url-rewrites" rules for a URL that is recorded into the
+    /// garden file so that the original URL is recorded.
+    pub fn unrewrite_url(&self, url: &str) -> String {
+        rewrite_url(
+            url,
+            self.url_rewrites
+                .iter()
+                .map(|(prefix, replacement)| (replacement, prefix)),
+        )
+    }
+
     /// Create a default Configuration
     pub fn new() -> Self {
         Configuration {
@@ -527,6 +851,71 @@ impl Configuration {
                     tree.variables[1].set_value(tree_path.clone());
                 }
             }
+            if tree.variables.len() >= 4 {
+                // Update TREE_URL at position 2 using the default remote's url expression.
+                if tree.variables[2].get_name() == "TREE_URL" {
+                    let url = tree
+                        .remotes
+                        .iter()
+                        .find(|remote| remote.get_name() == tree.default_remote.get_expr())
+                        .or_else(|| tree.remotes.first())
+                        .map(|remote| remote.get_expr().clone())
+                        .unwrap_or_default();
+                    tree.variables[2].set_expr(url);
+                }
+                // Update TREE_BRANCH at position 3.
+                if tree.variables[3].get_name() == "TREE_BRANCH" {
+                    let branch = tree.branch.get_expr().clone();
+                    tree.variables[3].set_expr(branch);
+                }
+            }
+        }
+    }
+
+    /// Set the ${GARDEN_CMD_QUIET} and ${GARDEN_CMD_VERBOSE} built-in variables so that
+    /// commands can forward "--quiet" and "-v" to the tools that they run.
+    pub fn set_command_options(&mut self, options: &CommandOptions) {
+        let quiet = if options.quiet { "--quiet" } else { "" };
+        let verbose = if options.verbose > 0 {
+            format!("-{}", "v".repeat(options.verbose as usize))
+        } else {
+            String::new()
+        };
+        for (name, value) in [
+            ("GARDEN_CMD_QUIET", quiet.to_string()),
+            ("GARDEN_CMD_VERBOSE", verbose),
+        ] {
+            if let Some(var) = self.variables.iter_mut().find(|var| var.get_name() == name) {
+                var.set_expr(value);
+                var.reset();
+            }
+        }
+    }
+
+    /// Layer the variables, commands and gardens from a user-level configuration
+    /// under this configuration. Entries that are already defined take precedence.
+    pub fn add_defaults(&mut self, defaults: Configuration) {
+        for variable in defaults.variables {
+            let name = variable.get_name();
+            if !self.variables.iter().any(|var| var.get_name() == name) {
+                self.variables.push(variable);
+            }
+        }
+        for command in defaults.commands {
+            let name = command.get_name();
+            if !self.commands.iter().any(|cmd| cmd.get_name() == name) {
+                self.commands.push(command);
+            }
+        }
+        for mut garden in defaults.gardens {
+            if !self
+                .gardens
+                .iter()
+                .any(|g| g.get_name() == garden.get_name())
+            {
+                garden.index = self.gardens.len() as GardenIndex;
+                self.gardens.push(garden);
+            }
         }
     }
 
@@ -554,19 +943,76 @@ impl Configuration {
             }
         }
 
+        // Trees that belong to a garden with a "root" are relative to the garden's root.
+        let garden_roots = self.garden_root_paths();
+
         // Evaluate the "path" expression.
         for (idx, value) in path_values.iter().enumerate() {
-            let result = self.eval_tree_path(value);
+            let result = self.eval_garden_tree_path(value, garden_roots[idx].as_deref());
             self.trees[idx].path.set_value(result);
         }
 
         // Evaluate the "symlink" expression.
         for (idx, value) in &symlink_values {
-            let result = self.eval_tree_path(value);
+            let result = self.eval_garden_tree_path(value, garden_roots[*idx].as_deref());
             self.trees[*idx].symlink.set_value(result);
         }
     }
 
+    /// Return the nearest garden root for each tree. The first garden that contains
+    /// a tree and defines a "root" provides the tree's root. Relative garden roots
+    /// are relative to garden.root.
+    fn garden_root_paths(&self) -> Vec<Option<String>> {
+        let mut result = vec![None; self.trees.len()];
+        for garden in &self.gardens {
+            if garden.root.is_empty() {
+                continue;
+            }
+            let root = self.tree_path(&eval::value(self, garden.root.get_expr()));
+            for (idx, tree) in self.trees.iter().enumerate() {
+                if result[idx].is_none() && self.garden_contains_tree(garden, tree) {
+                    result[idx] = Some(root.clone());
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Return true when a tree is referenced by a garden directly or through its groups.
+    fn garden_contains_tree(&self, garden: &Garden, tree: &Tree) -> bool {
+        let matches = |pattern: &str| {
+            glob::Pattern::new(pattern)
+                .map(|pattern| pattern.matches(tree.get_name()))
+                .unwrap_or(false)
+        };
+        if garden.trees.iter().any(|pattern| matches(pattern)) {
+            return true;
+        }
+        garden.groups.iter().any(|group_pattern| {
+            let group_pattern = match glob::Pattern::new(group_pattern) {
+                Ok(pattern) => pattern,
+                Err(_) => return false,
+            };
+            self.groups.iter().any(|group| {
+                group_pattern.matches(group.get_name())
+                    && group.members.iter().any(|pattern| matches(pattern))
+            })
+        })
+    }
+
+    /// Evaluate a tree path relative to a garden root, or garden.root when unspecified.
+    fn eval_garden_tree_path(&mut self, path: &str, garden_root: Option<&str>) -> String {
+        let value = eval::value(self, path);
+        match garden_root {
+            Some(root) if !std::path::Path::new(&value).is_absolute() => std::path::Path::new(root)
+                .join(&value)
+                .to_string_lossy()
+                .into(),
+            _ => self.tree_path(&value),
+        }
+    }
+
     /// Return a path string relative to the garden root
     pub fn tree_path(&self, path: &str) -> String {
         if std::path::PathBuf::from(path).is_absolute() {
@@ -629,6 +1075,47 @@ impl Configuration {
         self.config_path(&value)
     }
 
+    /// Return the URL that the garden file was fetched from.
+    pub fn get_url(&self) -> Option<&String> {
+        self.path.as_ref().and_then(|path| self.urls.get(path))
+    }
+
+    /// Resolve the path to an included or grafted garden file. Relative paths
+    /// are resolved against the URL of garden files that were fetched from URLs.
+    pub fn config_file_path(&self, path: &str) -> String {
+        match self.get_url() {
+            Some(url)
+                if !syntax::is_http_url(path) && !std::path::Path::new(path).is_absolute() =>
+            {
+                syntax::join_url(url, path)
+            }
+            _ => self.config_path(path),
+        }
+    }
+
+    /// Set the ${GROUP_NAME} built-in variable from a tree context.
+    /// Resolved variables are reset when the group changes so that variables
+    /// that reference ${GROUP_NAME} are evaluated using the new group.
+    fn set_group_name(&mut self, context: &TreeContext) {
+        let group_name = context
+            .group
+            .and_then(|idx| self.groups.get(idx))
+            .map(|group| group.get_name().to_string())
+            .unwrap_or_default();
+        let var = match self
+            .variables
+            .iter_mut()
+            .find(|var| var.get_name() == "GROUP_NAME")
+        {
+            Some(var) => var,
+            None => return,
+        };
+        if *var.get_expr() != group_name {
+            var.set_expr(group_name);
+            self.reset_variables();
+        }
+    }
+
     /// Reset resolved variables
     pub fn reset_variables(&mut self) {
         for var in &self.variables {
@@ -718,6 +1205,12 @@ pub struct Graft {
     name: String,
     pub root: String,
     pub config: String,
+    /// Inherit the variables from the parent configuration.
+    pub inherit: bool,
+    /// Tag, branch or commit to checkout when "config" is a Git URL.
+    pub git_ref: String,
+    /// Path to the garden file inside of the repository when "config" is a Git URL.
+    pub path: String,
 }
 
 impl_display!(Graft);
@@ -729,6 +1222,9 @@ impl Graft {
             name,
             root,
             config,
+            inherit: false,
+            git_ref: String::new(),
+            path: String::new(),
         }
     }
 
@@ -805,6 +1301,8 @@ pub struct TreeQuery {
     pub query: String,
     pub pattern: glob::Pattern,
     pub is_default: bool,
+    pub is_negated: bool,
+    pub is_path: bool,
     pub is_garden: bool,
     pub is_group: bool,
     pub is_tree: bool,
@@ -817,6 +1315,11 @@ impl_display_brief!(TreeQuery);
 
 impl TreeQuery {
     pub fn new(query: &str) -> Self {
+        // "!pattern" excludes the trees matched by the pattern.
+        let (is_negated, query) = match query.strip_prefix('!') {
+            Some(negated_query) => (true, negated_query),
+            None => (false, query),
+        };
         let mut is_default = false;
         let mut is_tree = false;
         let mut is_garden = false;
@@ -840,12 +1343,15 @@ impl TreeQuery {
         } else {
             is_default = true;
         }
+        let is_path = is_default && syntax::is_path_query(query);
         let glob_pattern = syntax::trim(query);
         let pattern = glob::Pattern::new(glob_pattern).unwrap_or_default();
 
         TreeQuery {
             query: query.into(),
             is_default,
+            is_negated,
+            is_path,
             is_garden,
             is_group,
             is_tree,
@@ -857,62 +1363,15 @@ impl TreeQuery {
     }
 }
 
-// Commands
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub enum Command {
-    Cmd,
-    Custom(String),
-    Exec,
-    Eval,
-    Grow,
-    Help,
-    Init,
-    Inspect,
-    List,
-    Plant,
-    Prune,
-    Shell,
-}
-
-impl std::default::Default for Command {
-    fn default() -> Self {
-        Command::Help
-    }
-}
-
-impl_display_brief!(Command);
-
-impl std::str::FromStr for Command {
-    type Err = (); // For the FromStr trait
-
-    fn from_str(src: &str) -> Result<Command, ()> {
-        match src {
-            "cmd" => Ok(Command::Cmd),
-            "exec" => Ok(Command::Exec),
-            "eval" => Ok(Command::Eval),
-            "grow" => Ok(Command::Grow),
-            "help" => Ok(Command::Help),
-            "init" => Ok(Command::Init),
-            "inspect" => Ok(Command::Inspect),
-            "list" => Ok(Command::List),
-            "ls" => Ok(Command::List),
-            "plant" => Ok(Command::Plant),
-            "prune" => Ok(Command::Prune),
-            "sh" => Ok(Command::Shell),
-            "shell" => Ok(Command::Shell),
-            _ => Ok(Command::Custom(src.into())),
-        }
-    }
-}
-
 // Is color enabled?
 // --color=<auto,on,off> overrides the default "auto" value.
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub enum ColorMode {
+    #[default]
     Auto, // "auto" enables color when a tty is detected.
-    Off,  // disable color
-    On,   // enable color
+    Off, // disable color
+    On,  // enable color
 }
 
 impl ColorMode {
@@ -945,16 +1404,10 @@ impl ColorMode {
     }
 }
 
-impl std::default::Default for ColorMode {
-    fn default() -> Self {
-        ColorMode::Auto
-    }
-}
-
 impl std::str::FromStr for ColorMode {
-    type Err = (); // For the FromStr trait
+    type Err = String; // For the FromStr trait
 
-    fn from_str(src: &str) -> Result<ColorMode, ()> {
+    fn from_str(src: &str) -> Result<ColorMode, String> {
         match src.to_lowercase().as_ref() {
             "auto" => Ok(ColorMode::Auto),
             "-1" => Ok(ColorMode::Auto),
@@ -963,18 +1416,270 @@ impl std::str::FromStr for ColorMode {
             "false" => Ok(ColorMode::Off),
             "true" => Ok(ColorMode::On),
             "never" => Ok(ColorMode::Off),
-            "always" => Ok(ColorMode::Off),
+            "always" => Ok(ColorMode::On),
             "off" => Ok(ColorMode::Off),
             "on" => Ok(ColorMode::On),
             "n" => Ok(ColorMode::Off),
             "y" => Ok(ColorMode::On),
             "no" => Ok(ColorMode::Off),
             "yes" => Ok(ColorMode::On),
-            _ => Err(()),
+            _ => Err(format!("invalid color mode: {}", src)),
+        }
+    }
+}
+
+/// Output formats for commands that can emit machine-readable output.
+/// --format=<text,json> overrides the default "text" value.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text, // Human-readable text output.
+    Json, // JSON output for use by scripts and tools.
+}
+
+impl OutputFormat {
+    pub fn names() -> &'static str {
+        "text, json"
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String; // For the FromStr trait
+
+    fn from_str(src: &str) -> Result<OutputFormat, String> {
+        match src.to_lowercase().as_ref() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("invalid output format: {}", src)),
+        }
+    }
+}
+
+/// Output formats used by "garden inspect".
+/// --format=<text,json,yaml> overrides the default "text" value.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum InspectFormat {
+    #[default]
+    Text, // Human-readable tree status.
+    Json, // Fully resolved configuration as JSON.
+    Yaml, // Fully resolved configuration as YAML.
+}
+
+impl InspectFormat {
+    pub fn names() -> &'static str {
+        "text, json, yaml"
+    }
+}
+
+impl std::str::FromStr for InspectFormat {
+    type Err = String; // For the FromStr trait
+
+    fn from_str(src: &str) -> Result<InspectFormat, String> {
+        match src.to_lowercase().as_ref() {
+            "text" => Ok(InspectFormat::Text),
+            "json" => Ok(InspectFormat::Json),
+            "yaml" => Ok(InspectFormat::Yaml),
+            _ => Err(format!("invalid inspect format: {}", src)),
+        }
+    }
+}
+
+/// Shell syntax used by "garden env" to print variables.
+/// --format=<sh,fish,dotenv> overrides the default "sh" value.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum EnvFormat {
+    #[default]
+    Sh, // export NAME='value'
+    Fish,   // set -gx NAME 'value'
+    Dotenv, // NAME="value"
+}
+
+impl EnvFormat {
+    pub fn names() -> &'static str {
+        "sh, fish, dotenv"
+    }
+}
+
+impl std::str::FromStr for EnvFormat {
+    type Err = String; // For the FromStr trait
+
+    fn from_str(src: &str) -> Result<EnvFormat, String> {
+        match src.to_lowercase().as_ref() {
+            "sh" => Ok(EnvFormat::Sh),
+            "fish" => Ok(EnvFormat::Fish),
+            "dotenv" => Ok(EnvFormat::Dotenv),
+            _ => Err(format!("invalid environment format: {}", src)),
         }
     }
 }
 
+/// Quoting applied to the values printed by "garden eval".
+/// --quote=<raw,shell,json> overrides the default "raw" value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QuoteMode {
+    #[default]
+    Raw, // value
+    Shell, // 'value'
+    Json,  // "value"
+}
+
+impl QuoteMode {
+    pub fn names() -> &'static str {
+        "raw, shell, json"
+    }
+
+    /// Quote a value.
+    pub fn quote(&self, value: &str) -> String {
+        match self {
+            QuoteMode::Raw => value.to_string(),
+            QuoteMode::Shell => shlex::quote(value).to_string(),
+            QuoteMode::Json => serde_json::to_string(value).unwrap_or_default(),
+        }
+    }
+}
+
+impl std::str::FromStr for QuoteMode {
+    type Err = String; // For the FromStr trait
+
+    fn from_str(src: &str) -> Result<QuoteMode, String> {
+        match src.to_lowercase().as_ref() {
+            "raw" => Ok(QuoteMode::Raw),
+            "shell" | "sh" => Ok(QuoteMode::Shell),
+            "json" => Ok(QuoteMode::Json),
+            _ => Err(format!("invalid quote mode: {}", src)),
+        }
+    }
+}
+
+/// Repository list formats used by "garden import" and "garden export".
+/// --format=<vcs,mr> overrides the default "vcs" value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReposFormat {
+    #[default]
+    Vcs, // vcstool ".repos" files
+    Mr, // myrepos ".mrconfig" files
+}
+
+impl ReposFormat {
+    pub fn names() -> &'static str {
+        "vcs, mr"
+    }
+}
+
+impl std::str::FromStr for ReposFormat {
+    type Err = String; // For the FromStr trait
+
+    fn from_str(src: &str) -> Result<ReposFormat, String> {
+        match src.to_lowercase().as_ref() {
+            "vcs" => Ok(ReposFormat::Vcs),
+            "mr" => Ok(ReposFormat::Mr),
+            _ => Err(format!("invalid repository format: {}", src)),
+        }
+    }
+}
+
+/// The order in which "garden cmd" runs multiple commands over multiple trees.
+/// --order=<tree,command> overrides the "garden.cmd-order" value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CommandOrder {
+    #[default]
+    Tree, // Run all of the commands in a tree before moving to the next tree.
+    Command, // Run a command in all of the trees before running the next command.
+}
+
+impl CommandOrder {
+    pub fn names() -> &'static str {
+        "tree, command"
+    }
+}
+
+impl std::str::FromStr for CommandOrder {
+    type Err = String; // For the FromStr trait
+
+    fn from_str(src: &str) -> Result<CommandOrder, String> {
+        match src.to_lowercase().as_ref() {
+            "tree" => Ok(CommandOrder::Tree),
+            "command" => Ok(CommandOrder::Command),
+            _ => Err(format!("invalid command order: {}", src)),
+        }
+    }
+}
+
+/// How "garden exec" reports the exit status when commands fail in multiple trees.
+/// --exit-status=<first,last,count> overrides the "garden.exit-status" value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExitStatusPolicy {
+    First, // Exit with the status of the first tree that failed.
+    #[default]
+    Last, // Exit with the status of the last tree that failed.
+    Count, // Exit with the number of trees that failed.
+}
+
+impl ExitStatusPolicy {
+    pub fn names() -> &'static str {
+        "first, last, count"
+    }
+}
+
+impl std::str::FromStr for ExitStatusPolicy {
+    type Err = String; // For the FromStr trait
+
+    fn from_str(src: &str) -> Result<ExitStatusPolicy, String> {
+        match src.to_lowercase().as_ref() {
+            "first" => Ok(ExitStatusPolicy::First),
+            "last" => Ok(ExitStatusPolicy::Last),
+            "count" => Ok(ExitStatusPolicy::Count),
+            _ => Err(format!("invalid exit status policy: {}", src)),
+        }
+    }
+}
+
+/// The scope in which a custom command is defined. Commands with the same name
+/// run in the order config, tree and then garden scope.
+/// "garden cmd --scope <scope>" runs the definitions from a single scope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandScope {
+    Config, // Commands defined in the top-level "commands" block.
+    Tree,   // Commands defined by a tree or its templates.
+    Garden, // Commands defined by the garden that a tree was matched through.
+}
+
+impl CommandScope {
+    pub fn names() -> &'static str {
+        "tree, garden, config"
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommandScope::Config => "config",
+            CommandScope::Tree => "tree",
+            CommandScope::Garden => "garden",
+        }
+    }
+}
+
+impl std::str::FromStr for CommandScope {
+    type Err = String; // For the FromStr trait
+
+    fn from_str(src: &str) -> Result<CommandScope, String> {
+        match src.to_lowercase().as_ref() {
+            "config" => Ok(CommandScope::Config),
+            "tree" => Ok(CommandScope::Tree),
+            "garden" => Ok(CommandScope::Garden),
+            _ => Err(format!("invalid command scope: {}", src)),
+        }
+    }
+}
+
+/// How the parent process environment is passed to commands run by "garden exec".
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EnvPassthrough {
+    #[default]
+    Filter, // Inherit the variables matched by "garden.env-filter", or all when unset.
+    Preserve, // Inherit the entire environment and ignore "garden.env-filter".
+    Clean,    // Inherit nothing. Only the garden-evaluated environment is used.
+}
+
 // Color is an alias for yansi::Paint.
 pub type Color<T> = yansi::Paint<T>;
 
@@ -1010,6 +1715,11 @@ pub fn display_tree(tree: &Tree, path: &str, verbose: u8) -> String {
     }
 }
 
+/// Return the prefix used when displaying output from a tree's commands.
+pub fn display_tree_prefix(tree: &Tree) -> String {
+    format!("{}{}", Color::blue(&tree.name).bold(), Color::cyan(":"))
+}
+
 /// Print a tree if it exists, otherwise print a missing tree
 pub fn print_tree(tree: &Tree, verbose: u8, quiet: bool) -> bool {
     if let Ok(path) = tree.path_as_ref() {
@@ -1041,26 +1751,43 @@ pub fn print_tree_details(tree: &Tree, verbose: u8, quiet: bool) {
 
 #[derive(Clone, Debug, Default)]
 pub struct CommandOptions {
-    pub args: Vec<String>,
     pub debug: Vec<String>,
+    pub excludes: Vec<String>,
     pub variables: Vec<String>,
     pub filename: Option<std::path::PathBuf>,
-    pub subcommand: Command,
+    pub log_dir: Option<std::path::PathBuf>,
+    pub overlays: Vec<std::path::PathBuf>,
+    pub profile: Option<String>,
+    pub profile_source: ProfileSource,
     pub chdir: String,
     pub filename_str: String,
     pub root: String,
     pub color_mode: ColorMode,
+    pub env_passthrough: EnvPassthrough,
+    pub capture: Option<OutputFormat>,
+    pub command_scope: Option<CommandScope>,
+    pub event_handler: Option<events::EventHandler>,
+    pub exit_status: Option<ExitStatusPolicy>,
+    pub failures: std::sync::Arc<cmd::Failures>,
     pub num_jobs: usize,
+    pub timeout: Option<std::time::Duration>,
+    pub working_directory: Option<String>,
     pub exact_depth: isize,
     pub max_depth: isize,
     pub min_depth: isize,
     pub verbose: u8,
     pub breadth_first: bool,
+    pub chdir_root: bool,
     pub dry_run: bool,
     pub exit_on_error: bool,
+    pub grow_missing: bool,
+    pub interactive: bool,
     pub keep_going: bool,
+    pub no_cache: bool,
     pub no_prompt: bool,
     pub quiet: bool,
+    pub refresh: bool,
+    pub user_config: bool,
 }
 
 impl CommandOptions {
@@ -1085,7 +1812,7 @@ impl CommandOptions {
         self
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self) -> Result<(), errors::GardenError> {
         // Allow specifying the config file: garden --config <path>
         if !self.filename_str.is_empty() {
             let path = std::path::PathBuf::from(&self.filename_str);
@@ -1096,6 +1823,12 @@ impl CommandOptions {
                 self.filename = Some(path);
             }
         }
+        // Override files are resolved before changing directories.
+        for overlay in self.overlays.iter_mut() {
+            if !syntax::is_http_url(&overlay.to_string_lossy()) {
+                *overlay = path::abspath(overlay);
+            }
+        }
 
         // Override garden.root: garden --root <path>
         if !self.root.is_empty() {
@@ -1107,16 +1840,39 @@ impl CommandOptions {
         // Change directories before searching for conifgs: garden --chdir <path>
         if !self.chdir.is_empty() {
             if let Err(err) = std::env::set_current_dir(&self.chdir) {
-                error!("could not chdir to '{}': {}", self.chdir, err);
+                return Err(errors::GardenError::OSError(format!(
+                    "could not chdir to '{}': {}",
+                    self.chdir, err
+                )));
             }
         }
 
         self.color_mode.update();
+
+        Ok(())
     }
 
     pub fn debug_level(&self, name: &str) -> u8 {
         self.debug.iter().filter(|&x| x == name).count() as u8
     }
+
+    /// Return the options used when fetching configuration URLs.
+    pub fn fetch_options(&self) -> FetchOptions {
+        FetchOptions {
+            refresh: self.refresh,
+            quiet: self.quiet,
+        }
+    }
+
+    /// Return the log file for a tree when "--log-dir" is in effect.
+    /// Path separators in the tree name are replaced with "_" so that trees
+    /// such as "lib/core" or "../core" cannot write outside of the log directory.
+    pub fn log_path(&self, tree_name: &str) -> Option<std::path::PathBuf> {
+        let name = tree_name.replace(['/', '\\'], "_");
+        self.log_dir
+            .as_ref()
+            .map(|log_dir| log_dir.join(format!("{}.log", name)))
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -1140,6 +1896,10 @@ impl ApplicationContext {
         };
         // Record the ID in the configuration.
         app_context.get_root_config_mut().set_id(root_id);
+        let options = app_context.options.clone();
+        app_context
+            .get_root_config_mut()
+            .set_command_options(&options);
 
         app_context
     }
@@ -1164,12 +1924,39 @@ impl ApplicationContext {
         self.get_config_mut(self.get_root_id())
     }
 
+    /// Return the configuration that contains the tree referenced by a tree context.
+    pub fn get_context_config(&self, context: &TreeContext) -> &Configuration {
+        match context.config {
+            Some(config_id) => self.get_config(config_id),
+            None => self.get_root_config(),
+        }
+    }
+
+    /// Return a mutable reference to the configuration for a tree context.
+    pub fn get_context_config_mut(&mut self, context: &TreeContext) -> &mut Configuration {
+        match context.config {
+            Some(config_id) => self.get_config_mut(config_id),
+            None => self.get_root_config_mut(),
+        }
+    }
+
+    /// Enter a tree context before evaluating values in its scope.
+    /// Returns the context's configuration with ${GROUP_NAME} set to the
+    /// group that matched the tree.
+    pub fn enter_context(&mut self, context: &TreeContext) -> &mut Configuration {
+        let config = self.get_context_config_mut(context);
+        config.set_group_name(context);
+        config
+    }
+
     /// Add a child Configuration graft onto the parent ConfigId.
     pub fn add_graft(&mut self, parent: ConfigId, config: Configuration) -> ConfigId {
         let graft_id = self.arena.new_node(config); // Take ownership of config.
         parent.append(graft_id, &mut self.arena);
 
         self.get_config_mut(graft_id).set_id(graft_id);
+        let options = self.options.clone();
+        self.get_config_mut(graft_id).set_command_options(&options);
 
         graft_id
     }
@@ -1186,6 +1973,29 @@ pub enum GitTreeType {
 
 impl_display!(GitTreeType);
 
+/// Represent the state of a symlink tree on disk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SymlinkStatus {
+    Ok,                  // The symlink points to its configured target.
+    Missing,             // The symlink has not been created.
+    Broken,              // The symlink points to a target that does not exist.
+    WrongTarget(String), // The symlink points to a different target.
+    NotSymlink,          // A file or directory exists in place of the symlink.
+}
+
+impl SymlinkStatus {
+    /// Return a short description of the symlink state.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SymlinkStatus::Ok => "ok",
+            SymlinkStatus::Missing => "missing",
+            SymlinkStatus::Broken => "broken",
+            SymlinkStatus::WrongTarget(_) => "wrong-target",
+            SymlinkStatus::NotSymlink => "not-a-symlink",
+        }
+    }
+}
+
 /// Represent "git worktree list" details queried from Git.
 #[derive(Clone, Debug)]
 pub struct GitTreeDetails {
@@ -1,5 +1,13 @@
 use super::errors;
 
+/// The separator used by PATH-like environment variables.
+#[cfg(windows)]
+pub const LIST_SEPARATOR: &str = ";";
+
+/// The separator used by PATH-like environment variables.
+#[cfg(not(windows))]
+pub const LIST_SEPARATOR: &str = ":";
+
 /// Return the current directoy as a PathBuf.
 pub fn current_dir() -> std::path::PathBuf {
     std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("."))
@@ -12,7 +20,7 @@ pub fn current_dir_string() -> String {
 
 /// Return the home directory for the current user.
 pub fn home_dir() -> std::path::PathBuf {
-    dirs::home_dir().unwrap_or_else(|| std::path::PathBuf::from("/tmp"))
+    dirs::home_dir().unwrap_or_else(std::env::temp_dir)
 }
 
 /// Convert a Path into an absolute path.
@@ -22,6 +30,25 @@ pub fn abspath(path: &std::path::Path) -> std::path::PathBuf {
         .unwrap_or_else(|_| path.to_path_buf())
 }
 
+/// Normalize a path by removing "." components and resolving ".." components
+/// without accessing the filesystem.
+pub fn normpath(path: &std::path::Path) -> std::path::PathBuf {
+    let mut result = std::path::PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => (),
+            std::path::Component::ParentDir => {
+                if !result.pop() {
+                    result.push(component);
+                }
+            }
+            _ => result.push(component),
+        }
+    }
+
+    result
+}
+
 /// Strip a prefix from a path. Returns a path as a string.
 pub fn strip_prefix_into_string(
     root: &std::path::Path,
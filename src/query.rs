@@ -7,17 +7,176 @@ use super::syntax;
 
 /// Resolve a tree query into a `Vec<garden::model::TreeContext>`.
 ///
+/// Queries can combine multiple terms using `&`. Terms that start with `!`
+/// exclude the trees that they match, e.g. `@group & !legacy*`. All trees are
+/// matched when a query consists of exclusions only. The configuration's
+/// `exclude_queries` are excluded from every query. Named queries from the
+/// `queries` block are referenced using `%name`.
+///
 /// Parameters:
 /// - `config`: `&garden::model::Configuration`.
 /// - `query`: Tree query `&str`.
+///
 /// Returns:
 /// - `Vec<garden::model::TreeContext>`
-
 pub fn resolve_trees(config: &model::Configuration, query: &str) -> Vec<model::TreeContext> {
+    resolve_query_terms(config, query, 0)
+}
+
+/// Named queries that reference themselves stop expanding at this depth.
+const MAX_NAMED_QUERY_DEPTH: usize = 16;
+
+/// Resolve a tree query that can contain multiple terms. The depth counts the
+/// number of named queries that were expanded to reach this query.
+fn resolve_query_terms(
+    config: &model::Configuration,
+    query: &str,
+    depth: usize,
+) -> Vec<model::TreeContext> {
+    let mut includes = Vec::new();
+    let mut excludes = Vec::new();
+    for term in query
+        .split('&')
+        .map(str::trim)
+        .filter(|term| !term.is_empty())
+    {
+        let tree_query = model::TreeQuery::new(term);
+        if tree_query.is_negated {
+            excludes.push(tree_query);
+        } else {
+            includes.push(tree_query);
+        }
+    }
+    if excludes.is_empty() && includes.len() <= 1 && config.exclude_queries.is_empty() {
+        return resolve_tree_query(config, &model::TreeQuery::new(query), depth);
+    }
+    for exclude_query in &config.exclude_queries {
+        excludes.push(model::TreeQuery::new(exclude_query));
+    }
+
+    let mut result = match includes.split_first() {
+        Some((first, rest)) => {
+            let mut result = resolve_tree_query(config, first, depth);
+            // Additional terms narrow down the result to their intersection.
+            for tree_query in rest {
+                let matched = tree_indexes(&resolve_tree_query(config, tree_query, depth));
+                result.retain(|ctx| matched.contains(&ctx.tree));
+            }
+            result
+        }
+        None if query.trim().is_empty() => Vec::new(),
+        // Queries consisting of exclusions only match every tree that is not skipped.
+        None => (0..config.trees.len())
+            .filter(|tree_idx| !config.trees[*tree_idx].is_skipped)
+            .map(|tree_idx| model::TreeContext::new(tree_idx, config.get_id(), None, None))
+            .collect(),
+    };
+
+    for tree_query in &excludes {
+        let excluded = tree_indexes(&resolve_tree_query(config, tree_query, depth));
+        result.retain(|ctx| !excluded.contains(&ctx.tree));
+    }
+
+    result
+}
+
+/// Resolve a tree query across the root configuration and its grafts.
+///
+/// `graft::query` expressions resolve `query` inside of the configuration
+/// grafted as `graft`. Wildcard queries match trees in the root configuration
+/// and in every grafted configuration. The returned contexts record the
+/// configuration that contains each tree. The root configuration's
+/// `exclude_queries` are excluded from the trees in every configuration.
+pub fn resolve_app_trees(app: &model::ApplicationContext, query: &str) -> Vec<model::TreeContext> {
+    let root_id = app.get_root_id();
+    let mut result = resolve_graft_trees(app, root_id, query);
+    // Trees in the root configuration have already been excluded by resolve_trees().
+    let mut graft_ids: Vec<model::ConfigId> = result
+        .iter()
+        .filter_map(|ctx| ctx.config)
+        .filter(|config_id| *config_id != root_id)
+        .collect();
+    graft_ids.sort_unstable();
+    graft_ids.dedup();
+    for exclude_query in &app.get_root_config().exclude_queries {
+        // Excluded names are matched inside of each graft and "graft::query"
+        // expressions are matched inside of the named graft.
+        let mut excluded = resolve_graft_trees(app, root_id, exclude_query);
+        for graft_id in &graft_ids {
+            excluded.append(&mut resolve_trees(app.get_config(*graft_id), exclude_query));
+        }
+        let excluded: std::collections::HashSet<_> =
+            excluded.iter().map(|ctx| (ctx.config, ctx.tree)).collect();
+        result.retain(|ctx| {
+            ctx.config == Some(root_id) || !excluded.contains(&(ctx.config, ctx.tree))
+        });
+    }
+
+    result
+}
+
+/// Resolve a tree query within the specified configuration and its grafts.
+fn resolve_graft_trees(
+    app: &model::ApplicationContext,
+    config_id: model::ConfigId,
+    query: &str,
+) -> Vec<model::TreeContext> {
+    let config = app.get_config(config_id);
+    let term = query.trim();
+    if let Some(graft_name) = syntax::graft_basename(term) {
+        if syntax::is_graft(term) && config.contains_graft(&graft_name) {
+            let graft_id = config
+                .get_graft(&graft_name)
+                .ok()
+                .and_then(|graft| *graft.get_id());
+            if let (Some(graft_id), Some(graft_query)) = (graft_id, syntax::trim_graft(term)) {
+                return resolve_graft_trees(app, graft_id, &graft_query);
+            }
+        }
+    }
+
+    let mut result = resolve_trees(config, query);
+    if is_wildcard(query) {
+        for graft in &config.grafts {
+            if let Some(graft_id) = graft.get_id() {
+                result.append(&mut resolve_graft_trees(app, *graft_id, query));
+            }
+        }
+    }
+
+    result
+}
+
+/// Return true when a query or pattern contains glob wildcards.
+pub fn is_wildcard(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Return true when a tree name pattern matches a tree. Trees that specify
+/// "skip: true" are only matched when they are named explicitly.
+fn pattern_matches_tree(pattern: &glob::Pattern, tree: &model::Tree) -> bool {
+    pattern.matches(tree.get_name()) && (!tree.is_skipped || pattern.as_str() == tree.get_name())
+}
+
+/// Return the set of tree indexes referenced by the tree contexts.
+fn tree_indexes(contexts: &[model::TreeContext]) -> std::collections::HashSet<model::TreeIndex> {
+    contexts.iter().map(|ctx| ctx.tree).collect()
+}
+
+/// Resolve a single tree query term into a `Vec<garden::model::TreeContext>`.
+fn resolve_tree_query(
+    config: &model::Configuration,
+    tree_query: &model::TreeQuery,
+    depth: usize,
+) -> Vec<model::TreeContext> {
     let mut result = Vec::new();
-    let tree_query = model::TreeQuery::new(query);
     let pattern = &tree_query.pattern;
 
+    // Directory-scoped queries match tree paths rather than names.
+    if tree_query.is_path {
+        return trees_from_path_query(config, &tree_query.query);
+    }
+
     if tree_query.include_gardens {
         result = garden_trees(config, pattern);
         if !result.is_empty() {
@@ -37,6 +196,11 @@ pub fn resolve_trees(config: &model::Configuration, query: &str) -> Vec<model::T
         if !result.is_empty() {
             return result;
         }
+        // Named queries are resolved after groups.
+        result = named_query_trees(config, pattern, depth);
+        if !result.is_empty() {
+            return result;
+        }
     }
 
     // No matching gardens or groups were found.
@@ -61,11 +225,37 @@ pub fn resolve_trees(config: &model::Configuration, query: &str) -> Vec<model::T
     result
 }
 
+/// Return tree contexts for every named query matching the specified pattern.
+/// The trees matched by each query are combined and duplicate trees are omitted.
+fn named_query_trees(
+    config: &model::Configuration,
+    pattern: &glob::Pattern,
+    depth: usize,
+) -> Vec<model::TreeContext> {
+    let mut result: Vec<model::TreeContext> = Vec::new();
+    if depth >= MAX_NAMED_QUERY_DEPTH {
+        return result;
+    }
+    for named_query in &config.queries {
+        if !pattern.matches(named_query.get_name()) {
+            continue;
+        }
+        for query in &named_query.queries {
+            for ctx in resolve_query_terms(config, query, depth + 1) {
+                if !result.iter().any(|existing| existing.tree == ctx.tree) {
+                    result.push(ctx);
+                }
+            }
+        }
+    }
+
+    result
+}
+
 /// Return tree contexts for every garden matching the specified pattern.
 /// Parameters:
 /// - config: `&garden::model::Configuration`
 /// - pattern: `&glob::Pattern`
-
 pub fn garden_trees(
     config: &model::Configuration,
     pattern: &glob::Pattern,
@@ -149,7 +339,6 @@ pub fn trees_from_group(
 /// - config: `&garden::model::Configuration`
 /// - tree: Tree name `&str`
 /// - garden_idx: `Option<garden::model::GardenIndex>`
-
 pub fn tree_from_name(
     config: &model::Configuration,
     tree: &str,
@@ -185,7 +374,6 @@ pub fn tree_from_name(
 /// - config: `&garden::model::Configuration`
 /// - tree: Tree name pattern `&str`
 /// - garden_idx: `Option<garden::model::GardenIndex>`
-
 pub fn trees_from_pattern(
     config: &model::Configuration,
     tree: &str,
@@ -200,7 +388,7 @@ pub fn trees_from_pattern(
 
     // Collect tree indexes for the configured trees
     for (tree_idx, cfg_tree) in config.trees.iter().enumerate() {
-        if pattern.matches(cfg_tree.get_name()) {
+        if pattern_matches_tree(&pattern, cfg_tree) {
             // Tree found
             result.push(model::TreeContext::new(
                 tree_idx,
@@ -223,6 +411,60 @@ pub fn trees_from_pattern(
     result
 }
 
+/// Return the trees whose paths are matched by a directory-scoped query.
+/// "dir/..." matches trees at any depth below "dir". Other queries are glob
+/// patterns where "*" does not match "/", e.g. "./dir/*" matches trees that
+/// are directly inside of "dir". Relative paths are relative to the garden root.
+/// Trees that specify "skip: true" are not matched by "dir/..." or glob patterns.
+pub fn trees_from_path_query(
+    config: &model::Configuration,
+    query: &str,
+) -> Vec<model::TreeContext> {
+    let mut result = Vec::new();
+    let (query_path, is_recursive) = match query.strip_suffix("...") {
+        Some(dirname) => (dirname, true),
+        None => (query, false),
+    };
+    let base = path::normpath(&config.root_path.join(query_path));
+    let pattern = if is_recursive {
+        None
+    } else {
+        match glob::Pattern::new(&base.to_string_lossy()) {
+            Ok(pattern) => Some(pattern),
+            Err(_) => return result,
+        }
+    };
+    let match_options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..glob::MatchOptions::default()
+    };
+    let include_skipped = !is_recursive && !is_wildcard(query);
+
+    for (tree_idx, tree) in config.trees.iter().enumerate() {
+        if tree.is_skipped && !include_skipped {
+            continue;
+        }
+        let tree_path = match tree.path_as_ref() {
+            Ok(tree_path) => path::normpath(std::path::Path::new(tree_path)),
+            Err(_) => continue,
+        };
+        let is_match = match &pattern {
+            Some(pattern) => pattern.matches_path_with(&tree_path, match_options),
+            None => tree_path.starts_with(&base),
+        };
+        if is_match {
+            result.push(model::TreeContext::new(
+                tree_idx,
+                config.get_id(),
+                None,
+                None,
+            ));
+        }
+    }
+
+    result
+}
+
 /// Return a tree context for the specified path string.
 pub fn tree_from_path(config: &model::Configuration, path: &str) -> Option<model::TreeContext> {
     tree_from_pathbuf(config, &std::path::PathBuf::from(path))
@@ -262,7 +504,6 @@ pub fn tree_from_pathbuf(
 }
 
 /// Return the name of an existing tree from the specified path.
-
 pub fn tree_name_from_path(
     config: &model::Configuration,
     path: &std::path::Path,
@@ -271,7 +512,6 @@ pub fn tree_name_from_path(
 }
 
 /// Return the name of an existing tree from an absolute path.
-
 pub fn tree_name_from_abspath(
     config: &model::Configuration,
     path: &std::path::Path,
@@ -300,11 +540,10 @@ pub fn tree_name_from_abspath(
 }
 
 /// Returns tree contexts matching the specified pattern
-
 fn trees(config: &model::Configuration, pattern: &glob::Pattern) -> Vec<model::TreeContext> {
     let mut result = Vec::new();
     for (tree_idx, tree) in config.trees.iter().enumerate() {
-        if pattern.matches(tree.get_name()) {
+        if pattern_matches_tree(pattern, tree) {
             result.push(model::TreeContext::new(
                 tree_idx,
                 config.get_id(),
@@ -319,7 +558,6 @@ fn trees(config: &model::Configuration, pattern: &glob::Pattern) -> Vec<model::T
 
 /// Return a Result<garden::model::TreeContext, garden::errors::GardenError>
 /// when the tree and optional garden are present.
-
 pub fn tree_context(
     config: &model::Configuration,
     tree: &str,
@@ -391,6 +629,29 @@ pub fn find_tree(
     tree_context(config, tree, garden)
 }
 
+/// Return the remote that a tree is cloned from and whose branches are tracked.
+/// The "default-remote" setting selects a remote by name. The first remote,
+/// which is "origin" when a "url" is specified, is used by default.
+pub fn default_remote(
+    config: &model::Configuration,
+    tree_idx: model::TreeIndex,
+    garden_idx: Option<model::GardenIndex>,
+) -> Option<&model::NamedVariable> {
+    let tree = &config.trees[tree_idx];
+    if !tree.default_remote.is_empty() {
+        let name = eval::tree_value(config, tree.default_remote.get_expr(), tree_idx, garden_idx);
+        if let Some(remote) = tree
+            .remotes
+            .iter()
+            .find(|remote| *remote.get_name() == name)
+        {
+            return Some(remote);
+        }
+    }
+
+    tree.remotes.first()
+}
+
 /// Return a path that that is either the tree's path or the tree's shared worktree path.
 pub fn shared_worktree_path(config: &model::Configuration, ctx: &model::TreeContext) -> String {
     let tree = &config.trees[ctx.tree];
@@ -409,3 +670,57 @@ pub fn shared_worktree_path(config: &model::Configuration, ctx: &model::TreeCont
 
     tree.get_name().to_string()
 }
+
+/// Return the dependency level of each tree in the configuration.
+/// Trees without "depends-on" entries are at level zero. Other trees are one level
+/// above the highest level of the trees that they depend on. Running trees in order
+/// of increasing level runs every tree after the trees that it depends on.
+/// Dependencies on trees that are not defined are ignored.
+pub fn tree_dependency_levels(
+    config: &model::Configuration,
+) -> Result<Vec<usize>, errors::GardenError> {
+    let mut levels: Vec<Option<usize>> = vec![None; config.trees.len()];
+    let mut stack = Vec::new();
+    for tree_idx in 0..config.trees.len() {
+        tree_dependency_level(config, tree_idx, &mut levels, &mut stack)?;
+    }
+
+    Ok(levels.into_iter().map(Option::unwrap_or_default).collect())
+}
+
+/// Calculate the dependency level for a single tree. "stack" contains the trees
+/// that are currently being visited and is used to detect dependency cycles.
+fn tree_dependency_level(
+    config: &model::Configuration,
+    tree_idx: model::TreeIndex,
+    levels: &mut Vec<Option<usize>>,
+    stack: &mut Vec<model::TreeIndex>,
+) -> Result<usize, errors::GardenError> {
+    if let Some(level) = levels[tree_idx] {
+        return Ok(level);
+    }
+    if let Some(start) = stack.iter().position(|idx| *idx == tree_idx) {
+        let mut cycle: Vec<&str> = stack[start..]
+            .iter()
+            .map(|idx| config.trees[*idx].get_name().as_str())
+            .collect();
+        cycle.push(config.trees[tree_idx].get_name());
+        return Err(errors::GardenError::InvalidConfiguration {
+            msg: format!("dependency cycle: {}", cycle.join(" -> ")),
+        });
+    }
+
+    stack.push(tree_idx);
+    let mut level = 0;
+    for name in &config.trees[tree_idx].depends_on {
+        let dependency_idx = config.trees.iter().position(|tree| tree.get_name() == name);
+        if let Some(dependency_idx) = dependency_idx {
+            let dependency_level = tree_dependency_level(config, dependency_idx, levels, stack)?;
+            level = level.max(dependency_level + 1);
+        }
+    }
+    stack.pop();
+    levels[tree_idx] = Some(level);
+
+    Ok(level)
+}
@@ -3,6 +3,17 @@ pub fn is_digit(string: &str) -> bool {
     string.chars().all(|c| c.is_ascii_digit())
 }
 
+/// Return true if `string` is the `${ARGS}` placeholder for command arguments.
+pub fn is_arguments(string: &str) -> bool {
+    string == "ARGS"
+}
+
+/// Return the OS environment variable name from an `env.NAME` variable reference.
+/// None is returned when `string` does not use the `env.` prefix.
+pub fn env_name(string: &str) -> Option<&str> {
+    string.strip_prefix("env.").filter(|name| !name.is_empty())
+}
+
 /// Return true if `string` is an `$ exec` expression.
 pub fn is_exec(string: &str) -> bool {
     string.starts_with("$ ")
@@ -23,6 +34,18 @@ pub fn is_tree(string: &str) -> bool {
     string.starts_with('@')
 }
 
+/// Return true if `string` is a directory-scoped tree query.
+/// "dir/..." matches trees at any depth below "dir". Paths that start with
+/// "./", "../" or "/" and contain glob characters match tree paths, e.g. "./dir/*".
+pub fn is_path_query(string: &str) -> bool {
+    if string == "..." || string.ends_with("/...") {
+        return true;
+    }
+    let is_path = string.starts_with("./") || string.starts_with("../") || string.starts_with('/');
+
+    is_path && string.contains(['*', '?', '['])
+}
+
 /// Return true if `string` is a `graft::value` expression.
 pub fn is_graft(string: &str) -> bool {
     string.contains("::")
@@ -60,13 +83,96 @@ pub fn split_string<'a>(string: &'a str, split: &str) -> (bool, &'a str, &'a str
     let split_len = split.len();
     // split offset, everything up to this point is before the split
     let before = string.find(split).unwrap_or(end);
-    let ok = before <= (end - split_len);
+    let ok = before + split_len <= end;
     // offset after the split
     let after = if ok { before + split_len } else { before };
 
     (ok, &string[..before], &string[after..])
 }
 
+/// Return the offset of the "}" that closes a "${" expression.
+/// The string must start immediately after the opening "${".
+pub fn find_closing_brace(string: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (idx, c) in string.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(idx),
+            '}' => depth -= 1,
+            _ => (),
+        }
+    }
+
+    None
+}
+
+/// Split the inside of a "${name:-default}" expression into its name and default value.
+pub fn split_default(string: &str) -> Option<(&str, &str)> {
+    let (ok, name, default) = split_string(string, ":-");
+    let is_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == ':' || c == '.' || c == '-');
+    if ok && is_name {
+        Some((name, default))
+    } else {
+        None
+    }
+}
+
+/// Split the inside of a "${ name(arg, ...) }" expression into its function name
+/// and its arguments. Commas inside of quoted strings and nested calls are not
+/// treated as argument separators.
+pub fn split_function(string: &str) -> Option<(&str, Vec<&str>)> {
+    let string = string.trim();
+    let open = string.find('(')?;
+    let name = string[..open].trim();
+    let is_name = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if !is_name || !string.ends_with(')') {
+        return None;
+    }
+    let inner = &string[open + 1..string.len() - 1];
+
+    let mut args = Vec::new();
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+    for (idx, c) in inner.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                args.push(inner[start..idx].trim());
+                start = idx + 1;
+            }
+            _ => (),
+        }
+    }
+    if quote.is_some() || depth != 0 {
+        return None;
+    }
+    let last = inner[start..].trim();
+    if !last.is_empty() || !args.is_empty() {
+        args.push(last);
+    }
+
+    Some((name, args))
+}
+
+/// Return the contents of a quoted string literal.
+pub fn unquote(string: &str) -> Option<&str> {
+    for quote in ['"', '\''] {
+        if string.len() >= 2 && string.starts_with(quote) && string.ends_with(quote) {
+            return Some(&string[1..string.len() - 1]);
+        }
+    }
+
+    None
+}
+
 /// Split a string into pre and post-graft namespace string refs
 pub fn split_graft(string: &str) -> (bool, &str, &str) {
     split_string(string, "::")
@@ -109,3 +215,177 @@ pub fn graft_basename(string: &str) -> Option<String> {
 
     Some(result)
 }
+
+/// Parse a duration such as "30", "30s", "5m", "2h", "30d" or "2w". Plain numbers
+/// are seconds. None is returned for durations that are not positive or that overflow.
+pub fn duration(string: &str) -> Option<std::time::Duration> {
+    let string = string.trim();
+    let (value, scale) = match string.char_indices().last()? {
+        (idx, 's') => (&string[..idx], 1),
+        (idx, 'm') => (&string[..idx], 60),
+        (idx, 'h') => (&string[..idx], 60 * 60),
+        (idx, 'd') => (&string[..idx], 24 * 60 * 60),
+        (idx, 'w') => (&string[..idx], 7 * 24 * 60 * 60),
+        _ => (string, 1),
+    };
+    let value: f64 = value.trim().parse().ok()?;
+    if !value.is_finite() || value <= 0.0 {
+        return None;
+    }
+
+    std::time::Duration::try_from_secs_f64(value * scale as f64).ok()
+}
+
+/// Parse a duration for command-line options. See duration() for details.
+pub fn parse_duration(value: &str) -> Result<std::time::Duration, String> {
+    duration(value).ok_or_else(|| {
+        format!(
+            "invalid duration '{}': expected <number>[s|m|h|d|w], e.g. 30s",
+            value
+        )
+    })
+}
+
+/// Return the protocol and host for a remote url, e.g. ("ssh", "example.com") for
+/// "git@example.com:repo.git". None is returned for local paths and "file://" urls.
+pub fn url_host(url: &str) -> Option<(&str, &str)> {
+    if let Some((scheme, rest)) = url.split_once("://") {
+        if scheme == "file" {
+            return None;
+        }
+        let authority = rest.split('/').next().unwrap_or_default();
+        let host = authority
+            .rsplit_once('@')
+            .map_or(authority, |(_, host)| host);
+        let host = match host.strip_prefix('[') {
+            Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+            None => host.split(':').next().unwrap_or_default(),
+        };
+        let protocol = match scheme {
+            "git+ssh" | "ssh+git" => "ssh",
+            _ => scheme,
+        };
+        return if host.is_empty() {
+            None
+        } else {
+            Some((protocol, host))
+        };
+    }
+    // scp-like "[user@]host:path" syntax. Colons after a slash are part of a local path.
+    let (authority, _) = url.split_once(':')?;
+    if authority.contains(['/', '\\']) {
+        return None;
+    }
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    // Single-letter hosts are Windows drive letters, e.g. "C:\garden\org.yaml".
+    if host.len() < 2 {
+        return None;
+    }
+
+    Some(("ssh", host))
+}
+
+/// Return true when a value is a Git URL rather than a local path, e.g.
+/// "https://example.com/repo.git", "file:///srv/repo.git" or "git@example.com:repo.git".
+pub fn is_git_url(value: &str) -> bool {
+    value.contains("://") || url_host(value).is_some()
+}
+
+/// Return true if a configuration path is an "http://" or "https://" URL.
+pub fn is_http_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Return the directory portion of a URL, e.g. "https://example.com/dir"
+/// for "https://example.com/dir/garden.yaml". Queries and fragments are removed.
+pub fn url_dirname(url: &str) -> &str {
+    let url = url.split(['?', '#']).next().unwrap_or(url);
+    let host_start = url.find("://").map_or(0, |idx| idx + 3);
+    match url[host_start..].rfind('/') {
+        Some(idx) => &url[..host_start + idx],
+        None => url,
+    }
+}
+
+/// Resolve a relative path against the directory of a URL.
+/// "." and ".." components are applied to the URL's path.
+pub fn join_url(url: &str, path: &str) -> String {
+    let dirname = url_dirname(url);
+    let host_end = dirname
+        .find("://")
+        .map(|idx| idx + 3)
+        .and_then(|start| dirname[start..].find('/').map(|idx| start + idx))
+        .unwrap_or(dirname.len());
+    let mut components: Vec<&str> = dirname[host_end..]
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .collect();
+    for component in path.split('/') {
+        match component {
+            "" | "." => (),
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+
+    format!("{}/{}", &dirname[..host_end], components.join("/"))
+}
+
+/// Parse the contents of a ".env" file into (name, value) pairs.
+///
+/// Lines contain "NAME=value" assignments and may start with "export".
+/// Blank lines and lines starting with "#" are ignored. Single-quoted values are
+/// used literally, double-quoted values support "\n", "\t", "\"" and "\\" escapes,
+/// and unquoted values end at a " #" comment. Lines without "=" are ignored.
+pub fn parse_env_file(content: &str) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (name, value) = match line.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => continue,
+        };
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            continue;
+        }
+        result.push((name.to_string(), env_file_value(value)));
+    }
+
+    result
+}
+
+/// Unquote a value from a ".env" file.
+fn env_file_value(value: &str) -> String {
+    if let Some(quoted) = value.strip_prefix('\'') {
+        return quoted.split('\'').next().unwrap_or_default().to_string();
+    }
+    if let Some(quoted) = value.strip_prefix('"') {
+        let mut result = String::new();
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some('n') => result.push('\n'),
+                    Some('t') => result.push('\t'),
+                    Some(other) => result.push(other),
+                    None => break,
+                },
+                _ => result.push(c),
+            }
+        }
+        return result;
+    }
+    match value.find(" #") {
+        Some(idx) => value[..idx].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
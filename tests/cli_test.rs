@@ -0,0 +1,38 @@
+use clap::CommandFactory;
+use clap::Parser;
+
+use garden::cli;
+
+/// Validate the command-line interface definitions.
+#[test]
+fn cli_debug_assert() {
+    cli::MainOptions::command().debug_assert();
+}
+
+/// Options after "--" are forwarded to custom commands.
+#[test]
+fn cmd_arguments_after_double_dash() {
+    let options = cli::MainOptions::parse_from([
+        "garden", "-q", "cmd", "-k", "tree", "cmd1", "cmd2", "--", "-a", "--", "b",
+    ]);
+    assert!(options.quiet);
+    match options.command {
+        cli::Command::Cmd(cmd_options) => {
+            assert_eq!(cmd_options.query, "tree");
+            assert_eq!(cmd_options.commands, ["cmd1", "cmd2"]);
+            assert_eq!(cmd_options.arguments, ["-a", "--", "b"]);
+            assert!(cmd_options.keep_going);
+        }
+        _ => panic!("expected cli::Command::Cmd"),
+    }
+}
+
+/// Unknown commands are parsed as custom commands.
+#[test]
+fn custom_command() {
+    let options = cli::MainOptions::parse_from(["garden", "build", "tree", "--", "x"]);
+    match options.command {
+        cli::Command::Custom(args) => assert_eq!(args, ["build", "tree", "--", "x"]),
+        _ => panic!("expected cli::Command::Custom"),
+    }
+}
@@ -0,0 +1,51 @@
+use garden::cmd;
+
+/// Shells are detected from their basename.
+#[test]
+fn shell_kind() {
+    assert_eq!(cmd::shell_kind("zsh"), cmd::ShellKind::Posix);
+    assert_eq!(cmd::shell_kind("/bin/bash"), cmd::ShellKind::Posix);
+    assert_eq!(cmd::shell_kind("pwsh"), cmd::ShellKind::PowerShell);
+    assert_eq!(
+        cmd::shell_kind(r"C:\Windows\System32\WindowsPowerShell\v1.0\powershell.exe"),
+        cmd::ShellKind::PowerShell
+    );
+    assert_eq!(cmd::shell_kind("CMD.EXE"), cmd::ShellKind::Cmd);
+    assert_eq!(
+        cmd::shell_kind(r"C:\Windows\System32\cmd.exe"),
+        cmd::ShellKind::Cmd
+    );
+}
+
+/// Command strings are passed to each kind of shell using its own syntax.
+#[test]
+fn shell_args() {
+    let arguments = vec!["a b".to_string(), "it's".to_string()];
+
+    let args = cmd::shell_args("bash", "echo $1", true, &arguments);
+    assert_eq!(args[..3], ["-e", "-c", "echo $1"]);
+    assert_eq!(args[4..], arguments[..]);
+
+    let args = cmd::shell_args("sh", "echo", false, &[]);
+    assert_eq!(args[..2], ["-c", "echo"]);
+
+    let args = cmd::shell_args("pwsh", "echo", true, &arguments);
+    assert_eq!(
+        args,
+        [
+            "-NoProfile",
+            "-Command",
+            "$ErrorActionPreference = 'Stop'; echo 'a b' 'it''s'",
+        ]
+    );
+
+    let args = cmd::shell_args("cmd.exe", "echo", true, &arguments);
+    assert_eq!(args, ["/D", "/C", r#"echo "a b" "it's""#]);
+
+    // Placeholders are replaced by quoted arguments instead of being appended.
+    let args = cmd::shell_args("pwsh", r#"echo "$2" $1 $3"#, false, &arguments);
+    assert_eq!(args[2], "echo 'it''s' 'a b' ");
+
+    let args = cmd::shell_args("cmd", r#"echo "$@" -- $*"#, false, &arguments);
+    assert_eq!(args[2], r#"echo "a b" "it's" -- "a b" "it's""#);
+}
@@ -14,6 +14,19 @@ fn initialize_environment() {
     std::env::set_var("PATH", "/usr/bin:/bin");
     std::env::set_var("EMPTY", "");
     std::env::remove_var("PYTHONPATH");
+    isolate_user_config();
+}
+
+/// Keep the user's ~/.config/garden/garden.yaml from affecting the tests.
+/// Commands spawned by the tests inherit these variables.
+fn isolate_user_config() {
+    std::env::set_var("GARDEN_NO_USER_CONFIG", "1");
+    std::env::set_var("XDG_CONFIG_HOME", user_config_home());
+}
+
+/// The $XDG_CONFIG_HOME used by the tests. The directory does not exist.
+fn user_config_home() -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/tmp/xdg-config")
 }
 
 pub fn from_string(string: &str) -> model::Configuration {
@@ -139,7 +152,9 @@ pub fn garden_config() -> garden::model::Configuration {
 /// Execute the "garden" command with the specified arguments.
 pub fn exec_garden(args: &[&str]) -> Result<()> {
     let mut exec = Command::cargo_bin("garden").expect("garden not found");
-    exec.args(args);
+    exec.env("GARDEN_NO_USER_CONFIG", "1")
+        .env("XDG_CONFIG_HOME", user_config_home())
+        .args(args);
 
     assert!(exec.status().expect("garden returned an error").success());
     Ok(())
@@ -149,7 +164,9 @@ pub fn exec_garden(args: &[&str]) -> Result<()> {
 /// Return the captured stdout value as a string.
 pub fn garden_capture(args: &[&str]) -> String {
     let mut exec = Command::cargo_bin("garden").expect("garden not found");
-    exec.args(args);
+    exec.env("GARDEN_NO_USER_CONFIG", "1")
+        .env("XDG_CONFIG_HOME", user_config_home())
+        .args(args);
 
     let capture = exec.output();
     assert!(capture.is_ok());
@@ -177,7 +194,7 @@ pub fn assert_cmd_status(cmd: &[&str], directory: &str, status: i32) {
             assert_eq!(val, status);
         }
         subprocess::ExitStatus::Undetermined => {
-            assert!(false, "undetermined exit status");
+            panic!("undetermined exit status");
         }
     }
 }
@@ -227,7 +244,7 @@ fn setup_tmp_bare_repo(name: &str, path: &str) {
 
 fn teardown_tmp_test_data(path: &str) {
     if let Err(err) = std::fs::remove_dir_all(path) {
-        assert!(false, "unable to remove '{}': {}", path, err);
+        panic!("unable to remove '{}': {}", path, err);
     }
 }
 
@@ -239,6 +256,7 @@ pub struct BareRepoFixture<'a> {
 impl<'a> BareRepoFixture<'a> {
     /// Create the test bare repository.
     pub fn new(name: &'a str) -> Self {
+        isolate_user_config();
         setup_tmp_bare_repo(name, "tests/tmp");
 
         Self { name }
@@ -37,7 +37,7 @@ fn variables() {
     .to_string();
 
     let config = common::from_string(&string);
-    assert_eq!(3, config.variables.len());
+    assert_eq!(9, config.variables.len());
 
     let mut i = 0;
     assert_eq!("GARDEN_ROOT", config.variables[i].get_name());
@@ -45,6 +45,24 @@ fn variables() {
     assert_eq!("/home/test/src", *config.variables[i].get_value().unwrap());
     i += 1;
 
+    assert_eq!("GARDEN_PROFILE", config.variables[i].get_name());
+    assert_eq!("", config.variables[i].get_expr());
+    i += 1;
+
+    assert_eq!("GARDEN_NAME", config.variables[i].get_name());
+    assert_eq!("", config.variables[i].get_expr());
+    i += 1;
+
+    assert_eq!("GROUP_NAME", config.variables[i].get_name());
+    assert_eq!("", config.variables[i].get_expr());
+    i += 1;
+
+    for name in ["GARDEN_CMD_NAME", "GARDEN_CMD_QUIET", "GARDEN_CMD_VERBOSE"] {
+        assert_eq!(name, config.variables[i].get_name());
+        assert_eq!("", config.variables[i].get_expr());
+        i += 1;
+    }
+
     assert_eq!("foo", config.variables[i].get_name());
     assert_eq!("foo_value", config.variables[i].get_expr());
     assert_eq!(None, config.variables[i].get_value());
@@ -151,6 +169,108 @@ fn templates() {
     assert_eq!("boo", config.templates[2].tree.variables[0].get_expr());
 }
 
+/// YAML merge keys merge anchored hashes into trees and templates.
+#[test]
+fn merge_keys() {
+    let string = r#"
+    templates:
+        base: &base
+            variables:
+                foo: bar
+            environment:
+                BASE: base
+        other: &other
+            depth: 1
+            variables:
+                foo: other
+    trees:
+        tree1:
+            <<: *base
+            url: https://example.com/tree1.git
+        tree2:
+            <<: [*other, *base]
+            variables:
+                foo: tree2
+        tree3:
+            url: https://example.com/tree3.git
+            <<: *other
+    "#
+    .to_string();
+
+    let config = common::from_string(&string);
+    let variable = |tree: &garden::model::Tree, name: &str| {
+        tree.variables
+            .iter()
+            .filter(|var| var.get_name() == name)
+            .map(|var| var.get_expr().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(2, config.templates.len());
+    assert_eq!(3, config.trees.len());
+
+    let tree1 = &config.trees[0];
+    assert_eq!("https://example.com/tree1.git", tree1.remotes[0].get_expr());
+    assert_eq!(vec!["bar"], variable(tree1, "foo"));
+    assert_eq!("BASE", tree1.environment[0].get_name());
+
+    // Explicit entries override merged entries. Earlier merge sources take precedence.
+    let tree2 = &config.trees[1];
+    assert_eq!(vec!["tree2"], variable(tree2, "foo"));
+    assert_eq!(1, tree2.clone_depth);
+    assert_eq!("BASE", tree2.environment[0].get_name());
+
+    let tree3 = &config.trees[2];
+    assert_eq!("https://example.com/tree3.git", tree3.remotes[0].get_expr());
+    assert_eq!(vec!["other"], variable(tree3, "foo"));
+}
+
+/// Template parameters are substituted using the arguments from each tree.
+#[test]
+fn template_parameters() {
+    let string = r#"
+    templates:
+        service:
+            parameters:
+                url-prefix: https://git.example.com
+                team: core
+            url: ${url-prefix}/${team}/${TREE_NAME}.git
+            variables:
+                owner: ${team}
+    trees:
+        api:
+            templates: service
+            arguments:
+                team: backend
+        web:
+            templates: service
+            arguments:
+                url-prefix: ssh://git@example.org
+    "#
+    .to_string();
+
+    let config = common::from_string(&string);
+    let api = &config.trees[0];
+    assert_eq!(
+        "https://git.example.com/backend/${TREE_NAME}.git",
+        api.remotes[0].get_expr()
+    );
+    assert_eq!("owner", api.variables[4].get_name());
+    assert_eq!("backend", api.variables[4].get_expr());
+
+    let web = &config.trees[1];
+    assert_eq!(
+        "ssh://git@example.org/core/${TREE_NAME}.git",
+        web.remotes[0].get_expr()
+    );
+    assert_eq!("core", web.variables[4].get_expr());
+
+    // Templates without arguments use the default values.
+    assert_eq!(
+        "https://git.example.com/core/${TREE_NAME}.git",
+        config.templates[0].tree.remotes[0].get_expr()
+    );
+}
+
 /// Groups
 #[test]
 fn groups() {
@@ -182,7 +302,7 @@ fn trees() {
     assert_eq!("origin", tree0.remotes[0].get_name());
     assert_eq!("https://github.com/git/git", tree0.remotes[0].get_expr());
 
-    assert_eq!(4, tree0.variables.len());
+    assert_eq!(6, tree0.variables.len());
 
     // TREE_NAME, highest precedence at position 0
     assert_eq!("TREE_NAME", tree0.variables[0].get_name());
@@ -197,11 +317,16 @@ fn trees() {
         tree0.variables[1].get_value().unwrap()
     );
 
-    assert_eq!("prefix", tree0.variables[2].get_name());
-    assert_eq!("~/.local", tree0.variables[2].get_expr());
+    // TREE_URL and TREE_BRANCH are evaluated from the tree's url and branch.
+    assert_eq!("TREE_URL", tree0.variables[2].get_name());
+    assert_eq!("https://github.com/git/git", tree0.variables[2].get_expr());
+    assert_eq!("TREE_BRANCH", tree0.variables[3].get_name());
+
+    assert_eq!("prefix", tree0.variables[4].get_name());
+    assert_eq!("~/.local", tree0.variables[4].get_expr());
     // From the template, effectively "hidden"
-    assert_eq!("prefix", tree0.variables[3].get_name());
-    assert_eq!("${TREE_PATH}/local", tree0.variables[3].get_expr());
+    assert_eq!("prefix", tree0.variables[5].get_name());
+    assert_eq!("${TREE_PATH}/local", tree0.variables[5].get_expr());
     // gitconfig
     assert_eq!(2, tree0.gitconfig.len());
     assert_eq!("user.name", tree0.gitconfig[0].get_name());
@@ -365,7 +490,7 @@ fn test_gardens(config: &garden::model::Configuration) {
         config.gardens[0].commands[0].get(1).get_expr()
     );
 
-    assert_eq!(1, config.gardens[0].variables.len());
+    assert_eq!(2, config.gardens[0].variables.len());
     assert_eq!("prefix", config.gardens[0].variables[0].get_name());
     assert_eq!(
         "~/apps/git-cola/current",
@@ -391,7 +516,9 @@ fn test_gardens(config: &garden::model::Configuration) {
     assert_eq!("git", config.gardens[1].get_name());
 
     assert!(config.gardens[1].environment.is_empty());
-    assert!(config.gardens[1].variables.is_empty());
+    assert_eq!(1, config.gardens[1].variables.len());
+    assert_eq!("GARDEN_NAME", config.gardens[1].variables[0].get_name());
+    assert_eq!("git", config.gardens[1].variables[0].get_expr());
     assert!(config.gardens[1].commands.is_empty());
 
     assert_eq!(vec!["cola"], config.gardens[1].groups);
@@ -427,6 +554,46 @@ fn tree_path() {
     );
 }
 
+/// Trees in gardens with a "root" or "prefix" are placed below the garden's root.
+#[test]
+fn garden_root_paths() {
+    let string = r#"
+    garden:
+        root: /home/test/src
+    trees:
+        app: {}
+        lib: {}
+        tool:
+            path: tools/tool
+        abs:
+            path: /opt/abs
+        other: {}
+    groups:
+        libs: [lib, tool]
+    gardens:
+        apps:
+            root: apps
+            trees: [app, abs]
+        libs:
+            prefix: /srv/libs
+            groups: libs
+        all:
+            root: all
+            trees: "*"
+    "#
+    .to_string();
+
+    let config = common::from_string(&string);
+    let path = |idx: usize| config.trees[idx].path_as_ref().unwrap().clone();
+    assert_eq!("/home/test/src/apps/app", path(0));
+    assert_eq!("/srv/libs/lib", path(1));
+    assert_eq!("/srv/libs/tools/tool", path(2));
+    // Absolute paths are not affected by garden roots.
+    assert_eq!("/opt/abs", path(3));
+    // The first garden that contains a tree provides its root.
+    assert_eq!("/home/test/src/all/other", path(4));
+}
+
 #[test]
 fn test_template_url() {
     let config = common::garden_config();
@@ -463,3 +630,402 @@ fn read_grafts() -> Result<()> {
 
     Ok(())
 }
+
+/// Grafts are read on demand by the tree queries that need them.
+#[test]
+fn read_query_grafts() -> Result<()> {
+    let options = garden::model::CommandOptions::new();
+    let config = garden::config::from_path_string("tests/data/garden.yaml", 0)?;
+    let mut app = garden::model::ApplicationContext::new(config, options);
+    assert!(app.get_root_config().grafts[0].get_id().is_none());
+
+    // Queries that do not reference grafts do not read them.
+    garden::config::read_query_grafts(&mut app, "tree1")?;
+    assert!(app.get_root_config().grafts[0].get_id().is_none());
+
+    // Graft queries read the named graft only.
+    garden::config::read_query_grafts(&mut app, "graft::graft")?;
+    assert!(app.get_root_config().grafts[0].get_id().is_some());
+    assert!(app.get_root_config().grafts[1].get_id().is_none());
+    let ctx = garden::query::find_tree(&app, app.get_root_id(), "graft::graft", None)?;
+    assert!(ctx.config.is_some());
+
+    // Wildcard queries read every graft.
+    garden::config::read_query_grafts(&mut app, "*")?;
+    assert!(app.get_root_config().grafts[1].get_id().is_some());
+
+    Ok(())
+}
+
+/// Files listed in "includes" are merged into the configuration.
+#[test]
+fn includes() -> Result<()> {
+    let config = garden::config::from_path_string("tests/data/includes/garden.yaml", 0)?;
+
+    let tree_names: Vec<&String> = config.trees.iter().map(|tree| tree.get_name()).collect();
+    assert_eq!(tree_names, ["a1", "shared", "b1"]);
+    // The including file overrides entries from the included files.
+    assert_eq!(
+        "https://example.com/root/shared.git",
+        config.trees[1].remotes[0].get_expr()
+    );
+    assert_eq!(2, config.groups.len());
+    assert_eq!(1, config.gardens.len());
+
+    // Later files override variables from earlier files.
+    assert_eq!("team-b", garden::eval::value(&config, "${team}"));
+    assert_eq!("root", garden::eval::value(&config, "${owner}"));
+
+    Ok(())
+}
+
+/// Editing a YAML document preserves comments, anchors and unchanged entries.
+#[test]
+fn edit_yaml_preserves_comments() -> Result<()> {
+    let string = r#"---
+# Configuration comment.
+variables:
+  base: &base https://example.com
+
+trees:
+  # The first tree.
+  tree1:
+    url: https://example.com/tree1.git  # Trailing comment.
+  tree2:
+    # Tree comment.
+    url: https://example.com/tree2.git
+"#;
+    let mut doc = yaml_rust::YamlLoader::load_from_str(string)?.remove(0);
+    if let yaml_rust::Yaml::Hash(ref mut doc_hash) = doc {
+        let trees_key = yaml_rust::Yaml::String("trees".into());
+        if let Some(yaml_rust::Yaml::Hash(trees)) = doc_hash.get_mut(&trees_key) {
+            let mut tree = yaml_rust::yaml::Hash::new();
+            tree.insert(
+                yaml_rust::Yaml::String("url".into()),
+                yaml_rust::Yaml::String("https://example.com/tree3.git".into()),
+            );
+            trees.insert(
+                yaml_rust::Yaml::String("tree3".into()),
+                yaml_rust::Yaml::Hash(tree),
+            );
+        }
+    }
+
+    let edited = garden::config::writer::edit_yaml(string, &doc);
+    let expect = format!(
+        "{}  tree3:\n    url: \"https://example.com/tree3.git\"\n",
+        string
+    );
+    assert_eq!(edited, Some(expect));
+
+    Ok(())
+}
+
+/// ConfigBuilder reads configuration files for library users.
+#[test]
+fn config_builder() -> Result<()> {
+    let app = garden::ConfigBuilder::new()
+        .path("tests/data/garden.yaml")
+        .set("gh_ssh=ssh://example.com")
+        .load()?;
+    let config = app.get_root_config();
+    assert_eq!(2, config.grafts.len());
+    assert_eq!(
+        "ssh://example.com",
+        garden::eval::value(config, "${gh_ssh}")
+    );
+
+    let contexts = garden::query::resolve_trees(config, "tree1");
+    assert_eq!(1, contexts.len());
+    let value = garden::eval::tree_value(config, "${tree_var}", contexts[0].tree, None);
+    assert_eq!("x1", value);
+
+    Ok(())
+}
+
+/// Override files are layered on top of the configuration.
+#[test]
+fn config_builder_overlay() -> Result<()> {
+    let app = garden::ConfigBuilder::new()
+        .path("tests/data/garden.yaml")
+        .overlay("tests/data/overlay.yaml")
+        .load()?;
+    let config = app.get_root_config();
+    assert_eq!(
+        "ssh://overlay.example.com",
+        garden::eval::value(config, "${gh_ssh}")
+    );
+    // Unmodified variables are retained.
+    assert_eq!("main", garden::eval::value(config, "${current_config}"));
+
+    // Tree entries are merged with the tree's existing definition.
+    let contexts = garden::query::resolve_trees(config, "@tree1");
+    assert_eq!(1, contexts.len());
+    let tree1 = &config.trees[contexts[0].tree];
+    assert_eq!(tree1.get_path().get_expr(), ".");
+    let value = garden::eval::tree_value(config, "${tree_var}", contexts[0].tree, None);
+    assert_eq!("overlay", value);
+
+    // Override files can add trees.
+    assert_eq!(1, garden::query::resolve_trees(config, "@tree3").len());
+
+    // Missing override files are errors.
+    let result = garden::ConfigBuilder::new()
+        .path("tests/data/garden.yaml")
+        .overlay("tests/data/does-not-exist.yaml")
+        .load();
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+/// Variables can be overridden in tree, group and garden scopes.
+#[test]
+fn config_builder_scoped_variables() -> Result<()> {
+    let app = garden::ConfigBuilder::new()
+        .path("tests/data/garden.yaml")
+        .set("tree1::tree_var=override")
+        .set("%trees::scoped=group")
+        .set(":dev::scoped=garden")
+        .load()?;
+    let config = app.get_root_config();
+    let tree1 = garden::query::resolve_trees(config, "@tree1")[0].tree;
+    let tree2 = garden::query::resolve_trees(config, "@tree2")[0].tree;
+    let example = garden::query::resolve_trees(config, "@example/tree")[0].tree;
+    let value = |expr: &str, tree, garden| garden::eval::tree_value(config, expr, tree, garden);
+
+    assert_eq!("override", value("${tree_var}", tree1, None));
+    assert_eq!("x2", value("${tree_var}", tree2, None));
+    assert_eq!("tree1", value("${TREE_NAME}", tree1, None));
+    assert_eq!("group", value("${scoped}", tree1, None));
+    assert_eq!("group", value("${scoped}", tree2, None));
+    assert_eq!("", value("${scoped}", example, None));
+
+    let dev = config
+        .gardens
+        .iter()
+        .position(|garden| garden.get_name() == "dev");
+    assert_eq!("garden", value("${scoped}", example, dev));
+
+    // Scopes that do not match any trees or gardens are errors.
+    let result = garden::ConfigBuilder::new()
+        .path("tests/data/garden.yaml")
+        .set("missing::name=value")
+        .load();
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+/// ConfigBuilder returns errors instead of exiting.
+#[test]
+fn config_builder_errors() {
+    let result = garden::ConfigBuilder::new()
+        .path("tests/data/does-not-exist.yaml")
+        .load();
+    assert!(result.is_err());
+
+    let string = r#"
+variables:
+  nested:
+    key: value
+"#;
+    let mut config = garden::model::Configuration::new();
+    let result = garden::config::parse(string, 0, &mut config);
+    assert!(matches!(
+        result,
+        Err(garden::errors::GardenError::InvalidConfiguration { .. })
+    ));
+}
+
+/// Event handlers receive start and finish events from "garden exec".
+#[test]
+fn config_builder_event_handler() -> Result<()> {
+    use garden::events::{ExecAction, ExecEvent, ExecEventHandler};
+
+    #[derive(Clone, Default)]
+    struct Recorder(std::sync::Arc<std::sync::Mutex<Vec<ExecEvent>>>);
+
+    impl ExecEventHandler for Recorder {
+        fn handle_event(&self, event: &ExecEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    let recorder = Recorder::default();
+    let mut app = garden::ConfigBuilder::new()
+        .path("tests/data/garden.yaml")
+        .quiet(true)
+        .event_handler(recorder.clone())
+        .load()?;
+    app.options.num_jobs = 1;
+    let command = vec!["true".to_string()];
+    garden::cmds::exec::exec(&mut app, "tree1", &command, &Default::default())?;
+
+    let events = recorder.0.lock().unwrap();
+    assert_eq!(2, events.len());
+    assert!(matches!(
+        &events[0],
+        ExecEvent::Start { action: ExecAction::Exec, tree, .. } if tree == "tree1"
+    ));
+    assert!(matches!(
+        &events[1],
+        ExecEvent::Finish { action: ExecAction::Exec, tree, exit_status: 0, .. } if tree == "tree1"
+    ));
+
+    Ok(())
+}
+
+/// URLs that sanitize to the same name are cached separately.
+#[test]
+fn cache_name() {
+    let name = garden::config::cache_name("https://example.com/a-b/c.yaml");
+    assert!(name.starts_with("example.com-a-b-c.yaml-"));
+    // A full SHA-1 digest is appended.
+    let digest = name.rsplit('-').next().unwrap_or_default();
+    assert_eq!(digest.len(), 40);
+    assert!(digest.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_ne!(
+        name,
+        garden::config::cache_name("https://example.com/a/b-c.yaml")
+    );
+    // Cache names are stable.
+    assert_eq!(
+        name,
+        garden::config::cache_name("https://example.com/a-b/c.yaml")
+    );
+}
+
+/// Failing trees are reported using GardenError::TreesFailed.
+#[test]
+fn exec_trees_failed() -> Result<()> {
+    let mut app = garden::ConfigBuilder::new()
+        .path("tests/data/garden.yaml")
+        .quiet(true)
+        .load()?;
+    app.options.num_jobs = 1;
+    app.options.keep_going = true;
+    let command = vec!["false".to_string()];
+    let result = garden::cmds::exec::exec(&mut app, "tree1", &command, &Default::default());
+    let err = result
+        .unwrap_err()
+        .downcast::<garden::errors::GardenError>()?;
+    match err {
+        garden::errors::GardenError::TreesFailed {
+            exit_status,
+            ref failures,
+        } => {
+            assert_eq!(exit_status, 1);
+            assert_eq!(
+                failures,
+                &vec![garden::errors::TreeFailure {
+                    tree: "tree1".into(),
+                    command: "false".into(),
+                    exit_status: 1,
+                }]
+            );
+        }
+        _ => panic!("unexpected error: {}", err),
+    }
+    assert_eq!(err.to_string(), "1 tree failed: tree1");
+    assert_eq!(i32::from(err), 1);
+
+    // Failures from an earlier command do not affect later commands.
+    let command = vec!["true".to_string()];
+    garden::cmds::exec::exec(&mut app, "tree1", &command, &Default::default())?;
+
+    Ok(())
+}
+
+/// Profiles override the garden file at load time.
+#[test]
+fn profiles() {
+    let string = r#"
+    garden:
+        root: /src
+    variables:
+        host: example.com
+    trees:
+        cola:
+            url: https://${host}/cola.git
+            profiles:
+                ci:
+                    depth: 1
+        docs:
+            url: https://example.com/docs.git
+    profiles:
+        work:
+            variables:
+                host: work.example.com
+            trees:
+                docs:
+                    url: git@work.example.com:docs.git
+        ci: {}
+    "#;
+
+    let mut config = garden::model::Configuration::new();
+    config.profile = Some("work".to_string());
+    garden::config::parse(string, 0, &mut config).unwrap();
+    assert_eq!(
+        "https://work.example.com/cola.git",
+        garden::eval::tree_value(&config, "${TREE_URL}", 0, None)
+    );
+    assert_eq!(
+        "git@work.example.com:docs.git",
+        config.trees[1].remotes[0].get_expr()
+    );
+    assert_eq!(0, config.trees[0].clone_depth);
+    assert_eq!("work", garden::eval::value(&config, "${GARDEN_PROFILE}"));
+
+    // Profile blocks in trees are layered over the tree.
+    let mut config = garden::model::Configuration::new();
+    config.profile = Some("ci".to_string());
+    garden::config::parse(string, 0, &mut config).unwrap();
+    assert_eq!(1, config.trees[0].clone_depth);
+    assert_eq!(
+        "https://example.com/docs.git",
+        config.trees[1].remotes[0].get_expr()
+    );
+
+    // Profiles must be defined when a "profiles" block exists.
+    let mut config = garden::model::Configuration::new();
+    config.profile = Some("home".to_string());
+    assert!(garden::config::parse(string, 0, &mut config).is_err());
+
+    // Grafts ignore profiles that they do not define.
+    let mut config = garden::model::Configuration::new();
+    config.profile = Some("home".to_string());
+    config.profile_source = garden::model::ProfileSource::Graft;
+    garden::config::parse(string, 0, &mut config).unwrap();
+    assert_eq!(
+        "https://example.com/cola.git",
+        garden::eval::tree_value(&config, "${TREE_URL}", 0, None)
+    );
+}
+
+/// Gardens can define "profiles" blocks.
+#[test]
+fn profiles_gardens() {
+    let string = r#"
+    garden:
+        root: /src
+    trees:
+        cola: https://example.com/cola.git
+        docs: https://example.com/docs.git
+    gardens:
+        dev:
+            trees: cola
+            profiles:
+                work:
+                    trees: [cola, docs]
+    "#;
+
+    let mut config = garden::model::Configuration::new();
+    garden::config::parse(string, 0, &mut config).unwrap();
+    assert_eq!(1, config.gardens[0].trees.len());
+
+    let mut config = garden::model::Configuration::new();
+    config.profile = Some("work".to_string());
+    garden::config::parse(string, 0, &mut config).unwrap();
+    assert_eq!(2, config.gardens[0].trees.len());
+}
@@ -34,6 +34,26 @@ fn config_variable() {
     assert_eq!("TEST/local", local);
 }
 
+/// ${name:-default} uses the default value when the variable is undefined or empty.
+#[test]
+fn variable_default_value() {
+    let config = common::garden_config();
+    let tree_idx: garden::model::TreeIndex = 0;
+
+    let value = garden::eval::tree_value(&config, "${test:-default}", tree_idx, None);
+    assert_eq!("TEST", value);
+
+    let value = garden::eval::tree_value(&config, "${undefined:-default}", tree_idx, None);
+    assert_eq!("default", value);
+
+    // Default values can contain variable expressions.
+    let value = garden::eval::tree_value(&config, "${undefined:-${test}/x}", tree_idx, None);
+    assert_eq!("TEST/x", value);
+
+    let value = garden::eval::value(&config, "${undefined:-}${test}");
+    assert_eq!("TEST", value);
+}
+
 /// ${TREE_NAME} should be set to the current tree's name
 #[test]
 fn tree_name() {
@@ -54,6 +74,32 @@ fn tree_path() {
     assert_eq!(expect, actual);
 }
 
+/// Expressions can call the basename, dirname and replace functions.
+#[test]
+fn expression_functions() {
+    let config = common::garden_config();
+    let tree_idx: garden::model::TreeIndex = 0;
+
+    let value = garden::eval::tree_value(&config, "${ dirname(TREE_PATH) }", tree_idx, None);
+    assert_eq!("/home/test/src", value);
+
+    let value = garden::eval::tree_value(&config, "${basename(TREE_PATH)}.txt", tree_idx, None);
+    assert_eq!("git.txt", value);
+
+    // Values returned by functions are not expanded again.
+    let expr = "${replace(TREE_PATH, '/home/test', '${HOME}')}";
+    let value = garden::eval::tree_value(&config, expr, tree_idx, None);
+    assert_eq!("${HOME}/src/git", value);
+
+    // Function calls can be nested.
+    let expr = "${basename(dirname(TREE_PATH))}";
+    let value = garden::eval::tree_value(&config, expr, tree_idx, None);
+    assert_eq!("src", value);
+
+    let value = garden::eval::value(&config, "${dirname('a/b/c')}");
+    assert_eq!("a/b", value);
+}
+
 /// ${GARDEN_ROOT} should be set to the garden.root configuration
 #[test]
 fn garden_path() {
@@ -333,6 +379,37 @@ fn environment_variables() {
     assert_eq!(value, "test");
 }
 
+/// ${env.NAME} reads the OS environment even when a garden variable has the same name.
+#[test]
+fn environment_variables_explicit_syntax() {
+    let string = r#"
+    variables:
+      GARDEN_TEST_SHADOWED: garden
+    trees:
+      example:
+        variables:
+          value: ${env.GARDEN_TEST_SHADOWED}
+    "#;
+    let config = common::from_string(string);
+    std::env::set_var("GARDEN_TEST_SHADOWED", "os");
+
+    let value = garden::eval::value(&config, "${GARDEN_TEST_SHADOWED}");
+    assert_eq!(value, "garden");
+
+    let value = garden::eval::value(&config, "${env.GARDEN_TEST_SHADOWED}");
+    assert_eq!(value, "os");
+
+    let value = garden::eval::tree_value(&config, "${value}", 0, None);
+    assert_eq!(value, "os");
+
+    // Undefined environment variables expand to empty strings and support defaults.
+    let value = garden::eval::value(&config, "${env.GARDEN_TEST_UNDEFINED:-default}");
+    assert_eq!(value, "default");
+
+    let value = garden::eval::tree_value(&config, "${env.GARDEN_TEST_UNDEFINED}", 0, None);
+    assert_eq!(value, "");
+}
+
 #[test]
 fn find_tree_in_graft() -> Result<()> {
     // See the "config.rs" tests for config-level validations.
@@ -395,3 +472,62 @@ fn eval_graft_tree() -> Result<()> {
 
     Ok(())
 }
+
+/// Malformed expressions and recursive variables are reported as errors.
+#[test]
+fn eval_errors() {
+    let string = r#"
+    variables:
+      recursive: ${self}
+      self: ${recursive}
+      unterminated: ${value
+      value: ok
+    trees:
+      tree:
+        path: /tmp
+    "#;
+    let config = common::from_string(string);
+
+    let err = garden::eval::try_value(&config, "${recursive}").unwrap_err();
+    assert_eq!(err.variable.as_deref(), Some("recursive"));
+    assert_eq!(err.scope.as_deref(), Some("variables"));
+    assert_eq!(err.expr, "${self}");
+    assert_eq!(
+        err.message,
+        "recursive variable reference: recursive -> self -> recursive"
+    );
+
+    let err = garden::eval::try_tree_value(&config, "x ${unterminated}", 0, None).unwrap_err();
+    assert_eq!(err.key().as_deref(), Some("variables.unterminated"));
+    assert_eq!(err.expr, "${value");
+
+    let err = garden::eval::try_value(&config, "${basename(a, b)}").unwrap_err();
+    assert!(err.variable.is_none());
+    assert_eq!(err.expr, "${basename(a, b)}");
+    assert_eq!(
+        err.message,
+        "basename() expects 1 argument but 2 were given"
+    );
+
+    let err = garden::eval::try_value(&config, "${unknown(value)}").unwrap_err();
+    assert_eq!(err.message, "unknown function: unknown()");
+
+    // The lenient functions expand variables that cannot be evaluated to empty strings.
+    assert_eq!(garden::eval::value(&config, "${recursive}"), "");
+    assert_eq!(
+        garden::eval::value(&config, "a-${recursive}-${value}"),
+        "a--ok"
+    );
+    assert_eq!(
+        garden::eval::tree_value(&config, "x ${unterminated} ${value}", 0, None),
+        "x  ok"
+    );
+    // Malformed expressions evaluate to empty strings.
+    assert_eq!(garden::eval::value(&config, "${unknown(value)}"), "");
+    assert_eq!(garden::eval::try_value(&config, "${value}").unwrap(), "ok");
+    // "$${" is an escaped "$" followed by "{".
+    assert_eq!(
+        garden::eval::try_value(&config, "$${value").unwrap(),
+        "${value"
+    );
+}
@@ -4,14 +4,15 @@
 #[path = "common/mod.rs"]
 pub mod common;
 use common::{
-    assert_cmd, assert_cmd_capture, assert_ref, assert_ref_missing, exec_garden, garden_capture,
-    BareRepoFixture,
+    assert_cmd, assert_cmd_capture, assert_cmd_status, assert_ref, assert_ref_missing, exec_garden,
+    garden_capture, BareRepoFixture,
 };
 
 use garden::git;
 use garden::model;
 
 use anyhow::Result;
+use assert_cmd::prelude::CommandCargoExt;
 use function_name::named;
 
 /// `garden grow` clones repositories
@@ -72,6 +73,37 @@ fn grow_clone_shallow() -> Result<()> {
     Ok(())
 }
 
+/// `garden grow --depth` creates shallow clones and `--unshallow` fetches the full history.
+#[test]
+#[named]
+fn grow_depth_and_unshallow() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let args = [
+        "--chdir",
+        &root,
+        "--config",
+        "tests/data/garden.yaml",
+        "grow",
+    ];
+    exec_garden(&[&args[..], &["--depth", "1", "example/tree"]].concat())?;
+
+    // Only one commit must be cloned because of "--depth 1".
+    let worktree = fixture.worktree("example/tree/repo");
+    let cmd = ["git", "rev-list", "HEAD"];
+    let output = assert_cmd_capture(&cmd, &worktree);
+    assert_eq!(output.lines().count(), 1);
+
+    // "--unshallow" fetches the missing history into the existing tree.
+    exec_garden(&[&args[..], &["--unshallow", "example/tree"]].concat())?;
+    let output = assert_cmd_capture(&cmd, &worktree);
+    assert!(output.lines().count() > 1);
+    let cmd = ["git", "rev-parse", "--is-shallow-repository"];
+    assert_eq!(assert_cmd_capture(&cmd, &worktree), "false");
+
+    Ok(())
+}
+
 /// `garden grow` clones a single branch with "single-branch: true".
 #[test]
 #[named]
@@ -152,6 +184,181 @@ fn grow_branch_default() -> Result<()> {
     Ok(())
 }
 
+/// "garden grow --single-branch" clones and checks out the configured branch only.
+#[test]
+#[named]
+fn grow_branch_single_branch_option() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    // garden grow --single-branch dev
+    exec_garden(&[
+        "--chdir",
+        &fixture.root(),
+        "--config",
+        "tests/data/branches.yaml",
+        "grow",
+        "--single-branch",
+        "dev",
+    ])?;
+
+    // The "dev" repository must have a branch called "dev" checked-out.
+    let worktree_dev = fixture.worktree("dev");
+    let cmd = ["git", "symbolic-ref", "--short", "HEAD"];
+    let output = assert_cmd_capture(&cmd, &worktree_dev);
+    assert_eq!(output, "dev");
+
+    // Only the origin/dev branch must exist because we cloned with --single-branch.
+    assert_ref(&worktree_dev, "origin/dev");
+    assert_ref_missing(&worktree_dev, "origin/default");
+
+    Ok(())
+}
+
+/// "garden grow" initializes submodules with "submodules: true"
+/// and "garden plant" records the setting.
+#[test]
+#[named]
+fn grow_submodules() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    // Create a repository with a submodule that points to repos/example.git.
+    let root = std::fs::canonicalize(fixture.root())?;
+    let root = root.to_string_lossy();
+    assert_cmd(&["git", "init", "--quiet", "super"], &root);
+    let super_path = fixture.path("super");
+    let cmd = [
+        "git",
+        "-c",
+        "protocol.file.allow=always",
+        "submodule",
+        "add",
+        "--quiet",
+        &format!("file://{}/repos/example.git", root),
+        "sub",
+    ];
+    assert_cmd(&cmd, &super_path);
+    assert_cmd(&["git", "commit", "--quiet", "-m", "add sub"], &super_path);
+    let cmd = [
+        "git",
+        "clone",
+        "--quiet",
+        "--bare",
+        "super",
+        "repos/super.git",
+    ];
+    assert_cmd(&cmd, &root);
+
+    let config = r#"
+trees:
+  super:
+    path: clone
+    url: file://${GARDEN_ROOT}/repos/super.git
+    submodules: true
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    // Submodules cannot be cloned from file:// URLs by default.
+    let status = std::process::Command::cargo_bin("garden")?
+        .args(["--chdir", &fixture.root(), "grow", "super"])
+        .env("GIT_CONFIG_COUNT", "1")
+        .env("GIT_CONFIG_KEY_0", "protocol.file.allow")
+        .env("GIT_CONFIG_VALUE_0", "always")
+        .status()?;
+    assert!(status.success());
+
+    // The submodule was cloned.
+    let submodule = fixture.path("clone/sub");
+    assert_ref(&submodule, "default");
+
+    // "garden plant" records submodules for existing trees.
+    exec_garden(&[
+        "--chdir",
+        &fixture.root(),
+        "plant",
+        "--output",
+        "planted.yaml",
+        "clone",
+    ])?;
+    let planted = std::fs::read_to_string(fixture.path("planted.yaml"))?;
+    assert!(planted.contains("submodules: true"));
+
+    Ok(())
+}
+
+/// "garden grow" borrows objects from "clone-reference" and "--reference" repositories.
+#[test]
+#[named]
+fn grow_clone_reference() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+trees:
+  configured:
+    url: file://${GARDEN_ROOT}/repos/example.git
+    clone-reference: repos/example.git
+  unconfigured:
+    url: file://${GARDEN_ROOT}/repos/example.git
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    let root = fixture.root();
+
+    let output = garden_capture(&["--chdir", &root, "-vv", "grow", "configured"]);
+    assert!(output.contains("--reference-if-able="));
+    assert!(output.contains("--dissociate"));
+
+    let cmd = [
+        "--chdir",
+        &root,
+        "-vv",
+        "grow",
+        "--reference",
+        "repos/example.git",
+        "unconfigured",
+    ];
+    let output = garden_capture(&cmd);
+    // Relative paths are made absolute.
+    assert!(output.contains("--reference-if-able=/"));
+
+    // Dissociated clones do not depend on the reference repository.
+    for name in ["configured", "unconfigured"] {
+        let worktree = fixture.worktree(name);
+        assert_ref(&worktree, "origin/default");
+        let alternates = format!("{}/.git/objects/info/alternates", worktree);
+        assert!(!std::path::Path::new(&alternates).exists());
+    }
+
+    Ok(())
+}
+
+/// SSH clones that libgit2 cannot complete are retried using "git clone",
+/// which uses OpenSSH and "~/.ssh/config" host aliases.
+#[cfg(unix)]
+#[test]
+#[named]
+fn grow_clone_ssh_fallback() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  example:
+    url: garden-test.invalid:${GARDEN_ROOT}/repos/example.git
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    // The fake ssh command runs the remote "git-upload-pack" command locally.
+    let ssh = std::path::absolute(format!("{}/fake-ssh", root))?;
+    std::fs::write(
+        &ssh,
+        "#!/bin/sh\nfor arg in \"$@\"; do command=\"$arg\"; done\nexec sh -c \"$command\"\n",
+    )?;
+    std::fs::set_permissions(&ssh, std::os::unix::fs::PermissionsExt::from_mode(0o755))?;
+
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "grow", "example"])
+        .env("GIT_SSH_COMMAND", &ssh);
+    cmd.assert().success();
+
+    let worktree = fixture.worktree("example");
+    assert_ref(&worktree, "origin/default");
+
+    Ok(())
+}
+
 /// This creates bare repositories based on the "bare.git" naming convention.
 /// The configuration does not specify "bare: true".
 #[test]
@@ -297,6 +504,77 @@ fn grow_symlinks() -> Result<()> {
     Ok(())
 }
 
+/// "garden grow --rebuild-symlinks" repairs broken and wrong-target symlinks
+/// and "garden ls" reports them.
+#[cfg(unix)]
+#[test]
+#[named]
+fn grow_rebuild_symlinks() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  link:
+    symlink: target
+  broken:
+    symlink: target
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    std::fs::create_dir_all(format!("{}/target", root))?;
+    std::fs::create_dir_all(format!("{}/other", root))?;
+    std::os::unix::fs::symlink("other", format!("{}/link", root))?;
+    std::os::unix::fs::symlink("missing", format!("{}/broken", root))?;
+
+    let output = garden_capture(&["--chdir", &root, "ls"]);
+    assert!(output.contains("symlinks:"));
+    assert!(output.contains("link    wrong-target -> other"));
+    assert!(output.contains("broken  broken"));
+
+    // Unhealthy symlinks are left as-is by default.
+    let output = std::process::Command::cargo_bin("garden")?
+        .args(["--chdir", &root, "grow", "link", "broken"])
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--rebuild-symlinks"));
+    assert_eq!(
+        fixture.pathbuf("link").read_link()?.to_string_lossy(),
+        "other"
+    );
+
+    exec_garden(&[
+        "--chdir",
+        &root,
+        "grow",
+        "--rebuild-symlinks",
+        "link",
+        "broken",
+    ])?;
+    for name in ["link", "broken"] {
+        assert_eq!(
+            fixture.pathbuf(name).read_link()?.to_string_lossy(),
+            "target"
+        );
+    }
+    let output = garden_capture(&["--chdir", &root, "ls"]);
+    assert!(!output.contains("symlinks:"));
+    let output = garden_capture(&["--chdir", &root, "ls", "--format", "json"]);
+    assert!(output.contains(r#""status": "ok""#));
+
+    // Directories in place of a symlink are never removed.
+    std::fs::remove_file(format!("{}/link", root))?;
+    std::fs::create_dir_all(format!("{}/link", root))?;
+    let output = std::process::Command::cargo_bin("garden")?
+        .args(["--chdir", &root, "grow", "--rebuild-symlinks", "link"])
+        .output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is not a symlink"));
+    assert!(!stderr.contains("--rebuild-symlinks"));
+    assert!(fixture.pathbuf("link").is_dir());
+
+    Ok(())
+}
+
 /// `garden grow` sets up git config settings
 #[test]
 #[named]
@@ -333,6 +611,102 @@ fn grow_gitconfig() -> Result<()> {
     Ok(())
 }
 
+/// "garden configure" re-applies tree and garden gitconfig settings to existing trees.
+#[test]
+#[named]
+fn configure_gitconfig() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  example:
+    branch: default
+    url: file://${GARDEN_ROOT}/repos/example.git
+    gitconfig:
+      user.name: A U Thor
+gardens:
+  work:
+    trees: example
+    gitconfig:
+      garden.team: core
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    exec_garden(&["--chdir", &root, "grow", "example"])?;
+
+    // Local modifications are reported and reset to the configured values.
+    let worktree = fixture.worktree("example");
+    assert_cmd(&["git", "config", "user.name", "Someone Else"], &worktree);
+    let output = garden_capture(&["--chdir", &root, "--color=off", "configure", ":work"]);
+    assert_eq!(
+        output,
+        "example: user.name: Someone Else -> A U Thor\n\
+         example: garden.team: (unset) -> core"
+    );
+    let cmd = ["git", "config", "user.name"];
+    assert_eq!(assert_cmd_capture(&cmd, &worktree), "A U Thor");
+    let cmd = ["git", "config", "garden.team"];
+    assert_eq!(assert_cmd_capture(&cmd, &worktree), "core");
+
+    // Nothing is reported once the settings match.
+    let output = garden_capture(&["--chdir", &root, "configure", ":work"]);
+    assert_eq!(output, "");
+
+    Ok(())
+}
+
+/// "garden configure --check" reports gitconfig drift without changing anything.
+#[test]
+#[named]
+fn configure_check() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  example:
+    url: file://${GARDEN_ROOT}/repos/example.git
+    gitconfig:
+      user.name: A U Thor
+gardens:
+  work:
+    trees: example
+    gitconfig:
+      garden.team: core
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    exec_garden(&["--chdir", &root, "grow", "example"])?;
+    let worktree = fixture.worktree("example");
+    assert_cmd(&["git", "config", "user.name", "Someone Else"], &worktree);
+
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args([
+        "--chdir",
+        &root,
+        "--color=off",
+        "configure",
+        "--check",
+        ":work",
+    ]);
+    let output = cmd.assert().failure().code(1).get_output().stdout.clone();
+    assert_eq!(
+        String::from_utf8_lossy(&output),
+        "# example\n\
+         - user.name = Someone Else\n\
+         + user.name = A U Thor\n\
+         + garden.team = core\n"
+    );
+    // Nothing was changed.
+    let cmd = ["git", "config", "user.name"];
+    assert_eq!(assert_cmd_capture(&cmd, &worktree), "Someone Else");
+    assert_cmd_status(&["git", "config", "garden.team"], &worktree, 1);
+
+    // No drift is reported once the settings have been applied.
+    exec_garden(&["--chdir", &root, "configure", ":work"])?;
+    let output = garden_capture(&["--chdir", &root, "configure", "--check", ":work"]);
+    assert_eq!(output, "");
+
+    Ok(())
+}
+
 /// This creates a worktree
 #[test]
 #[named]
@@ -385,6 +759,187 @@ fn grow_worktree_and_parent() -> Result<()> {
     // The "echo" command is: echo ${TREE_NAME} "$@"
     assert_eq!("default hello", output);
 
+    // "garden ls --worktrees" displays the child worktrees under their parent.
+    let output = garden_capture(&[
+        "--chdir",
+        &fixture.root(),
+        "--config",
+        "tests/data/worktree.yaml",
+        "--color=off",
+        "ls",
+        "--worktrees",
+    ]);
+    assert_eq!(output, "default [default]\n    dev [dev]  up to date");
+
+    Ok(())
+}
+
+/// "garden grow" creates worktree branches that do not exist in the remote
+/// and reuses local branches that already exist in the parent repository.
+#[test]
+#[named]
+fn grow_worktree_branches() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  parent:
+    url: file://${GARDEN_ROOT}/repos/example.git
+  feature:
+    worktree: parent
+    branch: feature
+  local:
+    worktree: parent
+    branch: local
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    // "feature" does not exist in the remote. It is created from the parent's HEAD.
+    exec_garden(&["--chdir", &root, "grow", "feature"])?;
+    let worktree_parent = fixture.worktree("parent");
+    let worktree_feature = fixture.worktree("feature");
+    assert_ref(&worktree_feature, "feature");
+    let cmd = ["git", "rev-parse", "HEAD"];
+    let parent_head = assert_cmd_capture(&cmd, &worktree_parent);
+    assert_eq!(assert_cmd_capture(&cmd, &worktree_feature), parent_head);
+
+    // Existing local branches are checked out into the worktree.
+    assert_cmd(&["git", "branch", "local", "HEAD~1"], &worktree_parent);
+    exec_garden(&["--chdir", &root, "grow", "local"])?;
+    let worktree_local = fixture.worktree("local");
+    let cmd = ["git", "symbolic-ref", "--short", "HEAD"];
+    assert_eq!(assert_cmd_capture(&cmd, &worktree_local), "local");
+    let cmd = ["git", "rev-parse", "HEAD"];
+    assert_ne!(assert_cmd_capture(&cmd, &worktree_local), parent_head);
+
+    Ok(())
+}
+
+/// `garden grow` clones trees from bundles and falls back to the url.
+#[test]
+#[named]
+fn grow_bundle() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  bundled:
+    url: file://${GARDEN_ROOT}/repos/missing.git
+    bundle: example.bundle
+    branch: default
+  fallback:
+    url: file://${GARDEN_ROOT}/repos/example.git
+    bundle: missing.bundle
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    assert_cmd(
+        &["git", "bundle", "create", "../../example.bundle", "--all"],
+        &fixture.path("repos/example.git"),
+    );
+
+    // The bundle is used even though the url does not exist.
+    exec_garden(&["--chdir", &root, "grow", "bundled"])?;
+    let worktree = fixture.worktree("bundled");
+    assert_ref(&worktree, "default");
+    // The "origin" remote points to the configured url.
+    let cmd = ["git", "config", "remote.origin.url"];
+    let url = assert_cmd_capture(&cmd, &worktree);
+    assert!(url.ends_with("/repos/missing.git"));
+
+    // Missing bundles fall back to the url.
+    exec_garden(&["--chdir", &root, "--quiet", "grow", "fallback"])?;
+    let worktree = fixture.worktree("fallback");
+    assert_ref(&worktree, "default");
+
+    Ok(())
+}
+
+/// "garden grow" adds every remote and tracks branches from the "default-remote".
+#[test]
+#[named]
+fn grow_default_remote() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  forked:
+    branch: default
+    default-remote: fork
+    remotes:
+      upstream: file://${GARDEN_ROOT}/repos/example.git
+      fork: file://${GARDEN_ROOT}/repos/example.git
+  upstream:
+    branch: default
+    remotes:
+      upstream: file://${GARDEN_ROOT}/repos/example.git
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    exec_garden(&["--chdir", &root, "grow", "forked", "upstream"])?;
+
+    // All remotes are added and the branch tracks the default remote.
+    let worktree = fixture.worktree("forked");
+    let remotes = assert_cmd_capture(&["git", "remote"], &worktree);
+    let mut remotes: Vec<&str> = remotes.lines().collect();
+    remotes.sort();
+    assert_eq!(remotes, ["fork", "upstream"]);
+    let cmd = ["git", "config", "branch.default.remote"];
+    assert_eq!(assert_cmd_capture(&cmd, &worktree), "fork");
+
+    // The first remote is cloned from when "default-remote" is unspecified.
+    let worktree = fixture.worktree("upstream");
+    assert_eq!(
+        assert_cmd_capture(&["git", "remote"], &worktree),
+        "upstream"
+    );
+    let cmd = ["git", "config", "branch.default.remote"];
+    assert_eq!(assert_cmd_capture(&cmd, &worktree), "upstream");
+
+    Ok(())
+}
+
+/// "garden grow" reconciles the remotes of existing trees with the garden file.
+#[test]
+#[named]
+fn grow_prune_remotes() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  example:
+    branch: default
+    url: file://${GARDEN_ROOT}/repos/example.git
+    remotes:
+      stale: file://${GARDEN_ROOT}/repos/stale.git
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    exec_garden(&["--chdir", &root, "grow", "example"])?;
+
+    // Changed urls are updated and new remotes are added. Unconfigured remotes
+    // are left as-is unless "--prune-remotes" is used.
+    let config = r#"
+trees:
+  example:
+    branch: default
+    url: file://${GARDEN_ROOT}/repos/moved.git
+    remotes:
+      fork: file://${GARDEN_ROOT}/repos/fork.git
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    exec_garden(&["--chdir", &root, "grow", "example"])?;
+    let worktree = fixture.worktree("example");
+    let remotes = assert_cmd_capture(&["git", "remote"], &worktree);
+    let mut remotes: Vec<&str> = remotes.lines().collect();
+    remotes.sort();
+    assert_eq!(remotes, ["fork", "origin", "stale"]);
+    let url = assert_cmd_capture(&["git", "config", "remote.origin.url"], &worktree);
+    assert!(url.ends_with("/repos/moved.git"), "{}", url);
+
+    exec_garden(&["--chdir", &root, "grow", "--prune-remotes", "example"])?;
+    let remotes = assert_cmd_capture(&["git", "remote"], &worktree);
+    let mut remotes: Vec<&str> = remotes.lines().collect();
+    remotes.sort();
+    assert_eq!(remotes, ["fork", "origin"]);
+
     Ok(())
 }
 
@@ -515,6 +1070,162 @@ fn eval_config_dir_with_chdir_and_root() {
     assert!(path.is_absolute());
 }
 
+/// "garden eval --all-trees" evaluates an expression in every matched tree.
+#[test]
+fn eval_all_trees() {
+    let cmd = [
+        "--chdir",
+        "tests/data",
+        "eval",
+        "--all-trees",
+        "${tree_var}",
+        "@tree*",
+    ];
+    assert_eq!(garden_capture(&cmd), "x1\nx2");
+
+    let cmd = [
+        "--chdir",
+        "tests/data",
+        "eval",
+        "--all-trees",
+        "--names",
+        "${tree_var}",
+        "%trees",
+    ];
+    assert_eq!(garden_capture(&cmd), "tree1\tx1\ntree2\tx2");
+
+    let cmd = [
+        "--chdir",
+        "tests/data",
+        "eval",
+        "--all-trees",
+        "--format",
+        "json",
+        "${TREE_NAME}",
+        "@tree1",
+    ];
+    let output = garden_capture(&cmd);
+    let doc: serde_json::Value = serde_json::from_str(&output).unwrap_or_default();
+    assert_eq!(doc["trees"][0]["name"], "tree1");
+    assert_eq!(doc["trees"][0]["value"], "tree1");
+}
+
+/// "garden eval --file" evaluates one expression per line from a file or stdin.
+#[test]
+#[named]
+fn eval_file() -> Result<()> {
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", "tests/data", "eval", "--file", "-", "tree1"])
+        .write_stdin("${TREE_NAME}\n${tree_var}\n\n");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    assert_eq!(String::from_utf8(output)?, "tree1\nx1\n\n");
+
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", "tests/data", "eval", "--all-trees", "--names"])
+        .args(["--file", "-", "%trees"])
+        .write_stdin("${TREE_NAME}\n${tree_var}\n");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    assert_eq!(
+        String::from_utf8(output)?,
+        "tree1\ttree1\ntree1\tx1\ntree2\ttree2\ntree2\tx2\n"
+    );
+
+    // Relative paths are resolved before "--chdir" takes effect.
+    let fixture = BareRepoFixture::new(function_name!());
+    let exprs = format!("{}/exprs.txt", fixture.root());
+    std::fs::write(&exprs, "${tree_var}\n${TREE_NAME}\n")?;
+    let cmd = [
+        "--chdir",
+        "tests/data",
+        "eval",
+        "--all-trees",
+        "--format",
+        "json",
+        "--file",
+        &exprs,
+        "@tree2",
+    ];
+    let output = garden_capture(&cmd);
+    let doc: serde_json::Value = serde_json::from_str(&output)?;
+    assert_eq!(doc["trees"][0]["name"], "tree2");
+    assert_eq!(
+        doc["trees"][0]["values"],
+        serde_json::json!(["x2", "tree2"])
+    );
+
+    // "--file" accepts at most a tree and a garden.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", "tests/data", "eval", "--file", "-"])
+        .args(["tree1", "dev", "extra"])
+        .write_stdin("${TREE_NAME}\n");
+    cmd.assert().failure().code(64);
+
+    // An expression is required without "--file".
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", "tests/data", "eval"]);
+    cmd.assert().failure().code(2);
+
+    Ok(())
+}
+
+/// "garden eval --quote" and "garden eval -z" print values safely for scripts.
+#[test]
+fn eval_quote_and_null() -> Result<()> {
+    let eval = |args: &[&str]| -> Result<String> {
+        let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+        cmd.args(["--chdir", "tests/data", "eval"]).args(args);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        Ok(String::from_utf8(output)?)
+    };
+    assert_eq!(eval(&["a b"])?, "a b\n");
+    assert_eq!(eval(&["--quote", "raw", "a b"])?, "a b\n");
+    assert_eq!(eval(&["--quote", "shell", "it's"])?, "\"it's\"\n");
+    assert_eq!(
+        eval(&["--quote", "json", "a\tb \"c\""])?,
+        "\"a\\tb \\\"c\\\"\"\n"
+    );
+    assert_eq!(eval(&["-z", "a\nb"])?, "a\nb\0");
+    assert_eq!(
+        eval(&["--null", "--all-trees", "--names", "${tree_var}", "%trees"])?,
+        "tree1\tx1\0tree2\tx2\0"
+    );
+    assert_eq!(
+        eval(&["--quote", "json", "--all-trees", "${TREE_NAME} x", "%trees"])?,
+        "\"tree1 x\"\n\"tree2 x\"\n"
+    );
+
+    // Quoting does not apply to the JSON document printed by "--format json".
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args([
+        "--chdir",
+        "tests/data",
+        "eval",
+        "--all-trees",
+        "--format",
+        "json",
+    ])
+    .args(["--null", "${TREE_NAME}"]);
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+/// Additional "--config" files override the earlier files.
+#[test]
+fn eval_config_overlay() {
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/tmp",
+        "--config",
+        "tests/data/garden.yaml",
+        "--config",
+        "tests/data/overlay.yaml",
+        "eval",
+        "${gh_ssh} ${current_config}",
+    ]);
+    assert_eq!(output, "ssh://overlay.example.com main");
+}
+
 /// Test pwd with both "--root" and "--chdir"
 #[test]
 fn eval_exec_pwd_with_root_and_chdir() {
@@ -609,21 +1320,71 @@ fn cmd_dash_dash_arguments_custom() {
     assert_eq!(format!("{}\n{}", msg, msg), output);
 }
 
-/// Test "." default for custom "garden <command>" with no arguments
+/// Test positional arguments and ${ARGS} placeholders in custom commands
 #[test]
-fn cmd_dot_default_no_args() {
-    let output = garden_capture(&["--quiet", "--chdir", "tests/data", "echo-dir"]);
-    assert_eq!("data", output);
+fn cmd_dash_dash_arguments_placed() {
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "echo-placed",
+        ".",
+        "--",
+        "a",
+        "b",
+    ]);
+    assert_eq!(output, "b -- a b -- a");
 }
 
-/// Test "." default for "garden <command>" with no arguments and echo
+/// "--log-format json" emits log records with command and tree spans to stderr.
 #[test]
-fn cmd_dot_default_no_args_echo() {
-    let output = garden_capture(&["--quiet", "--chdir", "tests/data", "echo-args"]);
-    let msg = "garden\narguments -- a b c -- -- x y z";
-    assert_eq!(msg, output);
-}
-
+fn log_format_json() -> Result<()> {
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args([
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "--log-level",
+        "info",
+        "--log-format",
+        "json",
+        "exec",
+        ".",
+        "false",
+    ]);
+    let output = cmd.assert().failure().code(1).get_output().clone();
+    let stderr = String::from_utf8(output.stderr)?;
+    let records: Vec<serde_json::Value> = stderr
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    let failed = records
+        .iter()
+        .find(|record| record["fields"]["message"] == "failed")
+        .expect("a failure must be logged");
+    assert_eq!(failed["level"], "ERROR");
+    assert_eq!(failed["fields"]["exit_status"], 1);
+    assert_eq!(failed["span"]["tree"], "current");
+    assert_eq!(failed["spans"][0]["command"], "exec");
+
+    Ok(())
+}
+
+/// Test "." default for custom "garden <command>" with no arguments
+#[test]
+fn cmd_dot_default_no_args() {
+    let output = garden_capture(&["--quiet", "--chdir", "tests/data", "echo-dir"]);
+    assert_eq!("data", output);
+}
+
+/// Test "." default for "garden <command>" with no arguments and echo
+#[test]
+fn cmd_dot_default_no_args_echo() {
+    let output = garden_capture(&["--quiet", "--chdir", "tests/data", "echo-args"]);
+    let msg = "garden\narguments -- a b c -- -- x y z";
+    assert_eq!(msg, output);
+}
+
 /// Test "." default for "garden <command>" with double-dash
 #[test]
 fn cmd_dot_default_double_dash() {
@@ -687,173 +1448,2391 @@ fn cmd_breadth_first_and_depth_first() {
     assert_eq!(expect, actual);
 }
 
-/// Test -n / --no-errexit and the shell "-e" behavior.
+/// Test "garden cmd --order {tree,command}" and the "garden.cmd-order" default.
 #[test]
-fn cmd_no_errexit() {
-    let output = garden_capture(&[
+#[named]
+fn cmd_order() -> Result<()> {
+    let actual = garden_capture(&[
         "--chdir",
         "tests/data",
         "--quiet",
         "cmd",
-        ".",
-        "error-command",
+        "--order",
+        "command",
+        "trees",
+        "tree-name",
+        "tree-var",
     ]);
-    assert_eq!(output, "ok");
+    assert_eq!(actual, "tree1\ntree2\nx1\nx2");
 
-    let output = garden_capture(&[
+    let actual = garden_capture(&[
         "--chdir",
         "tests/data",
         "--quiet",
         "cmd",
-        "--no-errexit",
-        ".",
-        "error-command",
+        "--order",
+        "tree",
+        "trees",
+        "tree-name",
+        "tree-var",
     ]);
-    assert_eq!(output, "ok\nafter error");
+    assert_eq!(actual, "tree1\nx1\ntree2\nx2");
+
+    // "garden.cmd-order" sets the default order.
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+garden:
+  cmd-order: command
+trees:
+  tree1:
+    path: .
+    commands:
+      a: echo a1
+      b: echo b1
+  tree2:
+    path: .
+    commands:
+      a: echo a2
+      b: echo b2
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    let root = fixture.root();
+    let actual = garden_capture(&["--chdir", &root, "--quiet", "cmd", "@*", "a", "b"]);
+    assert_eq!(actual, "a1\na2\nb1\nb2");
+
+    // --order overrides the configured default.
+    let actual = garden_capture(&[
+        "--chdir", &root, "--quiet", "cmd", "--order", "tree", "@*", "a", "b",
+    ]);
+    assert_eq!(actual, "a1\nb1\na2\nb2");
+
+    Ok(())
 }
 
+/// Test "garden env" with the sh, fish and dotenv formats.
 #[test]
-fn cmd_no_errexit_for_command_lists() {
-    let output = garden_capture(&[
-        "--chdir",
-        "tests/data",
-        "--quiet",
-        "cmd",
-        ".",
-        "error-command-list",
-    ]);
-    assert_eq!(output, "ok");
+#[named]
+fn cmd_env() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+trees:
+  tree1:
+    path: tree1
+    environment:
+      GARDEN_TEST_NAME=: ${TREE_NAME}
+      GARDEN_TEST_QUOTE=: it's
+  tree2:
+    path: tree2
+    environment:
+      GARDEN_TEST_PATH=: /first
+      GARDEN_TEST_PATH: /second
+gardens:
+  all:
+    trees: tree*
+    environment:
+      GARDEN_TEST_GARDEN=: value
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    let root = fixture.root();
 
-    let output = garden_capture(&[
-        "--chdir",
-        "tests/data",
-        "--quiet",
-        "cmd",
-        "--no-errexit",
-        ".",
-        "error-command-list",
-    ]);
-    assert_eq!(output, "ok\nafter error");
+    let output = garden_capture(&["--chdir", &root, "env", "tree1"]);
+    assert_eq!(
+        output,
+        "export GARDEN_TEST_NAME=tree1\nexport GARDEN_TEST_QUOTE=\"it's\""
+    );
+
+    let output = garden_capture(&["--chdir", &root, "env", "--format", "fish", "tree1"]);
+    assert_eq!(
+        output,
+        "set -gx GARDEN_TEST_NAME 'tree1'\nset -gx GARDEN_TEST_QUOTE 'it\\'s'"
+    );
+
+    // Variables that are modified multiple times are printed once.
+    let output = garden_capture(&["--chdir", &root, "env", "--format", "dotenv", "tree2"]);
+    assert_eq!(output, "GARDEN_TEST_PATH=\"/second:/first\"");
+
+    // Garden environments include the environment from each tree.
+    let output = garden_capture(&["--chdir", &root, "env", "--format", "dotenv", "all"]);
+    assert_eq!(
+        output,
+        "GARDEN_TEST_NAME=\"tree1\"\nGARDEN_TEST_QUOTE=\"it's\"\n\
+         GARDEN_TEST_PATH=\"/second:/first\"\nGARDEN_TEST_GARDEN=\"value\""
+    );
+
+    Ok(())
 }
 
-/// Test the interaction of --keep-going, --no-errexit and command lists.
+/// Tree queries resolve trees inside of grafted configurations.
 #[test]
-fn cmd_keep_going_and_no_errexit() {
-    // exit-on-error: true, keep-going: false, command: str
+#[named]
+fn cmd_graft_queries() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+grafts:
+  child: child.yaml
+trees:
+  root-tree:
+    path: .
+    commands:
+      name: echo root ${TREE_NAME}
+"#;
+    let child_config = r#"
+trees:
+  child-tree:
+    path: .
+    commands:
+      name: echo child ${TREE_NAME}
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    std::fs::write(format!("{}/child.yaml", fixture.root()), child_config)?;
+    let root = fixture.root();
+
+    let output = garden_capture(&["--chdir", &root, "--quiet", "cmd", "child::*", "name"]);
+    assert_eq!(output, "child child-tree");
+
     let output = garden_capture(&[
         "--chdir",
-        "tests/data",
+        &root,
         "--quiet",
-        "error-command",
-        "tree1",
-        "tree2",
+        "cmd",
+        "@child::child-tree",
+        "name",
     ]);
-    assert_eq!(output, "ok");
+    assert_eq!(output, "child child-tree");
 
-    // exit-on-error: false, keep-going: false, command: str
+    // Wildcard queries span the root configuration and its grafts.
+    let output = garden_capture(&["--chdir", &root, "--quiet", "cmd", "*", "name"]);
+    assert_eq!(output, "root root-tree\nchild child-tree");
+
+    // "--exclude" queries exclude trees from grafts.
     let output = garden_capture(&[
         "--chdir",
-        "tests/data",
+        &root,
         "--quiet",
-        "error-command",
-        "--no-errexit",
-        "tree1",
-        "tree2",
+        "--exclude",
+        "child-tree",
+        "cmd",
+        "*",
+        "name",
     ]);
-    assert_eq!(output, "ok\nafter error");
-
-    // exit-on-error: true, keep-going: true, command: str
+    assert_eq!(output, "root root-tree");
     let output = garden_capture(&[
         "--chdir",
-        "tests/data",
+        &root,
         "--quiet",
-        "error-command",
-        "--keep-going",
-        "tree1",
-        "tree2",
+        "--exclude",
+        "child::*",
+        "cmd",
+        "*",
+        "name",
     ]);
-    assert_eq!(output, "ok\nok");
+    assert_eq!(output, "root root-tree");
 
-    // exit-on-error: false, keep-going: true, command: str
     let output = garden_capture(&[
-        "--chdir",
-        "tests/data",
-        "--quiet",
-        "error-command",
-        "--keep-going",
-        "--no-errexit",
-        "tree1",
-        "tree2",
+        "--chdir", &root, "--quiet", "exec", "child::*", "echo", "ok",
     ]);
-    assert_eq!(output, "ok\nafter error\nok\nafter error");
+    assert_eq!(output, "ok");
 
-    // Same as above but with error-command-list instead of error-command.
-    // exit-on-error: true, keep-going: false, command: list
+    Ok(())
+}
+
+/// Grafts with "inherit: true" can read and override the parent's variables.
+#[test]
+#[named]
+fn graft_inherit_variables() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+grafts:
+  inherited:
+    config: child.yaml
+    inherit: true
+  isolated: child.yaml
+variables:
+  org: parent-org
+  shared: parent-shared
+"#;
+    let child_config = r#"
+variables:
+  shared: child-shared
+trees:
+  child-tree:
+    path: .
+    commands:
+      vars: echo ${org:-none} ${shared}
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    std::fs::write(format!("{}/child.yaml", fixture.root()), child_config)?;
+    let root = fixture.root();
+
+    let output = garden_capture(&["--chdir", &root, "--quiet", "cmd", "inherited::*", "vars"]);
+    assert_eq!(output, "parent-org child-shared");
+
+    let output = garden_capture(&["--chdir", &root, "--quiet", "cmd", "isolated::*", "vars"]);
+    assert_eq!(output, "none child-shared");
+
+    Ok(())
+}
+
+/// Grafts are only read when a command needs them.
+#[test]
+#[named]
+fn graft_lazy_loading() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+grafts:
+  child: child.yaml
+  missing: missing.yaml
+trees:
+  root-tree:
+    path: .
+"#;
+    let child_config = r#"
+trees:
+  child-tree:
+    path: .
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    std::fs::write(format!("{}/child.yaml", fixture.root()), child_config)?;
+    let root = fixture.root();
+
+    // Commands that do not use the grafts do not read them.
     let output = garden_capture(&[
         "--chdir",
-        "tests/data",
+        &root,
         "--quiet",
-        "error-command-list",
-        "tree1",
-        "tree2",
+        "exec",
+        "root-tree",
+        "echo",
+        "ok",
     ]);
     assert_eq!(output, "ok");
+    let output = garden_capture(&["--chdir", &root, "eval", "${child::value:-default}"]);
+    assert_eq!(output, "default");
 
-    // exit-on-error: false, keep-going: false, command: list
+    // "graft::query" only reads the named graft.
     let output = garden_capture(&[
-        "--chdir",
-        "tests/data",
-        "--quiet",
-        "error-command-list",
-        "--no-errexit",
-        "tree1",
-        "tree2",
+        "--chdir", &root, "--quiet", "exec", "child::*", "echo", "ok",
     ]);
-    assert_eq!(output, "ok\nafter error");
+    assert_eq!(output, "ok");
 
-    // exit-on-error: true, keep-going: true, command: list
+    // Wildcard queries read every graft.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "exec", "*", "echo", "ok"]);
+    let output = cmd.assert().failure().get_output().clone();
+    assert!(String::from_utf8(output.stderr)?.contains("missing: invalid graft"));
+
+    Ok(())
+}
+
+/// Test "depends-on" ordering in "garden cmd".
+#[test]
+#[named]
+fn cmd_depends_on() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+commands:
+  name: echo ${TREE_NAME}
+trees:
+  app:
+    path: .
+    depends-on: [lib, tools]
+  lib:
+    path: .
+    depends-on: core
+  core:
+    path: .
+  tools:
+    path: .
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    let root = fixture.root();
+
+    let output = garden_capture(&["--chdir", &root, "--quiet", "cmd", "*", "name"]);
+    assert_eq!(output, "core\ntools\nlib\napp");
+
+    // Dependencies are respected when running in parallel.
     let output = garden_capture(&[
-        "--chdir",
-        "tests/data",
-        "--quiet",
-        "error-command-list",
-        "--keep-going",
-        "tree1",
-        "tree2",
+        "--chdir", &root, "--quiet", "cmd", "--jobs", "4", "*", "name",
     ]);
-    assert_eq!(output, "ok\nok");
+    let lines: Vec<&str> = output.lines().collect();
+    let position = |line: &str| lines.iter().position(|entry| *entry == line).unwrap();
+    assert_eq!(lines.len(), 4);
+    assert!(position("core: core") < position("lib: lib"));
+    assert!(position("lib: lib") < position("app: app"));
+    assert!(position("tools: tools") < position("app: app"));
 
-    // exit-on-error: false, keep-going: true, command: list
-    let output = garden_capture(&[
-        "--chdir",
-        "tests/data",
-        "--quiet",
-        "error-command-list",
+    // Dependency cycles are reported as errors.
+    let config = r#"
+commands:
+  name: echo ${TREE_NAME}
+trees:
+  a:
+    path: .
+    depends-on: b
+  b:
+    path: .
+    depends-on: a
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "--quiet", "cmd", "*", "name"]);
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+/// "--keep-going" runs over every tree and summarizes the failing trees.
+#[test]
+#[named]
+fn keep_going_summary() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+commands:
+  check: |
+    echo ${TREE_NAME}
+    test ${TREE_NAME} != b
+templates:
+  named:
+    environment:
+      NAME: ${TREE_NAME}
+trees:
+  a: {path: ., templates: named}
+  b: {path: ., templates: named}
+  c: {path: ., templates: named}
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    // Execution stops at the first failing tree by default.
+    for command in [
+        &["cmd", "*", "check"][..],
+        &["exec", "*", "sh", "-c", "echo $NAME; test $NAME != b"],
+    ] {
+        let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+        cmd.args(["--chdir", &root, "--quiet"]).args(command);
+        let output = cmd.assert().failure().code(1).get_output().clone();
+        assert_eq!(String::from_utf8(output.stdout)?, "a\nb\n");
+    }
+
+    // "--keep-going" continues to the next tree and prints a summary.
+    for command in [
+        &["cmd", "--keep-going", "*", "check"][..],
+        &["exec", "-k", "*", "sh", "-c", "echo $NAME; test $NAME != b"],
+        &["cmd", "--keep-going", "--jobs", "2", "*", "check"],
+    ] {
+        let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+        cmd.args(["--chdir", &root, "--quiet", "--color=off"])
+            .args(command);
+        let output = cmd.assert().failure().code(1).get_output().clone();
+        let stdout = String::from_utf8(output.stdout)?;
+        assert_eq!(stdout.lines().count(), 3, "{}", stdout);
+        let stderr = String::from_utf8(output.stderr)?;
+        assert!(stderr.contains("error: 1 tree failed"), "{}", stderr);
+        assert!(stderr.contains("    b  exit status 1"), "{}", stderr);
+    }
+
+    Ok(())
+}
+
+/// "garden exec --dirty", "--clean" and "--behind" filter trees by their Git state.
+#[test]
+#[named]
+fn exec_state_filters() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+templates:
+  repo:
+    url: file://${GARDEN_ROOT}/repos/example.git
+trees:
+  clean:
+    templates: repo
+  dirty:
+    templates: repo
+  behind:
+    templates: repo
+  missing:
+    templates: repo
+  plain:
+    path: plain
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    std::fs::create_dir_all(format!("{}/plain", root))?;
+    exec_garden(&["--chdir", &root, "grow", "clean", "dirty", "behind"])?;
+    std::fs::write(format!("{}/dirty/untracked", root), "")?;
+    assert_cmd(
+        &["git", "reset", "--quiet", "--hard", "HEAD~1"],
+        &fixture.path("behind"),
+    );
+
+    let exec = |filters: &[&str]| -> String {
+        let mut args = vec!["--chdir", &root, "--quiet", "exec"];
+        args.extend_from_slice(filters);
+        args.extend_from_slice(&["*", "sh", "-c", "basename \"$(pwd)\""]);
+        garden_capture(&args)
+    };
+    assert_eq!(exec(&["--dirty"]), "dirty");
+    assert_eq!(exec(&["--clean"]), "clean\nbehind");
+    assert_eq!(exec(&["--behind"]), "behind");
+    assert_eq!(exec(&["--dirty", "--behind"]), "");
+    // Trees are filtered when running in parallel.
+    let output = exec(&["--clean", "--jobs", "2"]);
+    assert!(output.contains("clean: clean"));
+    assert!(output.contains("behind: behind"));
+    assert!(!output.contains("dirty"));
+
+    // --dirty and --clean are mutually exclusive.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "exec", "--dirty", "--clean", "*", "true"]);
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+/// "garden exec --exit-status" selects the exit status that is reported when trees fail.
+#[test]
+#[named]
+fn exec_exit_status_policy() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  a: {path: ., environment: {CODE: "3"}}
+  b: {path: ., environment: {CODE: "5"}}
+  c: {path: ., environment: {CODE: "0"}}
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    let command = ["*", "sh", "-c", "echo $CODE; exit $CODE"];
+
+    // "--fail-fast" stops at the first failing tree.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "--quiet", "exec", "--fail-fast"])
+        .args(command);
+    let output = cmd.assert().failure().code(3).get_output().clone();
+    assert_eq!(String::from_utf8(output.stdout)?, "3\n");
+
+    // The last failure is reported by default.
+    for (options, status) in [
+        (&["-k"][..], 5),
+        (&["-k", "--exit-status", "first"], 3),
+        (&["-k", "--exit-status", "last"], 5),
+        (&["-k", "--exit-status", "count"], 2),
+        (&["-k", "--exit-status", "count", "--jobs", "2"], 2),
+    ] {
+        let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+        cmd.args(["--chdir", &root, "--quiet", "exec"])
+            .args(options)
+            .args(command);
+        cmd.assert().failure().code(status);
+    }
+
+    // "--fail-fast" and "--keep-going" are mutually exclusive.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "exec", "--fail-fast", "-k"])
+        .args(command);
+    cmd.assert().failure().code(2);
+
+    // "garden.exit-status" sets the default policy.
+    let config = format!("{}garden:\n  exit-status: count\n", config);
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "--quiet", "exec", "-k"])
+        .args(command);
+    cmd.assert().failure().code(2);
+
+    // Failures are ordered by tree when later trees finish first in parallel.
+    let config = r#"
+commands:
+  verify: sleep $${DELAY}; exit $${CODE}
+trees:
+  a: {path: ., environment: {CODE: "3", DELAY: "0.5"}}
+  b: {path: ., environment: {CODE: "5", DELAY: "0"}}
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    let exec = ["*", "sh", "-c", "sleep $DELAY; exit $CODE"];
+    let cmd = ["*", "verify"];
+    let custom = ["*"];
+    for (subcommand, args) in [("exec", &exec[..]), ("cmd", &cmd), ("verify", &custom)] {
+        for (policy, status) in [("first", 3), ("last", 5), ("count", 2)] {
+            let mut command = assert_cmd::Command::cargo_bin("garden")?;
+            command
+                .args(["--chdir", &root, "--quiet", subcommand, "-k", "-j2"])
+                .args(["--exit-status", policy])
+                .args(args);
+            command.assert().failure().code(status);
+        }
+    }
+
+    Ok(())
+}
+
+/// "garden cmd --and" runs every command in a tree before the next tree starts
+/// and stops at the first failure.
+#[test]
+#[named]
+fn cmd_and_chaining() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+garden:
+  cmd-order: command
+commands:
+  one: echo one ${TREE_NAME}
+  two: |
+    echo two ${TREE_NAME}
+    test ${TREE_NAME} != b
+trees:
+  a: {path: .}
+  b: {path: .}
+  c: {path: .}
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    // "garden.cmd-order: command" runs each command across all of the trees.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "--quiet", "cmd", "*", "one", "two"]);
+    let output = cmd.assert().failure().code(1).get_output().clone();
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "one a\none b\none c\ntwo a\ntwo b\n"
+    );
+
+    // "--and" chains the commands within each tree and stops at the first failure.
+    for option in ["--and", "--no-errexit-between"] {
+        let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+        cmd.args([
+            "--chdir", &root, "--quiet", "cmd", option, "*", "one", "two",
+        ]);
+        let output = cmd.assert().failure().code(1).get_output().clone();
+        assert_eq!(
+            String::from_utf8(output.stdout)?,
+            "one a\ntwo a\none b\ntwo b\n"
+        );
+    }
+
+    // "--and" cannot be combined with options that continue after failures.
+    for option in [
         "--keep-going",
+        "--breadth-first",
+        "--jobs=2",
         "--no-errexit",
-        "tree1",
-        "tree2",
-    ]);
-    assert_eq!(output, "ok\nafter error\nok\nafter error");
+    ] {
+        let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+        cmd.args(["--chdir", &root, "cmd", "--and", option, "*", "one"]);
+        cmd.assert().failure().code(2);
+    }
 
-    // exit-on-error: true, keep-going: false, command: multi
+    // Custom commands support "--and".
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "--quiet", "two", "--and", "*"]);
+    let output = cmd.assert().failure().code(1).get_output().clone();
+    assert_eq!(String::from_utf8(output.stdout)?, "two a\ntwo b\n");
+    for option in ["--keep-going", "--jobs=2", "--no-errexit"] {
+        let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+        cmd.args(["--chdir", &root, "two", "--and", option, "*"]);
+        cmd.assert().failure().code(2);
+    }
+
+    Ok(())
+}
+
+/// "garden --profile" and "GARDEN_PROFILE" select a configuration profile.
+#[test]
+#[named]
+fn config_profiles() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+variables:
+  host: example.com
+profiles:
+  work:
+    variables:
+      host: work.example.com
+  home: {}
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    let output = garden_capture(&["--chdir", &root, "eval", "${host}"]);
+    assert_eq!(output, "example.com");
+    let output = garden_capture(&["--chdir", &root, "--profile", "work", "eval", "${host}"]);
+    assert_eq!(output, "work.example.com");
+
+    // GARDEN_PROFILE selects the profile when --profile is not specified.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.env("GARDEN_PROFILE", "work");
+    cmd.args(["--chdir", &root, "eval", "${GARDEN_PROFILE} ${host}"]);
+    cmd.assert().success().stdout("work work.example.com\n");
+
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.env("GARDEN_PROFILE", "work");
+    cmd.args(["--chdir", &root, "--profile", "home", "eval", "${host}"]);
+    cmd.assert().success().stdout("example.com\n");
+
+    // Unknown profiles are reported as configuration errors.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "--profile", "ci", "eval", "${host}"]);
+    let output = cmd.assert().failure().get_output().clone();
+    assert!(String::from_utf8(output.stderr)?.contains("unknown profile: ci"));
+
+    // Unknown profiles from GARDEN_PROFILE are reported as warnings.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.env("GARDEN_PROFILE", "ci");
+    cmd.args(["--chdir", &root, "eval", "${host}"]);
+    let output = cmd.assert().success().get_output().clone();
+    assert_eq!(String::from_utf8(output.stdout)?, "example.com\n");
+    assert!(String::from_utf8(output.stderr)?.contains("warning: profiles: unknown profile: ci"));
+
+    // Grafts ignore profiles that they do not define.
+    let graft = r#"
+variables:
+  host: graft.example.com
+trees:
+  graft-tree:
+    path: .
+    commands:
+      host: echo ${host}
+profiles:
+  other: {}
+"#;
+    std::fs::write(format!("{}/graft.yaml", root), graft)?;
+    let config = format!("{}grafts:\n  graft: graft.yaml\n", config);
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
     let output = garden_capture(&[
         "--chdir",
-        "tests/data",
+        &root,
         "--quiet",
+        "--profile",
+        "work",
         "cmd",
-        "tree*",
-        "error-command",
-        "error-command-list",
+        "graft::*",
+        "host",
     ]);
-    assert_eq!(output, "ok");
+    assert_eq!(output, "graft.example.com");
 
-    // exit-on-error: false, keep-going: false, command: multi
-    let output = garden_capture(&[
-        "--chdir",
-        "tests/data",
-        "--quiet",
-        "cmd",
+    Ok(())
+}
+
+/// "garden --config <url>" fetches the configuration and caches it.
+#[cfg(unix)]
+#[test]
+#[named]
+fn config_url() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let root = std::path::absolute(&root)?.to_string_lossy().to_string();
+    // A fake "curl" serves https://example.com/<path> from "served/<path>"
+    // and records the URLs that were fetched.
+    let bin = format!("{}/bin", root);
+    let served = format!("{}/served/workspace", bin);
+    std::fs::create_dir_all(&served)?;
+    let curl = format!("{}/curl", bin);
+    let script = r#"#!/bin/sh
+dir=$(dirname "$0")
+while test $# -gt 1
+do
+    test "$1" = --output && output=$2
+    shift
+done
+echo "$1" >>"$dir/urls"
+test -e "$dir/offline" && echo "could not resolve host" >&2 && exit 6
+file="$dir/served/${1#https://example.com/}"
+test -e "$file" || { echo "404 not found" >&2; exit 22; }
+cp "$file" "$output"
+"#;
+    std::fs::write(&curl, script)?;
+    std::fs::set_permissions(&curl, std::fs::Permissions::from_mode(0o755))?;
+    std::fs::write(
+        format!("{}/garden.yaml", served),
+        "variables:\n  name: first\n",
+    )?;
+
+    let path = format!("{}:{}", bin, std::env::var("PATH")?);
+    let cache = format!("{}/cache", root);
+    let url = "https://example.com/workspace/garden.yaml";
+    let garden = |args: &[&str]| -> Result<std::process::Output> {
+        let mut exec = assert_cmd::Command::cargo_bin("garden")?;
+        exec.env("PATH", &path)
+            .env("XDG_CACHE_HOME", &cache)
+            .args(["--chdir", &root, "--quiet"])
+            .args(args);
+        Ok(exec.output()?)
+    };
+    let fetched = || -> Result<usize> {
+        Ok(std::fs::read_to_string(format!("{}/urls", bin))?
+            .lines()
+            .count())
+    };
+
+    // Commands that do not read the configuration do not fetch it.
+    let output = garden(&["--config", url, "completion", "bash"])?;
+    assert!(output.status.success());
+    assert!(!std::path::Path::new(&format!("{}/urls", bin)).exists());
+
+    let output = garden(&["--config", url, "eval", "${name}"])?;
+    assert_eq!(String::from_utf8(output.stdout)?, "first\n");
+    assert_eq!(fetched()?, 1);
+    let cached = format!(
+        "{}/garden/configs/{}",
+        cache,
+        garden::config::cache_name(url)
+    );
+    assert!(std::path::Path::new(&cached).exists());
+
+    // The cached copy is used until "--refresh" is specified.
+    std::fs::write(
+        format!("{}/garden.yaml", served),
+        "variables:\n  name: second\n",
+    )?;
+    let output = garden(&["--config", url, "eval", "${name}"])?;
+    assert_eq!(String::from_utf8(output.stdout)?, "first\n");
+    assert_eq!(fetched()?, 1);
+    let output = garden(&["--config", url, "--refresh", "eval", "${name}"])?;
+    assert_eq!(String::from_utf8(output.stdout)?, "second\n");
+    assert_eq!(fetched()?, 2);
+
+    // The cached copy is used when the URL cannot be fetched.
+    std::fs::write(format!("{}/offline", bin), "")?;
+    let output = garden(&["--config", url, "--refresh", "eval", "${name}"])?;
+    assert_eq!(String::from_utf8(output.stdout)?, "second\n");
+    let output = garden(&["--config", "https://example.com/missing.yaml", "eval", "x"])?;
+    assert_eq!(output.status.code(), Some(78), "EX_CONFIG");
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("unable to fetch https://example.com/missing.yaml"));
+
+    Ok(())
+}
+
+/// Relative includes and ${GARDEN_CONFIG_DIR} in remote garden files resolve against the URL.
+#[cfg(unix)]
+#[test]
+#[named]
+fn config_url_includes() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let root = std::path::absolute(&root)?.to_string_lossy().to_string();
+    let bin = format!("{}/bin", root);
+    let served = format!("{}/served/workspace", bin);
+    std::fs::create_dir_all(format!("{}/shared", served))?;
+    let curl = format!("{}/curl", bin);
+    let script = r#"#!/bin/sh
+dir=$(dirname "$0")
+while test $# -gt 1
+do
+    test "$1" = --output && output=$2
+    shift
+done
+cp "$dir/served/${1#https://example.com/}" "$output"
+"#;
+    std::fs::write(&curl, script)?;
+    std::fs::set_permissions(&curl, std::fs::Permissions::from_mode(0o755))?;
+    let config = r#"
+includes:
+  - shared/common.yaml
+  - ${GARDEN_CONFIG_DIR}/extra.yaml
+variables:
+  name: main
+"#;
+    std::fs::write(format!("{}/garden.yaml", served), config)?;
+    let common = r#"
+includes:
+  - ../extra.yaml
+variables:
+  common: shared
+"#;
+    std::fs::write(format!("{}/shared/common.yaml", served), common)?;
+    std::fs::write(
+        format!("{}/extra.yaml", served),
+        "variables:\n  extra: value\n",
+    )?;
+
+    let path = format!("{}:{}", bin, std::env::var("PATH")?);
+    let cache = format!("{}/cache", root);
+    let output = assert_cmd::Command::cargo_bin("garden")?
+        .env("PATH", &path)
+        .env("XDG_CACHE_HOME", &cache)
+        .args(["--chdir", &root, "--quiet"])
+        .args(["--config", "https://example.com/workspace/garden.yaml"])
+        .args(["eval", "${name} ${common} ${extra} ${GARDEN_CONFIG_DIR}"])
+        .output()?;
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "main shared value https://example.com/workspace\n"
+    );
+
+    Ok(())
+}
+
+/// User defaults in $XDG_CONFIG_HOME/garden/garden.yaml are layered under the project.
+#[test]
+#[named]
+fn config_user_defaults() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let user_dir = format!("{}/xdg/garden", root);
+    std::fs::create_dir_all(&user_dir)?;
+    let user_config = r#"
+variables:
+  greeting: user
+  name: user
+commands:
+  hello: echo ${greeting} ${name}
+  status: echo user-status
+gardens:
+  personal:
+    trees: project
+"#;
+    std::fs::write(format!("{}/garden.yaml", user_dir), user_config)?;
+    let config = r#"
+variables:
+  name: project
+commands:
+  status: echo project-status
+trees:
+  project:
+    path: .
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    // Project variables and commands take precedence over the user defaults.
+    // XDG_CONFIG_HOME must be an absolute path.
+    let xdg_home = std::fs::canonicalize(fixture.path("xdg"))?;
+    for (args, expect) in [
+        (&["cmd", "project", "hello"][..], "user project"),
+        (&["cmd", "project", "status"], "project-status"),
+        (&["eval", "${greeting}", "project", "personal"], "user"),
+    ] {
+        let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+        cmd.env("XDG_CONFIG_HOME", &xdg_home)
+            .env_remove("GARDEN_NO_USER_CONFIG")
+            .args(["--chdir", &root, "--quiet"])
+            .args(args);
+        let output = cmd.assert().success().get_output().clone();
+        assert_eq!(String::from_utf8(output.stdout)?.trim_end(), expect);
+    }
+
+    // "$GARDEN_CONFIG_PATH" directories are searched for configuration files.
+    std::fs::write(format!("{}/other.yaml", user_dir), config)?;
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.env("GARDEN_CONFIG_PATH", xdg_home.join("garden"))
+        .env("GARDEN_NO_USER_CONFIG", "1")
+        .args([
+            "--chdir",
+            &root,
+            "--config",
+            "other.yaml",
+            "eval",
+            "${name}",
+        ]);
+    let output = cmd.assert().success().get_output().clone();
+    assert_eq!(String::from_utf8(output.stdout)?.trim_end(), "project");
+
+    Ok(())
+}
+
+/// "garden.env-filter", "--preserve-env" and "--clean-env" control the environment
+/// that is inherited by "garden exec".
+#[test]
+#[named]
+fn exec_env_passthrough() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+garden:
+  env-filter: [PATH, "GARDEN_KEEP_*"]
+trees:
+  tree:
+    path: .
+    environment:
+      TREE_ENV: tree
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    let script = "echo ${TREE_ENV:-x} ${GARDEN_KEEP_ME:-x} ${GARDEN_DROP_ME:-x}";
+    for (option, expect) in [
+        (None, "tree keep x"),
+        (Some("--preserve-env"), "tree keep drop"),
+        (Some("--clean-env"), "tree x x"),
+    ] {
+        let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+        cmd.env("GARDEN_KEEP_ME", "keep")
+            .env("GARDEN_DROP_ME", "drop")
+            .args(["--chdir", &root, "--quiet", "exec"])
+            .args(option)
+            .args(["tree", "/bin/sh", "-c", script]);
+        let output = cmd.assert().success().get_output().clone();
+        assert_eq!(String::from_utf8(output.stdout)?.trim_end(), expect);
+    }
+
+    Ok(())
+}
+
+/// "--log-dir" writes the output of each tree into "<log-dir>/<tree>.log".
+#[test]
+#[named]
+fn exec_log_dir() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  example:
+    url: repos/example.git
+  nested/example:
+    url: repos/example.git
+commands:
+  greet: |
+    echo hello ${TREE_NAME}
+    echo warning ${TREE_NAME} >&2
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    // Relative paths are relative to the current directory, not to "--chdir".
+    // The fixture root is a relative path so "log_dir" is relative as well.
+    let log_dir = format!("{}/logs", root);
+    assert!(std::path::Path::new(&log_dir).is_relative());
+    exec_garden(&["--chdir", &root, "grow", "--log-dir", &log_dir, "*"])?;
+    assert!(!std::path::Path::new(&format!("{}/{}", root, log_dir)).exists());
+    let output = garden_capture(&[
+        "--chdir",
+        &root,
+        "--quiet",
+        "exec",
+        "--log-dir",
+        &log_dir,
+        "*",
+        "echo",
+        "exec",
+    ]);
+    assert_eq!(output, "exec\nexec");
+    let output = garden_capture(&[
+        "--chdir",
+        &root,
+        "--quiet",
+        "cmd",
+        "-j",
+        "2",
+        "--log-dir",
+        &log_dir,
+        "*",
+        "greet",
+    ]);
+    assert!(output.contains("example: hello example"));
+    assert!(output.contains("nested/example: hello nested/example"));
+
+    let log = std::fs::read_to_string(format!("{}/example.log", log_dir))?;
+    let lines: Vec<&str> = log.lines().collect();
+    assert!(lines[0].starts_with("git clone"));
+    assert_eq!(lines[1], "exec");
+    assert!(lines.contains(&"hello example"));
+    assert!(lines.contains(&"warning example"));
+    // Path separators in tree names are replaced so logs stay inside of the log directory.
+    let log = std::fs::read_to_string(format!("{}/nested_example.log", log_dir))?;
+    assert!(log.contains("hello nested/example\n"));
+    assert!(!log.contains("hello example\n"));
+    assert!(!std::path::Path::new(&format!("{}/nested", log_dir)).exists());
+
+    // Captured stdout and stderr are interleaved in the log as they are written.
+    let script = "echo one; sleep 0.2; echo two >&2; sleep 0.2; echo three";
+    exec_garden(&[
+        "--chdir",
+        &root,
+        "--quiet",
+        "exec",
+        "--capture",
+        "--log-dir",
+        &log_dir,
+        "example",
+        "sh",
+        "-c",
+        script,
+    ])?;
+    let log = std::fs::read_to_string(format!("{}/example.log", log_dir))?;
+    assert!(log.ends_with("one\ntwo\nthree\n"));
+
+    Ok(())
+}
+
+/// "garden exec --grow-missing" grows missing trees before running the command.
+#[test]
+#[named]
+fn exec_grow_missing() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  example:
+    url: repos/example.git
+  local:
+    path: .
+gardens:
+  all:
+    trees: [example, local]
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    // Missing trees are skipped by default.
+    let output = garden_capture(&["--chdir", &root, "--quiet", "exec", "all", "pwd"]);
+    assert_eq!(output.lines().count(), 1);
+    assert!(!std::path::Path::new(&format!("{}/example", root)).exists());
+
+    let output = garden_capture(&[
+        "--chdir",
+        &root,
+        "--quiet",
+        "exec",
+        "--grow-missing",
+        "all",
+        "git",
+        "rev-parse",
+        "--show-toplevel",
+    ]);
+    fixture.worktree("example");
+    assert_eq!(output.lines().count(), 2);
+    assert!(output
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .ends_with("/example"));
+
+    // Trees that fail to grow set the exit status under --keep-going.
+    let config = r#"
+trees:
+  broken:
+    url: file:///nonexistent/repo.git
+  local:
+    path: .
+"#;
+    std::fs::write(format!("{}/broken.yaml", root), config)?;
+    for jobs in ["1", "2"] {
+        let args = [
+            "--chdir",
+            &root,
+            "--config",
+            "broken.yaml",
+            "--quiet",
+            "exec",
+            "--grow-missing",
+            "--keep-going",
+            "--jobs",
+            jobs,
+            "*",
+            "true",
+        ];
+        let output = std::process::Command::cargo_bin("garden")?
+            .args(args)
+            .output()?;
+        assert_eq!(output.status.code(), Some(128), "jobs: {jobs}");
+    }
+
+    // The failing hook and clone commands are reported in "failures".
+    let config = r#"
+hooks:
+  pre-grow: test ${TREE_NAME} != hooked
+trees:
+  hooked:
+    url: file:///nonexistent/hooked.git
+  broken:
+    url: file:///nonexistent/repo.git
+"#;
+    std::fs::write(format!("{}/broken.yaml", root), config)?;
+    let output = std::process::Command::cargo_bin("garden")?
+        .args(["--chdir", &root, "--config", "broken.yaml", "--quiet"])
+        .args([
+            "exec",
+            "--grow-missing",
+            "-k",
+            "--format",
+            "json",
+            "*",
+            "true",
+        ])
+        .output()?;
+    let doc: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(doc["failures"][0]["tree"], "hooked");
+    assert_eq!(doc["failures"][0]["command"], "test hooked != hooked");
+    assert_eq!(doc["failures"][1]["tree"], "broken");
+    let command = doc["failures"][1]["command"].as_str().unwrap_or_default();
+    assert!(command.starts_with("git clone"));
+    assert!(command.contains("file:///nonexistent/repo.git"));
+
+    Ok(())
+}
+
+/// "garden exec --chdir-root" runs commands from the garden root.
+#[test]
+#[named]
+fn exec_chdir_root() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  app:
+    path: apps/app
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    std::fs::create_dir_all(format!("{}/apps/app", root))?;
+    let script = r#"echo "$(basename "$PWD") ${TREE_NAME:-x} $(basename "${TREE_PATH:-x}")""#;
+
+    let output = garden_capture(&[
+        "--chdir", &root, "--quiet", "exec", "app", "sh", "-c", script,
+    ]);
+    assert_eq!(output, "app x x");
+
+    let output = garden_capture(&[
+        "--chdir",
+        &root,
+        "--quiet",
+        "exec",
+        "--chdir-root",
+        "app",
+        "sh",
+        "-c",
+        script,
+    ]);
+    assert_eq!(output, format!("{} app app", function_name!()));
+
+    let output = garden_capture(&[
+        "--chdir",
+        &root,
+        "--quiet",
+        "exec",
+        "--chdir-root",
+        "--capture",
+        "app",
+        "sh",
+        "-c",
+        script,
+    ]);
+    assert_eq!(output, format!("{} app app", function_name!()));
+
+    Ok(())
+}
+
+/// "garden exec --capture" groups the output of each tree.
+#[test]
+#[named]
+fn exec_capture() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+templates:
+  named:
+    environment:
+      NAME: ${TREE_NAME}
+trees:
+  a: {path: ., templates: named}
+  b: {path: ., templates: named}
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    let script = "echo $NAME; sleep 0.1; echo $NAME done; test $NAME != b";
+
+    // Output is grouped by tree even when the trees run in parallel.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args([
+        "--chdir",
+        &root,
+        "--color=off",
+        "exec",
+        "--capture",
+        "-k",
+        "-j",
+        "2",
+    ])
+    .args(["*", "sh", "-c", script]);
+    let output = cmd.assert().failure().code(1).get_output().clone();
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "# a\na\na done\n# b  exit status 1\nb\nb done\n"
+    );
+
+    // "--format json" records the output and exit status of each tree.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "exec", "--format", "json", "-k", "*"])
+        .args(["sh", "-c", script]);
+    let output = cmd.assert().failure().code(1).get_output().clone();
+    let doc: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(doc["trees"][0]["name"], "a");
+    assert_eq!(doc["trees"][0]["exit_status"], 0);
+    assert_eq!(doc["trees"][0]["stdout"], "a\na done\n");
+    assert_eq!(doc["trees"][1]["name"], "b");
+    assert_eq!(doc["trees"][1]["exit_status"], 1);
+    // The failing trees are reported in "failures".
+    assert_eq!(doc["failures"].as_array().map(Vec::len), Some(1));
+    assert_eq!(doc["failures"][0]["tree"], "b");
+    assert_eq!(doc["failures"][0]["command"], format!("sh -c {}", script));
+    assert_eq!(doc["failures"][0]["exit_status"], 1);
+
+    Ok(())
+}
+
+/// "garden exec --interactive" passes the terminal through to a command in a single tree.
+#[test]
+#[named]
+fn exec_interactive() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  a: {path: .}
+  b: {path: .}
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    // Interactive commands read from garden's stdin and write to its stdout.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "--quiet", "exec", "--interactive", "a"])
+        .args(["sh", "-c", "read line; echo \"got $line\""])
+        .write_stdin("hello\n");
+    let output = cmd.assert().success().get_output().clone();
+    assert_eq!(String::from_utf8(output.stdout)?, "got hello\n");
+
+    // The exit status of the interactive command is returned.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "exec", "-i", "a", "sh", "-c", "exit 3"]);
+    cmd.assert().failure().code(3);
+
+    // Interactive commands run in a single tree.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "exec", "--interactive", "*", "true"]);
+    let output = cmd
+        .assert()
+        .failure()
+        .code(garden::errors::EX_USAGE)
+        .get_output()
+        .clone();
+    assert!(String::from_utf8(output.stderr)?.contains("requires a single tree"));
+
+    // Trees matched more than once run once. Trees in grafts are distinct trees.
+    let config = r#"
+grafts:
+  graft: graft.yaml
+trees:
+  a: {path: .}
+groups:
+  one: [a]
+  two: [a]
+gardens:
+  both:
+    groups: [one, two]
+"#;
+    std::fs::write(format!("{}/interactive.yaml", root), config)?;
+    std::fs::write(format!("{}/graft.yaml", root), "trees:\n  a: {path: .}\n")?;
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "--config", "interactive.yaml", "--quiet"])
+        .args(["exec", "-i", "both", "echo", "run"]);
+    let output = cmd.assert().success().get_output().clone();
+    assert_eq!(String::from_utf8(output.stdout)?, "run\n");
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "--config", "interactive.yaml"])
+        .args(["exec", "-i", "*", "true"]);
+    let output = cmd
+        .assert()
+        .failure()
+        .code(garden::errors::EX_USAGE)
+        .get_output()
+        .clone();
+    assert!(String::from_utf8(output.stderr)?.contains("2 trees matched"));
+
+    // Output capture and parallel jobs cannot be used with "--interactive".
+    for option in ["--capture", "--jobs=2", "--format=json", "--timeout=1s"] {
+        let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+        cmd.args([
+            "--chdir",
+            &root,
+            "exec",
+            "--interactive",
+            option,
+            "a",
+            "true",
+        ]);
+        cmd.assert().failure().code(2);
+    }
+
+    Ok(())
+}
+
+/// Test "garden import" and "garden export" with vcstool and myrepos files.
+#[test]
+#[named]
+fn cmd_import_export() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    std::fs::write(format!("{}/garden.yaml", root), "trees: {}\n")?;
+    let repos = r#"
+repositories:
+  src/app:
+    type: git
+    url: https://example.com/app.git
+    version: main
+  src/svn:
+    type: svn
+    url: https://example.com/svn
+"#;
+    std::fs::write(format!("{}/deps.repos", root), repos)?;
+    let mrconfig = r#"[DEFAULT]
+jobs = 4
+
+[lib/core]
+checkout = git clone -b dev 'https://example.com/core.git' 'core'
+
+[lib/docs]
+update = true
+"#;
+    std::fs::write(format!("{}/.mrconfig", root), mrconfig)?;
+
+    exec_garden(&["--chdir", &root, "--quiet", "import", "deps.repos"])?;
+    exec_garden(&[
+        "--chdir",
+        &root,
+        "--quiet",
+        "import",
+        "--format",
+        "mr",
+        ".mrconfig",
+    ])?;
+    let output = std::fs::read_to_string(format!("{}/garden.yaml", root))?;
+    assert!(output.contains("src/app:"));
+    assert!(output.contains("url: \"https://example.com/app.git\""));
+    assert!(output.contains("lib/core:"));
+    assert!(output.contains("branch: dev"));
+    assert!(!output.contains("src/svn"));
+    assert!(!output.contains("lib/docs"));
+
+    let output = garden_capture(&["--chdir", &root, "--quiet", "export"]);
+    let expect = r#"---
+repositories:
+  src/app:
+    type: git
+    url: "https://example.com/app.git"
+    version: main
+  lib/core:
+    type: git
+    url: "https://example.com/core.git"
+    version: dev"#;
+    assert_eq!(output, expect);
+
+    let output = garden_capture(&[
+        "--chdir", &root, "--quiet", "export", "--format", "mr", "lib/core",
+    ]);
+    let expect = "[lib/core]\ncheckout = git clone --branch dev https://example.com/core.git core";
+    assert_eq!(output, expect);
+
+    // The url of the tree's "default-remote" is exported.
+    let config = r#"
+trees:
+  fork:
+    default-remote: fork
+    remotes:
+      upstream: https://example.com/upstream.git
+      fork: https://example.com/fork.git
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    let output = garden_capture(&["--chdir", &root, "--quiet", "export", "--format", "mr"]);
+    let expect = "[fork]\ncheckout = git clone https://example.com/fork.git fork";
+    assert_eq!(output, expect);
+
+    Ok(())
+}
+
+/// "garden shell" without a query chooses a tree interactively.
+#[test]
+#[named]
+fn cmd_shell_picker() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+garden:
+  shell: pwd
+trees:
+  one:
+    path: one
+  two:
+    path: two
+  missing:
+    path: missing
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    std::fs::create_dir_all(format!("{}/one", fixture.root()))?;
+    std::fs::create_dir_all(format!("{}/two", fixture.root()))?;
+    let root = fixture.root();
+
+    // Trees can be chosen by number.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "shell"]).write_stdin("2\n");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let expect = fixture.pathbuf("two").canonicalize()?;
+    assert_eq!(String::from_utf8(output)?.trim(), expect.to_string_lossy());
+
+    // Trees can be chosen by name.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "shell"]).write_stdin("one\n");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let expect = fixture.pathbuf("one").canonicalize()?;
+    assert_eq!(String::from_utf8(output)?.trim(), expect.to_string_lossy());
+
+    // Trees that do not exist on disk cannot be chosen.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "shell"])
+        .write_stdin("missing\n");
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+/// "garden cd" prints tree paths for the "garden shell-init" shell functions.
+#[test]
+#[named]
+fn cmd_cd_and_shell_init() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+trees:
+  one:
+    path: one
+  missing:
+    path: missing
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    std::fs::create_dir_all(format!("{}/one", fixture.root()))?;
+    let root = fixture.root();
+    let expect = fixture.pathbuf("one").canonicalize()?;
+
+    let output = garden_capture(&["--chdir", &root, "cd", "one"]);
+    assert_eq!(output, expect.to_string_lossy());
+
+    // Trees must exist on disk.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "cd", "missing"]);
+    cmd.assert().failure();
+
+    // The shell function changes directory into the tree.
+    let garden = assert_cmd::cargo::cargo_bin("garden");
+    let bin_dir = garden.parent().unwrap_or(std::path::Path::new("."));
+    let path = format!("{}:{}", bin_dir.display(), std::env::var("PATH")?);
+    let script = r#"eval "$(garden shell-init bash)" && garden cd one && pwd"#;
+    let output = std::process::Command::new("bash")
+        .args(["-c", script])
+        .current_dir(&root)
+        .env("PATH", path)
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout)?.trim(),
+        expect.to_string_lossy()
+    );
+
+    Ok(())
+}
+
+/// Commands run in their "cwd" directory or in the "--directory" path.
+#[test]
+#[named]
+fn cmd_directory() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+commands:
+  ui:
+    run: basename "$(pwd)"
+    cwd: ui
+  root:
+    run: basename "$(pwd)"
+    cwd: ${GARDEN_ROOT}/docs
+trees:
+  app:
+    path: app
+    commands:
+      here: basename "$(pwd)"
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    for path in ["app/ui", "app/tests", "docs"] {
+        std::fs::create_dir_all(format!("{}/{}", fixture.root(), path))?;
+    }
+    let root = fixture.root();
+
+    let output = garden_capture(&[
+        "--chdir", &root, "--quiet", "cmd", "app", "ui", "root", "here",
+    ]);
+    assert_eq!(output, "ui\ndocs\napp");
+
+    // --directory overrides the "cwd" setting.
+    let output = garden_capture(&[
+        "--chdir",
+        &root,
+        "--quiet",
+        "cmd",
+        "--directory",
+        "tests",
+        "app",
+        "ui",
+        "here",
+    ]);
+    assert_eq!(output, "tests\ntests");
+
+    Ok(())
+}
+
+/// ${GROUP_NAME} is available to tree variables that are used by "cwd".
+#[test]
+#[named]
+fn cmd_group_name_cwd() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+commands:
+  where:
+    run: basename "$(pwd)"
+    cwd: ${subdir}
+trees:
+  app:
+    path: app
+    variables:
+      subdir: ${GROUP_NAME}-dir
+groups:
+  alpha: app
+  beta: app
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    for path in ["app/alpha-dir", "app/beta-dir"] {
+        std::fs::create_dir_all(format!("{}/{}", root, path))?;
+    }
+
+    let output = garden_capture(&["--chdir", &root, "--quiet", "where", "%alpha", "%beta"]);
+    assert_eq!(output, "alpha-dir\nbeta-dir");
+
+    let cmd = [
+        "--chdir",
+        &root,
+        "eval",
+        "--all-trees",
+        "${subdir}",
+        "%beta",
+    ];
+    let output = garden_capture(&cmd);
+    assert!(output.contains("beta-dir"));
+
+    let cmd = ["--chdir", &root, "inspect", "--format", "json", "%beta"];
+    let output = garden_capture(&cmd);
+    assert!(output.contains("\"subdir\": \"beta-dir\""));
+
+    Ok(())
+}
+
+/// Tree, group and garden metadata is available through built-in variables.
+#[test]
+#[named]
+fn cmd_builtin_variables() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+commands:
+  info: echo "${GARDEN_NAME}:${GROUP_NAME}:${TREE_NAME}:${TREE_URL}:${TREE_BRANCH}"
+variables:
+  host: example.com
+trees:
+  tree:
+    path: .
+    url: https://${host}/tree.git
+    branch: dev
+  other:
+    path: .
+    url: https://${host}/origin.git
+    default-remote: upstream
+    remotes:
+      upstream: https://${host}/upstream.git
+groups:
+  group: tree
+gardens:
+  garden:
+    groups: group
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    let output = garden_capture(&["--chdir", &root, "--quiet", "info", "tree"]);
+    assert_eq!(output, "::tree:https://example.com/tree.git:dev");
+    let output = garden_capture(&["--chdir", &root, "--quiet", "info", "%group"]);
+    assert_eq!(output, ":group:tree:https://example.com/tree.git:dev");
+    let output = garden_capture(&["--chdir", &root, "--quiet", "info", ":garden"]);
+    assert_eq!(output, "garden:group:tree:https://example.com/tree.git:dev");
+    // TREE_URL is the url of the default remote.
+    let output = garden_capture(&["--chdir", &root, "--quiet", "info", "other"]);
+    assert_eq!(output, "::other:https://example.com/upstream.git:");
+
+    Ok(())
+}
+
+/// ${GARDEN_CMD_NAME}, ${GARDEN_CMD_QUIET} and ${GARDEN_CMD_VERBOSE} are available to commands.
+#[test]
+#[named]
+fn cmd_command_variables() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+commands:
+  first: echo "${GARDEN_CMD_NAME}:${GARDEN_CMD_QUIET}:${GARDEN_CMD_VERBOSE}"
+  second: echo "${GARDEN_CMD_NAME}"
+trees:
+  tree:
+    path: .
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    let output = garden_capture(&[
+        "--chdir", &root, "--quiet", "cmd", "tree", "first", "second",
+    ]);
+    assert_eq!(output, "first:--quiet:\nsecond");
+    let output = garden_capture(&["--chdir", &root, "-vv", "first", "tree"]);
+    assert!(output.lines().any(|line| line == "first::-vv"));
+    let output = garden_capture(&["--chdir", &root, "eval", "[${GARDEN_CMD_VERBOSE}]"]);
+    assert_eq!(output, "[]");
+
+    Ok(())
+}
+
+/// "garden cmd --scope" runs the command definitions from a single scope.
+#[test]
+#[named]
+fn cmd_scope() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+commands:
+  info: echo config
+trees:
+  tree:
+    path: .
+    commands:
+      info: echo tree
+gardens:
+  garden:
+    trees: tree
+    commands:
+      info: echo garden
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    let hooks_config = format!(
+        "{}hooks:\n  pre-cmd: echo pre\n  post-cmd: echo post\n",
+        config
+    );
+    std::fs::write(format!("{}/hooks.yaml", root), hooks_config)?;
+
+    // Definitions run in the order config, tree and then garden.
+    let output = garden_capture(&["--chdir", &root, "--quiet", "cmd", ":garden", "info"]);
+    assert_eq!(output, "config\ntree\ngarden");
+    for scope in ["config", "tree", "garden"] {
+        let args = [
+            "--chdir", &root, "--quiet", "cmd", "--scope", scope, ":garden", "info",
+        ];
+        assert_eq!(garden_capture(&args), scope);
+    }
+    // Custom commands support "--scope".
+    let output = garden_capture(&[
+        "--chdir", &root, "--quiet", "info", "--scope", "tree", ":garden",
+    ]);
+    assert_eq!(output, "tree");
+    // Hooks run around the definitions from the selected scope.
+    for scope in ["config", "tree", "garden"] {
+        let args = [
+            "--chdir",
+            &root,
+            "--config",
+            "hooks.yaml",
+            "--quiet",
+            "cmd",
+            "--scope",
+            scope,
+            ":garden",
+            "info",
+        ];
+        assert_eq!(garden_capture(&args), format!("pre\n{}\npost", scope));
+    }
+    // The scopes that ran are reported when "--verbose" is used.
+    let output = garden_capture(&["--chdir", &root, "--verbose", "info", ":garden"]);
+    assert!(output
+        .lines()
+        .any(|line| line == "# info: config, tree, garden"));
+
+    Ok(())
+}
+
+/// "garden cmd --list" prints the resolved commands without running them.
+#[test]
+#[named]
+fn cmd_list() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+commands:
+  probe: |
+    false
+    touch probed
+trees:
+  tree:
+    path: .
+    commands:
+      build:
+        cwd: src
+        run: touch built
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    let output = garden_capture(&[
+        "--chdir",
+        &root,
+        "--quiet",
+        "cmd",
+        "--list",
+        "--no-errexit",
+        "tree",
+        "build",
+        "probe",
+    ]);
+    assert!(output.starts_with("tree:"));
+    assert!(output.contains("    build:\n"));
+    assert!(output.contains("        errexit: false\n"));
+    assert!(output.contains("/src\n"));
+    assert!(output.contains("            touch built\n"));
+    assert!(output.contains("    probe:\n"));
+    assert!(output.contains("            false\n            touch probed"));
+    // Nothing was run.
+    assert!(!std::path::Path::new(&format!("{}/built", root)).exists());
+    assert!(!std::path::Path::new(&format!("{}/probed", root)).exists());
+
+    // --no-errexit runs the remaining statements after a failing probe.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "--quiet", "cmd", "tree", "probe"]);
+    cmd.assert().failure();
+    assert!(!std::path::Path::new(&format!("{}/probed", root)).exists());
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args([
+        "--chdir",
+        &root,
+        "--quiet",
+        "cmd",
+        "--no-errexit",
+        "tree",
+        "probe",
+    ]);
+    cmd.assert().success();
+    assert!(std::path::Path::new(&format!("{}/probed", root)).exists());
+
+    Ok(())
+}
+
+/// "--timeout" and the "timeout" command setting terminate long-running commands.
+#[test]
+#[named]
+fn cmd_timeout() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+commands:
+  fast: echo fast
+  slow:
+    run: sleep 10; echo slow
+    timeout: 0.5s
+trees:
+  tree:
+    path: .
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    let start = std::time::Instant::now();
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "--quiet", "cmd", "tree", "slow"]);
+    let output = cmd.assert().code(124).get_output().clone();
+    assert!(String::from_utf8(output.stderr)?.contains("timed out after 500ms"));
+
+    // The whole process group is terminated, including the output pipes held by "sleep".
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "--quiet", "exec", "--jobs", "2"])
+        .args([
+            "--timeout",
+            "1",
+            "tree",
+            "/bin/sh",
+            "-c",
+            "sleep 10; echo slow",
+        ]);
+    let output = cmd.assert().code(124).get_output().clone();
+    assert!(!String::from_utf8(output.stdout)?.contains("slow"));
+    assert!(start.elapsed() < std::time::Duration::from_secs(8));
+
+    // Commands that complete before the timeout are unaffected.
+    let output = garden_capture(&[
+        "--chdir",
+        &root,
+        "--quiet",
+        "cmd",
+        "--timeout",
+        "5s",
+        "tree",
+        "fast",
+    ]);
+    assert_eq!(output, "fast");
+    let output = garden_capture(&[
+        "--chdir",
+        &root,
+        "--quiet",
+        "exec",
+        "--timeout",
+        "5s",
+        "tree",
+        "echo",
+        "exec",
+    ]);
+    assert_eq!(output, "exec");
+
+    // Invalid durations are rejected.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args([
+        "--chdir",
+        &root,
+        "exec",
+        "--timeout",
+        "soon",
+        "tree",
+        "true",
+    ]);
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+/// "env-file" entries are loaded into the environment used by commands.
+#[test]
+#[named]
+fn exec_env_file() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  tree:
+    path: tree
+    env-file: .env.local
+    environment:
+      OVERRIDE=: tree
+gardens:
+  garden:
+    trees: tree
+    env-file:
+      - garden.env
+      - missing.env
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    std::fs::create_dir_all(format!("{}/tree", root))?;
+    std::fs::write(
+        format!("{}/tree/.env.local", root),
+        "TREE_ENV=\"from tree\"\nOVERRIDE=env-file\n",
+    )?;
+    std::fs::write(format!("{}/garden.env", root), "GARDEN_ENV=garden\n")?;
+
+    // Values from the tree's env file are used and "environment" takes precedence.
+    let output = garden_capture(&[
+        "--chdir",
+        &root,
+        "--quiet",
+        "exec",
+        "tree",
+        "sh",
+        "-c",
+        "echo \"$TREE_ENV:$OVERRIDE:$GARDEN_ENV\"",
+    ]);
+    assert_eq!(output, "from tree:tree:");
+
+    // Garden env files are loaded when the tree is used through a garden.
+    let output = garden_capture(&[
+        "--chdir",
+        &root,
+        "--quiet",
+        "exec",
+        "garden",
+        "sh",
+        "-c",
+        "echo \"$TREE_ENV:$GARDEN_ENV\"",
+    ]);
+    assert_eq!(output, "from tree:garden");
+
+    Ok(())
+}
+
+/// Grafts can be cloned from Git URLs and pinned to a ref.
+#[test]
+#[named]
+fn graft_remote_url() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let abs_root = std::path::absolute(&root)?.to_string_lossy().to_string();
+    let remote = format!("{}/remote", root);
+    let commit_version = |version: &str| {
+        let config = format!(
+            "trees:\n  shared:\n    path: .\n    commands:\n      version: echo {} ${{GARDEN_ROOT}}\n",
+            version
+        );
+        std::fs::write(format!("{}/garden.yaml", remote), config).unwrap();
+        assert_cmd(&["git", "add", "garden.yaml"], &remote);
+        assert_cmd(&["git", "commit", "--quiet", "-m", version], &remote);
+    };
+    std::fs::create_dir_all(&remote)?;
+    assert_cmd(&["git", "init", "--quiet"], &remote);
+    commit_version("v1");
+    assert_cmd(&["git", "tag", "v1"], &remote);
+    commit_version("v2");
+
+    let config = format!(
+        r#"
+grafts:
+  pinned:
+    config: file://{0}/remote
+    ref: v1
+  latest: file://{0}/remote
+"#,
+        abs_root
+    );
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    std::fs::create_dir_all(format!("{}/pinned", root))?;
+    std::fs::create_dir_all(format!("{}/latest", root))?;
+    let cache = format!("{}/cache", abs_root);
+    let garden = |args: &[&str]| -> Result<String> {
+        let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+        cmd.env("XDG_CACHE_HOME", &cache)
+            .args(["--chdir", &root, "--quiet"])
+            .args(args);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        Ok(String::from_utf8(output)?.trim_end().to_string())
+    };
+
+    // Trees are grown inside of a directory named after the graft by default.
+    // Remote grafts are cloned when a query uses them for the first time.
+    let output = garden(&["cmd", "pinned::shared", "version"])?;
+    assert_eq!(output, format!("v1 {}/pinned", abs_root));
+    let cloned =
+        || -> Result<usize> { Ok(std::fs::read_dir(format!("{}/garden/grafts", cache))?.count()) };
+    assert_eq!(cloned()?, 1);
+    let output = garden(&["cmd", "latest::shared", "version"])?;
+    assert_eq!(output, format!("v2 {}/latest", abs_root));
+
+    // "garden cache update" updates the grafts that are not pinned to a tag.
+    commit_version("v3");
+    let output = garden(&["cmd", "latest::shared", "version"])?;
+    assert_eq!(output, format!("v2 {}/latest", abs_root));
+    garden(&["cache", "update"])?;
+    let output = garden(&["cmd", "latest::shared", "version"])?;
+    assert_eq!(output, format!("v3 {}/latest", abs_root));
+    let output = garden(&["cmd", "pinned::shared", "version"])?;
+    assert_eq!(output, format!("v1 {}/pinned", abs_root));
+
+    Ok(())
+}
+
+/// SIGTERM is forwarded to the running command and no further trees are visited.
+#[cfg(unix)]
+#[test]
+#[named]
+fn cmd_signals() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+commands:
+  slow: echo $$$$ >> pids; exec sleep 30
+trees:
+  tree1:
+    path: .
+  tree2:
+    path: .
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    let pids = format!("{}/pids", root);
+
+    let start = std::time::Instant::now();
+    let mut child = std::process::Command::cargo_bin("garden")?
+        .args(["--chdir", &root, "--quiet", "cmd", "tree*", "slow"])
+        .spawn()?;
+    while !std::fs::read_to_string(&pids).is_ok_and(|pids| pids.ends_with('\n')) {
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    std::process::Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()?;
+    let status = child.wait()?;
+    assert_eq!(status.code(), Some(143));
+    assert!(start.elapsed() < std::time::Duration::from_secs(20));
+
+    // The command was terminated and the second tree was never visited.
+    let output = std::fs::read_to_string(&pids)?;
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].parse::<u32>().is_ok());
+    let is_running = std::process::Command::new("kill")
+        .args(["-0", lines[0]])
+        .stderr(std::process::Stdio::null())
+        .status()?
+        .success();
+    assert!(!is_running);
+
+    Ok(())
+}
+
+/// Test directory-scoped tree queries.
+#[test]
+#[named]
+fn cmd_path_queries() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+commands:
+  name: echo ${TREE_NAME}
+trees:
+  a:
+    path: services/a
+  b:
+    path: services/b
+  c:
+    path: services/b/c
+  other:
+    path: other
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    for path in ["services/a", "services/b/c", "other"] {
+        std::fs::create_dir_all(format!("{}/{}", fixture.root(), path))?;
+    }
+    let root = fixture.root();
+
+    let output = garden_capture(&["--chdir", &root, "--quiet", "cmd", "./services/...", "name"]);
+    assert_eq!(output, "a\nb\nc");
+
+    let output = garden_capture(&["--chdir", &root, "--quiet", "cmd", "services/b/...", "name"]);
+    assert_eq!(output, "b\nc");
+
+    // Glob patterns do not match across directories.
+    let output = garden_capture(&["--chdir", &root, "--quiet", "cmd", "./services/*", "name"]);
+    assert_eq!(output, "a\nb");
+
+    let output = garden_capture(&[
+        "--chdir",
+        &root,
+        "--quiet",
+        "exec",
+        "./services/*/c",
+        "echo",
+        "ok",
+    ]);
+    assert_eq!(output, "ok");
+
+    Ok(())
+}
+
+/// Test "garden check" diagnostics.
+#[test]
+#[named]
+fn cmd_check() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+variables:
+  a: ${b}
+  b: ${a}
+templates:
+  base:
+    url: https://example.com/base.git
+trees:
+  one:
+    path: one
+    templates: [base, missing]
+    urll: https://example.com/one.git
+  two:
+    path: one
+groups:
+  group: ["one", "[invalid"]
+gardens:
+  garden:
+    groups: undefined
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    let mut exec = assert_cmd::Command::cargo_bin("garden")?;
+    exec.args(["--chdir", &root, "--color", "never", "check"]);
+    let output = exec.output()?;
+    assert_eq!(output.status.code(), Some(65), "errors return EX_DATAERR");
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[0], "error: trees.one.urll: unknown key");
+    assert_eq!(
+        lines[1],
+        "error: trees.one.templates: undefined template: missing"
+    );
+    assert!(lines[2].starts_with("error: groups.group: invalid glob pattern '[invalid'"));
+    assert_eq!(
+        lines[3],
+        "error: gardens.garden.groups: undefined group: undefined"
+    );
+    assert!(lines[4].starts_with("warning: trees.two.path: duplicate path used by tree one: "));
+    assert!(lines[4].ends_with("/one"));
+    assert_eq!(
+        lines[5],
+        "error: variables.a: cyclic variable reference: a -> b -> a"
+    );
+
+    // Diagnostics can be reported as JSON.
+    let mut exec = assert_cmd::Command::cargo_bin("garden")?;
+    exec.args(["--chdir", &root, "check", "--format", "json"]);
+    let stdout = String::from_utf8(exec.output()?.stdout)?;
+    let diagnostics: serde_json::Value = serde_json::from_str(&stdout)?;
+    assert_eq!(diagnostics[0]["severity"], "error");
+    assert_eq!(diagnostics[0]["location"], "trees.one.urll");
+    assert_eq!(diagnostics[0]["message"], "unknown key");
+
+    Ok(())
+}
+
+/// Evaluation errors are reported with the location of the variable.
+#[test]
+#[named]
+fn eval_error_locations() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+variables:
+  name: value
+  broken: ${dirname(name, extra)}
+trees:
+  tree:
+    path: ${GARDEN_ROOT}
+    variables:
+      unterminated: ${name
+    environment:
+      BROKEN: prefix-${unterminated}
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    let mut exec = assert_cmd::Command::cargo_bin("garden")?;
+    exec.args(["--chdir", &root, "eval", "${broken}"]);
+    let output = exec.output()?;
+    assert_eq!(output.status.code(), Some(65), "errors return EX_DATAERR");
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains(
+            "garden.yaml:4: variables.broken: dirname() expects 1 argument but 2 were given"
+        ),
+        "{}",
+        stderr
+    );
+
+    let mut exec = assert_cmd::Command::cargo_bin("garden")?;
+    exec.args(["--chdir", &root, "eval", "${unterminated}", "tree"]);
+    let output = exec.output()?;
+    assert_eq!(output.status.code(), Some(65));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains(
+            "garden.yaml:9: trees.tree.variables.unterminated: unterminated \"${\" expression: ${name"
+        ),
+        "{}",
+        stderr
+    );
+
+    // Commands that evaluate the environment report errors as warnings and expand
+    // the variables that cannot be evaluated to empty strings.
+    let mut exec = assert_cmd::Command::cargo_bin("garden")?;
+    exec.args(["--chdir", &root, "env", "tree"]);
+    let output = exec.output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("export BROKEN=prefix-\n"));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("warning: ")
+            && stderr.contains("garden.yaml:9: trees.tree.variables.unterminated"),
+        "{}",
+        stderr
+    );
+
+    // "garden check" reports malformed expressions without evaluating them.
+    let mut exec = assert_cmd::Command::cargo_bin("garden")?;
+    exec.args(["--chdir", &root, "--color", "never", "check"]);
+    let output = exec.output()?;
+    assert_eq!(output.status.code(), Some(65));
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", stdout);
+    assert!(lines[0].starts_with("error: "));
+    assert!(lines[0].ends_with(
+        "garden.yaml:4: variables.broken: dirname() expects 1 argument but 2 were given: ${dirname(name, extra)}"
+    ));
+    assert!(lines[1].ends_with(
+        "garden.yaml:9: trees.tree.variables.unterminated: unterminated \"${\" expression: ${name"
+    ));
+
+    Ok(())
+}
+
+/// Test -n / --no-errexit and the shell "-e" behavior.
+#[test]
+fn cmd_no_errexit() {
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "cmd",
+        ".",
+        "error-command",
+    ]);
+    assert_eq!(output, "ok");
+
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "cmd",
+        "--no-errexit",
+        ".",
+        "error-command",
+    ]);
+    assert_eq!(output, "ok\nafter error");
+}
+
+#[test]
+fn cmd_no_errexit_for_command_lists() {
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "cmd",
+        ".",
+        "error-command-list",
+    ]);
+    assert_eq!(output, "ok");
+
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "cmd",
+        "--no-errexit",
+        ".",
+        "error-command-list",
+    ]);
+    assert_eq!(output, "ok\nafter error");
+}
+
+/// Test the interaction of --keep-going, --no-errexit and command lists.
+#[test]
+fn cmd_keep_going_and_no_errexit() {
+    // exit-on-error: true, keep-going: false, command: str
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "error-command",
+        "tree1",
+        "tree2",
+    ]);
+    assert_eq!(output, "ok");
+
+    // exit-on-error: false, keep-going: false, command: str
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "error-command",
+        "--no-errexit",
+        "tree1",
+        "tree2",
+    ]);
+    assert_eq!(output, "ok\nafter error");
+
+    // exit-on-error: true, keep-going: true, command: str
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "error-command",
+        "--keep-going",
+        "tree1",
+        "tree2",
+    ]);
+    assert_eq!(output, "ok\nok");
+
+    // exit-on-error: false, keep-going: true, command: str
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "error-command",
+        "--keep-going",
+        "--no-errexit",
+        "tree1",
+        "tree2",
+    ]);
+    assert_eq!(output, "ok\nafter error\nok\nafter error");
+
+    // Same as above but with error-command-list instead of error-command.
+    // exit-on-error: true, keep-going: false, command: list
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "error-command-list",
+        "tree1",
+        "tree2",
+    ]);
+    assert_eq!(output, "ok");
+
+    // exit-on-error: false, keep-going: false, command: list
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "error-command-list",
+        "--no-errexit",
+        "tree1",
+        "tree2",
+    ]);
+    assert_eq!(output, "ok\nafter error");
+
+    // exit-on-error: true, keep-going: true, command: list
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "error-command-list",
+        "--keep-going",
+        "tree1",
+        "tree2",
+    ]);
+    assert_eq!(output, "ok\nok");
+
+    // exit-on-error: false, keep-going: true, command: list
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "error-command-list",
+        "--keep-going",
+        "--no-errexit",
+        "tree1",
+        "tree2",
+    ]);
+    assert_eq!(output, "ok\nafter error\nok\nafter error");
+
+    // exit-on-error: true, keep-going: false, command: multi
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "cmd",
+        "tree*",
+        "error-command",
+        "error-command-list",
+    ]);
+    assert_eq!(output, "ok");
+
+    // exit-on-error: false, keep-going: false, command: multi
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "cmd",
         "--no-errexit",
         "tree*",
         "error-command",
@@ -861,18 +3840,1386 @@ fn cmd_keep_going_and_no_errexit() {
     ]);
     assert_eq!(output, "ok\nafter error");
 
-    // exit-on-error: true, keep-going: true, command: multi
+    // exit-on-error: true, keep-going: true, command: multi
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "cmd",
+        "--keep-going",
+        "tree*",
+        "error-command",
+        "error-command-list",
+    ]);
+    assert_eq!(output, "ok\nok\nok\nok");
+}
+
+/// Test "garden cmd --jobs" and "garden exec --jobs".
+#[test]
+fn cmd_and_exec_jobs() {
+    // Output from parallel commands is prefixed with the tree name.
+    // The order in which trees complete is not deterministic so sort the output.
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "cmd",
+        "--jobs",
+        "2",
+        "trees",
+        "tree-name",
+        "tree-var",
+    ]);
+    let mut lines: Vec<&str> = output.lines().collect();
+    lines.sort();
+    assert_eq!(
+        lines,
+        ["tree1: tree1", "tree1: x1", "tree2: tree2", "tree2: x2"]
+    );
+
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "--quiet",
+        "exec",
+        "--jobs",
+        "2",
+        "trees",
+        "echo",
+        "hello",
+    ]);
+    let mut lines: Vec<&str> = output.lines().collect();
+    lines.sort();
+    assert_eq!(lines, ["tree1: hello", "tree2: hello"]);
+}
+
+/// Test "garden ls --format json".
+#[test]
+fn ls_format_json() -> Result<()> {
+    let output = garden_capture(&["--chdir", "tests/data", "ls", "--format", "json"]);
+    let doc: serde_json::Value = serde_json::from_str(&output)?;
+
+    let trees = doc["trees"].as_array().expect("trees must be an array");
+    let tree1 = trees
+        .iter()
+        .find(|tree| tree["name"] == "tree1")
+        .expect("tree1 must be listed");
+    assert_eq!(tree1["exists"], true);
+    assert_eq!(tree1["groups"], serde_json::json!(["trees"]));
+    assert_eq!(tree1["gardens"], serde_json::json!([]));
+
+    let example_tree = trees
+        .iter()
+        .find(|tree| tree["name"] == "example/tree")
+        .expect("example/tree must be listed");
+    assert_eq!(example_tree["gardens"], serde_json::json!(["dev"]));
+    assert_eq!(example_tree["remotes"][1]["name"], "publish");
+    assert_eq!(
+        example_tree["remotes"][1]["url"],
+        "git@github.com:user/example.git"
+    );
+
+    assert_eq!(doc["groups"][0]["name"], "trees");
+    assert_eq!(doc["gardens"][0]["name"], "dev");
+
+    Ok(())
+}
+
+/// "garden ls" displays the trees in aligned name, path, branch and status columns.
+/// Git repositories are only checked for changes when using "--status".
+#[test]
+#[named]
+fn ls_columns() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  repo:
+    url: file://${GARDEN_ROOT}/repos/example.git
+  missing-tree: {}
+  plain: {}
+groups:
+  all: [repo, plain]
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    std::fs::create_dir_all(format!("{}/plain", root))?;
+    exec_garden(&["--chdir", &root, "grow", "repo"])?;
+    let abs_root = std::path::absolute(&root)?.to_string_lossy().to_string();
+
+    // Output is aligned without colors or truncation when stdout is not a terminal.
+    let output = garden_capture(&["--chdir", &root, "ls"]);
+    let expect = format!(
+        r#"groups:
+    all
+trees:
+    repo          {0}/repo          default
+    missing-tree  {0}/missing-tree  -        -
+    plain         {0}/plain         -        ?"#,
+        abs_root
+    );
+    assert_eq!(output, expect);
+
+    // Git repositories are inspected when using "--status".
+    let output = garden_capture(&["--chdir", &root, "ls", "--status"]);
+    assert!(output.contains(&format!("{}/repo          default  ✓", abs_root)));
+
+    // Uncommitted changes are reported in the status column.
+    std::fs::write(format!("{}/repo/untracked", root), "")?;
+    let output = garden_capture(&["--chdir", &root, "ls"]);
+    assert!(output.contains(&format!("{}/repo          default\n", abs_root)));
+    let output = garden_capture(&["--chdir", &root, "ls", "--status"]);
+    assert!(output.contains(&format!("{}/repo          default  *", abs_root)));
+
+    Ok(())
+}
+
+/// Test "garden ls --paths" and "garden ls -z".
+#[test]
+#[named]
+fn ls_paths() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  spaces:
+    path: with spaces
+  plain: {}
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    let abs_root = std::path::absolute(&root)?.to_string_lossy().to_string();
+
+    let output = garden_capture(&["--chdir", &root, "ls", "--paths"]);
+    let expect = format!("{0}/with spaces\n{0}/plain", abs_root);
+    assert_eq!(output, expect);
+
+    // Paths are terminated by NUL characters using "-z".
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "ls", "-z"]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let expect = format!("{0}/with spaces\0{0}/plain\0", abs_root);
+    assert_eq!(String::from_utf8(output)?, expect);
+
+    // "--paths" cannot be combined with other listings.
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "ls", "--paths", "--commands"]);
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+/// Test "garden ls --commands".
+#[test]
+fn ls_commands() -> Result<()> {
+    let output = garden_capture(&["--chdir", "tests/data", "ls", "--commands"]);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines[0], "commands:");
+    assert!(lines.contains(&"    tree-name"), "{}", output);
+    assert!(lines.contains(&"tree current:"), "{}", output);
+    assert!(lines.contains(&"    echo-args"), "{}", output);
+
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "ls",
+        "--commands",
+        "--format",
+        "json",
+    ]);
+    let doc: serde_json::Value = serde_json::from_str(&output)?;
+    let commands = doc["commands"]
+        .as_array()
+        .expect("commands must be an array");
+    assert_eq!(commands[0]["name"], "pwd");
+    assert_eq!(commands[0]["scope"], "config");
+    let echo_args = commands
+        .iter()
+        .find(|command| command["name"] == "echo-args")
+        .expect("echo-args must be listed");
+    assert_eq!(echo_args["scope"], "tree");
+    assert_eq!(echo_args["tree"], "current");
+
+    Ok(())
+}
+
+/// Test "help" descriptions in "garden help <command>" and "garden ls --commands".
+#[test]
+#[named]
+fn custom_command_help() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+commands:
+  build:
+    help: |
+      Build the project
+      Artifacts are written to the build directory.
+    run: echo build
+  test: echo test
+trees:
+  project:
+    path: .
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    let output = garden_capture(&["--chdir", &root, "help", "build"]);
+    assert!(output.starts_with("Build the project\n"), "{}", output);
+    assert!(output.contains("Usage: garden build"), "{}", output);
+
+    let output = garden_capture(&["--chdir", &root, "ls", "--commands"]);
+    assert_eq!(output, "commands:\n    build  Build the project\n    test");
+
+    let output = garden_capture(&["--chdir", &root, "help", "grow"]);
+    assert!(output.contains("Usage: garden grow"), "{}", output);
+
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "help", "missing"]);
+    cmd.assert().failure().code(64);
+
+    Ok(())
+}
+
+/// Test "garden inspect --format json" and "garden inspect --format yaml".
+#[test]
+fn inspect_format_json_yaml() -> Result<()> {
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "inspect",
+        "--format",
+        "json",
+        "@example/tree",
+    ]);
+    let doc: serde_json::Value = serde_json::from_str(&output)?;
+    let tree = &doc["trees"][0];
+    assert_eq!(tree["name"], "example/tree");
+    assert!(tree["path"]
+        .as_str()
+        .unwrap_or_default()
+        .ends_with("tests/data/example/tree/repo"));
+    assert_eq!(tree["remotes"][1]["url"], "git@github.com:user/example.git");
+    assert_eq!(tree["variables"]["gh_ssh"], "git@github.com");
+    assert_eq!(tree["variables"]["TREE_NAME"], "example/tree");
+    assert!(tree["environment"]["PATH"]
+        .as_str()
+        .unwrap_or_default()
+        .contains("tests/data/example/tree/repo"));
+    assert_eq!(
+        tree["commands"]["tree-name"],
+        serde_json::json!(["echo \"example/tree\""])
+    );
+
+    // Gardens are reported along with the tree.
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "inspect",
+        "--format",
+        "json",
+        "dev",
+    ]);
+    let doc: serde_json::Value = serde_json::from_str(&output)?;
+    let trees = doc["trees"].as_array().expect("trees must be an array");
+    assert!(trees.iter().all(|tree| tree["garden"] == "dev"));
+
+    let output = garden_capture(&[
+        "--chdir",
+        "tests/data",
+        "inspect",
+        "--format",
+        "yaml",
+        "@current",
+    ]);
+    assert!(output.contains("name: current"));
+    assert!(output.contains("  echo-dir:\n"));
+
+    Ok(())
+}
+
+/// "garden completion" completes tree queries using names from the configuration.
+#[test]
+fn completion_tree_queries() -> Result<()> {
+    let mut exec = assert_cmd::Command::cargo_bin("garden")?;
+    exec.env("GARDEN_COMPLETE", "fish")
+        .args(["--", "garden", "--chdir", "tests/data", "exec", ""]);
+    let output = String::from_utf8(exec.output()?.stdout)?;
+    let names: Vec<&str> = output
+        .lines()
+        .filter_map(|line| line.split('\t').next())
+        .collect();
+    assert!(names.contains(&"dev"), "gardens are completed");
+    assert!(names.contains(&"trees"), "groups are completed");
+    assert!(names.contains(&"tree1"), "trees are completed");
+
+    let output = garden_capture(&["completion", "bash"]);
+    assert!(output.contains("GARDEN_COMPLETE=\"bash\""));
+
+    Ok(())
+}
+
+/// "garden lock" records tree commits and "garden grow" checks them out.
+#[test]
+#[named]
+fn cmd_lock_and_grow() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    // Use a garden file inside the fixture so that garden.lock is written there.
+    let config = r#"
+trees:
+  example/tree:
+    path: example/tree/repo
+    url: file://${GARDEN_ROOT}/repos/example.git
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    exec_garden(&["--chdir", &fixture.root(), "grow", "example/tree"])?;
+    exec_garden(&["--chdir", &fixture.root(), "lock"])?;
+
+    // The lockfile records the current commit.
+    let worktree = fixture.worktree("example/tree/repo");
+    let head = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+    let lockfile = std::fs::read_to_string(fixture.pathbuf("garden.lock"))?;
+    assert!(
+        lockfile.contains(&head),
+        "garden.lock must contain {}",
+        head
+    );
+
+    // Lock the tree to the previous commit and grow it again.
+    let repo = fixture.path("repos/example.git");
+    let previous = assert_cmd_capture(&["git", "rev-parse", "default~1"], &repo);
+    std::fs::write(
+        fixture.pathbuf("garden.lock"),
+        lockfile.replace(&head, &previous),
+    )?;
+    std::fs::remove_dir_all(fixture.pathbuf("example"))?;
+    exec_garden(&["--chdir", &fixture.root(), "grow", "example/tree"])?;
+    let output = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+    assert_eq!(output, previous);
+
+    // "garden grow --no-lock" ignores the lockfile.
+    std::fs::remove_dir_all(fixture.pathbuf("example"))?;
+    exec_garden(&[
+        "--chdir",
+        &fixture.root(),
+        "grow",
+        "--no-lock",
+        "example/tree",
+    ])?;
+    let output = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+    assert_eq!(output, head);
+
+    // Existing trees that do not match the lockfile are reported but not modified.
+    let output = std::process::Command::cargo_bin("garden")?
+        .args(["--chdir", &fixture.root(), "grow", "example/tree"])
+        .output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("does not match the lockfile"), "{}", stderr);
+    let output = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+    assert_eq!(output, head);
+
+    // "garden grow --locked" refuses to checkout trees with uncommitted changes.
+    std::fs::write(format!("{}/dirty.txt", worktree), "dirty")?;
+    assert_cmd(&["git", "add", "dirty.txt"], &worktree);
+    let output = std::process::Command::cargo_bin("garden")?
+        .args([
+            "--chdir",
+            &fixture.root(),
+            "grow",
+            "--locked",
+            "example/tree",
+        ])
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("uncommitted changes"), "{}", stderr);
+    let output = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+    assert_eq!(output, head);
+
+    // "garden grow --locked" checks out the locked commit in clean trees.
+    assert_cmd(&["git", "reset", "--hard"], &worktree);
+    exec_garden(&[
+        "--chdir",
+        &fixture.root(),
+        "grow",
+        "--locked",
+        "example/tree",
+    ])?;
+    let output = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+    assert_eq!(output, previous);
+
+    Ok(())
+}
+
+/// "ref" pins trees to a tag and "garden grow --checkout" overrides it.
+#[test]
+#[named]
+fn grow_ref() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let repo = fixture.path("repos/example.git");
+    let previous = assert_cmd_capture(&["git", "rev-parse", "default~1"], &repo);
+    let head = assert_cmd_capture(&["git", "rev-parse", "default"], &repo);
+    assert_cmd(&["git", "tag", "v1.0", &previous], &repo);
+    let config = r#"
+trees:
+  pinned:
+    ref: v1.0
+    url: file://${GARDEN_ROOT}/repos/example.git
+  single:
+    branch: default
+    ref: v1.0
+    single-branch: true
+    url: file://${GARDEN_ROOT}/repos/example.git
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    exec_garden(&["--chdir", &root, "grow", "pinned", "single"])?;
+
+    // Tags are fetched when they are not present in single-branch clones.
+    for tree in ["pinned", "single"] {
+        let worktree = fixture.worktree(tree);
+        let output = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+        assert_eq!(output, previous);
+    }
+
+    // "garden grow --checkout" overrides the "ref" using a commit ID.
+    std::fs::remove_dir_all(fixture.pathbuf("pinned"))?;
+    exec_garden(&["--chdir", &root, "grow", "--checkout", &head, "pinned"])?;
+    let worktree = fixture.worktree("pinned");
+    let output = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+    assert_eq!(output, head);
+
+    // Existing trees checkout the pinned ref.
+    exec_garden(&["--chdir", &root, "grow", "pinned"])?;
+    let output = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+    assert_eq!(output, previous);
+
+    // Existing trees with uncommitted changes are left as-is.
+    std::fs::write(format!("{}/pinned/untracked.txt", root), "untracked")?;
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "grow", "--checkout", &head, "pinned"]);
+    let output = cmd.assert().success().get_output().clone();
+    assert!(String::from_utf8(output.stderr)?.contains("warning: pinned: unable to checkout"));
+    let output = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+    assert_eq!(output, previous);
+    std::fs::remove_file(format!("{}/pinned/untracked.txt", root))?;
+
+    // Worktrees checkout the pinned ref when they are created and when they exist.
+    let config = format!(
+        "{}  pinned-worktree:\n    worktree: pinned\n    branch: pinned\n    ref: v1.0\n",
+        config
+    );
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    exec_garden(&["--chdir", &root, "grow", "pinned-worktree"])?;
+    let worktree = fixture.worktree("pinned-worktree");
+    let output = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+    assert_eq!(output, previous);
+    exec_garden(&[
+        "--chdir",
+        &root,
+        "grow",
+        "--checkout",
+        &head,
+        "pinned-worktree",
+    ])?;
+    let output = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+    assert_eq!(output, head);
+
+    // Unknown refs are reported as errors.
+    std::fs::remove_dir_all(fixture.pathbuf("pinned"))?;
+    let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+    cmd.args(["--chdir", &root, "grow", "--checkout", "v9.9", "pinned"]);
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+/// "garden snapshot" records tree state and "garden restore" restores it.
+#[test]
+#[named]
+fn cmd_snapshot_and_restore() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  example:
+    branch: default
+    url: file://${GARDEN_ROOT}/repos/example.git
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    exec_garden(&["--chdir", &root, "grow", "example"])?;
+
+    // Record a commit and an uncommitted change.
+    let worktree = fixture.worktree("example");
+    let notes = format!("{}/example/notes.txt", root);
+    std::fs::write(&notes, "committed\n")?;
+    assert_cmd(&["git", "add", "notes.txt"], &worktree);
+    assert_cmd(&["git", "commit", "-m", "notes"], &worktree);
+    let head = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+    std::fs::write(&notes, "modified\n")?;
+
+    // Snapshots do not modify the worktree.
+    exec_garden(&["--chdir", &root, "snapshot"])?;
+    assert_eq!(std::fs::read_to_string(&notes)?, "modified\n");
+    let snapshot = std::fs::read_to_string(fixture.pathbuf("garden.snapshot"))?;
+    assert!(snapshot.contains("branch: default"));
+    assert!(snapshot.contains(&head));
+    assert!(snapshot.contains("stash:"));
+
+    // Trees with uncommitted changes are not restored without --force.
+    let cmd = ["--chdir", &root, "restore"];
+    assert!(assert_cmd::Command::cargo_bin("garden")?
+        .args(cmd)
+        .assert()
+        .try_failure()
+        .is_ok());
+
+    // Restore the branch, commit and uncommitted changes.
+    assert_cmd(&["git", "checkout", "--force", "dev"], &worktree);
+    exec_garden(&["--chdir", &root, "restore"])?;
+    let cmd = ["git", "symbolic-ref", "--short", "HEAD"];
+    assert_eq!(assert_cmd_capture(&cmd, &worktree), "default");
+    let cmd = ["git", "rev-parse", "HEAD"];
+    assert_eq!(assert_cmd_capture(&cmd, &worktree), head);
+    assert_eq!(std::fs::read_to_string(&notes)?, "modified\n");
+    // The stash entry is dropped once it has been restored.
+    let cmd = ["git", "stash", "list"];
+    assert_eq!(assert_cmd_capture(&cmd, &worktree), "");
+
+    Ok(())
+}
+
+/// "garden sync" fetches remotes and fast-forwards trees that are not dirty.
+#[test]
+#[named]
+fn cmd_sync() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+trees:
+  example/tree:
+    path: example/tree/repo
+    url: file://${GARDEN_ROOT}/repos/example.git
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    exec_garden(&["--chdir", &fixture.root(), "grow", "example/tree"])?;
+
+    // Add a new commit to the remote repository.
+    let repo = fixture.path("repos/example.git");
+    let cmd = [
+        "git",
+        "commit-tree",
+        "-m",
+        "commit 3",
+        "-p",
+        "default",
+        "default^{tree}",
+    ];
+    let commit = assert_cmd_capture(&cmd, &repo);
+    assert_cmd(&["git", "update-ref", "refs/heads/default", &commit], &repo);
+
+    // Trees with uncommitted changes are skipped.
+    let worktree = fixture.worktree("example/tree/repo");
+    std::fs::write(format!("{}/file.txt", worktree), "dirty")?;
+    assert_cmd(&["git", "add", "file.txt"], &worktree);
+    exec_garden(&["--chdir", &fixture.root(), "sync", "example/tree"])?;
+    let head = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+    assert_ne!(head, commit);
+
+    // Clean trees are fast-forwarded.
+    assert_cmd(&["git", "reset", "--quiet", "--hard"], &worktree);
+    exec_garden(&["--chdir", &fixture.root(), "sync", "example/tree"])?;
+    let head = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &worktree);
+    assert_eq!(head, commit);
+
+    Ok(())
+}
+
+/// Test "garden branch" for creating, listing and deleting branches.
+#[test]
+#[named]
+fn cmd_branch() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  one:
+    url: file://${GARDEN_ROOT}/repos/example.git
+    branch: default
+  two:
+    url: file://${GARDEN_ROOT}/repos/example.git
+    branch: default
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    exec_garden(&["--chdir", &root, "grow", "one", "two"])?;
+    let one = fixture.worktree("one");
+    let two = fixture.worktree("two");
+    let current = ["git", "symbolic-ref", "--short", "HEAD"];
+
+    // New branches are created in every tree.
+    exec_garden(&["--chdir", &root, "--quiet", "branch", "feature", "*"])?;
+    assert_eq!(assert_cmd_capture(&current, &one), "feature");
+    assert_eq!(assert_cmd_capture(&current, &two), "feature");
+
+    // Existing remote branches are tracked.
+    exec_garden(&["--chdir", &root, "--quiet", "branch", "dev", "one"])?;
+    assert_eq!(assert_cmd_capture(&current, &one), "dev");
+    let cmd = ["git", "config", "branch.dev.remote"];
+    assert_eq!(assert_cmd_capture(&cmd, &one), "origin");
+
+    // "--from" creates branches from the specified ref.
+    exec_garden(&[
+        "--chdir", &root, "--quiet", "branch", "--from", "HEAD~1", "topic", "two",
+    ])?;
+    let head = assert_cmd_capture(&["git", "rev-parse", "HEAD"], &two);
+    let parent = assert_cmd_capture(&["git", "rev-parse", "default~1"], &two);
+    assert_eq!(head, parent);
+
+    // "--list" displays the current branch of each tree.
+    let output = garden_capture(&["--chdir", &root, "--color=off", "branch", "--list", "*"]);
+    assert_eq!(output, "one  dev\ntwo  topic");
+
+    // Branches are deleted from every tree that has them.
+    exec_garden(&["--chdir", &root, "--quiet", "branch", "default", "*"])?;
+    exec_garden(&[
+        "--chdir", &root, "--quiet", "branch", "--delete", "feature", "*",
+    ])?;
+    assert_ref_missing(&one, "refs/heads/feature");
+    assert_ref_missing(&two, "refs/heads/feature");
+    exec_garden(&[
+        "--chdir", &root, "--quiet", "branch", "-d", "--force", "topic", "*",
+    ])?;
+    assert_ref_missing(&two, "refs/heads/topic");
+
+    Ok(())
+}
+
+/// "garden cmd" runs the "pre-cmd" and "post-cmd" hooks around each command.
+#[test]
+fn cmd_hooks() {
     let output = garden_capture(&[
         "--chdir",
         "tests/data",
+        "--config",
+        "hooks.yaml",
         "--quiet",
         "cmd",
-        "--keep-going",
-        "tree*",
-        "error-command",
-        "error-command-list",
+        "current",
+        "hello",
+        "undefined",
     ]);
-    assert_eq!(output, "ok\nok\nok\nok");
+    assert_eq!(output, "pre-cmd current\nhello\npost-cmd data");
+}
+
+/// "garden grow" runs the "post-grow" hook inside of newly grown trees.
+#[test]
+#[named]
+fn grow_hooks() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    exec_garden(&[
+        "--chdir",
+        &fixture.root(),
+        "--config",
+        "tests/data/hooks.yaml",
+        "grow",
+        "example/tree",
+    ])?;
+    // fixture.path() asserts that the path exists.
+    fixture.path("example/tree/repo/post-grow");
+
+    Ok(())
+}
+
+/// "garden grow" runs "post-clone" commands once after cloning a tree.
+#[test]
+#[named]
+fn grow_post_clone() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+templates:
+  setup:
+    post-clone: echo ${TREE_NAME} >> ${GARDEN_ROOT}/post-clone.log
+trees:
+  example:
+    url: file://${GARDEN_ROOT}/repos/example.git
+    templates: setup
+  pending:
+    url: file://${GARDEN_ROOT}/repos/example.git
+    post-clone:
+      - test -e ${GARDEN_ROOT}/ready
+      - echo ${TREE_NAME} >> ${GARDEN_ROOT}/post-clone.log
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    let root = fixture.root();
+    let log = format!("{}/post-clone.log", root);
+
+    exec_garden(&["--chdir", &root, "grow", "example"])?;
+    let state = fixture.path("example/.git/garden-post-clone");
+    assert_eq!(std::fs::read_to_string(&state)?, "done\n");
+    exec_garden(&["--chdir", &root, "grow", "example"])?;
+    assert_eq!(std::fs::read_to_string(&log)?, "example\n");
+
+    // Failed post-clone commands are retried by the next "garden grow".
+    let status = std::process::Command::cargo_bin("garden")?
+        .args(["--chdir", &root, "--quiet", "grow", "pending"])
+        .status()?;
+    assert!(!status.success());
+    let state = fixture.path("pending/.git/garden-post-clone");
+    assert_eq!(std::fs::read_to_string(&state)?, "pending\n");
+    assert!(!std::fs::read_to_string(&log)?.contains("pending"));
+    std::fs::write(format!("{}/ready", root), "")?;
+    exec_garden(&["--chdir", &root, "grow", "pending"])?;
+    assert_eq!(std::fs::read_to_string(&state)?, "done\n");
+    exec_garden(&["--chdir", &root, "grow", "pending"])?;
+    assert_eq!(std::fs::read_to_string(&log)?, "example\npending\n");
+
+    Ok(())
+}
+
+/// "garden grow" installs Git LFS and pulls LFS objects for trees that specify "lfs".
+#[test]
+#[named]
+fn grow_lfs() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+trees:
+  example:
+    url: file://${GARDEN_ROOT}/repos/example.git
+    lfs: true
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    // The exit status depends on whether git-lfs is installed.
+    let output = std::process::Command::cargo_bin("garden")?
+        .args(["--chdir", &fixture.root(), "-vv", "grow", "example"])
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("git lfs install --local"));
+    fixture.worktree("example");
+
+    Ok(())
+}
+
+/// "garden grow" applies "garden.url-rewrites" and "garden plant" reverses them.
+#[test]
+#[named]
+fn grow_url_rewrites() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let repos = std::fs::canonicalize(fixture.path("repos"))?;
+    let config = format!(
+        r#"
+garden:
+  url-rewrites:
+    "git@example.com:": file://{}/
+trees:
+  example:
+    url: git@example.com:example.git
+    remotes:
+      upstream: git@example.com:example.git
+"#,
+        repos.to_string_lossy()
+    );
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    exec_garden(&["--chdir", &root, "grow", "example"])?;
+
+    let worktree = fixture.worktree("example");
+    let expect = format!("file://{}/example.git", repos.to_string_lossy());
+    for remote in ["origin", "upstream"] {
+        let key = format!("remote.{}.url", remote);
+        let url = assert_cmd_capture(&["git", "config", &key], &worktree);
+        assert_eq!(url, expect);
+    }
+
+    // The original URLs are recorded when planting.
+    exec_garden(&[
+        "--chdir",
+        &root,
+        "plant",
+        "--output",
+        "planted.yaml",
+        "example",
+    ])?;
+    let planted = std::fs::read_to_string(fixture.path("planted.yaml"))?;
+    assert!(planted.contains("url: \"git@example.com:example.git\""));
+    assert!(planted.contains("upstream: \"git@example.com:example.git\""));
+
+    Ok(())
+}
+
+/// Commands use the most specific shell: command, garden, tree and then garden.shell.
+#[cfg(unix)]
+#[test]
+#[named]
+fn cmd_shell_override() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+garden:
+  shell: ${GARDEN_ROOT}/config-shell
+trees:
+  tree-a:
+    path: .
+    shell: ${GARDEN_ROOT}/tree-shell
+    commands:
+      test: "true"
+      other:
+        shell: ${GARDEN_ROOT}/command-shell
+        run: "true"
+  tree-b:
+    path: .
+    commands:
+      test: "true"
+gardens:
+  garden:
+    trees: tree-b
+    shell: ${GARDEN_ROOT}/garden-shell
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    // Each "shell" reports its own name.
+    for name in [
+        "config-shell",
+        "tree-shell",
+        "command-shell",
+        "garden-shell",
+    ] {
+        let path = format!("{}/{}", fixture.root(), name);
+        std::fs::write(&path, "#!/bin/sh\necho \"$(basename \"$0\")\"\n")?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    let root = fixture.root();
+    let output = garden_capture(&["--chdir", &root, "--quiet", "cmd", "tree-a", "test"]);
+    assert_eq!(output, "tree-shell");
+
+    let output = garden_capture(&["--chdir", &root, "--quiet", "cmd", "tree-a", "other"]);
+    assert_eq!(output, "command-shell");
+
+    let output = garden_capture(&["--chdir", &root, "--quiet", "cmd", "tree-b", "test"]);
+    assert_eq!(output, "config-shell");
+
+    let output = garden_capture(&["--chdir", &root, "--quiet", "cmd", "garden", "test"]);
+    assert_eq!(output, "garden-shell");
+
+    Ok(())
+}
+
+/// Exec expressions are cached when "garden.cache" is enabled.
+#[test]
+#[named]
+fn eval_exec_cache() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+garden:
+  cache: true
+variables:
+  value: $ cat ${GARDEN_CONFIG_DIR}/input.txt
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    std::fs::write(format!("{}/input.txt", fixture.root()), "one")?;
+    let root = fixture.root();
+    let output = garden_capture(&["--chdir", &root, "eval", "${value}"]);
+    assert_eq!(output, "one");
+    fixture.path("garden.cache");
+
+    // The cached value is used until the cache is bypassed or cleared.
+    std::fs::write(format!("{}/input.txt", fixture.root()), "two")?;
+    let output = garden_capture(&["--chdir", &root, "eval", "${value}"]);
+    assert_eq!(output, "one");
+    let output = garden_capture(&["--chdir", &root, "--no-cache", "eval", "${value}"]);
+    assert_eq!(output, "two");
+
+    exec_garden(&["--chdir", &root, "--quiet", "cache", "clear"])?;
+    let output = garden_capture(&["--chdir", &root, "eval", "${value}"]);
+    assert_eq!(output, "two");
+
+    Ok(())
+}
+
+/// "garden fetch" fetches remotes and reports the updated refs.
+#[test]
+#[named]
+fn cmd_fetch() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+trees:
+  example/tree:
+    path: example/tree/repo
+    url: file://${GARDEN_ROOT}/repos/example.git
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    exec_garden(&["--chdir", &fixture.root(), "grow", "example/tree"])?;
+
+    // Add a new commit to the remote repository.
+    let repo = fixture.path("repos/example.git");
+    let cmd = [
+        "git",
+        "commit-tree",
+        "-m",
+        "commit 3",
+        "-p",
+        "dev",
+        "dev^{tree}",
+    ];
+    let commit = assert_cmd_capture(&cmd, &repo);
+    assert_cmd(&["git", "update-ref", "refs/heads/dev", &commit], &repo);
+
+    let output = garden_capture(&["--chdir", &fixture.root(), "fetch", "example/tree"]);
+    assert!(output.contains("origin/dev"), "origin/dev was updated");
+    assert!(output.ends_with("1 tree fetched, 1 ref updated"));
+
+    let worktree = fixture.worktree("example/tree/repo");
+    let output = assert_cmd_capture(&["git", "rev-parse", "origin/dev"], &worktree);
+    assert_eq!(output, commit);
+
+    Ok(())
+}
+
+/// "garden diff" summarizes uncommitted changes and unpushed commits.
+#[test]
+#[named]
+fn cmd_diff() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+trees:
+  example/tree:
+    path: example/tree/repo
+    url: file://${GARDEN_ROOT}/repos/example.git
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+    exec_garden(&["--chdir", &fixture.root(), "grow", "example/tree"])?;
+
+    // Clean trees are not displayed.
+    let output = garden_capture(&["--chdir", &fixture.root(), "diff", "example/tree"]);
+    assert!(output.is_empty());
+
+    // Create a local commit and stage a new file.
+    let worktree = fixture.worktree("example/tree/repo");
+    let cmd = ["git", "commit", "--quiet", "--allow-empty", "-m", "local"];
+    assert_cmd(&cmd, &worktree);
+    std::fs::write(format!("{}/new-file", worktree), "content\n")?;
+    assert_cmd(&["git", "add", "new-file"], &worktree);
+
+    // Tree headers are displayed on stdout alongside the changes.
+    let output = garden_capture(&["--chdir", &fixture.root(), "diff", "example/tree"]);
+    assert!(output.starts_with("# example/tree"));
+    assert!(output.contains("ahead 1, behind 0 (origin/default)"));
+    assert!(output.contains("new-file | 1 +"));
+    assert!(output.contains("1 file changed, 1 insertion(+)"));
+
+    let output = garden_capture(&[
+        "--chdir",
+        &fixture.root(),
+        "diff",
+        "--patch",
+        "example/tree",
+    ]);
+    assert!(output.contains("+content"));
+
+    Ok(())
+}
+
+/// "garden vars" lists variables along with their scope and resolved values.
+#[test]
+#[named]
+fn cmd_vars() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let config = r#"
+variables:
+  name: config
+  greeting: hello ${name}
+trees:
+  tree:
+    variables:
+      name: tree
+gardens:
+  garden:
+    trees: tree
+    variables:
+      name: garden
+"#;
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+
+    let output = garden_capture(&["--chdir", &fixture.root(), "vars"]);
+    assert!(output.contains("config name = config"));
+    assert!(output.contains("config greeting = hello config"));
+    assert!(output.contains("# hello ${name}"));
+
+    let output = garden_capture(&["--chdir", &fixture.root(), "vars", "tree", "garden"]);
+    assert!(output.contains("garden name = garden"));
+    assert!(output.contains("tree   name = tree (overridden by garden)"));
+    assert!(output.contains("config name = config (overridden by garden)"));
+    assert!(output.contains("config greeting = hello garden"));
+
+    Ok(())
+}
+
+/// Repositories that match "discover" patterns are available as trees.
+#[test]
+#[named]
+fn discover_trees() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    assert_cmd(&["git", "init", "--quiet", "src/one"], &root);
+    assert_cmd(&["git", "init", "--quiet", "src/two"], &root);
+    assert_cmd(&["git", "init", "--quiet", "vendor/lib"], &root);
+    std::fs::create_dir_all(format!("{}/src/plain", root))?;
+    let cmd = [
+        "git",
+        "remote",
+        "add",
+        "origin",
+        "https://example.com/one.git",
+    ];
+    assert_cmd(&cmd, &format!("{}/src/one", root));
+    let config = r#"
+discover:
+  code: src/*
+  vendored: [vendor/*, missing/*]
+trees:
+  two:
+    path: src/two
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+
+    // Discovered trees are named after their path relative to the root.
+    // Configured trees take precedence over discovered trees.
+    let output = garden_capture(&["--chdir", &root, "--quiet", "exec", "%code", "pwd"]);
+    let abs_root = std::path::absolute(&root)?.to_string_lossy().to_string();
+    assert_eq!(
+        output,
+        format!("{}/src/one\n{}/src/two", abs_root, abs_root)
+    );
+    let output = garden_capture(&["--chdir", &root, "eval", "${TREE_NAME}", "src/one"]);
+    assert_eq!(output, "src/one");
+    let output = garden_capture(&["--chdir", &root, "eval", "${TREE_URL}", "src/one"]);
+    assert_eq!(output, "https://example.com/one.git");
+    let output = garden_capture(&["--chdir", &root, "--quiet", "exec", "%vendored", "pwd"]);
+    assert_eq!(output, format!("{}/vendor/lib", abs_root));
+
+    // Configured trees whose path expressions resolve to a discovered
+    // repository are not discovered again.
+    let expr_config = r#"
+discover:
+  code: src/*
+trees:
+  one:
+    path: ${GARDEN_ROOT}/src/one
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), expr_config)?;
+    let output = garden_capture(&["--chdir", &root, "--quiet", "exec", "%code", "pwd"]);
+    assert_eq!(
+        output,
+        format!("{}/src/one\n{}/src/two", abs_root, abs_root)
+    );
+    let output = garden_capture(&["--chdir", &root, "eval", "${TREE_NAME}", "src/one"]);
+    assert_eq!(output, "one");
+    let output = garden_capture(&["--chdir", &root, "ls", "--paths"]);
+    assert_eq!(output.matches("/src/one").count(), 1, "{}", output);
+
+    // Discovered trees can be referenced from other groups.
+    let config = format!("{}groups:\n  mine: [src/one]\n", config);
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    exec_garden(&["--chdir", &root, "check"])?;
+
+    Ok(())
+}
+
+/// "garden prune" protects repositories with unsaved work and filters by age.
+#[test]
+#[named]
+fn cmd_prune_filters() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), "trees: {}\n")?;
+    let root = fixture.root();
+    assert_cmd(&["git", "init", "--quiet", "clean"], &root);
+    assert_cmd(&["git", "init", "--quiet", "dirty"], &root);
+    std::fs::write(format!("{}/dirty/file", root), "unsaved\n")?;
+
+    // Repositories with uncommitted changes are skipped.
+    let output = garden_capture(&["--chdir", &root, "prune", "--dry-run"]);
+    assert!(output.contains("Would delete: "));
+    assert!(output.contains("/clean"));
+    assert!(output.contains("/dirty (uncommitted changes, use '--force' to prune)"));
+
+    let output = garden_capture(&["--chdir", &root, "prune", "--dry-run", "--force"]);
+    assert!(!output.contains("Skipped"));
+
+    // Recently used repositories are not pruned when using --older-than.
+    let cmd = ["--chdir", &root, "prune", "--dry-run", "--older-than", "1d"];
+    let output = garden_capture(&cmd);
+    assert!(!output.contains("/clean"));
+    let cmd = ["touch", "-d", "2 days ago", "clean/.git/HEAD"];
+    assert_cmd(&cmd, &root);
+    let cmd = ["--chdir", &root, "prune", "--dry-run", "--older-than", "1d"];
+    let output = garden_capture(&cmd);
+    assert!(output.contains("/clean"));
+    // Ages that overflow are rejected instead of wrapping around to a small age.
+    let mut exec = assert_cmd::Command::cargo_bin("garden")?;
+    exec.args(["--chdir", &root, "prune", "--dry-run"])
+        .args(["--older-than", "99999999999999999w"]);
+    exec.assert().failure().code(2);
+
+    // Nothing is removed in dry-run mode.
+    fixture.path("clean");
+    fixture.path("dirty");
+
+    exec_garden(&["--chdir", &root, "prune", "--no-prompt", "--rm"])?;
+    assert!(!std::path::Path::new(&format!("{}/clean", root)).exists());
+    fixture.path("dirty");
+
+    Ok(())
+}
+
+/// "garden prune" skips repositories where any local branch has unpushed commits.
+#[test]
+#[named]
+fn cmd_prune_unpushed() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), "trees: {}\n")?;
+    let root = fixture.root();
+    let cmd = ["git", "clone", "--quiet", "repos/example.git", "pushed"];
+    assert_cmd(&cmd, &root);
+    let cmd = ["git", "clone", "--quiet", "repos/example.git", "local"];
+    assert_cmd(&cmd, &root);
+    // Record a commit on a branch that is not checked out.
+    let worktree = fixture.worktree("local");
+    assert_cmd(&["git", "checkout", "--quiet", "-b", "topic"], &worktree);
+    let cmd = ["git", "commit", "--quiet", "--allow-empty", "-m", "topic"];
+    assert_cmd(&cmd, &worktree);
+    assert_cmd(&["git", "checkout", "--quiet", "-"], &worktree);
+
+    let output = garden_capture(&["--chdir", &root, "prune", "--dry-run"]);
+    assert!(output.contains("/pushed"));
+    assert!(output.contains("/local (unpushed commits on topic, use '--force' to prune)"));
+
+    exec_garden(&["--chdir", &root, "prune", "--no-prompt", "--rm"])?;
+    assert!(!std::path::Path::new(&format!("{}/pushed", root)).exists());
+    fixture.path("local");
+
+    // Branches whose commits exist on a remote can be pruned.
+    let cmd = ["git", "update-ref", "refs/remotes/origin/topic", "topic"];
+    assert_cmd(&cmd, &worktree);
+    exec_garden(&["--chdir", &root, "prune", "--no-prompt", "--rm"])?;
+    assert!(!std::path::Path::new(&format!("{}/local", root)).exists());
+
+    Ok(())
+}
+
+/// "garden doctor" reports missing local repositories and unknown shells
+#[test]
+#[named]
+fn cmd_doctor() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"
+trees:
+  example:
+    url: ${GARDEN_ROOT}/repos/example.git
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    let output = garden_capture(&["--chdir", &root, "doctor", "--offline"]);
+    assert!(output.contains("ok: git: git version"));
+    assert!(output.contains("is writable"));
+    assert!(!output.contains("error:"));
+
+    let config = r#"
+garden:
+  shell: garden-doctor-missing-shell
+trees:
+  missing:
+    url: ${GARDEN_ROOT}/repos/missing.git
+"#;
+    std::fs::write(format!("{}/garden.yaml", root), config)?;
+    let output = std::process::Command::cargo_bin("garden")?
+        .args(["--chdir", &root, "doctor", "--offline"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(69));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("error: shell garden-doctor-missing-shell:"));
+    assert!(stdout.contains("change \"garden.shell\""));
+    assert!(stdout.contains("error: trees.missing.remotes.origin:"));
+    assert!(stdout.contains("repos/missing.git does not exist"));
+
+    Ok(())
+}
+
+/// "garden tree add" adds tree entries to the garden file
+#[test]
+#[named]
+fn cmd_tree_add() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"# Trees
+templates:
+  rust:
+    commands:
+      build: cargo build
+trees:
+  # Existing tree.
+  existing:
+    url: https://example.com/existing.git
+groups:
+  backend: existing
+"#;
+    let config_path = format!("{}/garden.yaml", root);
+    std::fs::write(&config_path, config)?;
+
+    exec_garden(&[
+        "--chdir",
+        &root,
+        "tree",
+        "add",
+        "api",
+        "--url",
+        "https://example.com/api.git",
+        "--template",
+        "rust",
+        "--group",
+        "backend",
+        "--group",
+        "services",
+    ])?;
+    exec_garden(&[
+        "--chdir", &root, "tree", "add", "docs", "--path", "src/docs", "--branch", "main",
+    ])?;
+    let expect = r#"# Trees
+templates:
+  rust:
+    commands:
+      build: cargo build
+trees:
+  # Existing tree.
+  existing:
+    url: https://example.com/existing.git
+  api:
+    templates: rust
+    url: "https://example.com/api.git"
+  docs:
+    path: src/docs
+    branch: main
+groups:
+  backend:
+    - existing
+    - api
+  services:
+    - api
+"#;
+    assert_eq!(std::fs::read_to_string(&config_path)?, expect);
+
+    // The new trees are usable without being grown.
+    let output = garden_capture(&["--chdir", &root, "eval", "${TREE_URL}", "api"]);
+    assert_eq!(output, "https://example.com/api.git");
+
+    // Existing trees and unknown templates are reported as errors.
+    for args in [["existing", "--url", "x"], ["new", "--template", "go"]] {
+        let mut cmd = assert_cmd::Command::cargo_bin("garden")?;
+        cmd.args(["--chdir", &root, "tree", "add"]).args(args);
+        cmd.assert().failure().code(garden::errors::EX_USAGE);
+    }
+    assert_eq!(std::fs::read_to_string(&config_path)?, expect);
+
+    Ok(())
+}
+
+/// "garden uproot" removes trees from the garden file
+#[test]
+#[named]
+fn cmd_uproot() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = r#"# Trees
+trees:
+  clean:
+    url: https://example.com/clean.git
+  dirty:
+    url: https://example.com/dirty.git
+  keep:
+    url: https://example.com/keep.git
+groups:
+  all: [clean, dirty, keep]
+gardens:
+  garden:
+    trees: clean
+"#;
+    let config_path = format!("{}/garden.yaml", root);
+    std::fs::write(&config_path, config)?;
+    assert_cmd(&["git", "init", "--quiet", "clean"], &root);
+    assert_cmd(&["git", "init", "--quiet", "dirty"], &root);
+    std::fs::write(format!("{}/dirty/file", root), "unsaved\n")?;
+
+    // Unknown trees are reported as errors.
+    let mut exec = assert_cmd::Command::cargo_bin("garden")?;
+    exec.args(["--chdir", &root, "uproot", "missing"]);
+    exec.assert().failure();
+
+    // Trees with unsaved work are not deleted.
+    let mut exec = assert_cmd::Command::cargo_bin("garden")?;
+    exec.args(["--chdir", &root, "uproot", "--delete", "dirty"]);
+    exec.assert().failure();
+    assert_eq!(std::fs::read_to_string(&config_path)?, config);
+    fixture.path("dirty/file");
+
+    exec_garden(&[
+        "--chdir",
+        &root,
+        "uproot",
+        "--delete",
+        "--references",
+        "clean",
+    ])?;
+    assert!(!std::path::Path::new(&format!("{}/clean", root)).exists());
+    let expect = r#"# Trees
+trees:
+  dirty:
+    url: https://example.com/dirty.git
+  keep:
+    url: https://example.com/keep.git
+groups:
+  all:
+    - dirty
+    - keep
+gardens:
+  garden:
+    trees: []
+"#;
+    assert_eq!(std::fs::read_to_string(&config_path)?, expect);
+
+    // Trees are kept on disk unless --delete is used.
+    exec_garden(&["--chdir", &root, "uproot", "dirty"])?;
+    fixture.path("dirty/file");
+    let output = garden_capture(&["--chdir", &root, "ls"]);
+    assert!(!output.contains("dirty"));
+
+    Ok(())
+}
+
+/// "garden uproot" does not delete trees that are only defined by "includes" files.
+#[test]
+#[named]
+fn cmd_uproot_included_tree() -> Result<()> {
+    let fixture = BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let config = "includes: [included.yaml]\n";
+    let config_path = format!("{}/garden.yaml", root);
+    std::fs::write(&config_path, config)?;
+    std::fs::write(
+        format!("{}/included.yaml", root),
+        "trees:\n  included:\n    url: https://example.com/included.git\n",
+    )?;
+    assert_cmd(&["git", "init", "--quiet", "included"], &root);
+
+    let mut exec = assert_cmd::Command::cargo_bin("garden")?;
+    exec.args(["--chdir", &root, "uproot", "--delete", "included"]);
+    let output = exec
+        .assert()
+        .failure()
+        .code(garden::errors::EX_CONFIG)
+        .get_output()
+        .clone();
+    assert!(String::from_utf8(output.stderr)?.contains("tree is not defined in"));
+    // Neither the garden file nor the tree's working directory were modified.
+    assert_eq!(std::fs::read_to_string(&config_path)?, config);
+    fixture.worktree("included");
+
+    Ok(())
 }
 
 /// "garden prune" prunes specific depths
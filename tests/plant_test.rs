@@ -172,3 +172,224 @@ fn plant_git_worktree() -> Result<()> {
 
     Ok(())
 }
+
+/// `garden plant --scan` plants every repository found inside of a directory.
+#[test]
+#[named]
+fn plant_scan() -> Result<()> {
+    let fixture = common::BareRepoFixture::new(function_name!());
+    // Create an empty garden.yaml using "garden init".
+    common::exec_garden(&["--chdir", &fixture.root(), "init"])?;
+
+    // Create a parent worktree called "parent" and a child worktree called "child".
+    let cmd = ["git", "clone", "--quiet", "repos/example.git", "parent"];
+    common::assert_cmd(&cmd, &fixture.root());
+    let cmd = [
+        "git",
+        "worktree",
+        "add",
+        "--track",
+        "-B",
+        "dev",
+        "../child",
+        "origin/dev",
+    ];
+    common::assert_cmd(&cmd, &fixture.path("parent"));
+
+    // garden plant --scan .
+    common::exec_garden(&["--chdir", &fixture.root(), "plant", "--scan", "."])?;
+
+    let garden_yaml = fixture.path("garden.yaml");
+    let path = Some(std::path::PathBuf::from(&garden_yaml));
+    let cfg = garden::config::new(&path, &fixture.root(), 0, None)?;
+    assert_eq!(3, cfg.trees.len());
+    // Child worktrees are planted after their parent repositories.
+    assert_eq!("parent", cfg.trees[0].get_name());
+    assert_eq!("repos/example.git", cfg.trees[1].get_name());
+    assert!(cfg.trees[1].is_bare_repository);
+    assert_eq!("child", cfg.trees[2].get_name());
+    assert!(cfg.trees[2].is_worktree);
+    assert_eq!(cfg.trees[2].worktree.get_expr(), "parent");
+
+    Ok(())
+}
+
+/// "garden plant --scan" skips directories excluded by ".gardenignore" files.
+#[test]
+#[named]
+fn plant_scan_gardenignore() -> Result<()> {
+    let fixture = common::BareRepoFixture::new(function_name!());
+    common::exec_garden(&["--chdir", &fixture.root(), "init"])?;
+
+    for path in ["vendor/lib", "archive/old", "src/app"] {
+        let cmd = ["git", "clone", "--quiet", "repos/example.git", path];
+        common::assert_cmd(&cmd, &fixture.root());
+    }
+    // An empty ".gardenignore" skips its directory.
+    std::fs::write(format!("{}/vendor/.gardenignore", fixture.root()), "")?;
+    // Patterns skip matching entries. "repos" contains bare repositories.
+    std::fs::write(
+        format!("{}/.gardenignore", fixture.root()),
+        "# Archived repositories.\narchive/\nrepos\n",
+    )?;
+
+    common::exec_garden(&["--chdir", &fixture.root(), "plant", "--scan", "."])?;
+
+    let garden_yaml = fixture.path("garden.yaml");
+    let path = Some(std::path::PathBuf::from(&garden_yaml));
+    let cfg = garden::config::new(&path, &fixture.root(), 0, None)?;
+    assert_eq!(1, cfg.trees.len());
+    assert_eq!("src/app", cfg.trees[0].get_name());
+
+    Ok(())
+}
+
+/// "garden plant" records "lfs: true" for repositories that use Git LFS.
+#[test]
+#[named]
+fn plant_lfs() -> Result<()> {
+    let fixture = common::BareRepoFixture::new(function_name!());
+    common::exec_garden(&["--chdir", &fixture.root(), "init"])?;
+    for name in ["lfs", "plain"] {
+        let cmd = ["git", "clone", "--quiet", "repos/example.git", name];
+        common::assert_cmd(&cmd, &fixture.root());
+    }
+    std::fs::write(
+        format!("{}/lfs/.gitattributes", fixture.root()),
+        "*.bin filter=lfs diff=lfs merge=lfs -text\n",
+    )?;
+
+    common::exec_garden(&["--chdir", &fixture.root(), "plant", "lfs", "plain"])?;
+
+    let garden_yaml = fixture.path("garden.yaml");
+    let path = Some(std::path::PathBuf::from(&garden_yaml));
+    let cfg = garden::config::new(&path, &fixture.root(), 0, None)?;
+    assert_eq!("lfs", cfg.trees[0].get_name());
+    assert!(cfg.trees[0].is_lfs);
+    assert_eq!("plain", cfg.trees[1].get_name());
+    assert!(!cfg.trees[1].is_lfs);
+
+    Ok(())
+}
+
+/// "garden plant" records "default-remote" for repositories whose primary remote
+/// is not named "origin".
+#[test]
+#[named]
+fn plant_default_remote() -> Result<()> {
+    let fixture = common::BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    common::exec_garden(&["--chdir", &root, "init"])?;
+    // "detected" has a single "upstream" remote.
+    let cmd = [
+        "git",
+        "clone",
+        "--quiet",
+        "-o",
+        "upstream",
+        "repos/example.git",
+        "detected",
+    ];
+    common::assert_cmd(&cmd, &root);
+    // "forked" has "origin" and "upstream" remotes.
+    let cmd = ["git", "clone", "--quiet", "repos/example.git", "forked"];
+    common::assert_cmd(&cmd, &root);
+    let cmd = ["git", "remote", "add", "upstream", "../repos/example.git"];
+    common::assert_cmd(&cmd, &fixture.path("forked"));
+
+    common::exec_garden(&["--chdir", &root, "plant", "detected", "forked"])?;
+    let garden_yaml = fixture.path("garden.yaml");
+    let path = Some(std::path::PathBuf::from(&garden_yaml));
+    let cfg = garden::config::new(&path, &root, 0, None)?;
+    assert_eq!("detected", cfg.trees[0].get_name());
+    assert_eq!("upstream", cfg.trees[0].default_remote.get_expr());
+    assert_eq!(1, cfg.trees[0].remotes.len());
+    assert_eq!("upstream", cfg.trees[0].remotes[0].get_name());
+    assert_eq!("forked", cfg.trees[1].get_name());
+    assert!(cfg.trees[1].default_remote.is_empty());
+
+    // --default-remote selects the primary remote.
+    common::exec_garden(&[
+        "--chdir",
+        &root,
+        "plant",
+        "--default-remote",
+        "upstream",
+        "forked",
+    ])?;
+    let cfg = garden::config::new(&path, &root, 0, None)?;
+    assert_eq!("upstream", cfg.trees[1].default_remote.get_expr());
+    assert_eq!("origin", cfg.trees[1].remotes[0].get_name());
+    assert_eq!("upstream", cfg.trees[1].remotes[1].get_name());
+
+    // "--default-remote origin" removes the recorded primary remote.
+    common::exec_garden(&[
+        "--chdir",
+        &root,
+        "plant",
+        "--default-remote",
+        "origin",
+        "forked",
+    ])?;
+    let cfg = garden::config::new(&path, &root, 0, None)?;
+    assert!(cfg.trees[1].default_remote.is_empty());
+    let output = std::fs::read_to_string(&garden_yaml)?;
+    assert_eq!(output.matches("default-remote").count(), 1);
+
+    Ok(())
+}
+
+/// "garden plant" retains YAML anchors and merge keys and does not repeat the
+/// values that a tree inherits through merge keys.
+#[test]
+#[named]
+fn plant_merge_keys() -> Result<()> {
+    let fixture = common::BareRepoFixture::new(function_name!());
+    let root = fixture.root();
+    let cmd = ["git", "clone", "--quiet", "repos/example.git", "example"];
+    common::assert_cmd(&cmd, &root);
+    let cmd = ["git", "config", "remote.origin.url"];
+    let url = common::assert_cmd_capture(&cmd, &fixture.path("example"));
+    let config = format!(
+        "templates:\n  defaults: &defaults\n    url: {}\ntrees:\n  example:\n    <<: *defaults\n",
+        url
+    );
+    let garden_yaml = format!("{}/garden.yaml", root);
+    std::fs::write(&garden_yaml, &config)?;
+
+    // Planting a tree whose values are inherited leaves the file untouched.
+    common::exec_garden(&["--chdir", &root, "plant", "example"])?;
+    assert_eq!(config, std::fs::read_to_string(&garden_yaml)?);
+
+    // New values are added alongside the merge key.
+    let cmd = ["git", "remote", "add", "upstream", "upstream-url"];
+    common::assert_cmd(&cmd, &fixture.path("example"));
+    common::exec_garden(&["--chdir", &root, "plant", "example"])?;
+    let expect = format!("{}    remotes:\n      upstream: upstream-url\n", config);
+    assert_eq!(expect, std::fs::read_to_string(&garden_yaml)?);
+
+    let path = Some(std::path::PathBuf::from(&garden_yaml));
+    let cfg = garden::config::new(&path, &root, 0, None)?;
+    assert_eq!(&url, cfg.trees[0].remotes[0].get_expr());
+    assert_eq!("upstream", cfg.trees[0].remotes[1].get_name());
+
+    Ok(())
+}
+
+/// `garden plant` preserves comments in the garden file.
+#[test]
+#[named]
+fn plant_preserves_comments() -> Result<()> {
+    let fixture = common::BareRepoFixture::new(function_name!());
+    let config = "# Garden file comment.\ntrees:\n  # Tree comment.\n  example: {}\n";
+    std::fs::write(format!("{}/garden.yaml", fixture.root()), config)?;
+
+    // garden plant repos/example.git
+    common::exec_garden(&["--chdir", &fixture.root(), "plant", "repos/example.git"])?;
+
+    let garden_yaml = std::fs::read_to_string(fixture.path("garden.yaml"))?;
+    assert!(garden_yaml.starts_with(config));
+    assert!(garden_yaml.contains("  repos/example.git:\n    bare: true\n"));
+
+    Ok(())
+}
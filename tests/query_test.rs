@@ -56,6 +56,108 @@ fn resolve_trees_group_with_wildcards() {
     assert_eq!(5, result[1].tree);
 }
 
+#[test]
+fn resolve_trees_with_exclusions() {
+    let mut config = common::garden_config();
+    // "@c*" matches tree 1.
+    let result = garden::query::resolve_trees(&config, "cola & !@c*");
+    let trees: Vec<usize> = result.iter().map(|ctx| ctx.tree).collect();
+    assert_eq!(trees, [0, 2]);
+
+    // Exclusions alone match all trees except for the excluded trees.
+    let result = garden::query::resolve_trees(&config, "!%annex");
+    assert_eq!(config.trees.len() - 2, result.len());
+    assert!(!result.iter().any(|ctx| ctx.tree == 4 || ctx.tree == 5));
+
+    // Multiple terms match the trees that are common to all terms.
+    let result = garden::query::resolve_trees(&config, "%rev* & @c*");
+    assert_eq!(1, result.len());
+    assert_eq!(1, result[0].tree);
+
+    // "--exclude" queries are applied to every query.
+    config.exclude_queries.push("@c*".into());
+    let result = garden::query::resolve_trees(&config, "cola");
+    let trees: Vec<usize> = result.iter().map(|ctx| ctx.tree).collect();
+    assert_eq!(trees, [0, 2]);
+}
+
+#[test]
+fn resolve_trees_skips_trees_unless_named() {
+    let string = r#"
+    trees:
+      app: {}
+      vendored:
+        skip: true
+    groups:
+      all: "*"
+      named: vendored
+    "#;
+    let config = common::from_string(string);
+    assert!(config.trees[1].is_skipped);
+
+    // Wildcards and exclusions do not match skipped trees.
+    for query in ["@*", "%all", "!app"] {
+        let result = garden::query::resolve_trees(&config, query);
+        let trees: Vec<usize> = result.iter().map(|ctx| ctx.tree).collect();
+        let expect: &[usize] = if query == "!app" { &[] } else { &[0] };
+        assert_eq!(trees, expect, "{query}");
+    }
+    // Skipped trees are matched when named explicitly.
+    for query in ["vendored", "@vendored", "%named"] {
+        let result = garden::query::resolve_trees(&config, query);
+        assert_eq!(1, result.len(), "{query}");
+        assert_eq!(1, result[0].tree);
+    }
+}
+
+#[test]
+fn resolve_trees_named_queries() {
+    let string = r#"
+    trees:
+      backend-api: {}
+      backend-legacy: {}
+      frontend: {}
+      tools: {}
+    groups:
+      backend: backend-*
+      shared: tools
+    queries:
+      services:
+        - "%backend & !*legacy"
+        - "@frontend"
+      shared: "@frontend"
+      everything:
+        - "%services"
+        - tools
+      cycle: "%cycle"
+    "#;
+    let config = common::from_string(string);
+    assert_eq!(config.queries.len(), 4);
+
+    // The trees matched by each query are combined.
+    let result = garden::query::resolve_trees(&config, "%services");
+    let trees: Vec<usize> = result.iter().map(|ctx| ctx.tree).collect();
+    assert_eq!(trees, [0, 2]);
+
+    // Named queries can be used without a prefix and combined with other terms.
+    let result = garden::query::resolve_trees(&config, "services & !frontend");
+    let trees: Vec<usize> = result.iter().map(|ctx| ctx.tree).collect();
+    assert_eq!(trees, [0]);
+
+    // Named queries can reference other named queries.
+    let result = garden::query::resolve_trees(&config, "%everything");
+    let trees: Vec<usize> = result.iter().map(|ctx| ctx.tree).collect();
+    assert_eq!(trees, [0, 2, 3]);
+
+    // Groups take precedence over named queries with the same name.
+    let result = garden::query::resolve_trees(&config, "%shared");
+    let trees: Vec<usize> = result.iter().map(|ctx| ctx.tree).collect();
+    assert_eq!(trees, [3]);
+
+    // Queries that reference themselves do not match any trees.
+    assert!(garden::query::resolve_trees(&config, "%cycle").is_empty());
+}
+
 #[test]
 fn trees_from_pattern() {
     let config = common::garden_config();
@@ -177,3 +177,148 @@ fn graft_basename() {
     assert!(value.is_some());
     assert_eq!("foo", value.unwrap());
 }
+
+#[test]
+fn split_default() {
+    assert_eq!(
+        Some(("name", "value")),
+        syntax::split_default("name:-value")
+    );
+    assert_eq!(Some(("name", "")), syntax::split_default("name:-"));
+    assert_eq!(Some(("a::b", "${c}")), syntax::split_default("a::b:-${c}"));
+    assert_eq!(None, syntax::split_default(":-value"));
+    assert_eq!(None, syntax::split_default("name"));
+}
+
+#[test]
+fn split_function() {
+    assert_eq!(
+        Some(("dirname", vec!["TREE_PATH"])),
+        syntax::split_function(" dirname(TREE_PATH) ")
+    );
+    assert_eq!(
+        Some(("replace", vec!["name", "\",\"", "basename(a)"])),
+        syntax::split_function("replace(name, \",\", basename(a))")
+    );
+    assert_eq!(Some(("f", vec![])), syntax::split_function("f()"));
+    assert_eq!(None, syntax::split_function("name"));
+    assert_eq!(None, syntax::split_function("f(\"a)"));
+}
+
+#[test]
+fn find_closing_brace() {
+    assert_eq!(Some(4), syntax::find_closing_brace("name}"));
+    assert_eq!(Some(12), syntax::find_closing_brace("a:-${b}/${c}}"));
+    assert_eq!(None, syntax::find_closing_brace("a:-${b"));
+}
+
+#[test]
+fn duration() {
+    let seconds = std::time::Duration::from_secs;
+    assert_eq!(Some(seconds(30)), syntax::duration("30"));
+    assert_eq!(Some(seconds(30)), syntax::duration("30s"));
+    assert_eq!(Some(seconds(300)), syntax::duration("5m"));
+    assert_eq!(Some(seconds(7200)), syntax::duration(" 2h "));
+    assert_eq!(Some(seconds(5 * 86400)), syntax::duration("5d"));
+    assert_eq!(Some(seconds(2 * 604800)), syntax::duration("2w"));
+    assert_eq!(
+        Some(std::time::Duration::from_millis(500)),
+        syntax::duration("0.5s")
+    );
+    assert_eq!(None, syntax::duration("0"));
+    assert_eq!(None, syntax::duration("-1"));
+    assert_eq!(None, syntax::duration("5y"));
+    assert_eq!(None, syntax::duration(""));
+    // Durations that overflow are rejected.
+    assert_eq!(None, syntax::duration("99999999999999999w"));
+    assert_eq!(None, syntax::duration("inf"));
+    assert!(syntax::parse_duration("99999999999999999w").is_err());
+}
+
+#[test]
+fn url_host() {
+    assert_eq!(
+        Some(("ssh", "github.com")),
+        syntax::url_host("git@github.com:garden-rs/garden.git")
+    );
+    assert_eq!(
+        Some(("ssh", "example.com")),
+        syntax::url_host("ssh://git@example.com:2222/repo.git")
+    );
+    assert_eq!(
+        Some(("https", "example.com")),
+        syntax::url_host("https://user@example.com/repo.git")
+    );
+    assert_eq!(
+        Some(("git", "::1")),
+        syntax::url_host("git://[::1]/repo.git")
+    );
+    assert_eq!(Some(("ssh", "host")), syntax::url_host("host:repo.git"));
+    assert_eq!(None, syntax::url_host("file:///srv/repo.git"));
+    assert_eq!(None, syntax::url_host("/srv/repo.git"));
+    assert_eq!(None, syntax::url_host("./repo:name.git"));
+    assert_eq!(None, syntax::url_host("repo.git"));
+}
+
+#[test]
+fn parse_env_file() {
+    let content = r#"
+# comment
+PLAIN=value
+export EXPORTED=yes
+SPACED = padded  # trailing comment
+SINGLE='literal $HOME \n'
+DOUBLE="line\none \"quoted\""
+EMPTY=
+not an assignment
+"#;
+    let values = syntax::parse_env_file(content);
+    let expect = [
+        ("PLAIN", "value"),
+        ("EXPORTED", "yes"),
+        ("SPACED", "padded"),
+        ("SINGLE", "literal $HOME \\n"),
+        ("DOUBLE", "line\none \"quoted\""),
+        ("EMPTY", ""),
+    ];
+    let expect: Vec<(String, String)> = expect
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    assert_eq!(values, expect);
+}
+
+#[test]
+fn is_git_url() {
+    assert!(syntax::is_git_url("https://example.com/repo.git"));
+    assert!(syntax::is_git_url("file:///srv/repo.git"));
+    assert!(syntax::is_git_url("git@example.com:repo.git"));
+    assert!(!syntax::is_git_url("tools/garden.yaml"));
+    assert!(!syntax::is_git_url("/srv/garden.yaml"));
+    assert!(!syntax::is_git_url("${GARDEN_CONFIG_DIR}/garden.yaml"));
+    assert!(!syntax::is_git_url("C:\\garden\\org.yaml"));
+    assert!(!syntax::is_git_url("C:garden.yaml"));
+    assert!(!syntax::is_git_url("D:/garden/org.yaml"));
+}
+
+#[test]
+fn join_url() {
+    let url = "https://example.com/workspace/garden.yaml?ref=main";
+    assert_eq!(syntax::url_dirname(url), "https://example.com/workspace");
+    assert_eq!(
+        syntax::join_url(url, "common.yaml"),
+        "https://example.com/workspace/common.yaml"
+    );
+    assert_eq!(
+        syntax::join_url(url, "./shared/../other/x.yaml"),
+        "https://example.com/workspace/other/x.yaml"
+    );
+    assert_eq!(
+        syntax::join_url(url, "../../../x.yaml"),
+        "https://example.com/x.yaml"
+    );
+    assert_eq!(
+        syntax::join_url("https://example.com/garden.yaml", "x.yaml"),
+        "https://example.com/x.yaml"
+    );
+}